@@ -0,0 +1,114 @@
+//! Protection of the visitors against the active content of uploads, see `--protect-uploads`
+//!
+//! HTML, SVG and XML files may run scripts in the origin of miniserve when they're opened, with
+//! the credentials of whoever opens them. The ones in upload directories are sent as attachments,
+//! in a sandbox in case the browser shows them anyway, unless they're in a trusted path.
+
+use std::path::PathBuf;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web,
+};
+use mime::Mime;
+
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::file_utils::{pretty_url_path, requested_path};
+
+/// true if documents of the `mime` type may run scripts
+pub fn is_active(mime: &Mime) -> bool {
+    mime.subtype() == mime::HTML || mime.subtype() == mime::XML || mime.suffix() == Some(mime::XML)
+}
+
+/// Path relative to the served path of the file the file service serves at the URL path `link`,
+/// including the fallbacks of `--pretty-urls` and `--spa` when nothing is found there
+fn served_file(conf: &MiniserveConfig, link: &str) -> Option<PathBuf> {
+    if let Some(path) = requested_path(conf, link) {
+        let local_path = conf.path.join(&path);
+        // Directories are served as their index file, if any, or listed
+        if local_path.is_dir() {
+            let index = path.join(conf.index.as_ref()?);
+            return conf.path.join(&index).is_file().then_some(index);
+        }
+        if local_path.is_file() {
+            return Some(path);
+        }
+    }
+
+    // The handler of `--pretty-urls` replaces the one of `--spa`
+    if conf.pretty_urls {
+        let path = pretty_url_path(link);
+        conf.path.join(&path).is_file().then_some(path)
+    } else if conf.spa {
+        conf.index.clone()
+    } else {
+        None
+    }
+}
+
+/// Protect the files served by the wrapped service, if they're uploads which may run scripts.
+///
+/// Every file of the upload directories is sent without content type sniffing, so that the
+/// browser can't mistake the other ones for active content either.
+pub async fn protect_uploads(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let protected = conf.protect_uploads
+        && served_file(&conf, req.path()).is_some_and(|path| conf.upload_protected(&path));
+    let mut res = next.call(req).await?;
+    if !protected {
+        return Ok(res);
+    }
+
+    let headers = res.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    let active = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<Mime>().ok())
+        .is_some_and(|mime| is_active(&mime));
+    if active {
+        // The file name given by the file service is kept
+        let disposition = headers
+            .get(header::CONTENT_DISPOSITION)
+            .and_then(|disposition| disposition.to_str().ok())
+            .and_then(|disposition| disposition.strip_prefix("inline"))
+            .and_then(|params| HeaderValue::from_str(&format!("attachment{params}")).ok())
+            .unwrap_or(HeaderValue::from_static("attachment"));
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("sandbox"),
+        );
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("text/html; charset=utf-8", true)]
+    #[case("application/xhtml+xml", true)]
+    #[case("image/svg+xml", true)]
+    #[case("text/xml", true)]
+    #[case("application/xml", true)]
+    #[case("application/rss+xml", true)]
+    #[case("text/plain", false)]
+    #[case("image/png", false)]
+    #[case("application/json", false)]
+    fn active_content_types(#[case] mime: &str, #[case] expected: bool) {
+        assert_eq!(is_active(&mime.parse().unwrap()), expected);
+    }
+}
@@ -1,5 +1,4 @@
-use std::fs::File;
-use std::io::{Cursor, Read, Write};
+use std::fs::{self, File, Metadata};
 use std::path::{Path, PathBuf};
 
 use libflate::gzip::Encoder;
@@ -9,6 +8,7 @@ use tar::Builder;
 use zip::{ZipWriter, write};
 
 use crate::errors::RuntimeError;
+use crate::file_utils::Visibility;
 
 /// Available archive methods
 #[derive(Deserialize, Clone, Copy, EnumIter, EnumString, Display)]
@@ -54,13 +54,12 @@ impl ArchiveMethod {
 
     /// Make an archive out of the given directory, and write the output to the given writer.
     ///
-    /// Recursively includes all files and subdirectories.
-    ///
-    /// If `skip_symlinks` is `true`, symlinks fill not be followed and will just be ignored.
+    /// Recursively includes all files and subdirectories that `visibility` allows, i.e. exactly
+    /// what the listing shows.
     pub fn create_archive<T, W>(
         self,
         dir: T,
-        skip_symlinks: bool,
+        visibility: Visibility,
         out: W,
     ) -> Result<(), RuntimeError>
     where
@@ -69,21 +68,113 @@ impl ArchiveMethod {
     {
         let dir = dir.as_ref();
         match self {
-            Self::TarGz => tar_gz(dir, skip_symlinks, out),
-            Self::Tar => tar_dir(dir, skip_symlinks, out),
-            Self::Zip => zip_dir(dir, skip_symlinks, out),
+            Self::TarGz => tar_gz(dir, visibility, out),
+            Self::Tar => tar_dir(dir, visibility, out),
+            Self::Zip => zip_dir(dir, visibility, out),
+        }
+    }
+}
+
+/// Entry of a directory tree being archived
+struct ArchiveEntry {
+    /// Location of the entry on disk
+    path: PathBuf,
+
+    /// Path of the entry inside the archive
+    name: PathBuf,
+
+    /// Metadata of the entry, with symlinks followed
+    metadata: Metadata,
+}
+
+/// Call `f` with `src_dir` and everything below it that `visibility` allows.
+///
+/// `src_dir` is named `inner_folder` inside the archive. Directories come before their content,
+/// and entries of a directory are sorted by name. Like in the listing, entries whose metadata
+/// can't be read (e.g. broken symlinks) and entries which are neither files nor directories are
+/// skipped.
+fn walk_visible<F>(
+    src_dir: &Path,
+    inner_folder: &Path,
+    visibility: Visibility,
+    mut f: F,
+) -> Result<(), RuntimeError>
+where
+    F: FnMut(&ArchiveEntry) -> Result<(), RuntimeError>,
+{
+    let metadata = fs::metadata(src_dir).map_err(|e| {
+        RuntimeError::IoError(
+            format!("Could not get metadata of '{}'", src_dir.display()),
+            e,
+        )
+    })?;
+    let mut stack = vec![ArchiveEntry {
+        path: src_dir.to_path_buf(),
+        name: inner_folder.to_path_buf(),
+        metadata,
+    }];
+
+    while let Some(entry) = stack.pop() {
+        let children = if entry.metadata.is_dir() {
+            visible_children(&entry, visibility)?
+        } else {
+            vec![]
+        };
+        f(&entry)?;
+        stack.extend(children.into_iter().rev());
+    }
+
+    Ok(())
+}
+
+/// Entries of the `parent` directory that `visibility` allows, sorted by name
+fn visible_children(
+    parent: &ArchiveEntry,
+    visibility: Visibility,
+) -> Result<Vec<ArchiveEntry>, RuntimeError> {
+    let read_dir = fs::read_dir(&parent.path).map_err(|e| {
+        RuntimeError::IoError(
+            format!("Could not read directory '{}'", parent.path.display()),
+            e,
+        )
+    })?;
+
+    let mut children = vec![];
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry
+            .map_err(|e| RuntimeError::IoError("Could not read directory entry".to_string(), e))?;
+        let is_symlink = dir_entry.file_type().is_ok_and(|t| t.is_symlink());
+        if !visibility.allows(&dir_entry.file_name(), is_symlink) {
+            continue;
         }
+
+        let path = dir_entry.path();
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if !metadata.is_dir() && !metadata.is_file() {
+            continue;
+        }
+
+        children.push(ArchiveEntry {
+            name: parent.name.join(dir_entry.file_name()),
+            path,
+            metadata,
+        });
     }
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(children)
 }
 
 /// Write a gzipped tarball of `dir` in `out`.
-fn tar_gz<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), RuntimeError>
+fn tar_gz<W>(dir: &Path, visibility: Visibility, out: W) -> Result<(), RuntimeError>
 where
     W: std::io::Write,
 {
     let mut out = Encoder::new(out).map_err(|e| RuntimeError::IoError("GZIP".to_string(), e))?;
 
-    tar_dir(dir, skip_symlinks, &mut out)?;
+    tar_dir(dir, visibility, &mut out)?;
 
     out.finish()
         .into_result()
@@ -115,7 +206,7 @@ where
 /// ├── f
 /// └── g
 /// ```
-fn tar_dir<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), RuntimeError>
+fn tar_dir<W>(dir: &Path, visibility: Visibility, out: W) -> Result<(), RuntimeError>
 where
     W: std::io::Write,
 {
@@ -129,7 +220,7 @@ where
         )
     })?;
 
-    tar(dir, directory.to_string(), skip_symlinks, out)
+    tar(dir, directory.to_string(), visibility, out)
         .map_err(|e| RuntimeError::ArchiveCreationError("tarball".to_string(), Box::new(e)))
 }
 
@@ -139,7 +230,7 @@ where
 fn tar<W>(
     src_dir: &Path,
     inner_folder: String,
-    skip_symlinks: bool,
+    visibility: Visibility,
     out: W,
 ) -> Result<(), RuntimeError>
 where
@@ -147,20 +238,23 @@ where
 {
     let mut tar_builder = Builder::new(out);
 
-    tar_builder.follow_symlinks(!skip_symlinks);
-
-    // Recursively adds the content of src_dir into the archive stream
-    tar_builder
-        .append_dir_all(inner_folder, src_dir)
-        .map_err(|e| {
+    // Recursively adds the visible content of src_dir into the archive stream
+    walk_visible(src_dir, Path::new(&inner_folder), visibility, |entry| {
+        let result = if entry.metadata.is_dir() {
+            tar_builder.append_dir(&entry.name, &entry.path)
+        } else {
+            tar_builder.append_path_with_name(&entry.path, &entry.name)
+        };
+        result.map_err(|e| {
             RuntimeError::IoError(
                 format!(
-                    "Failed to append the content of '{}' to the TAR archive",
-                    src_dir.to_str().unwrap_or("file")
+                    "Failed to append '{}' to the TAR archive",
+                    entry.path.to_str().unwrap_or("file")
                 ),
                 e,
             )
-        })?;
+        })
+    })?;
 
     // Finish the archive
     tar_builder.into_inner().map_err(|e| {
@@ -193,102 +287,55 @@ where
 /// ├── f
 /// └── g
 /// ```
+///
+/// The archive is streamed to `out` as it's being built: file contents are copied in chunks and
+/// entries use data descriptors (and zip64 where needed), so memory usage doesn't depend on the
+/// size of the directory.
 fn create_zip_from_directory<W>(
     out: W,
     directory: &Path,
-    skip_symlinks: bool,
+    visibility: Visibility,
 ) -> Result<(), RuntimeError>
 where
-    W: std::io::Write + std::io::Seek,
+    W: std::io::Write,
 {
     let options =
         write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
-    let mut paths_queue: Vec<PathBuf> = vec![directory.to_path_buf()];
     let zip_root_folder_name = directory.file_name().ok_or_else(|| {
         RuntimeError::InvalidPathError("Directory name terminates in \"..\"".to_string())
     })?;
 
-    let mut zip_writer = ZipWriter::new(out);
-    let mut buffer = Vec::new();
-    while !paths_queue.is_empty() {
-        let next = paths_queue.pop().ok_or_else(|| {
-            RuntimeError::ArchiveCreationDetailError("Could not get path from queue".to_string())
-        })?;
-        let current_dir = next.as_path();
-        let directory_entry_iterator = std::fs::read_dir(current_dir)
-            .map_err(|e| RuntimeError::IoError("Could not read directory".to_string(), e))?;
-        let zip_directory = Path::new(zip_root_folder_name).join(
-            current_dir.strip_prefix(directory).map_err(|_| {
-                RuntimeError::ArchiveCreationDetailError(
-                    "Could not append base directory".to_string(),
-                )
-            })?,
-        );
-
-        for entry in directory_entry_iterator {
-            let entry_path = entry
-                .ok()
-                .ok_or_else(|| {
-                    RuntimeError::InvalidPathError(
-                        "Directory name terminates in \"..\"".to_string(),
-                    )
-                })?
-                .path();
-            let entry_metadata = std::fs::metadata(entry_path.clone()).map_err(|e| {
-                RuntimeError::IoError(
-                    format!(
-                        "Could not get file metadata of '{}'",
-                        entry_path.to_string_lossy()
-                    )
-                    .to_string(),
-                    e,
-                )
-            })?;
-
-            if entry_metadata.file_type().is_symlink() && skip_symlinks {
-                continue;
-            }
-            let current_entry_name = entry_path.file_name().ok_or_else(|| {
-                RuntimeError::InvalidPathError("Invalid file or directory name".to_string())
-            })?;
-
-            // To let every software correctly parse the file structure in ZIP files that are produced
-            // on any platform (esp. Windows), always use forward slashes. The documentation:
-            // https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
-            let relative_path = if cfg!(windows) {
-                let branch = zip_directory
-                    .as_os_str()
-                    .to_string_lossy()
-                    .trim_end_matches(r"\") // every branch ends with two backslashes "\\".
-                    .replace(r"\", "/"); // every branch uses backslash "\" as path separators.
-                let leaf = current_entry_name.to_string_lossy();
-                format!("{branch}/{leaf}") // construct a Unix-style path in the simplest way.
-            } else {
-                zip_directory
-                    .join(current_entry_name)
-                    .into_os_string()
-                    .to_string_lossy()
-                    .into_owned()
-            };
-
-            if entry_metadata.is_file() {
-                let mut f = File::open(&entry_path)
+    let mut zip_writer = ZipWriter::new_stream(out).set_auto_large_file();
+    walk_visible(
+        directory,
+        Path::new(zip_root_folder_name),
+        visibility,
+        |entry| {
+            // To let every software correctly parse the file structure in ZIP files that are
+            // produced on any platform (esp. Windows), always use forward slashes. The
+            // documentation: https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
+            let relative_path = entry
+                .name
+                .iter()
+                .map(|component| component.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if entry.metadata.is_file() {
+                let mut f = File::open(&entry.path)
                     .map_err(|e| RuntimeError::IoError("Could not open file".to_string(), e))?;
-                f.read_to_end(&mut buffer).map_err(|e| {
-                    RuntimeError::IoError("Could not read from file".to_string(), e)
-                })?;
-                zip_writer.start_file(relative_path, options).map_err(|_| {
-                    RuntimeError::ArchiveCreationDetailError(
-                        "Could not add file path to ZIP".to_string(),
-                    )
-                })?;
-                zip_writer.write(buffer.as_ref()).map_err(|_| {
-                    RuntimeError::ArchiveCreationDetailError(
-                        "Could not write file to ZIP".to_string(),
-                    )
+                let file_options = options.large_file(entry.metadata.len() > u32::MAX as u64);
+                zip_writer
+                    .start_file(relative_path, file_options)
+                    .map_err(|_| {
+                        RuntimeError::ArchiveCreationDetailError(
+                            "Could not add file path to ZIP".to_string(),
+                        )
+                    })?;
+                std::io::copy(&mut f, &mut zip_writer).map_err(|e| {
+                    RuntimeError::IoError("Could not write file to ZIP".to_string(), e)
                 })?;
-                buffer.clear();
-            } else if entry_metadata.is_dir() {
+            } else {
                 zip_writer
                     .add_directory(relative_path, options)
                     .map_err(|_| {
@@ -296,10 +343,10 @@ where
                             "Could not add directory path to ZIP".to_string(),
                         )
                     })?;
-                paths_queue.push(entry_path.clone());
             }
-        }
-    }
+            Ok(())
+        },
+    )?;
 
     zip_writer.finish().map_err(|_| {
         RuntimeError::ArchiveCreationDetailError("Could not finish writing ZIP archive".to_string())
@@ -309,27 +356,20 @@ where
 
 /// Writes a zip of `dir` in `out`.
 ///
-/// The content of `src_dir` will be saved in the archive as the  folder named .
-fn zip_data<W>(src_dir: &Path, skip_symlinks: bool, mut out: W) -> Result<(), RuntimeError>
+/// The content of `src_dir` will be saved in the archive as a folder named after `src_dir`.
+fn zip_data<W>(src_dir: &Path, visibility: Visibility, out: W) -> Result<(), RuntimeError>
 where
     W: std::io::Write,
 {
-    let mut data = Vec::new();
-    let memory_file = Cursor::new(&mut data);
-    create_zip_from_directory(memory_file, src_dir, skip_symlinks).map_err(|e| {
+    create_zip_from_directory(out, src_dir, visibility).map_err(|e| {
         RuntimeError::ArchiveCreationError(
             "Failed to create the ZIP archive".to_string(),
             Box::new(e),
         )
-    })?;
-
-    out.write_all(data.as_mut_slice())
-        .map_err(|e| RuntimeError::IoError("Failed to write the ZIP archive".to_string(), e))?;
-
-    Ok(())
+    })
 }
 
-fn zip_dir<W>(dir: &Path, skip_symlinks: bool, out: W) -> Result<(), RuntimeError>
+fn zip_dir<W>(dir: &Path, visibility: Visibility, out: W) -> Result<(), RuntimeError>
 where
     W: std::io::Write,
 {
@@ -343,6 +383,6 @@ where
         )
     })?;
 
-    zip_data(dir, skip_symlinks, out)
+    zip_data(dir, visibility, out)
         .map_err(|e| RuntimeError::ArchiveCreationError("zip".to_string(), Box::new(e)))
 }
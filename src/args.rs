@@ -1,8 +1,10 @@
 use std::fmt::Display;
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use bytesize::ByteSize;
 use clap::{Parser, ValueEnum, ValueHint};
 
 use crate::auth;
@@ -22,6 +24,7 @@ pub enum DuplicateFile {
     Error,
     Overwrite,
     Rename,
+    Version,
 }
 
 #[derive(ValueEnum, Clone)]
@@ -50,6 +53,20 @@ pub enum LogColor {
 #[derive(Parser)]
 #[command(name = "miniserve", author, about, version)]
 pub struct CliArgs {
+    /// Read options from a TOML config file
+    ///
+    /// Every option can be set in the config file using its name in snake_case as the key, for
+    /// instance `port = 8080`, `auth = ["joe:123"]` or `path = "/srv/files"`. Flags take a boolean.
+    /// Options given on the command line or through environment variables take precedence over
+    /// the values from the config file.
+    #[arg(long, value_hint = ValueHint::FilePath, env = "MINISERVE_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration (merged from the config file, environment variables and
+    /// command line) as TOML and exit
+    #[arg(long = "print-config")]
+    pub print_config: bool,
+
     /// Be verbose, includes emitting access logs
     #[arg(short = 'v', long = "verbose", env = "MINISERVE_VERBOSE")]
     pub verbose: bool,
@@ -228,6 +245,32 @@ pub struct CliArgs {
     #[arg(short = 'u', long = "upload-files", value_hint = ValueHint::FilePath, num_args(0..=1), value_delimiter(','), env = "MINISERVE_ALLOWED_UPLOAD_DIR")]
     pub allowed_upload_dir: Option<Vec<PathBuf>>,
 
+    /// Serve the HTML, SVG and XML files of the upload directories as attachments
+    ///
+    /// Such files could run scripts with the credentials of whoever opens them, so they're
+    /// downloaded instead, in a sandbox. Files of the upload directories are also sent without
+    /// content type sniffing.
+    #[arg(
+        long = "protect-uploads",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_PROTECT_UPLOADS"
+    )]
+    pub protect_uploads: bool,
+
+    /// Path whose files are served as usual with --protect-uploads
+    ///
+    /// Like the upload directories, the path is relative to the serve dir. This parameter can be
+    /// used multiple times to trust several paths.
+    #[arg(
+        long = "trusted-path",
+        value_hint = ValueHint::FilePath,
+        requires = "protect_uploads",
+        num_args(1),
+        value_delimiter(','),
+        env = "MINISERVE_TRUSTED_PATHS"
+    )]
+    pub trusted_paths: Vec<PathBuf>,
+
     /// Configure amount of concurrent uploads when visiting the website. Must have
     /// upload-files option enabled for this setting to matter.
     ///
@@ -267,6 +310,33 @@ pub struct CliArgs {
     #[arg(long = "directory-size", env = "MINISERVE_DIRECTORY_SIZE")]
     pub directory_size: bool,
 
+    /// Enable recursive filename search
+    ///
+    /// Adds a search box to the listing which looks for files and directories below the current
+    /// directory whose names contain the search text or match it as a glob pattern (like `*.txt`).
+    /// This is disabled by default because it is a potentially fairly IO intensive operation.
+    #[arg(long = "enable-search", env = "MINISERVE_ENABLE_SEARCH")]
+    pub enable_search: bool,
+
+    /// Maximum number of results returned by a single search
+    #[arg(
+        long = "search-max-results",
+        env = "MINISERVE_SEARCH_MAX_RESULTS",
+        default_value = "1000"
+    )]
+    pub search_max_results: usize,
+
+    /// Maximum time in seconds a single search may run for
+    ///
+    /// Results found until then are still returned.
+    #[arg(
+        long = "search-timeout",
+        env = "MINISERVE_SEARCH_TIMEOUT",
+        default_value = "5",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub search_timeout: u64,
+
     /// Enable creating directories
     #[arg(
         short = 'U',
@@ -279,7 +349,8 @@ pub struct CliArgs {
     /// Enable creating pastebin 'pastes'
     ///
     /// 'pastes' are plaintext files created in the current directory. Creation requires file
-    /// uploads be enabled.
+    /// uploads be enabled. Pastes can also be sent from the command line to `/__paste`, like
+    /// `curl --data-binary @- http://host/__paste`, which answers with their URL.
     #[arg(
         long = "pastebin",
         requires = "allowed_upload_dir",
@@ -287,6 +358,26 @@ pub struct CliArgs {
     )]
     pub pastebin_enabled: bool,
 
+    /// Turn the server into an upload-only drop box
+    ///
+    /// Uploading and creating pastes work as usual, but the listing only shows the entries
+    /// uploaded during the current browser session, and files can't be downloaded. Archive
+    /// downloads, copies and README rendering are disabled too.
+    #[arg(
+        long = "drop-box",
+        requires = "allowed_upload_dir",
+        conflicts_with_all = [
+            "enable_search",
+            "allowed_rm_dir",
+            "disable_indexing",
+            "enable_webdav",
+            "index",
+            "pretty_urls",
+        ],
+        env = "MINISERVE_DROP_BOX"
+    )]
+    pub drop_box: bool,
+
     /// Specify uploadable media types
     #[arg(
         short = 'm',
@@ -306,6 +397,79 @@ pub struct CliArgs {
     )]
     pub media_type_raw: Option<String>,
 
+    /// Maximum size of a single uploaded file, like 100MB or 2GiB
+    #[arg(
+        long = "max-file-size",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_MAX_FILE_SIZE"
+    )]
+    pub max_file_size: Option<ByteSize>,
+
+    /// Maximum total size of the files uploaded in a single request, like 100MB or 2GiB
+    #[arg(
+        long = "max-request-size",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_MAX_REQUEST_SIZE"
+    )]
+    pub max_request_size: Option<ByteSize>,
+
+    /// Maximum total size of each allowed upload directory, like 10GB
+    ///
+    /// Uploads which would make an upload directory, or the served directory if uploads are
+    /// allowed everywhere, grow beyond this size are rejected. The versions kept of its files
+    /// count towards its size.
+    #[arg(
+        long = "upload-quota",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_UPLOAD_QUOTA"
+    )]
+    pub upload_quota: Option<ByteSize>,
+
+    /// Maximum total size of the files uploaded by each authenticated user, like 1GB
+    ///
+    /// Files count against the quota of the user who uploaded them until they are deleted.
+    /// Requires authentication.
+    #[arg(
+        long = "user-quota",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_USER_QUOTA"
+    )]
+    pub user_quota: Option<ByteSize>,
+
+    /// Delete uploaded files this long after their upload, like 12h or 7d
+    ///
+    /// Expired files are deleted by a background task, and the listing shows when each uploaded
+    /// file expires. Uploaders may ask for their files to expire sooner with the `expire` query
+    /// parameter of the upload APIs, or with the expiry choice of the pastebin form.
+    #[arg(
+        long = "upload-expiry",
+        value_parser(parse_duration),
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_UPLOAD_EXPIRY"
+    )]
+    pub upload_expiry: Option<Duration>,
+
+    /// Allow uploaders to have .zip, .tar and .tar.gz uploads extracted where they were uploaded
+    ///
+    /// Extraction is asked for with the "Extract archives" checkbox of the upload form, or the
+    /// `extract=true` query parameter of the upload APIs. The archive is removed once extracted.
+    /// Each extracted entry follows `--on-duplicate-files`, and archives holding paths or
+    /// symlinks leaving the upload directory are rejected.
+    #[arg(
+        long = "extract-archives",
+        requires = "allowed_upload_dir",
+        env = "MINISERVE_EXTRACT_ARCHIVES"
+    )]
+    pub extract_archives: bool,
+
+    /// Maximum total size of the files extracted from a single archive, like 1GiB
+    #[arg(
+        long = "max-extract-size",
+        default_value = "1GiB",
+        env = "MINISERVE_MAX_EXTRACT_SIZE"
+    )]
+    pub max_extract_size: ByteSize,
+
     /// What to do if existing files with same name is present during file upload
     ///
     /// If you enable renaming files, the renaming will occur by
@@ -313,6 +477,11 @@ pub struct CliArgs {
     /// extension. For example file.txt will be uploaded as
     /// file-1.txt, the number will be increased until an available
     /// filename is found.
+    ///
+    /// If you enable versioning, replaced files are kept as numbered
+    /// versions in a `.miniserve-versions` directory in the served
+    /// path, which is never listed or served. Their history can be
+    /// downloaded and restored from the listing.
     #[arg(
         short = 'o',
         long = "on-duplicate-files",
@@ -321,6 +490,10 @@ pub struct CliArgs {
     )]
     pub on_duplicate_files: DuplicateFile,
 
+    /// Number of versions kept per file with `--on-duplicate-files version`, 0 to keep all of them
+    #[arg(long, default_value = "10", env = "MINISERVE_MAX_VERSIONS")]
+    pub max_versions: usize,
+
     /// Enable file and directory deletion (and optionally specify for which directory)
     #[arg(
         short = 'R',
@@ -332,6 +505,18 @@ pub struct CliArgs {
     )]
     pub allowed_rm_dir: Option<Vec<PathBuf>>,
 
+    /// Move deleted files and directories to a trash instead of deleting them permanently
+    ///
+    /// The trash is kept in a `.miniserve-trash` directory in the served path, which is never
+    /// listed or served. Trashed entries can be restored or purged from the trash page linked in
+    /// the listing footer.
+    #[arg(long, requires = "allowed_rm_dir", env = "MINISERVE_TRASH")]
+    pub trash: bool,
+
+    /// Number of days after which trashed entries are purged, 0 to keep them until purged by hand
+    #[arg(long, default_value = "30", env = "MINISERVE_TRASH_RETENTION")]
+    pub trash_retention: u64,
+
     /// Enable uncompressed tar archive generation
     #[arg(short = 'r', long = "enable-tar", env = "MINISERVE_ENABLE_TAR")]
     pub enable_tar: bool,
@@ -341,9 +526,6 @@ pub struct CliArgs {
     pub enable_tar_gz: bool,
 
     /// Enable zip archive generation
-    ///
-    /// WARNING: Zipping large directories can result in out-of-memory exception
-    /// because zip generation is done in memory and cannot be sent on the fly
     #[arg(short = 'z', long = "enable-zip", env = "MINISERVE_ENABLE_ZIP")]
     pub enable_zip: bool,
 
@@ -433,6 +615,26 @@ pub struct CliArgs {
     #[arg(long, env = "MINISERVE_README")]
     pub readme: bool,
 
+    /// Render Markdown files as HTML pages
+    ///
+    /// Markdown files are rendered with a table of contents when they are opened, and their source
+    /// stays available with the `raw=true` query parameter.
+    #[arg(long = "render-markdown", env = "MINISERVE_RENDER_MARKDOWN")]
+    pub render_markdown: bool,
+
+    /// Directory to cache the thumbnails of the gallery view in
+    ///
+    /// Thumbnails are generated on demand and kept as long as the image they were made from is
+    /// unchanged. The directory must belong to the user running miniserve. If this option is not
+    /// set, `miniserve/thumbnails` in the cache directory of the user will be used, or a private
+    /// temporary directory if there is none.
+    #[arg(
+        long = "thumbnail-cache-dir",
+        value_hint = ValueHint::DirPath,
+        env = "MINISERVE_THUMBNAIL_CACHE_DIR"
+    )]
+    pub thumbnail_cache_dir: Option<PathBuf>,
+
     /// Disable indexing
     ///
     /// This will prevent directory listings from being generated
@@ -440,10 +642,18 @@ pub struct CliArgs {
     #[arg(short = 'I', long, env = "MINISERVE_DISABLE_INDEXING")]
     pub disable_indexing: bool,
 
-    /// Enable read-only WebDAV support (PROPFIND requests)
+    /// Enable read-only WebDAV support (PROPFIND requests), see --webdav-write for writes
     #[arg(long, env = "MINISERVE_ENABLE_WEBDAV")]
     pub enable_webdav: bool,
 
+    /// Let WebDAV clients modify files (PUT, MKCOL, DELETE, MOVE and COPY requests)
+    ///
+    /// Writes follow the same rules as the web interface: creating files needs --upload-files,
+    /// creating directories also needs --mkdir and deleting needs --rm-files, each limited to their
+    /// allowed directories. Moving needs both. --on-duplicate-files and --chmod apply as well.
+    #[arg(long, requires = "enable_webdav", env = "MINISERVE_WEBDAV_WRITE")]
+    pub webdav_write: bool,
+
     /// Show served file size in exact bytes
     #[arg(long, default_value_t = SizeDisplay::Human, env = "MINISERVE_SIZE_DISPLAY")]
     pub size_display: SizeDisplay,
@@ -574,6 +784,32 @@ pub fn parse_header(src: &str) -> Result<HeaderMap, httparse::Error> {
     Ok(header_map)
 }
 
+/// Parse a duration given as a number followed by a unit among s, m, h, d and w, like 30m or 7d
+pub fn parse_duration(src: &str) -> Result<Duration, String> {
+    let invalid =
+        || format!("Invalid duration {src:?}. Expected a number and a unit, like 30m, 12h or 7d");
+    let unit_start = src
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = src.split_at(unit_start);
+    let amount = amount.parse::<u64>().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    match amount.checked_mul(unit_secs) {
+        Some(0) => Err(format!(
+            "Invalid duration {src:?}. It must be longer than 0"
+        )),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(invalid()),
+    }
+}
+
 #[cfg(unix)]
 pub fn parse_file_mode(src: &str) -> Result<u16, std::num::ParseIntError> {
     u16::from_str_radix(src, 8)
@@ -640,4 +876,30 @@ mod tests {
         let err = parse_auth(auth_string).unwrap_err();
         assert_eq!(format!("{err}"), err_msg.to_owned());
     }
+
+    #[rstest(
+        src, secs,
+        case("30s", 30),
+        case("30m", 30 * 60),
+        case("12h", 12 * 60 * 60),
+        case("7d", 7 * 24 * 60 * 60),
+        case("2w", 14 * 24 * 60 * 60)
+    )]
+    fn parse_duration_valid(src: &str, secs: u64) {
+        assert_eq!(parse_duration(src).unwrap(), Duration::from_secs(secs));
+    }
+
+    #[rstest(
+        src,
+        case(""),
+        case("7"),
+        case("d"),
+        case("0d"),
+        case("7 days"),
+        case("-1h"),
+        case("99999999999999999999w")
+    )]
+    fn parse_duration_invalid(src: &str) {
+        assert!(parse_duration(src).is_err());
+    }
 }
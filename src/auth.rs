@@ -69,6 +69,7 @@ pub fn get_hash<T: Digest>(text: &str) -> Vec<u8> {
     hasher.finalize().to_vec()
 }
 
+#[derive(Clone)]
 pub struct CurrentUser {
     pub name: String,
 }
@@ -77,10 +78,11 @@ pub async fn handle_auth(
     req: ServiceRequest,
     cred: BasicAuth,
 ) -> actix_web::Result<ServiceRequest, (actix_web::Error, ServiceRequest)> {
-    let required_auth = &req
-        .app_data::<web::Data<crate::MiniserveConfig>>()
+    let conf = req
+        .app_data::<web::Data<crate::SharedConfig>>()
         .unwrap()
-        .auth;
+        .load();
+    let required_auth = &conf.auth;
 
     req.extensions_mut().insert(CurrentUser {
         name: cred.user_id().to_string(),
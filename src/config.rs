@@ -3,11 +3,18 @@ use std::{
     io::{BufRead, BufReader},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use actix_web::http::header::HeaderMap;
 use anyhow::{Context, Result, anyhow};
 
+#[cfg(feature = "tls")]
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
 #[cfg(feature = "tls")]
 use rustls_pemfile as pemfile;
 
@@ -19,7 +26,7 @@ use crate::{
     file_utils::sanitize_path,
     listing::{SortingMethod, SortingOrder},
     renderer::ThemeSlug,
-    tailscale,
+    tailscale, thumbnail,
 };
 
 /// Possible characters for random routes
@@ -116,6 +123,15 @@ pub struct MiniserveConfig {
     /// Enable recursive directory size calculation
     pub directory_size: bool,
 
+    /// Enable recursive filename search
+    pub search_enabled: bool,
+
+    /// Maximum number of results of a search
+    pub search_max_results: usize,
+
+    /// Maximum duration of a search
+    pub search_timeout: Duration,
+
     /// Enable creating directories
     pub mkdir_enabled: bool,
 
@@ -125,6 +141,9 @@ pub struct MiniserveConfig {
     /// Enable pastepin creation
     pub pastebin_enabled: bool,
 
+    /// Only show the entries uploaded during the current session, and never serve files
+    pub drop_box: bool,
+
     /// Max amount of concurrency when uploading multiple files
     pub web_upload_concurrency: usize,
 
@@ -135,18 +154,54 @@ pub struct MiniserveConfig {
     /// List of allowed upload directories
     pub allowed_upload_dir: Vec<String>,
 
+    /// If enabled, the active content of the upload directories is served as attachments
+    pub protect_uploads: bool,
+
+    /// Paths whose files are served as usual even if they're protected uploads
+    pub trusted_paths: Vec<String>,
+
     /// HTML accept attribute value
     pub uploadable_media_type: Option<String>,
 
+    /// Maximum size of a single uploaded file
+    pub max_upload_file_size: Option<u64>,
+
+    /// Maximum total size of the files uploaded in a single request
+    pub max_upload_request_size: Option<u64>,
+
+    /// Maximum total size of each allowed upload directory
+    pub upload_quota: Option<u64>,
+
+    /// Maximum total size of the files uploaded by each authenticated user
+    pub user_quota: Option<u64>,
+
+    /// Time after which uploaded files are deleted
+    pub upload_expiry: Option<Duration>,
+
+    /// Allow uploaded archives to be extracted
+    pub extract_archives: bool,
+
+    /// Maximum total size of the files extracted from a single archive
+    pub max_extract_size: u64,
+
     /// What to do on upload if filename already exists
     pub on_duplicate_files: DuplicateFile,
 
+    /// Number of versions kept per file when versioning replaced files, None to keep all of them
+    pub max_versions: Option<usize>,
+
     /// Enable file and directory deletion
     pub rm_enabled: bool,
 
     /// List of allowed deletion directories
     pub allowed_rm_dir: Vec<String>,
 
+    /// If enabled, deletions move entries to the trash
+    pub trash_enabled: bool,
+
+    /// Age after which trashed entries are purged, None to keep them
+    pub trash_retention: Option<Duration>,
+
     /// If false, creation of uncompressed tar archives is disabled
     pub tar_enabled: bool,
 
@@ -183,12 +238,21 @@ pub struct MiniserveConfig {
     /// If enabled, render the readme from the current directory
     pub readme: bool,
 
+    /// If enabled, render Markdown files as HTML pages
+    pub render_markdown: bool,
+
+    /// Directory the thumbnails of images are cached in
+    pub thumbnail_cache_dir: PathBuf,
+
     /// If enabled, indexing is disabled.
     pub disable_indexing: bool,
 
-    /// If enabled, respond to WebDAV requests (read-only).
+    /// If enabled, respond to WebDAV requests (read-only unless `webdav_write` is set).
     pub webdav_enabled: bool,
 
+    /// Allow WebDAV requests modifying files
+    pub webdav_write: bool,
+
     /// If enabled, will show in exact byte size of the file
     pub show_exact_bytes: bool,
 
@@ -199,6 +263,10 @@ pub struct MiniserveConfig {
     #[cfg(not(feature = "tls"))]
     pub tls_rustls_config: Option<()>,
 
+    /// Certificate resolver of `tls_rustls_config`, used to swap certificates at runtime
+    #[cfg(feature = "tls")]
+    pub tls_cert_resolver: Option<Arc<ReloadableCertResolver>>,
+
     /// Optional external URL to prepend to file links in listings
     pub file_external_url: Option<String>,
 
@@ -240,6 +308,11 @@ impl MiniserveConfig {
                 auth.push(parse_auth(line?.as_str())?);
             }
         }
+        if args.user_quota.is_some() && auth.is_empty() {
+            return Err(anyhow!(
+                "--user-quota requires authentication with --auth or --auth-file"
+            ));
+        }
 
         // Format some well-known routes at paths that are very unlikely to conflict with real
         // files.
@@ -273,27 +346,17 @@ impl MiniserveConfig {
         };
 
         #[cfg(feature = "tls")]
-        let tls_rustls_server_config =
+        let (tls_rustls_server_config, tls_cert_resolver) =
             if let (Some(tls_cert), Some(tls_key)) = (args.tls_cert, args.tls_key) {
-                let cert_file = &mut BufReader::new(
-                    File::open(&tls_cert)
-                        .context(format!("Couldn't access TLS certificate {tls_cert:?}"))?,
-                );
-                let key_file = &mut BufReader::new(
-                    File::open(&tls_key).context(format!("Couldn't access TLS key {tls_key:?}"))?,
-                );
-                let cert_chain = pemfile::certs(cert_file)
-                    .map(|cert| cert.expect("Invalid certificate in certificate chain"))
-                    .collect();
-                let private_key = pemfile::private_key(key_file)
-                    .context("Reading private key file")?
-                    .expect("No private key found");
+                let resolver = Arc::new(ReloadableCertResolver::new(load_certified_key(
+                    &tls_cert, &tls_key,
+                )?));
                 let server_config = rustls::ServerConfig::builder()
                     .with_no_client_auth()
-                    .with_single_cert(cert_chain, private_key)?;
-                Some(server_config)
+                    .with_cert_resolver(resolver.clone());
+                (Some(server_config), Some(resolver))
             } else {
-                None
+                (None, None)
             };
 
         #[cfg(not(feature = "tls"))]
@@ -320,6 +383,8 @@ impl MiniserveConfig {
             .transpose()?
             .unwrap_or_default();
 
+        let trusted_paths = validate_allowed_paths(&args.trusted_paths, args.hidden)?;
+
         let allowed_rm_dir = args
             .allowed_rm_dir
             .as_ref()
@@ -334,7 +399,16 @@ impl MiniserveConfig {
         #[cfg(unix)]
         let upload_chmod = args.chmod.unwrap_or_else(get_default_filemode);
 
-        Ok(Self {
+        let thumbnail_cache_dir = match args.thumbnail_cache_dir {
+            Some(dir) => dir,
+            None => thumbnail::default_cache_dir()
+                .context("Couldn't create a directory to cache thumbnails in")?,
+        };
+        thumbnail::check_cache_dir(&thumbnail_cache_dir).context(format!(
+            "Refusing to cache thumbnails in {thumbnail_cache_dir:?}"
+        ))?;
+
+        let mut config = Self {
             verbose: args.verbose,
             path: args.path.unwrap_or_else(|| PathBuf::from(".")),
             temp_upload_directory: args.temp_upload_directory,
@@ -359,18 +433,35 @@ impl MiniserveConfig {
             quiet: args.quiet,
             pretty_urls: args.pretty_urls,
             on_duplicate_files: args.on_duplicate_files,
+            max_versions: (args.max_versions > 0).then_some(args.max_versions),
             show_qrcode: args.qrcode,
             directory_size: args.directory_size,
+            search_enabled: args.enable_search,
+            search_max_results: args.search_max_results,
+            search_timeout: Duration::from_secs(args.search_timeout),
             mkdir_enabled: args.mkdir_enabled,
             file_upload: args.allowed_upload_dir.is_some(),
             pastebin_enabled: args.pastebin_enabled,
+            drop_box: args.drop_box,
             web_upload_concurrency: args.web_upload_concurrency,
             #[cfg(unix)]
             upload_chmod,
             allowed_upload_dir,
+            protect_uploads: args.protect_uploads,
+            trusted_paths,
             uploadable_media_type,
+            max_upload_file_size: args.max_file_size.map(|size| size.as_u64()),
+            max_upload_request_size: args.max_request_size.map(|size| size.as_u64()),
+            upload_quota: args.upload_quota.map(|size| size.as_u64()),
+            user_quota: args.user_quota.map(|size| size.as_u64()),
+            upload_expiry: args.upload_expiry,
+            extract_archives: args.extract_archives,
+            max_extract_size: args.max_extract_size.as_u64(),
             rm_enabled: args.allowed_rm_dir.is_some(),
             allowed_rm_dir,
+            trash_enabled: args.trash,
+            trash_retention: (args.trash_retention > 0)
+                .then(|| Duration::from_secs(args.trash_retention * 24 * 60 * 60)),
             tar_enabled: args.enable_tar,
             tar_gz_enabled: args.enable_tar_gz,
             zip_enabled: args.enable_zip,
@@ -382,14 +473,136 @@ impl MiniserveConfig {
             hide_theme_selector: args.hide_theme_selector,
             show_wget_footer: args.show_wget_footer,
             readme: args.readme,
+            render_markdown: args.render_markdown,
+            thumbnail_cache_dir,
             disable_indexing: args.disable_indexing,
             webdav_enabled: args.enable_webdav,
+            webdav_write: args.webdav_write,
             tls_rustls_config: tls_rustls_server_config,
+            #[cfg(feature = "tls")]
+            tls_cert_resolver,
             compress_response: args.compress_response,
             show_exact_bytes,
             file_external_url: args.file_external_url,
             log_color: args.log_color,
-        })
+        };
+        if config.drop_box {
+            config.restrict_to_drop_box();
+        }
+
+        Ok(config)
+    }
+
+    /// Turn off the features which would expose the contents of a drop box, see `--drop-box`
+    pub fn restrict_to_drop_box(&mut self) {
+        self.tar_enabled = false;
+        self.tar_gz_enabled = false;
+        self.zip_enabled = false;
+        self.search_enabled = false;
+        self.readme = false;
+        self.render_markdown = false;
+    }
+
+    /// true if files and directories may be created in `dir`, relative to the served path
+    pub fn upload_allowed(&self, dir: &Path) -> bool {
+        self.file_upload
+            && (self.allowed_upload_dir.is_empty()
+                || self.allowed_upload_dir.iter().any(|s| dir.starts_with(s)))
+    }
+
+    /// true if the active content at `path`, relative to the served path, is served as an
+    /// attachment, see `--protect-uploads`
+    pub fn upload_protected(&self, path: &Path) -> bool {
+        self.protect_uploads
+            && self.upload_allowed(path.parent().unwrap_or(Path::new("")))
+            && !self.trusted_paths.iter().any(|s| path.starts_with(s))
+    }
+
+    /// true if `path`, relative to the served path, may be removed
+    pub fn rm_allowed(&self, path: &Path) -> bool {
+        self.rm_enabled
+            && (self.allowed_rm_dir.is_empty()
+                || self.allowed_rm_dir.iter().any(|s| path.starts_with(s)))
+    }
+}
+
+/// Read the TLS certificate chain and the matching private key from their PEM files
+#[cfg(feature = "tls")]
+fn load_certified_key(tls_cert: &Path, tls_key: &Path) -> Result<CertifiedKey> {
+    let cert_file = &mut BufReader::new(
+        File::open(tls_cert).context(format!("Couldn't access TLS certificate {tls_cert:?}"))?,
+    );
+    let key_file = &mut BufReader::new(
+        File::open(tls_key).context(format!("Couldn't access TLS key {tls_key:?}"))?,
+    );
+    let cert_chain = pemfile::certs(cert_file)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid certificate in certificate chain")?;
+    let private_key = pemfile::private_key(key_file)
+        .context("Reading private key file")?
+        .ok_or(anyhow!("No private key found"))?;
+    CertifiedKey::from_der(
+        cert_chain,
+        private_key,
+        &rustls::crypto::ring::default_provider(),
+    )
+    .context("Invalid TLS certificate or key")
+}
+
+/// Serves the TLS certificate for new connections while allowing it to be replaced at runtime
+///
+/// Connections which are already established keep using the certificate they were set up with.
+#[cfg(feature = "tls")]
+#[derive(Debug)]
+pub struct ReloadableCertResolver(RwLock<Arc<CertifiedKey>>);
+
+#[cfg(feature = "tls")]
+impl ReloadableCertResolver {
+    pub fn new(certified_key: CertifiedKey) -> Self {
+        Self(RwLock::new(Arc::new(certified_key)))
+    }
+
+    /// Certificate handed out to new connections
+    pub fn get(&self) -> Arc<CertifiedKey> {
+        self.0
+            .read()
+            .expect("TLS certificate lock poisoned")
+            .clone()
+    }
+
+    /// Replace the certificate handed out to new connections
+    pub fn set(&self, certified_key: Arc<CertifiedKey>) {
+        *self.0.write().expect("TLS certificate lock poisoned") = certified_key;
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.get())
+    }
+}
+
+/// Configuration shared by all workers, which can be replaced while the server is running
+///
+/// Handlers should load the configuration once per request so that they work with a consistent
+/// snapshot even if it's replaced in the meantime.
+#[derive(Debug)]
+pub struct SharedConfig(RwLock<Arc<MiniserveConfig>>);
+
+impl SharedConfig {
+    pub fn new(config: MiniserveConfig) -> Self {
+        Self(RwLock::new(Arc::new(config)))
+    }
+
+    /// Current configuration
+    pub fn load(&self) -> Arc<MiniserveConfig> {
+        self.0.read().expect("Config lock poisoned").clone()
+    }
+
+    /// Replace the configuration used by all subsequent requests
+    pub fn store(&self, config: MiniserveConfig) {
+        *self.0.write().expect("Config lock poisoned") = Arc::new(config);
     }
 }
 
@@ -0,0 +1,310 @@
+//! Support for reading `CliArgs` values from a TOML configuration file
+//!
+//! The configuration file is merged with the command line by turning its values into regular
+//! command line arguments, which are then parsed by clap together with the real ones. This means
+//! every value in the file goes through exactly the same value parsers and validation rules as
+//! its command line counterpart.
+
+use std::any::TypeId;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches};
+
+use crate::args::CliArgs;
+
+/// Arguments which only make sense on the command line and can't be set in a config file
+const CLI_ONLY_ARGS: &[&str] = &[
+    "config",
+    "print_config",
+    "print_completions",
+    "print_manpage",
+];
+
+/// Parse the command line arguments, merging in the values of the `--config` file if one was
+/// given.
+///
+/// Values provided on the command line or through `MINISERVE_*` environment variables always take
+/// precedence over the values in the config file.
+///
+/// Invalid command line arguments print clap's usual error message and exit the process.
+pub fn parse_args() -> Result<(CliArgs, ArgMatches)> {
+    try_parse_args().map_err(|e| match e.downcast::<clap::Error>() {
+        Ok(e) => e.exit(),
+        Err(e) => e,
+    })
+}
+
+/// Like [`parse_args`], but returns an error instead of exiting on invalid arguments.
+pub fn try_parse_args() -> Result<(CliArgs, ArgMatches)> {
+    parse_args_from(std::env::args_os())
+}
+
+fn parse_args_from(argv: impl IntoIterator<Item = OsString>) -> Result<(CliArgs, ArgMatches)> {
+    let mut argv = argv.into_iter().collect::<Vec<_>>();
+
+    // The command line on its own might not be valid yet (e.g. `--spa` with `index` coming from
+    // the config file), so we first look for the config file without validating anything.
+    let preliminary_matches = CliArgs::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&argv)
+        .ok();
+    let config_path = preliminary_matches
+        .as_ref()
+        .and_then(|m| m.get_one::<PathBuf>("config").cloned());
+
+    if let (Some(config_path), Some(preliminary_matches)) = (config_path, preliminary_matches) {
+        let table = read_config_file(&config_path)?;
+        let config_args = config_to_args(&CliArgs::command(), &preliminary_matches, &table)
+            .with_context(|| format!("Invalid config file {config_path:?}"))?;
+        argv = merge_argv(argv, config_args);
+    }
+
+    let matches = CliArgs::command().try_get_matches_from(argv)?;
+    let args = CliArgs::from_arg_matches(&matches)?;
+    Ok((args, matches))
+}
+
+/// Read and parse the TOML config file at `path`
+fn read_config_file(path: &Path) -> Result<toml::Table> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read config file {path:?}"))?;
+    content
+        .parse::<toml::Table>()
+        .with_context(|| format!("Couldn't parse config file {path:?}"))
+}
+
+/// Arguments generated from a config file
+#[derive(Debug, Default, PartialEq)]
+struct ConfigArgs {
+    /// Options in their `--long=value` form
+    options: Vec<OsString>,
+
+    /// Value of the positional path argument, if set by the config file
+    path: Option<OsString>,
+}
+
+/// Build the command line arguments corresponding to the values of the config file `table`.
+///
+/// Values for arguments that have been explicitly provided in `matches` (or that conflict with an
+/// explicitly provided argument) are skipped, so that the command line always wins.
+fn config_to_args(cmd: &Command, matches: &ArgMatches, table: &toml::Table) -> Result<ConfigArgs> {
+    let is_explicit = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+
+    let mut config_args = ConfigArgs::default();
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str())
+            .filter(|_| !CLI_ONLY_ARGS.contains(&id.as_str()))
+            .ok_or_else(|| anyhow!("Unknown option '{key}'"))?;
+
+        let conflicts_with_explicit = cmd.get_arguments().any(|other| {
+            is_explicit(other.get_id().as_str())
+                && (cmd
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|a| a.get_id() == other.get_id())
+                    || cmd
+                        .get_arg_conflicts_with(other)
+                        .iter()
+                        .any(|a| a.get_id() == arg.get_id()))
+        });
+        if is_explicit(&id) || conflicts_with_explicit {
+            continue;
+        }
+
+        let values = toml_to_values(key, value)?;
+        if arg.is_positional() {
+            match values.as_deref() {
+                Some([path]) => config_args.path = Some(path.into()),
+                _ => bail!("Option '{key}' expects a single value"),
+            }
+            continue;
+        }
+
+        let long = arg
+            .get_long()
+            .ok_or_else(|| anyhow!("Option '{key}' can't be set in a config file"))?;
+        match values {
+            None => (),
+            Some(values) if values.is_empty() => {
+                config_args.options.push(format!("--{long}").into())
+            }
+            Some(values) => config_args
+                .options
+                .extend(values.into_iter().map(|v| format!("--{long}={v}").into())),
+        }
+    }
+
+    Ok(config_args)
+}
+
+/// Convert a TOML value to the list of its command line values.
+///
+/// `true` and empty arrays turn into a flag without values, `false` turns into `None`
+/// (argument not set at all).
+fn toml_to_values(key: &str, value: &toml::Value) -> Result<Option<Vec<String>>> {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        _ => Err(anyhow!("Unsupported value type for option '{key}'")),
+    };
+
+    match value {
+        toml::Value::Boolean(true) => Ok(Some(vec![])),
+        toml::Value::Boolean(false) => Ok(None),
+        toml::Value::Array(values) => values.iter().map(scalar).collect::<Result<_>>().map(Some),
+        value => scalar(value).map(|v| Some(vec![v])),
+    }
+}
+
+/// Put the config file arguments in front of the real command line arguments.
+///
+/// The config path is appended at the very end after a `--` separator so that it can't be
+/// mistaken for the value of an option with an optional value (like `-u`).
+fn merge_argv(argv: Vec<OsString>, config_args: ConfigArgs) -> Vec<OsString> {
+    let mut argv = argv.into_iter();
+    let mut merged = argv.next().into_iter().collect::<Vec<_>>();
+    merged.extend(config_args.options);
+
+    let rest = argv.collect::<Vec<_>>();
+    let has_separator = rest.iter().any(|a| a == "--");
+    merged.extend(rest);
+    if let Some(path) = config_args.path {
+        if !has_separator {
+            merged.push("--".into());
+        }
+        merged.push(path);
+    }
+
+    merged
+}
+
+/// Render the effective configuration described by `matches` as a TOML config file.
+///
+/// Default values are included so that the output shows everything miniserve is running with.
+pub fn render_config(matches: &ArgMatches) -> Result<String> {
+    let cmd = CliArgs::command();
+    let mut table = toml::Table::new();
+
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        if CLI_ONLY_ARGS.contains(&id) {
+            continue;
+        }
+
+        if let Some(value) = arg_to_toml(arg, matches) {
+            table.insert(id.to_owned(), value);
+        }
+    }
+
+    toml::to_string(&table).context("Couldn't serialize the configuration")
+}
+
+/// Convert the value(s) of `arg` in `matches` into their TOML representation
+fn arg_to_toml(arg: &Arg, matches: &ArgMatches) -> Option<toml::Value> {
+    let id = arg.get_id().as_str();
+    if let ArgAction::SetTrue = arg.get_action() {
+        return Some(toml::Value::Boolean(matches.get_flag(id)));
+    }
+
+    let is_integer = [
+        TypeId::of::<u16>(),
+        TypeId::of::<u64>(),
+        TypeId::of::<usize>(),
+    ]
+    .iter()
+    .any(|t| arg.get_value_parser().type_id() == *t);
+    let values = matches
+        .get_raw(id)?
+        .map(|v| {
+            let v = v.to_string_lossy();
+            match v.parse::<i64>() {
+                Ok(i) if is_integer => toml::Value::Integer(i),
+                _ => toml::Value::String(v.into_owned()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match arg.get_action() {
+        ArgAction::Append => Some(toml::Value::Array(values)),
+        _ if values.is_empty() => Some(toml::Value::Boolean(true)),
+        _ => values.into_iter().next(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    fn config_args(cli: &[&str], config: &str) -> Result<ConfigArgs> {
+        let cmd = CliArgs::command();
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(std::iter::once("miniserve").chain(cli.iter().copied()))?;
+        config_to_args(&cmd, &matches, &config.parse::<toml::Table>()?)
+    }
+
+    #[rstest]
+    #[case("verbose = true", &["--verbose"])]
+    #[case("verbose = false", &[])]
+    #[case("port = 1234", &["--port=1234"])]
+    #[case("title = \"hello\"", &["--title=hello"])]
+    #[case("color-scheme = \"monokai\"", &["--color-scheme=monokai"])]
+    #[case("allowed_upload_dir = true", &["--upload-files"])]
+    #[case("allowed_upload_dir = [\"a\", \"b\"]", &["--upload-files=a", "--upload-files=b"])]
+    fn config_values_to_args(#[case] config: &str, #[case] expected: &[&str]) {
+        let args = config_args(&[], config).unwrap();
+        assert_eq!(
+            args.options,
+            expected.iter().map(OsString::from).collect::<Vec<_>>()
+        );
+        assert_eq!(args.path, None);
+    }
+
+    #[test]
+    fn config_path_is_positional() {
+        let args = config_args(&[], "path = \"/srv\"").unwrap();
+        assert_eq!(args.path, Some(OsString::from("/srv")));
+    }
+
+    #[rstest]
+    #[case(&["--port", "4321"], "port = 1234")]
+    #[case(&["/srv"], "path = \"/tmp\"")]
+    #[case(&["--random-route"], "route_prefix = \"foo\"")]
+    fn command_line_overrides_config(#[case] cli: &[&str], #[case] config: &str) {
+        assert_eq!(config_args(cli, config).unwrap(), ConfigArgs::default());
+    }
+
+    #[rstest]
+    #[case("not_an_option = 1")]
+    #[case("print_manpage = true")]
+    #[case("title = { a = 1 }")]
+    fn invalid_config_is_rejected(#[case] config: &str) {
+        assert!(config_args(&[], config).is_err());
+    }
+
+    #[test]
+    fn merge_argv_appends_path_after_separator() {
+        let merged = merge_argv(
+            vec!["miniserve".into(), "-u".into()],
+            ConfigArgs {
+                options: vec!["--verbose".into()],
+                path: Some("/srv".into()),
+            },
+        );
+        assert_eq!(merged, ["miniserve", "--verbose", "-u", "--", "/srv"]);
+    }
+}
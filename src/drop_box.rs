@@ -0,0 +1,112 @@
+//! Upload-only drop box, see `--drop-box`
+//!
+//! Uploaders get a session cookie, and the listing only shows the entries uploaded during their
+//! session. Files are never served, so uploaders can't see what others uploaded. Sessions are kept
+//! in memory and forgotten on restart.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+use actix_web::{
+    HttpMessage, HttpRequest,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web,
+};
+
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::file_utils::relative_to_root;
+
+/// Name of the session cookie
+const SESSION_COOKIE: &str = "miniserve_drop_box";
+
+/// Entries uploaded during each session, relative to the served path
+static UPLOADS: LazyLock<Mutex<HashMap<String, HashSet<PathBuf>>>> =
+    LazyLock::new(Default::default);
+
+/// Identifier of the drop box session of a request
+#[derive(Clone)]
+pub struct Session(pub String);
+
+/// Attach a [`Session`] to requests if the drop box is enabled, starting a new one with a cookie
+/// when the request doesn't belong to any
+pub async fn session_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    if !conf.drop_box {
+        return next.call(req).await;
+    }
+
+    // Only ids which could have been generated are kept
+    let existing = cookie_value(&req, SESSION_COOKIE).filter(|id| {
+        id.len() == 21
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    });
+    let id = existing.clone().unwrap_or_else(|| nanoid::nanoid!());
+    req.extensions_mut().insert(Session(id.clone()));
+
+    let mut res = next.call(req).await?;
+    if existing.is_none() {
+        let path = if conf.route_prefix.is_empty() {
+            "/"
+        } else {
+            &conf.route_prefix
+        };
+        let cookie = format!("{SESSION_COOKIE}={id}; Path={path}; HttpOnly; SameSite=Strict");
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            res.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+    }
+    Ok(res)
+}
+
+/// Value of the cookie of `req` called `name`
+fn cookie_value(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value.to_owned())
+}
+
+/// Session of `req`, if the drop box is enabled
+pub fn session(req: &HttpRequest) -> Option<Session> {
+    req.extensions().get::<Session>().cloned()
+}
+
+/// Record that the entry at the local `path` was uploaded during `session`, along with the
+/// directories leading to it
+pub fn record_upload(conf: &MiniserveConfig, session: Option<&Session>, path: &Path) {
+    let (Some(session), Some(path)) = (session, relative_to_root(&conf.path, path)) else {
+        return;
+    };
+
+    let mut uploads = UPLOADS.lock().unwrap();
+    let uploaded = uploads.entry(session.0.clone()).or_default();
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        uploaded.insert(ancestor.to_path_buf());
+    }
+}
+
+/// true if the entry at `path`, relative to the served path, was uploaded during `session`
+pub fn uploaded_during(session: Option<&Session>, path: &Path) -> bool {
+    session.is_some_and(|session| {
+        UPLOADS
+            .lock()
+            .unwrap()
+            .get(&session.0)
+            .is_some_and(|uploaded| uploaded.contains(path))
+    })
+}
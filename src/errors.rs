@@ -4,13 +4,13 @@ use actix_web::{
     HttpRequest, HttpResponse, ResponseError,
     body::{BoxBody, MessageBody},
     dev::{ResponseHead, ServiceRequest, ServiceResponse},
-    http::{StatusCode, header},
+    http::{Method, StatusCode, header},
     middleware::Next,
     web,
 };
 use thiserror::Error;
 
-use crate::{MiniserveConfig, renderer::render_error};
+use crate::{SharedConfig, paste::PASTE_ROUTE, renderer::render_error, tus::TUS_ROUTE};
 
 #[derive(Debug, Error)]
 pub enum StartupError {
@@ -53,6 +53,18 @@ pub enum RuntimeError {
     #[error("Upload not allowed to this directory")]
     UploadForbiddenError,
 
+    /// Uploaded file or request larger than the configured limits
+    #[error("Upload too large\ncaused by: {0}")]
+    UploadTooLargeError(String),
+
+    /// Uploaded file of a media type that isn't allowed
+    #[error("Media type not allowed for upload\ncaused by: {0}")]
+    UnsupportedMediaTypeError(String),
+
+    /// Upload which doesn't fit in the space left by the upload quotas
+    #[error("Upload quota exceeded\ncaused by: {0}")]
+    QuotaExceededError(String),
+
     /// Remove not allowed
     #[error("Remove not allowed to this directory")]
     RmForbiddenError,
@@ -73,6 +85,10 @@ pub enum RuntimeError {
     #[error("An error occurred while creating the {0}\ncaused by: {1}")]
     ArchiveCreationError(String, Box<RuntimeError>),
 
+    /// Might occur when an uploaded archive can't be extracted
+    #[error("Failed to extract {0}\ncaused by: {1}")]
+    ArchiveExtractionError(String, String),
+
     /// More specific archive creation failure reason
     #[error("{0}")]
     ArchiveCreationDetailError(String),
@@ -100,12 +116,16 @@ impl ResponseError for RuntimeError {
             E::MultipartError(_) => S::BAD_REQUEST,
             E::DuplicateFileError => S::CONFLICT,
             E::UploadForbiddenError => S::FORBIDDEN,
+            E::UploadTooLargeError(_) => S::PAYLOAD_TOO_LARGE,
+            E::UnsupportedMediaTypeError(_) => S::UNSUPPORTED_MEDIA_TYPE,
+            E::QuotaExceededError(_) => S::INSUFFICIENT_STORAGE,
             E::RmForbiddenError => S::FORBIDDEN,
             E::InvalidPathError(_) => S::BAD_REQUEST,
             E::InsufficientPermissionsError(_) => S::FORBIDDEN,
             E::ParseError(_, _) => S::BAD_REQUEST,
             E::ArchiveCreationError(_, err) => err.status_code(),
             E::ArchiveCreationDetailError(_) => S::INTERNAL_SERVER_ERROR,
+            E::ArchiveExtractionError(_, _) => S::BAD_REQUEST,
             E::InvalidHttpCredentials => S::UNAUTHORIZED,
             E::InvalidHttpRequestError(_) => S::BAD_REQUEST,
             E::RouteNotFoundError(_) => S::NOT_FOUND,
@@ -136,7 +156,7 @@ pub async fn error_page_middleware(
     let res = next.call(req).await?.map_into_boxed_body();
 
     if (res.status().is_client_error() || res.status().is_server_error())
-        && res.request().path() != "/upload"
+        && !is_upload_request(res.request())
         && res
             .headers()
             .get(header::CONTENT_TYPE)
@@ -154,6 +174,21 @@ pub async fn error_page_middleware(
     }
 }
 
+/// Whether `req` is an upload whose client expects plain-text errors rather than a web page
+fn is_upload_request(req: &HttpRequest) -> bool {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let Some(path) = req.path().strip_prefix(&conf.route_prefix) else {
+        return false;
+    };
+
+    // Raw uploads are sent to the path of the file itself
+    let raw_upload = matches!(*req.method(), Method::POST | Method::PUT)
+        && req
+            .match_pattern()
+            .is_some_and(|pattern| pattern.ends_with("/{tail}*"));
+    ["/upload", PASTE_ROUTE].contains(&path) || path.starts_with(TUS_ROUTE) || raw_upload
+}
+
 fn map_error_page(req: &HttpRequest, head: &mut ResponseHead, body: BoxBody) -> BoxBody {
     let error_msg = match body.try_into_bytes() {
         Ok(bytes) => bytes,
@@ -165,7 +200,7 @@ fn map_error_page(req: &HttpRequest, head: &mut ResponseHead, body: BoxBody) ->
         _ => return BoxBody::new(error_msg),
     };
 
-    let conf = req.app_data::<web::Data<MiniserveConfig>>().unwrap();
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
     let return_address = req
         .headers()
         .get(header::REFERER)
@@ -177,7 +212,7 @@ fn map_error_page(req: &HttpRequest, head: &mut ResponseHead, body: BoxBody) ->
         mime::TEXT_HTML_UTF_8.essence_str().try_into().unwrap(),
     );
 
-    BoxBody::new(render_error(error_msg, head.status, conf, return_address).into_string())
+    BoxBody::new(render_error(error_msg, head.status, &conf, return_address).into_string())
 }
 
 pub fn log_error_chain(description: String) {
@@ -0,0 +1,203 @@
+//! Automatic expiry of uploaded files, see `--upload-expiry`
+//!
+//! The expiry date of each uploaded file is recorded in [`EXPIRY_FILE`] along with its
+//! modification time, so that a file which took the place of an expiring one in another way than
+//! an upload isn't deleted in its stead. Expired files are deleted by [`purge_expired`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::args::parse_duration;
+use crate::config::MiniserveConfig;
+use crate::errors::RuntimeError;
+use crate::file_utils::relative_to_root;
+use crate::quota;
+
+/// Name of the file recording when uploaded files expire, at the root of the served path. It's
+/// never listed or served.
+pub const EXPIRY_FILE: &str = ".miniserve-expiry.json";
+
+/// Interval between two runs of [`purge_expired`]
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Serializes the updates of [`EXPIRY_FILE`]
+static LEDGER_LOCK: futures::lock::Mutex<()> = futures::lock::Mutex::new(());
+
+/// When an uploaded file expires
+#[derive(Serialize, Deserialize)]
+struct Expiry {
+    expires_at: SystemTime,
+
+    /// Modification time of the file when it was uploaded
+    modified: Option<SystemTime>,
+}
+
+/// Expiring files by their path relative to the served path
+type Ledger = BTreeMap<PathBuf, Expiry>;
+
+/// Query parameters of the upload APIs asking for uploaded files to expire
+#[derive(Deserialize, Default)]
+pub struct ExpiryQueryParameters {
+    /// Time after which the files expire, like 1h or 7d
+    expire: Option<String>,
+}
+
+impl ExpiryQueryParameters {
+    /// Time after which the uploaded files expire, which can't be longer than the `conf` allows
+    pub fn expiry(&self, conf: &MiniserveConfig) -> Result<Option<Duration>, RuntimeError> {
+        let requested = match self.expire.as_deref() {
+            None | Some("") => None,
+            Some(expire) => Some(parse_duration(expire).map_err(|e| {
+                RuntimeError::InvalidHttpRequestError(format!(
+                    "Invalid value for 'expire' parameter: {e}"
+                ))
+            })?),
+        };
+        Ok(requested.into_iter().chain(conf.upload_expiry).min())
+    }
+}
+
+/// Record that the file just uploaded to the local `path` expires after `expiry`, or never.
+///
+/// The files which expired are deleted along the way, rather than only by the next run of the
+/// background task.
+pub async fn record_upload(conf: &MiniserveConfig, path: &Path, expiry: Option<Duration>) {
+    if let Err(e) = purge_expired(conf).await {
+        warn!("Failed to delete the expired uploads: {e}");
+    }
+    let Some(relative_path) = relative_to_root(&conf.path, path) else {
+        return;
+    };
+    if let Err(e) = record(&conf.path, path, relative_path.clone(), expiry).await {
+        warn!("Failed to record the expiry of {relative_path:?}: {e}");
+    }
+}
+
+/// Record the expiry of the file at the local `path`, at `relative_path` in the served directory
+/// `root`
+async fn record(
+    root: &Path,
+    path: &Path,
+    relative_path: PathBuf,
+    expiry: Option<Duration>,
+) -> Result<(), RuntimeError> {
+    let _lock = LEDGER_LOCK.lock().await;
+    let mut ledger = read_ledger(root).await?;
+    match expiry {
+        Some(expiry) => {
+            let modified = fs::metadata(path)
+                .await
+                .ok()
+                .and_then(|m| m.modified().ok());
+            ledger.insert(
+                relative_path,
+                Expiry {
+                    expires_at: SystemTime::now() + expiry,
+                    modified,
+                },
+            );
+        }
+        // A file replacing an expiring one doesn't inherit its expiry
+        None if ledger.remove(&relative_path).is_some() => (),
+        None => return Ok(()),
+    }
+    write_ledger(root, &ledger).await
+}
+
+/// Delete the uploaded files of the served directory of `conf` which expired
+pub async fn purge_expired(conf: &MiniserveConfig) -> Result<(), RuntimeError> {
+    let _lock = LEDGER_LOCK.lock().await;
+    let ledger = read_ledger(&conf.path).await?;
+    if ledger.is_empty() {
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    let mut kept = Ledger::new();
+    let mut purged = false;
+    for (relative_path, expiry) in ledger {
+        let path = conf.path.join(&relative_path);
+        // Files which are gone or were replaced in another way are forgotten
+        let Ok(metadata) = fs::symlink_metadata(&path).await else {
+            continue;
+        };
+        if !metadata.is_file() || metadata.modified().ok() != expiry.modified {
+            continue;
+        }
+
+        if expiry.expires_at > now {
+            kept.insert(relative_path, expiry);
+            continue;
+        }
+        info!("Deleting {relative_path:?} after its expiry");
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                warn!("Failed to delete the expired {relative_path:?}: {e}");
+                kept.insert(relative_path, expiry);
+            }
+            _ => purged = true,
+        }
+    }
+
+    if purged {
+        quota::forget_usage();
+    }
+    write_ledger(&conf.path, &kept).await
+}
+
+/// Expiry dates of the files of `dir`, relative to the served directory `root`, by name
+pub fn expiry_dates(root: &Path, dir: &Path) -> HashMap<OsString, SystemTime> {
+    let Ok(ledger) = std::fs::read(root.join(EXPIRY_FILE)) else {
+        return HashMap::new();
+    };
+    serde_json::from_slice::<Ledger>(&ledger)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(path, expiry)| {
+            path.parent() == Some(dir)
+                && std::fs::symlink_metadata(root.join(path))
+                    .is_ok_and(|m| m.is_file() && m.modified().ok() == expiry.modified)
+        })
+        .filter_map(|(path, expiry)| Some((path.file_name()?.to_owned(), expiry.expires_at)))
+        .collect()
+}
+
+/// Read the ledger of the served directory `root`
+async fn read_ledger(root: &Path) -> Result<Ledger, RuntimeError> {
+    match fs::read(root.join(EXPIRY_FILE)).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Ledger::new()),
+        Err(e) => Err(RuntimeError::IoError(
+            format!("Failed to read {EXPIRY_FILE}"),
+            e,
+        )),
+        Ok(ledger) => serde_json::from_slice(&ledger)
+            .map_err(|e| RuntimeError::ParseError(EXPIRY_FILE.to_string(), e.to_string())),
+    }
+}
+
+/// Write the `ledger` of the served directory `root`, removing it once empty
+async fn write_ledger(root: &Path, ledger: &Ledger) -> Result<(), RuntimeError> {
+    let path = root.join(EXPIRY_FILE);
+    if ledger.is_empty() {
+        return match fs::remove_file(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(RuntimeError::IoError(
+                format!("Failed to remove {EXPIRY_FILE}"),
+                e,
+            )),
+            _ => Ok(()),
+        };
+    }
+
+    let ledger = serde_json::to_vec(ledger)
+        .map_err(|e| RuntimeError::IoError(format!("Failed to write {EXPIRY_FILE}"), e.into()))?;
+    fs::write(path, ledger)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to write {EXPIRY_FILE}"), e))
+}
@@ -0,0 +1,665 @@
+//! Extraction of uploaded archives, see `--extract-archives`
+//!
+//! Archives are first unpacked to a temporary directory, checking every entry along the way, and
+//! their entries are only moved to the upload directory once the whole archive is known to be
+//! safe. Extracted files go through the same checks of their media type and size, and the same
+//! duplicate, version, quota and drop box handling as uploaded files.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use libflate::gzip::Decoder;
+use log::{info, warn};
+use serde::Deserialize;
+use tempfile::TempDir;
+use tokio::fs;
+
+use crate::args::DuplicateFile;
+use crate::config::MiniserveConfig;
+use crate::drop_box;
+use crate::errors::RuntimeError;
+use crate::file_op::{
+    MEDIA_TYPE_DETECTION_LEN, UploadLimits, move_upload, resolve_duplicate, upload_file_path,
+};
+use crate::file_utils::{Visibility, is_internal, relative_to_root};
+use crate::{expiry, quota, versions};
+
+/// Most entries a single archive may hold
+const MAX_ENTRIES: usize = 10_000;
+
+/// Longest symlink target read from a zip archive
+const MAX_SYMLINK_TARGET_LEN: u64 = 4096;
+
+/// Query parameters of the upload APIs asking for uploaded archives to be extracted
+#[derive(Deserialize, Default)]
+pub struct ExtractQueryParameters {
+    #[serde(default)]
+    pub extract: bool,
+}
+
+/// Archive formats which can be extracted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Format of an archive called `file_name`, if it's one that can be extracted
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_ascii_lowercase();
+        if file_name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if file_name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// What an archive entry is
+enum EntryKind {
+    /// A regular file, whose contents were unpacked to a temporary file
+    File {
+        temp_path: PathBuf,
+        size: u64,
+    },
+    Dir,
+    Symlink {
+        target: PathBuf,
+    },
+}
+
+/// An entry unpacked from an archive
+struct Unpacked {
+    /// Path of the entry relative to the directory the archive is extracted to
+    path: PathBuf,
+    kind: EntryKind,
+}
+
+/// Checks and collects the entries of an archive while it's being read
+struct Unpacker<'a> {
+    /// Name of the archive, for error messages
+    archive_name: &'a str,
+
+    /// Directory the contents of files are unpacked to
+    staging: &'a Path,
+
+    visibility: Visibility,
+
+    /// Restrictions on every extracted file, like on uploaded ones
+    limits: UploadLimits<'a>,
+
+    /// Maximum total size of the extracted files
+    max_size: u64,
+
+    /// Total size of the files unpacked so far
+    size: u64,
+
+    /// Number of entries read so far, including the skipped ones
+    read: usize,
+
+    entries: Vec<Unpacked>,
+}
+
+impl Unpacker<'_> {
+    /// Checked path of the entry called `name` in the archive, or `None` if it must be skipped
+    ///
+    /// Paths leaving the extraction directory, like absolute paths or paths with `..`, are
+    /// rejected, as are paths to miniserve's internal entries. Hidden entries are skipped unless
+    /// they're shown.
+    fn entry_path(&mut self, name: &Path) -> Result<Option<PathBuf>, RuntimeError> {
+        self.read += 1;
+        if self.read > MAX_ENTRIES {
+            return Err(RuntimeError::UploadTooLargeError(format!(
+                "{} holds more than {MAX_ENTRIES} entries",
+                self.archive_name
+            )));
+        }
+
+        let mut path = PathBuf::new();
+        for component in name.components() {
+            match component {
+                Component::Normal(name) if is_internal(name) => {
+                    return Err(RuntimeError::InvalidPathError(format!(
+                        "{} holds the reserved path {name:?}",
+                        self.archive_name
+                    )));
+                }
+                Component::Normal(name) if !self.visibility.allows(name, false) => {
+                    return Ok(None);
+                }
+                Component::Normal(name) => path.push(name),
+                Component::CurDir => (),
+                _ => {
+                    return Err(RuntimeError::InvalidPathError(format!(
+                        "{} holds {name:?}, which leaves the extraction directory",
+                        self.archive_name
+                    )));
+                }
+            }
+        }
+        Ok(path.file_name().is_some().then_some(path))
+    }
+
+    /// Unpack the file called `name` from `contents`, which must be accepted as an upload
+    fn add_file(&mut self, name: &Path, contents: &mut impl Read) -> Result<(), RuntimeError> {
+        let Some(path) = self.entry_path(name)? else {
+            return Ok(());
+        };
+
+        let temp_path = self.staging.join(self.entries.len().to_string());
+        let io_error = |e| RuntimeError::IoError(format!("Failed to extract {path:?}"), e);
+        let mut file = File::create(&temp_path).map_err(io_error)?;
+        // Reading one more byte than allowed is enough to catch archives extracting to too much
+        let left = self.max_size - self.size;
+        let size = io::copy(&mut contents.take(left + 1), &mut file).map_err(io_error)?;
+        if size > left {
+            return Err(RuntimeError::UploadTooLargeError(format!(
+                "{} extracts to more than {}",
+                self.archive_name,
+                ByteSize::b(self.max_size)
+            )));
+        }
+        self.size += size;
+
+        let entry_name = path.to_string_lossy();
+        self.limits.check_size(&entry_name, size)?;
+        let mut head = Vec::with_capacity(MEDIA_TYPE_DETECTION_LEN);
+        File::open(&temp_path)
+            .and_then(|file| {
+                file.take(MEDIA_TYPE_DETECTION_LEN as u64)
+                    .read_to_end(&mut head)
+            })
+            .map_err(io_error)?;
+        self.limits.check_media_type(&entry_name, &head)?;
+
+        self.entries.push(Unpacked {
+            path,
+            kind: EntryKind::File { temp_path, size },
+        });
+        Ok(())
+    }
+
+    /// Record the directory called `name`
+    fn add_dir(&mut self, name: &Path) -> Result<(), RuntimeError> {
+        if let Some(path) = self.entry_path(name)? {
+            self.entries.push(Unpacked {
+                path,
+                kind: EntryKind::Dir,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the symlink called `name` pointing to `target`
+    fn add_symlink(&mut self, name: &Path, target: &Path) -> Result<(), RuntimeError> {
+        let Some(path) = self.entry_path(name)? else {
+            return Ok(());
+        };
+        if self.visibility.no_symlinks {
+            return Err(RuntimeError::InvalidPathError(format!(
+                "{} holds the symlink {path:?}, but symlinks aren't allowed",
+                self.archive_name
+            )));
+        }
+        self.entries.push(Unpacked {
+            path,
+            kind: EntryKind::Symlink {
+                target: target.to_path_buf(),
+            },
+        });
+        Ok(())
+    }
+
+    /// Check the entries as a whole once the archive is completely read
+    ///
+    /// Symlinks of the archive must point inside the extraction directory without going through
+    /// other symlinks of the archive, so that they can't be chained to point outside of it, nor
+    /// through hidden or internal entries, so that they can't expose them. No entry may be extracted
+    /// through one of them.
+    fn finish(self) -> Result<Vec<Unpacked>, RuntimeError> {
+        let symlinks = self
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry.kind, EntryKind::Symlink { .. }))
+            .map(|entry| entry.path.as_path())
+            .collect::<HashSet<_>>();
+
+        for entry in &self.entries {
+            if entry
+                .path
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| symlinks.contains(ancestor))
+            {
+                return Err(RuntimeError::InvalidPathError(format!(
+                    "{} holds {:?}, which is behind a symlink",
+                    self.archive_name, entry.path
+                )));
+            }
+
+            if let EntryKind::Symlink { target } = &entry.kind
+                && !symlink_stays_inside(&entry.path, target, &symlinks, self.visibility)
+            {
+                return Err(RuntimeError::InvalidPathError(format!(
+                    "{} holds the symlink {:?} to {target:?}, which leaves the extraction directory or points to a hidden entry",
+                    self.archive_name, entry.path
+                )));
+            }
+        }
+
+        Ok(self.entries)
+    }
+}
+
+/// true if the symlink at `link` pointing to `target` resolves inside the extraction directory
+/// without going through any of the `symlinks` of the archive, all relative to the extraction
+/// directory, nor through any entry the `visibility` rules hide
+fn symlink_stays_inside(
+    link: &Path,
+    target: &Path,
+    symlinks: &HashSet<&Path>,
+    visibility: Visibility,
+) -> bool {
+    let mut resolved = link.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut components = target.components().peekable();
+    while let Some(component) = components.next() {
+        match component {
+            Component::Normal(name) if visibility.allows(name, false) => resolved.push(name),
+            Component::CurDir => continue,
+            Component::ParentDir if resolved.pop() => (),
+            _ => return false,
+        }
+        // The target itself may be another symlink, which is checked on its own
+        if components.peek().is_some() && symlinks.contains(resolved.as_path()) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Read all the entries of the zip archive at `archive`
+fn unpack_zip(archive: &Path, unpacker: &mut Unpacker) -> Result<(), RuntimeError> {
+    let invalid = |e: zip::result::ZipError| {
+        RuntimeError::ArchiveExtractionError(unpacker.archive_name.to_string(), e.to_string())
+    };
+    let file = File::open(archive)
+        .map_err(|e| RuntimeError::IoError(format!("Failed to read {archive:?}"), e))?;
+    let mut zip = zip::ZipArchive::new(BufReader::new(file)).map_err(invalid)?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(invalid)?;
+        let name = PathBuf::from(entry.name());
+        if entry.is_dir() {
+            unpacker.add_dir(&name)?;
+        } else if entry.is_symlink() {
+            let mut target = String::new();
+            (&mut entry)
+                .take(MAX_SYMLINK_TARGET_LEN)
+                .read_to_string(&mut target)
+                .map_err(|e| RuntimeError::IoError(format!("Failed to extract {name:?}"), e))?;
+            unpacker.add_symlink(&name, Path::new(&target))?;
+        } else {
+            unpacker.add_file(&name, &mut entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read all the entries of the tar archive from `reader`
+fn unpack_tar(reader: impl Read, unpacker: &mut Unpacker) -> Result<(), RuntimeError> {
+    let invalid = |e: io::Error| {
+        RuntimeError::ArchiveExtractionError(unpacker.archive_name.to_string(), e.to_string())
+    };
+    let mut tar = tar::Archive::new(reader);
+
+    for entry in tar.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let name = entry.path().map_err(invalid)?.into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                unpacker.add_file(&name, &mut entry)?
+            }
+            tar::EntryType::Directory => unpacker.add_dir(&name)?,
+            tar::EntryType::Symlink => {
+                let target = entry.link_name().map_err(invalid)?.unwrap_or_default();
+                unpacker.add_symlink(&name, &target)?
+            }
+            tar::EntryType::Link => {
+                return Err(RuntimeError::ArchiveExtractionError(
+                    unpacker.archive_name.to_string(),
+                    format!("hard links like {name:?} aren't supported"),
+                ));
+            }
+            other => warn!("Skipping {name:?} of the unsupported type {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// Read and check all the entries of `archive`, unpacking files to `staging`
+///
+/// Each file must be of one of the `media_types` and no larger than `max_file_size`, if given,
+/// and all of them no larger than `max_size` together.
+fn unpack(
+    archive: &Path,
+    format: ArchiveFormat,
+    staging: &Path,
+    visibility: Visibility,
+    media_types: Option<&str>,
+    max_file_size: Option<u64>,
+    max_size: u64,
+) -> Result<Vec<Unpacked>, RuntimeError> {
+    let archive_name = archive
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let mut unpacker = Unpacker {
+        archive_name: &archive_name,
+        staging,
+        visibility,
+        limits: UploadLimits {
+            media_types,
+            max_size: max_file_size,
+            ..Default::default()
+        },
+        max_size,
+        size: 0,
+        read: 0,
+        entries: vec![],
+    };
+
+    let open = || {
+        File::open(archive)
+            .map(BufReader::new)
+            .map_err(|e| RuntimeError::IoError(format!("Failed to read {archive:?}"), e))
+    };
+    match format {
+        ArchiveFormat::Zip => unpack_zip(archive, &mut unpacker)?,
+        ArchiveFormat::Tar => unpack_tar(open()?, &mut unpacker)?,
+        ArchiveFormat::TarGz => {
+            let decoder = Decoder::new(open()?).map_err(|e| {
+                RuntimeError::ArchiveExtractionError(archive_name.clone(), e.to_string())
+            })?;
+            unpack_tar(decoder, &mut unpacker)?
+        }
+    }
+    unpacker.finish()
+}
+
+/// Fail before anything is extracted to `dir` if `entries` can't all be extracted there
+fn check_extractable(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    entries: &[Unpacked],
+) -> Result<(), RuntimeError> {
+    for entry in entries {
+        let target = upload_file_path(
+            dir,
+            &entry.path.to_string_lossy(),
+            conf.show_hidden,
+            !conf.no_symlinks,
+            true,
+        )?;
+
+        // Directories are created along the way, like for folder uploads
+        let needed_dir = match entry.kind {
+            EntryKind::Dir => &target,
+            _ => target.parent().unwrap_or(dir),
+        };
+        if !conf.mkdir_enabled && !needed_dir.is_dir() {
+            return Err(RuntimeError::InsufficientPermissionsError(
+                needed_dir.display().to_string(),
+            ));
+        }
+
+        match (&entry.kind, target.symlink_metadata()) {
+            (EntryKind::Dir, Ok(metadata)) if !metadata.is_dir() => {
+                return Err(RuntimeError::DuplicateFileError);
+            }
+            (EntryKind::File { .. } | EntryKind::Symlink { .. }, Ok(metadata))
+                if metadata.is_dir() || matches!(conf.on_duplicate_files, DuplicateFile::Error) =>
+            {
+                return Err(RuntimeError::DuplicateFileError);
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Create a symlink to `target` at `path`, replacing what's there following the
+/// `on_duplicate_files` policy
+async fn create_symlink(
+    conf: &MiniserveConfig,
+    path: PathBuf,
+    target: &Path,
+) -> Result<PathBuf, RuntimeError> {
+    let path = resolve_duplicate(path, conf.on_duplicate_files)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.map_err(|e| {
+            RuntimeError::IoError(format!("Failed to create {}", parent.display()), e)
+        })?;
+    }
+    if fs::symlink_metadata(&path).await.is_ok() {
+        versions::keep(conf, &path).await?;
+        if let Err(e) = fs::remove_file(&path).await
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            return Err(RuntimeError::IoError(
+                format!("Failed to replace {path:?}"),
+                e,
+            ));
+        }
+    }
+
+    #[cfg(unix)]
+    fs::symlink(target, &path)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to create symlink {path:?}"), e))?;
+    #[cfg(not(unix))]
+    warn!("Skipping symlink {path:?} to {target:?}, which isn't supported on this platform");
+
+    Ok(path)
+}
+
+/// Extract the archive which was just uploaded to the local `archive` next to it, then remove it.
+///
+/// Files that aren't archives of a supported format are left alone, and false is returned. The
+/// extracted files count against the quotas of `user` in place of the archive, and may not exceed
+/// the space the archive frees along with what's left in the quotas. They expire after `expiry`,
+/// if given.
+///
+/// The archive is removed whether extraction succeeds or not, so that a failed upload doesn't
+/// leave anything behind. Nothing is extracted unless all the entries of the archive are valid.
+pub async fn extract_upload(
+    conf: &MiniserveConfig,
+    archive: &Path,
+    user: Option<&str>,
+    session: Option<&drop_box::Session>,
+    expiry: Option<Duration>,
+) -> Result<bool, RuntimeError> {
+    let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let Some(format) = ArchiveFormat::from_file_name(&file_name) else {
+        return Ok(false);
+    };
+    let dir = archive.parent().unwrap_or(Path::new(""));
+
+    if let Err(e) = extract(conf, archive, format, dir, user, session, expiry).await {
+        remove_archive(archive).await;
+        return Err(e);
+    }
+    info!("Extracted {archive:?} to {dir:?}");
+    Ok(true)
+}
+
+/// Remove the uploaded `archive`, if it's still there
+async fn remove_archive(archive: &Path) {
+    if let Err(e) = fs::remove_file(archive).await
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        warn!("Failed to remove the uploaded archive {archive:?}: {e}");
+    }
+    quota::forget_usage();
+}
+
+/// Extract `archive` of the given `format` to `dir`
+async fn extract(
+    conf: &MiniserveConfig,
+    archive: &Path,
+    format: ArchiveFormat,
+    dir: &Path,
+    user: Option<&str>,
+    session: Option<&drop_box::Session>,
+    expiry: Option<Duration>,
+) -> Result<(), RuntimeError> {
+    let archive_size = fs::metadata(archive)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to read {archive:?}"), e))?
+        .len();
+    let relative_dir = relative_to_root(&conf.path, archive)
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let quota_left = quota::space_left(conf, &relative_dir, user)
+        .await?
+        .map(|left| left + archive_size);
+    let max_size = quota_left.map_or(conf.max_extract_size, |left| {
+        left.min(conf.max_extract_size)
+    });
+
+    let temp_upload_directory = conf.temp_upload_directory.clone();
+    let staging = tokio::task::spawn_blocking(move || match temp_upload_directory {
+        Some(temp_directory) => TempDir::new_in(temp_directory),
+        None => TempDir::new(),
+    })
+    .await
+    .map_err(|e| RuntimeError::IoError("Failed to complete extraction task".into(), e.into()))?
+    .map_err(|e| RuntimeError::IoError("Failed to create temporary directory".into(), e))?;
+
+    let visibility = Visibility {
+        show_hidden: conf.show_hidden,
+        no_symlinks: conf.no_symlinks,
+    };
+    let (archive_path, staging_path) = (archive.to_path_buf(), staging.path().to_path_buf());
+    let (media_types, max_file_size) = (
+        conf.uploadable_media_type.clone(),
+        conf.max_upload_file_size,
+    );
+    let entries = tokio::task::spawn_blocking(move || {
+        unpack(
+            &archive_path,
+            format,
+            &staging_path,
+            visibility,
+            media_types.as_deref(),
+            max_file_size,
+            max_size,
+        )
+    })
+    .await
+    .map_err(|e| RuntimeError::IoError("Failed to complete extraction task".into(), e.into()))??;
+    // The archive may hold a file of the same name, which mustn't be removed along with it
+    remove_archive(archive).await;
+    check_extractable(conf, dir, &entries)?;
+
+    // The extracted files are reserved before being moved, in case other uploads complete
+    // meanwhile
+    let reservation = quota::reserve(conf, &relative_dir, user);
+    if let Some(reservation) = &reservation {
+        let size = entries
+            .iter()
+            .map(|entry| match entry.kind {
+                EntryKind::File { size, .. } => size,
+                _ => 0,
+            })
+            .sum();
+        let archive_name = archive.file_name().unwrap_or_default().to_string_lossy();
+        reservation.grow(&archive_name, size).await?;
+    }
+
+    for entry in entries {
+        let path = dir.join(&entry.path);
+        let created = match entry.kind {
+            EntryKind::Dir => {
+                fs::create_dir_all(&path).await.map_err(|e| {
+                    RuntimeError::IoError(format!("Failed to create {}", path.display()), e)
+                })?;
+                path
+            }
+            EntryKind::File { temp_path, size } => {
+                let path = resolve_duplicate(path, conf.on_duplicate_files)?;
+                move_upload(conf, &temp_path, &path).await?;
+                quota::record_upload(conf, &path, size, user).await;
+                expiry::record_upload(conf, &path, expiry).await;
+                path
+            }
+            EntryKind::Symlink { target } => create_symlink(conf, path, &target).await?,
+        };
+        drop_box::record_upload(conf, session, &created);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("archive.zip", Some(ArchiveFormat::Zip))]
+    #[case("ARCHIVE.ZIP", Some(ArchiveFormat::Zip))]
+    #[case("archive.tar", Some(ArchiveFormat::Tar))]
+    #[case("archive.tar.gz", Some(ArchiveFormat::TarGz))]
+    #[case("archive.tgz", Some(ArchiveFormat::TarGz))]
+    #[case("archive.gz", None)]
+    #[case("archive.txt", None)]
+    fn test_archive_format(#[case] file_name: &str, #[case] expected: Option<ArchiveFormat>) {
+        assert_eq!(ArchiveFormat::from_file_name(file_name), expected);
+    }
+
+    #[rstest]
+    #[case("link", "file", &[], true)]
+    #[case("dir/link", "../file", &[], true)]
+    #[case("dir/link", "./sub/../file", &[], true)]
+    #[case("link", "../file", &[], false)]
+    #[case("dir/link", "../../file", &[], false)]
+    #[case("link", "/etc/passwd", &[], false)]
+    #[case("link", "other", &["other"], true)]
+    #[case("link", "sub/up/..", &["sub/up"], false)]
+    #[case("link", "other/file", &["other"], false)]
+    #[case("link", ".env", &[], false)]
+    #[case("link", ".miniserve-trash", &[], false)]
+    #[case("dir/link", "../.miniserve-versions/file", &[], false)]
+    #[case("link", "dir/.hidden/../file", &[], false)]
+    fn test_symlink_stays_inside(
+        #[case] link: &str,
+        #[case] target: &str,
+        #[case] symlinks: &[&str],
+        #[case] expected: bool,
+    ) {
+        let symlinks = symlinks.iter().map(Path::new).collect();
+        assert_eq!(
+            symlink_stays_inside(
+                Path::new(link),
+                Path::new(target),
+                &symlinks,
+                Visibility {
+                    show_hidden: false,
+                    no_symlinks: false,
+                },
+            ),
+            expected
+        );
+    }
+}
@@ -1,8 +1,9 @@
-//! Handlers for file upload and removal
+//! Handlers for file upload, removal, moves and copies
 
 #[cfg(target_family = "unix")]
 use std::collections::HashSet;
 
+use std::cell::Cell;
 use std::io::ErrorKind;
 
 #[cfg(target_family = "unix")]
@@ -13,11 +14,15 @@ use std::path::{Component, Path, PathBuf};
 #[cfg(target_family = "unix")]
 use std::sync::Arc;
 
-use actix_web::{HttpRequest, HttpResponse, http::header, web};
+use std::time::Duration;
+
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, http::header, web};
 use async_walkdir::WalkDir;
+use bytesize::ByteSize;
 use futures::{StreamExt, TryStreamExt};
 use log::{error, info, warn};
-use serde::Deserialize;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
 use sha2::digest::DynDigest;
 use sha2::{Digest, Sha256, Sha512};
 use tempfile::NamedTempFile;
@@ -28,16 +33,57 @@ use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 
 use crate::{
-    args::DuplicateFile, config::MiniserveConfig, errors::RuntimeError,
-    file_utils::contains_symlink, file_utils::sanitize_path,
+    args::DuplicateFile,
+    auth::CurrentUser,
+    config::{MiniserveConfig, SharedConfig},
+    drop_box,
+    errors::RuntimeError,
+    expiry::{self, ExpiryQueryParameters},
+    extract::{self, ExtractQueryParameters},
+    file_utils::Visibility,
+    file_utils::contains_symlink,
+    file_utils::media_type_allowed,
+    file_utils::sanitize_path,
+    paste::PASTE_ROUTE,
+    quota, trash, versions,
 };
 
-enum FileHash {
+/// Expected hash of an uploaded file
+pub enum FileHash {
     SHA256(String),
     SHA512(String),
 }
 
 impl FileHash {
+    /// Expected hash `hash` computed with the hash function called `function`
+    pub fn new(function: &str, hash: String) -> Result<Self, RuntimeError> {
+        match function.to_ascii_uppercase().as_str() {
+            "SHA256" => Ok(Self::SHA256(hash)),
+            "SHA512" => Ok(Self::SHA512(hash)),
+            sha => Err(RuntimeError::InvalidHttpRequestError(format!(
+                "Invalid header value found for 'X-File-Hash-Function'. Supported values are SHA256 or SHA512. Found {sha}.",
+            ))),
+        }
+    }
+
+    /// Expected hash given by the `X-File-Hash` and `X-File-Hash-Function` headers of a request,
+    /// if any
+    pub fn from_request(req: &HttpRequest) -> Result<Option<Self>, RuntimeError> {
+        let header = |name| req.headers().get(name).and_then(|h| h.to_str().ok());
+        match (header("X-File-Hash"), header("X-File-Hash-Function")) {
+            (Some(hash), Some(function)) => Self::new(function, hash.to_string()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Name of the hash function, as given in `X-File-Hash-Function`
+    pub fn function(&self) -> &'static str {
+        match self {
+            Self::SHA256(_) => "SHA256",
+            Self::SHA512(_) => "SHA512",
+        }
+    }
+
     pub fn get_hasher(&self) -> Box<dyn DynDigest> {
         match self {
             Self::SHA256(_) => Box::new(Sha256::new()),
@@ -104,46 +150,208 @@ pub async fn recursive_dir_size(dir: &Path) -> Result<u64, RuntimeError> {
     Ok(total_size)
 }
 
-/// Saves file data from a multipart form field (`field`) to `file_path`. Optionally overwriting
-/// existing file and comparing the uploaded file checksum to the user provided `file_hash`.
-///
-/// Returns total bytes written to file.
-async fn save_file(
-    field: &mut actix_multipart::Field,
-    mut file_path: PathBuf,
+/// Find the first name of the form `{file_name}-{N}.{file_ext}` next to `file_path` that isn't
+/// taken yet (e.g. file-1.txt, file-2.txt, etc)
+pub fn free_file_name(file_path: &Path) -> PathBuf {
+    // extract extension of the file and the file stem without extension
+    // file.txt => (file, txt)
+    let file_name = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_ext = file_path.extension().map(|s| s.to_string_lossy());
+    (1..)
+        .map(|i| match &file_ext {
+            Some(ext) => file_path.with_file_name(format!("{file_name}-{i}.{ext}")),
+            None => file_path.with_file_name(format!("{file_name}-{i}")),
+        })
+        .find(|fp| !fp.exists())
+        .unwrap()
+}
+
+/// Set the permissions configured with `--chmod` on the files created at `path`, recursively if
+/// it's a directory.
+#[cfg(unix)]
+pub async fn set_created_permissions(path: &Path, chmod: u16) -> Result<(), RuntimeError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut files = vec![path.to_path_buf()];
+    if path.is_dir() {
+        let mut entries = WalkDir::new(path);
+        files.clear();
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(|e| {
+                RuntimeError::InvalidPathError(format!("Failed to read {path:?}: {e}"))
+            })?;
+            if entry.file_type().await.is_ok_and(|t| t.is_file()) {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    for file in files {
+        let perms = std::fs::Permissions::from_mode(chmod.into());
+        fs::set_permissions(&file, perms).await.map_err(|err| {
+            RuntimeError::IoError(format!("Failed to chmod {chmod:o} {file:?}"), err)
+        })?;
+    }
+    Ok(())
+}
+
+/// Path to save an upload to `file_path` at, following `on_duplicate_files` if it's taken
+pub fn resolve_duplicate(
+    file_path: PathBuf,
     on_duplicate_files: DuplicateFile,
-    file_checksum: Option<&FileHash>,
-    temporary_upload_directory: Option<&PathBuf>,
-    #[cfg(unix)] chmod: u16,
-) -> Result<u64, RuntimeError> {
+) -> Result<PathBuf, RuntimeError> {
     if file_path.exists() {
         match on_duplicate_files {
             DuplicateFile::Error => return Err(RuntimeError::DuplicateFileError),
-            DuplicateFile::Overwrite => (),
-            DuplicateFile::Rename => {
-                // extract extension of the file and the file stem without extension
-                // file.txt => (file, txt)
-                let file_name = file_path.file_stem().unwrap_or_default().to_string_lossy();
-                let file_ext = file_path.extension().map(|s| s.to_string_lossy());
-                for i in 1.. {
-                    // increment the number N in {file_name}-{N}.{file_ext}
-                    // format until available name is found (e.g. file-1.txt, file-2.txt, etc)
-                    let fp = if let Some(ext) = &file_ext {
-                        file_path.with_file_name(format!("{file_name}-{i}.{ext}"))
-                    } else {
-                        file_path.with_file_name(format!("{file_name}-{i}"))
-                    };
-                    // If we have a file name that doesn't exist yet then we'll use that.
-                    if !fp.exists() {
-                        file_path = fp;
-                        break;
-                    }
+            // Versions are kept by `move_upload`, once the upload is complete
+            DuplicateFile::Overwrite | DuplicateFile::Version => (),
+            DuplicateFile::Rename => return Ok(free_file_name(&file_path)),
+        }
+    }
+    Ok(file_path)
+}
+
+/// Move a completely uploaded temporary file to `file_path` and apply the upload permissions of
+/// `conf`, keeping the file it replaces as a version if `conf` says so.
+///
+/// The missing directories leading to `file_path` are created, see [`upload_file_path`].
+pub async fn move_upload(
+    conf: &MiniserveConfig,
+    temp_path: &Path,
+    file_path: &Path,
+) -> Result<(), RuntimeError> {
+    let prepared = async {
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                RuntimeError::IoError(format!("Failed to create {}", parent.display()), e)
+            })?;
+        }
+        versions::keep(conf, file_path).await
+    }
+    .await;
+    if let Err(e) = prepared {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+    if let Err(err) = tokio::fs::rename(&temp_path, &file_path).await {
+        match err.kind() {
+            ErrorKind::CrossesDevices => {
+                warn!(
+                    "File writen to {temp_path:?} must be copied to {file_path:?} because it's on a different filesystem"
+                );
+                let copy_result = tokio::fs::copy(&temp_path, &file_path).await;
+                if let Err(e) = tokio::fs::remove_file(&temp_path).await {
+                    error!("Failed to clean up temp file at {temp_path:?} with error {e:?}");
                 }
+                copy_result.map_err(|e| {
+                    RuntimeError::IoError(
+                        format!("Failed to copy file from {temp_path:?} to {file_path:?}"),
+                        e,
+                    )
+                })?;
+            }
+            _ => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return Err(RuntimeError::IoError(
+                    format!("Failed to move temporary file {temp_path:?} to {file_path:?}",),
+                    err,
+                ));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        let chmod = conf.upload_chmod;
+        info!("Changing file mode (chmod) to {chmod:o}");
+        use std::os::unix::fs::PermissionsExt;
+        let perms = std::fs::Permissions::from_mode(chmod.into());
+        if let Err(err) = tokio::fs::set_permissions(&file_path, perms).await {
+            return Err(RuntimeError::IoError(
+                format!("Failed to chmod {chmod:o} {file_path:?}"),
+                err,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of bytes at the start of uploaded files used to detect their media type
+pub const MEDIA_TYPE_DETECTION_LEN: usize = 8192;
+
+/// Restrictions on an uploaded file
+#[derive(Clone, Copy, Default)]
+pub struct UploadLimits<'a> {
+    /// Accepted file extensions and media types, see [`media_type_allowed`]
+    pub media_types: Option<&'a str>,
+
+    /// Maximum size of the file in bytes
+    pub max_size: Option<u64>,
+
+    /// Bytes left in the upload quotas the file counts against, see [`quota::space_left`]
+    pub quota_left: Option<u64>,
+
+    /// Reservation grown as the file is written, so that concurrent uploads keep to the quotas
+    pub reservation: Option<&'a quota::Reservation>,
+}
+
+impl UploadLimits<'_> {
+    /// Fail if a file called `file_name` whose contents start with `head` isn't accepted
+    pub fn check_media_type(&self, file_name: &str, head: &[u8]) -> Result<(), RuntimeError> {
+        match self.media_types {
+            Some(accept) if !media_type_allowed(accept, file_name, head) => {
+                Err(RuntimeError::UnsupportedMediaTypeError(format!(
+                    "{file_name} isn't of an accepted type ({accept})"
+                )))
             }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if a file called `file_name` of `size` bytes is too large
+    pub fn check_size(&self, file_name: &str, size: u64) -> Result<(), RuntimeError> {
+        match self.max_size {
+            Some(max_size) if size > max_size => Err(RuntimeError::UploadTooLargeError(format!(
+                "{file_name} exceeds the upload size limit of {}",
+                ByteSize::b(max_size)
+            ))),
+            _ => quota::check(self.quota_left, file_name, size),
         }
     }
+}
+
+/// A file saved by [`save_file`]
+pub struct SavedFile {
+    /// Path the file was saved at, after following the `on_duplicate_files` policy
+    pub path: PathBuf,
+
+    /// Total bytes written to the file
+    pub size: u64,
+
+    /// Hex-encoded hash of the file, computed with the function of the user provided hash if any,
+    /// SHA256 otherwise
+    pub hash: String,
+
+    /// Name of the hash function the hash was computed with
+    pub hash_function: &'static str,
+}
 
-    let temp_upload_directory = temporary_upload_directory.cloned();
+/// Saves file data from a stream (`field`), like a multipart form field or a request body, to
+/// `file_path`. Existing files are handled as `conf` says, and the uploaded file checksum is
+/// optionally compared to the user provided `file_hash`.
+///
+/// The upload is aborted as soon as it breaks the `limits`.
+pub async fn save_file<E: std::fmt::Display>(
+    field: &mut (impl futures::Stream<Item = Result<actix_web::web::Bytes, E>> + Unpin),
+    mut file_path: PathBuf,
+    conf: &MiniserveConfig,
+    limits: UploadLimits<'_>,
+    file_checksum: Option<&FileHash>,
+) -> Result<SavedFile, RuntimeError> {
+    file_path = resolve_duplicate(file_path, conf.on_duplicate_files)?;
+
+    let temp_upload_directory = conf.temp_upload_directory.clone();
     // Tempfile doesn't support async operations, so we'll do it on a background thread.
     let temp_upload_directory_task = tokio::task::spawn_blocking(move || {
         // If the user provided a temporary directory path, then use it.
@@ -184,19 +392,46 @@ async fn save_file(
     let mut temp_file = tokio::fs::File::from_std(file);
 
     let mut written_len = 0;
-    let mut hasher = file_checksum.as_ref().map(|h| h.get_hasher());
+    let (mut hasher, hash_function) = match file_checksum {
+        Some(file_hash) => (file_hash.get_hasher(), file_hash.function()),
+        None => (FileHash::SHA256(String::new()).get_hasher(), "SHA256"),
+    };
     let mut save_upload_file_error: Option<RuntimeError> = None;
+    let file_name = file_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    // Start of the file until its media type is checked
+    let mut unchecked_head = limits.media_types.map(|_| Vec::new());
 
     // This while loop take a stream (in this case `field`) and awaits
     // new chunks from the websocket connection. The while loop reads
     // the file from the HTTP connection and writes it to disk or until
     // the stream from the multipart request is aborted.
     while let Some(Ok(bytes)) = field.next().await {
-        // If the hasher exists (if the user has also sent a chunksum with the request)
-        // then we want to update the hasher with the new bytes uploaded.
-        if let Some(hasher) = hasher.as_mut() {
-            hasher.update(&bytes)
+        if let Err(e) = limits.check_size(&file_name, written_len + bytes.len() as u64) {
+            save_upload_file_error = Some(e);
+            break;
+        }
+        if let Some(reservation) = limits.reservation
+            && let Err(e) = reservation.grow(&file_name, bytes.len() as u64).await
+        {
+            save_upload_file_error = Some(e);
+            break;
         }
+        if let Some(head) = unchecked_head.as_mut() {
+            let missing = MEDIA_TYPE_DETECTION_LEN - head.len();
+            head.extend_from_slice(&bytes[..missing.min(bytes.len())]);
+            if head.len() == MEDIA_TYPE_DETECTION_LEN {
+                if let Err(e) = limits.check_media_type(&file_name, head) {
+                    save_upload_file_error = Some(e);
+                    break;
+                }
+                unchecked_head = None;
+            }
+        }
+        hasher.update(&bytes);
         // Write the bytes from the stream into our temporary file.
         if let Err(e) = temp_file.write_all(&bytes).await {
             // Failed to write to file. Drop it and return the error
@@ -208,6 +443,14 @@ async fn save_file(
         written_len += bytes.len() as u64;
     }
 
+    // Files shorter than what's needed to detect their media type
+    if save_upload_file_error.is_none()
+        && let Some(head) = unchecked_head
+        && let Err(e) = limits.check_media_type(&file_name, &head)
+    {
+        save_upload_file_error = Some(e);
+    }
+
     if save_upload_file_error.is_none() {
         // Flush the changes to disk so that we are sure they are there.
         if let Err(e) = temp_file.flush().await {
@@ -238,70 +481,86 @@ async fn save_file(
     // - https://github.com/actix/actix-web/discussions/3011
     // Therefore, we are relying on the fact that the web UI uploads a
     // hash of the file to determine if it was completed uploaded or not.
-    if let Some(hasher) = hasher
-        && let Some(expected_hash) = file_checksum.as_ref().map(|f| f.get_hash())
+    let actual_hash = hex::encode(hasher.finalize());
+    if let Some(expected_hash) = file_checksum.as_ref().map(|f| f.get_hash())
+        && actual_hash != expected_hash
     {
-        let actual_hash = hex::encode(hasher.finalize());
-        if actual_hash != expected_hash {
-            warn!(
-                "The expected file hash {expected_hash} did not match the calculated hash of {actual_hash}. This can be caused if a file upload was aborted."
-            );
-            let _ = tokio::fs::remove_file(&temp_path).await;
-            return Err(RuntimeError::UploadHashMismatchError);
-        }
+        warn!(
+            "The expected file hash {expected_hash} did not match the calculated hash of {actual_hash}. This can be caused if a file upload was aborted."
+        );
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(RuntimeError::UploadHashMismatchError);
     }
 
     info!("File upload successful to {temp_path:?}. Moving to {file_path:?}",);
-    if let Err(err) = tokio::fs::rename(&temp_path, &file_path).await {
-        match err.kind() {
-            ErrorKind::CrossesDevices => {
-                warn!(
-                    "File writen to {temp_path:?} must be copied to {file_path:?} because it's on a different filesystem"
-                );
-                let copy_result = tokio::fs::copy(&temp_path, &file_path).await;
-                if let Err(e) = tokio::fs::remove_file(&temp_path).await {
-                    error!("Failed to clean up temp file at {temp_path:?} with error {e:?}");
-                }
-                copy_result.map_err(|e| {
-                    RuntimeError::IoError(
-                        format!("Failed to copy file from {temp_path:?} to {file_path:?}"),
-                        e,
-                    )
-                })?;
-            }
-            _ => {
-                let _ = tokio::fs::remove_file(&temp_path).await;
-                return Err(RuntimeError::IoError(
-                    format!("Failed to move temporary file {temp_path:?} to {file_path:?}",),
-                    err,
-                ));
-            }
-        }
-    }
-
-    #[cfg(unix)]
-    {
-        info!("Changing file mode (chmod) to {chmod:o}");
-        use std::os::unix::fs::PermissionsExt;
-        let perms = std::fs::Permissions::from_mode(chmod.into());
-        if let Err(err) = tokio::fs::set_permissions(&file_path, perms).await {
-            return Err(RuntimeError::IoError(
-                format!("Failed to chmod {chmod:o} {file_path:?}"),
-                err,
-            ));
-        }
-    }
+    move_upload(conf, &temp_path, &file_path).await?;
 
-    Ok(written_len)
+    Ok(SavedFile {
+        path: file_path,
+        size: written_len,
+        hash: actual_hash,
+        hash_function,
+    })
 }
 
 struct HandleMultipartOpts<'a> {
-    on_duplicate_files: DuplicateFile,
     allow_mkdir: bool,
     allow_hidden_paths: bool,
     allow_symlinks: bool,
     file_hash: Option<&'a FileHash>,
-    upload_directory: Option<&'a PathBuf>,
+    limits: UploadLimits<'a>,
+    max_request_size: Option<u64>,
+    /// Bytes of the files of the request received so far
+    received: &'a Cell<u64>,
+    /// Configuration the files are saved with
+    conf: &'a MiniserveConfig,
+    /// Directory the files are uploaded to, relative to the served path
+    dir: &'a Path,
+    /// Name of the user uploading the files, if authentication is enabled
+    user: Option<&'a str>,
+    /// Drop box session the files are uploaded in, if the drop box is enabled
+    session: Option<&'a drop_box::Session>,
+    /// Extract the uploaded archives
+    extract: bool,
+    /// Time after which the uploaded files expire, if they do
+    expiry: Option<Duration>,
+}
+
+/// Path to upload a file called `filename` to in the directory `dir`, which must have gone through
+/// [`upload_target_dir`].
+///
+/// Files of uploaded folders are called by their path relative to `dir`. The directories leading
+/// to them are created by [`move_upload`], which needs `allow_mkdir` if they don't exist yet.
+pub fn upload_file_path(
+    dir: &Path,
+    filename: &str,
+    allow_hidden_paths: bool,
+    allow_symlinks: bool,
+    allow_mkdir: bool,
+) -> Result<PathBuf, RuntimeError> {
+    let filename_path = sanitize_path(Path::new(filename), allow_hidden_paths)
+        .ok_or_else(|| RuntimeError::InvalidPathError("Invalid file name to upload".to_string()))?;
+    let file_path = dir.join(filename_path);
+    let parent = file_path.parent().unwrap_or(dir);
+
+    // Ensure there are no illegal symlinks in the file upload path
+    if !allow_symlinks {
+        match contains_symlink(parent) {
+            Err(err) => Err(RuntimeError::InsufficientPermissionsError(err.to_string()))?,
+            Ok(true) => Err(RuntimeError::InsufficientPermissionsError(format!(
+                "{parent:?} traverses through a symlink"
+            )))?,
+            Ok(false) => (),
+        }
+    }
+
+    if !allow_mkdir && !parent.is_dir() {
+        return Err(RuntimeError::InsufficientPermissionsError(
+            parent.display().to_string(),
+        ));
+    }
+
+    Ok(file_path)
 }
 
 /// Handles a single field in a multipart form
@@ -309,15 +568,21 @@ async fn handle_multipart(
     mut field: actix_multipart::Field,
     path: PathBuf,
     opts: HandleMultipartOpts<'_>,
-    #[cfg(unix)] chmod: u16,
 ) -> Result<u64, RuntimeError> {
     let HandleMultipartOpts {
-        on_duplicate_files,
         allow_mkdir,
         allow_hidden_paths,
         allow_symlinks,
         file_hash,
-        upload_directory,
+        limits,
+        max_request_size,
+        received,
+        conf,
+        dir,
+        user,
+        session,
+        extract,
+        expiry,
     } = opts;
     let field_name = field.name().expect("No name field found").to_string();
 
@@ -397,7 +662,10 @@ async fn handle_multipart(
                 format!("Failed to create {}", user_given_path.display()),
                 err,
             )),
-            Ok(_) => Ok(0),
+            Ok(_) => {
+                drop_box::record_upload(conf, session, &absolute_path);
+                Ok(0)
+            }
         };
     }
 
@@ -412,50 +680,64 @@ async fn handle_multipart(
             )
         })?;
 
-    let filename_path = sanitize_path(Path::new(&filename), allow_hidden_paths)
-        .ok_or_else(|| RuntimeError::InvalidPathError("Invalid file name to upload".to_string()))?;
+    let file_path = upload_file_path(
+        &path,
+        filename,
+        allow_hidden_paths,
+        allow_symlinks,
+        allow_mkdir,
+    )?;
 
-    // Ensure there are no illegal symlinks in the file upload path
-    if !allow_symlinks {
-        match contains_symlink(&path) {
-            Err(err) => Err(RuntimeError::InsufficientPermissionsError(err.to_string()))?,
-            Ok(true) => Err(RuntimeError::InsufficientPermissionsError(format!(
-                "{path:?} traverses through a symlink"
-            )))?,
-            Ok(false) => (),
-        }
+    // The file may only use what's left of the request size limit and of the quotas
+    let left_in_request = max_request_size.map(|max| max.saturating_sub(received.get()));
+    let reservation = quota::reserve(conf, dir, user);
+    let limits = UploadLimits {
+        max_size: limits.max_size.into_iter().chain(left_in_request).min(),
+        quota_left: limits
+            .quota_left
+            .map(|left| left.saturating_sub(received.get())),
+        reservation: reservation.as_ref(),
+        ..limits
+    };
+
+    let saved = save_file(&mut field, file_path, conf, limits, file_hash).await?;
+    received.set(received.get() + saved.size);
+    quota::record_upload(conf, &saved.path, saved.size, user).await;
+    drop(reservation);
+    let extracted =
+        extract && extract::extract_upload(conf, &saved.path, user, session, expiry).await?;
+    if !extracted {
+        expiry::record_upload(conf, &saved.path, expiry).await;
+        drop_box::record_upload(conf, session, &saved.path);
     }
+    Ok(saved.size)
+}
 
-    save_file(
-        &mut field,
-        path.join(filename_path),
-        on_duplicate_files,
-        file_hash,
-        upload_directory,
-        #[cfg(unix)]
-        chmod,
-    )
-    .await
+/// Fail early if the body of `req` is announced to be larger than the `limits` allow
+pub fn check_content_length(req: &HttpRequest, limits: UploadLimits) -> Result<(), RuntimeError> {
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u64>().ok());
+    match content_length {
+        Some(length) => limits.check_size("The request", length),
+        None => Ok(()),
+    }
 }
 
-/// Query parameters used by upload and rm APIs
+/// Query parameters used by upload, rm, mv and cp APIs
 #[derive(Deserialize, Default)]
 pub struct FileOpQueryParameters {
-    path: PathBuf,
+    pub path: PathBuf,
 }
 
-/// Handle incoming request to upload a file or create a directory.
-/// Target file path is expected as path parameter in URI and is interpreted as relative from
-/// server root directory. Any path which will go outside of this directory is considered
-/// invalid.
-/// This method returns future.
-pub async fn upload_file(
-    req: HttpRequest,
-    query: web::Query<FileOpQueryParameters>,
-    payload: web::Payload,
-) -> Result<HttpResponse, RuntimeError> {
-    let conf = req.app_data::<web::Data<MiniserveConfig>>().unwrap();
-    let upload_path = sanitize_path(&query.path, conf.show_hidden).ok_or_else(|| {
+/// Directory to upload to for the `path` parameter of an upload request, relative to the server
+/// root directory.
+///
+/// The directory isn't canonicalized so that symlinks can still be checked for if needed.
+pub fn upload_target_dir(conf: &MiniserveConfig, path: &Path) -> Result<PathBuf, RuntimeError> {
+    let upload_path = sanitize_path(path, conf.show_hidden).ok_or_else(|| {
         RuntimeError::InvalidPathError("Invalid value for 'path' parameter".to_string())
     })?;
     let app_root_dir = conf.path.canonicalize().map_err(|e| {
@@ -463,19 +745,11 @@ pub async fn upload_file(
     })?;
 
     // Disallow paths outside of allowed directories
-    let upload_allowed = conf.allowed_upload_dir.is_empty()
-        || conf
-            .allowed_upload_dir
-            .iter()
-            .any(|s| upload_path.starts_with(s));
-
-    if !upload_allowed {
+    if !conf.upload_allowed(&upload_path) {
         return Err(RuntimeError::UploadForbiddenError);
     }
 
     // Disallow the target path to go outside of the served directory
-    // The target directory shouldn't be canonicalized when it gets passed to
-    // handle_multipart so that it can check for symlinks if needed
     let non_canonicalized_target_dir = app_root_dir.join(upload_path);
     match non_canonicalized_target_dir.canonicalize() {
         Ok(path) if !conf.no_symlinks => Ok(path),
@@ -485,28 +759,39 @@ pub async fn upload_file(
         )),
     }?;
 
-    let upload_directory = conf.temp_upload_directory.as_ref();
-
-    let file_hash = if let (Some(hash), Some(hash_function)) = (
-        req.headers()
-            .get("X-File-Hash")
-            .and_then(|h| h.to_str().ok()),
-        req.headers()
-            .get("X-File-Hash-Function")
-            .and_then(|h| h.to_str().ok()),
-    ) {
-        match hash_function.to_ascii_uppercase().as_str() {
-            "SHA256" => Some(FileHash::SHA256(hash.to_string())),
-            "SHA512" => Some(FileHash::SHA512(hash.to_string())),
-            sha => {
-                return Err(RuntimeError::InvalidHttpRequestError(format!(
-                    "Invalid header value found for 'X-File-Hash-Function'. Supported values are SHA256 or SHA512. Found {sha}.",
-                )));
-            }
-        }
-    } else {
-        None
-    };
+    Ok(non_canonicalized_target_dir)
+}
+
+/// Handle incoming request to upload a file or create a directory.
+/// Target file path is expected as path parameter in URI and is interpreted as relative from
+/// server root directory. Any path which will go outside of this directory is considered
+/// invalid.
+/// This method returns future.
+pub async fn upload_file(
+    req: HttpRequest,
+    query: web::Query<FileOpQueryParameters>,
+    extract: web::Query<ExtractQueryParameters>,
+    expire: web::Query<ExpiryQueryParameters>,
+    payload: web::Payload,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let non_canonicalized_target_dir = upload_target_dir(&conf, &query.path)?;
+    let expiry = expire.expiry(&conf)?;
+    let file_hash = FileHash::from_request(&req)?;
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    let user = current_user.as_ref().map(|user| user.name.as_str());
+    let session = drop_box::session(&req);
+    let dir = sanitize_path(&query.path, conf.show_hidden).unwrap_or_default();
+    let quota_left = quota::space_left(&conf, &dir, user).await?;
+    check_content_length(
+        &req,
+        UploadLimits {
+            max_size: conf.max_upload_request_size,
+            quota_left,
+            ..Default::default()
+        },
+    )?;
+    let received = Cell::new(0);
 
     let hash_ref = file_hash.as_ref();
     actix_multipart::Multipart::new(req.headers(), payload)
@@ -516,15 +801,25 @@ pub async fn upload_file(
                 field,
                 non_canonicalized_target_dir.clone(),
                 HandleMultipartOpts {
-                    on_duplicate_files: conf.on_duplicate_files,
                     allow_mkdir: conf.mkdir_enabled,
                     allow_hidden_paths: conf.show_hidden,
                     allow_symlinks: !conf.no_symlinks,
                     file_hash: hash_ref,
-                    upload_directory,
+                    limits: UploadLimits {
+                        media_types: conf.uploadable_media_type.as_deref(),
+                        max_size: conf.max_upload_file_size,
+                        quota_left,
+                        reservation: None,
+                    },
+                    max_request_size: conf.max_upload_request_size,
+                    received: &received,
+                    conf: &conf,
+                    dir: &dir,
+                    user,
+                    session: session.as_ref(),
+                    extract: conf.extract_archives && extract.extract,
+                    expiry,
                 },
-                #[cfg(unix)]
-                conf.upload_chmod,
             )
         })
         .try_collect::<Vec<u64>>()
@@ -541,6 +836,126 @@ pub async fn upload_file(
         .finish())
 }
 
+/// Response to a raw upload
+#[derive(Serialize)]
+struct RawUploadResponse {
+    /// Path the file was saved at, relative to the server root directory
+    path: String,
+
+    /// Size of the file in bytes
+    size: u64,
+
+    /// Hex-encoded hash of the file
+    hash: String,
+
+    /// Name of the hash function the hash was computed with
+    hash_function: &'static str,
+
+    /// Whether the file was an archive which was extracted, and removed
+    extracted: bool,
+}
+
+/// Handle incoming request to upload a file from the raw request body, like `curl -T` or
+/// `curl --data-binary` send it.
+///
+/// Target file path is the path of the request, relative to the server root directory. The file
+/// goes through the same checks as files uploaded with [`upload_file`], and the response
+/// describes the saved file in JSON.
+pub async fn upload_raw(
+    req: HttpRequest,
+    extract: web::Query<ExtractQueryParameters>,
+    expire: web::Query<ExpiryQueryParameters>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let expiry = expire.expiry(&conf)?;
+    let invalid_path = || RuntimeError::InvalidPathError("Invalid file path to upload".to_string());
+
+    let path = req
+        .path()
+        .strip_prefix(&conf.route_prefix)
+        .ok_or_else(invalid_path)?;
+    // Actions which aren't enabled must not turn into uploads
+    if ["/upload", "/rm", "/mv", "/cp", PASTE_ROUTE].contains(&path) {
+        return Err(RuntimeError::RouteNotFoundError(path.to_string()));
+    }
+    let path = percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|e| RuntimeError::ParseError(path.to_string(), e.to_string()))?;
+    let path = Path::new(&*path);
+    let (dir, filename) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(filename)) if !path.as_os_str().to_string_lossy().ends_with('/') => {
+            (dir, filename.to_string_lossy())
+        }
+        _ => return Err(invalid_path()),
+    };
+
+    let target_dir = upload_target_dir(&conf, dir)?;
+    if !target_dir.is_dir() {
+        return Err(RuntimeError::InvalidPathError(format!(
+            "cannot upload file to {}, since it's not a directory",
+            target_dir.display()
+        )));
+    }
+    let file_path = upload_file_path(
+        &target_dir,
+        &filename,
+        conf.show_hidden,
+        !conf.no_symlinks,
+        conf.mkdir_enabled,
+    )?;
+    let file_hash = FileHash::from_request(&req)?;
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    let user = current_user.as_ref().map(|user| user.name.as_str());
+    let dir = sanitize_path(dir, conf.show_hidden).unwrap_or_default();
+    let reservation = quota::reserve(&conf, &dir, user);
+
+    // The request holds nothing but the file
+    let limits = UploadLimits {
+        media_types: conf.uploadable_media_type.as_deref(),
+        max_size: conf
+            .max_upload_file_size
+            .into_iter()
+            .chain(conf.max_upload_request_size)
+            .min(),
+        quota_left: quota::space_left(&conf, &dir, user).await?,
+        reservation: reservation.as_ref(),
+    };
+    check_content_length(&req, limits)?;
+    limits.check_media_type(&filename, &[])?;
+
+    let saved = save_file(&mut payload, file_path, &conf, limits, file_hash.as_ref()).await?;
+    quota::record_upload(&conf, &saved.path, saved.size, user).await;
+    drop(reservation);
+    let session = drop_box::session(&req);
+    let extracted = conf.extract_archives
+        && extract.extract
+        && extract::extract_upload(&conf, &saved.path, user, session.as_ref(), expiry).await?;
+    if !extracted {
+        expiry::record_upload(&conf, &saved.path, expiry).await;
+        drop_box::record_upload(&conf, session.as_ref(), &saved.path);
+    }
+
+    let app_root_dir = conf.path.canonicalize().map_err(|e| {
+        RuntimeError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let relative_path = saved
+        .path
+        .strip_prefix(&app_root_dir)
+        .unwrap_or(&saved.path);
+
+    Ok(HttpResponse::Created().json(RawUploadResponse {
+        path: Path::new("/")
+            .join(relative_path)
+            .to_string_lossy()
+            .into_owned(),
+        size: saved.size,
+        hash: saved.hash,
+        hash_function: saved.hash_function,
+        extracted,
+    }))
+}
+
 /// Handle incoming request to remove a file or directory.
 ///
 /// Target file path is expected as path parameter in URI and is interpreted as relative from
@@ -550,7 +965,7 @@ pub async fn rm_file(
     req: HttpRequest,
     query: web::Query<FileOpQueryParameters>,
 ) -> Result<HttpResponse, RuntimeError> {
-    let conf = req.app_data::<web::Data<MiniserveConfig>>().unwrap();
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
     let rm_path = sanitize_path(&query.path, conf.show_hidden).ok_or_else(|| {
         RuntimeError::InvalidPathError("Invalid value for 'path' parameter".to_string())
     })?;
@@ -560,10 +975,7 @@ pub async fn rm_file(
     })?;
 
     // Disallow paths outside of allowed directories
-    let rm_allowed = conf.allowed_rm_dir.is_empty()
-        || conf.allowed_rm_dir.iter().any(|s| rm_path.starts_with(s));
-
-    if !rm_allowed {
+    if !conf.rm_allowed(&rm_path) {
         return Err(RuntimeError::RmForbiddenError);
     }
 
@@ -583,19 +995,248 @@ pub async fn rm_file(
         )));
     }
 
-    // Remove
-    let rm_res = if canonicalized_rm_path.is_dir() {
-        fs::remove_dir_all(&canonicalized_rm_path).await
+    // Remove, or move to the trash
+    if conf.trash_enabled {
+        let current_user = req.extensions().get::<CurrentUser>().cloned();
+        trash::move_to_trash(
+            &conf.path,
+            &canonicalized_rm_path,
+            &rm_path,
+            current_user.as_ref().map(|user| user.name.as_str()),
+        )
+        .await?;
     } else {
-        fs::remove_file(&canonicalized_rm_path).await
+        let rm_res = if canonicalized_rm_path.is_dir() {
+            fs::remove_dir_all(&canonicalized_rm_path).await
+        } else {
+            fs::remove_file(&canonicalized_rm_path).await
+        };
+        if let Err(err) = rm_res {
+            Err(RuntimeError::IoError(
+                format!("Failed to remove {rm_path:?}"),
+                err,
+            ))?;
+        }
+    }
+    quota::forget_usage();
+
+    let return_path = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("/");
+
+    Ok(HttpResponse::SeeOther()
+        .append_header((header::LOCATION, return_path))
+        .finish())
+}
+
+/// Form parameters used by the mv and cp APIs
+#[derive(Deserialize)]
+pub struct DestinationFormParameters {
+    destination: PathBuf,
+}
+
+/// Whether a transfer keeps its source around
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Move,
+    Copy,
+}
+
+/// Handle incoming request to move or rename a file or directory.
+///
+/// The source path is expected as path parameter in URI and the destination as `destination`
+/// form field, both relative to the server root directory. The source must be allowed to be
+/// removed and the destination to be uploaded to.
+pub async fn mv_file(
+    req: HttpRequest,
+    query: web::Query<FileOpQueryParameters>,
+    form: web::Form<DestinationFormParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    transfer_file(&req, &query.path, &form.destination, Transfer::Move).await
+}
+
+/// Handle incoming request to copy a file or directory.
+///
+/// Takes the same parameters as [`mv_file`]. The destination must be allowed to be uploaded to.
+pub async fn cp_file(
+    req: HttpRequest,
+    query: web::Query<FileOpQueryParameters>,
+    form: web::Form<DestinationFormParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    transfer_file(&req, &query.path, &form.destination, Transfer::Copy).await
+}
+
+/// Move or copy `path` to `destination`, both relative to the server root directory, following
+/// the same rules as uploads and removals.
+async fn transfer_file(
+    req: &HttpRequest,
+    path: &Path,
+    destination: &Path,
+    transfer: Transfer,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let invalid_param = |name: &str| {
+        RuntimeError::InvalidPathError(format!("Invalid value for '{name}' parameter"))
     };
-    if let Err(err) = rm_res {
-        Err(RuntimeError::IoError(
-            format!("Failed to remove {rm_path:?}"),
-            err,
-        ))?;
+    // Neither path may be the served directory itself
+    let src_path = sanitize_path(path, conf.show_hidden)
+        .filter(|p| p.file_name().is_some())
+        .ok_or_else(|| invalid_param("path"))?;
+    let dest_path = sanitize_path(destination, conf.show_hidden)
+        .filter(|p| p.file_name().is_some())
+        .ok_or_else(|| invalid_param("destination"))?;
+    let dest_dir = dest_path.parent().unwrap_or(Path::new(""));
+
+    // Disallow paths outside of allowed directories
+    if transfer == Transfer::Move && !conf.rm_allowed(&src_path) {
+        return Err(RuntimeError::RmForbiddenError);
+    }
+    if !conf.upload_allowed(dest_dir) {
+        return Err(RuntimeError::UploadForbiddenError);
+    }
+
+    let app_root_dir = conf.path.canonicalize().map_err(|e| {
+        RuntimeError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let source = app_root_dir.join(&src_path);
+    let target_dir = app_root_dir.join(dest_dir);
+
+    // Handle non-existent path
+    if source.symlink_metadata().is_err() {
+        return Err(RuntimeError::RouteNotFoundError(format!(
+            "{src_path:?} does not exist"
+        )));
     }
 
+    // Disallow either path to go outside of the served directory
+    let canonicalized_source = match source.canonicalize() {
+        Ok(path) if !conf.no_symlinks => Ok(path),
+        Ok(path) if path.starts_with(&app_root_dir) => Ok(path),
+        _ => Err(RuntimeError::InvalidHttpRequestError(
+            "Invalid value for 'path' parameter".to_string(),
+        )),
+    }?;
+    let canonicalized_target_dir = match target_dir.canonicalize() {
+        Ok(path) if !path.is_dir() => Err(RuntimeError::InvalidPathError(format!(
+            "cannot move or copy to {}, since it's not a directory",
+            dest_dir.display()
+        ))),
+        Ok(path) if !conf.no_symlinks => Ok(path),
+        Ok(path) if path.starts_with(&app_root_dir) => Ok(path),
+        _ => Err(RuntimeError::InvalidHttpRequestError(
+            "Invalid value for 'destination' parameter".to_string(),
+        )),
+    }?;
+
+    // Ensure there are no illegal symlinks
+    if conf.no_symlinks {
+        for path in [&source, &target_dir] {
+            match contains_symlink(path) {
+                Err(err) => Err(RuntimeError::InsufficientPermissionsError(err.to_string()))?,
+                Ok(true) => Err(RuntimeError::InsufficientPermissionsError(format!(
+                    "{path:?} traverses through a symlink"
+                )))?,
+                Ok(false) => (),
+            }
+        }
+    }
+
+    // A directory can't be moved or copied into itself
+    if canonicalized_target_dir.starts_with(&canonicalized_source) {
+        return Err(RuntimeError::InvalidPathError(format!(
+            "cannot move or copy {} into itself",
+            src_path.display()
+        )));
+    }
+
+    let mut target = target_dir.join(dest_path.file_name().unwrap());
+
+    // The whole entry is reserved before it's copied or moved into another quota
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    let user = current_user.as_ref().map(|user| user.name.as_str());
+    let reservation = quota::reserve_transfer(
+        &conf,
+        &src_path,
+        &dest_path,
+        transfer == Transfer::Copy,
+        user,
+    )
+    .await?;
+
+    if let Ok(metadata) = target.symlink_metadata() {
+        // Replacing the destination mustn't remove the source along with it
+        if matches!(
+            conf.on_duplicate_files,
+            DuplicateFile::Overwrite | DuplicateFile::Version
+        ) && target
+            .canonicalize()
+            .is_ok_and(|path| canonicalized_source.starts_with(path))
+        {
+            return Err(RuntimeError::InvalidPathError(format!(
+                "cannot replace {} with {}",
+                dest_path.display(),
+                src_path.display()
+            )));
+        }
+        match conf.on_duplicate_files {
+            DuplicateFile::Error => return Err(RuntimeError::DuplicateFileError),
+            // Replacing a whole directory is a removal, which is only allowed where removals are
+            DuplicateFile::Overwrite if metadata.is_dir() && !conf.rm_allowed(&dest_path) => {
+                return Err(RuntimeError::RmForbiddenError);
+            }
+            DuplicateFile::Overwrite if conf.trash_enabled => {
+                trash::move_to_trash(&conf.path, &target, &dest_path, user).await?;
+            }
+            DuplicateFile::Overwrite => {
+                let rm_res = if metadata.is_dir() {
+                    fs::remove_dir_all(&target).await
+                } else {
+                    fs::remove_file(&target).await
+                };
+                rm_res.map_err(|err| {
+                    RuntimeError::IoError(format!("Failed to replace {dest_path:?}"), err)
+                })?;
+            }
+            DuplicateFile::Version if metadata.is_dir() => {
+                return Err(RuntimeError::DuplicateFileError);
+            }
+            DuplicateFile::Version => versions::keep(&conf, &target).await?,
+            DuplicateFile::Rename => target = free_file_name(&target),
+        }
+    }
+
+    match transfer {
+        Transfer::Move => fs::rename(&source, &target).await.map_err(|err| {
+            RuntimeError::IoError(format!("Failed to move {src_path:?} to {dest_path:?}"), err)
+        })?,
+        Transfer::Copy => {
+            let visibility = Visibility {
+                show_hidden: conf.show_hidden,
+                no_symlinks: conf.no_symlinks,
+            };
+            let copied = target.clone();
+            tokio::task::spawn_blocking(move || copy_visible(&source, &copied, visibility))
+                .await
+                .map_err(|err| {
+                    RuntimeError::IoError("Failed to complete copy task".to_string(), err.into())
+                })?
+                .map_err(|err| {
+                    RuntimeError::IoError(
+                        format!("Failed to copy {src_path:?} to {dest_path:?}"),
+                        err,
+                    )
+                })?;
+
+            #[cfg(unix)]
+            set_created_permissions(&target, conf.upload_chmod).await?;
+            quota::record_copy(&conf, &target, user).await;
+        }
+    }
+    drop(reservation);
+    quota::forget_usage();
+
     let return_path = req
         .headers()
         .get(header::REFERER)
@@ -606,3 +1247,30 @@ pub async fn rm_file(
         .append_header((header::LOCATION, return_path))
         .finish())
 }
+
+/// Copy `source` to `target`, recursively if it's a directory.
+///
+/// Entries below `source` that `visibility` hides aren't copied. Like in the listing, entries
+/// whose metadata can't be read (e.g. broken symlinks) and entries which are neither files nor
+/// directories are skipped.
+fn copy_visible(source: &Path, target: &Path, visibility: Visibility) -> std::io::Result<()> {
+    if !std::fs::metadata(source)?.is_dir() {
+        return std::fs::copy(source, target).map(|_| ());
+    }
+
+    std::fs::create_dir(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+        if !visibility.allows(&entry.file_name(), is_symlink) {
+            continue;
+        }
+        match std::fs::metadata(entry.path()) {
+            Ok(metadata) if metadata.is_dir() || metadata.is_file() => {
+                copy_visible(&entry.path(), &target.join(entry.file_name()), visibility)?
+            }
+            _ => continue,
+        }
+    }
+    Ok(())
+}
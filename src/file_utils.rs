@@ -1,13 +1,30 @@
 #[cfg(unix)]
 use rustix::{fs::Mode, process::umask};
 use std::{
+    ffi::OsStr,
     io,
     path::{Component, Path, PathBuf},
 };
 
+use percent_encoding::percent_decode_str;
+
+use crate::config::MiniserveConfig;
+use crate::expiry::EXPIRY_FILE;
+use crate::quota::QUOTA_FILE;
+use crate::trash::TRASH_DIR;
+use crate::versions::VERSIONS_DIR;
+
+/// true if `name` is the name of an entry miniserve keeps its own state in at the root of the
+/// served path, see [`TRASH_DIR`], [`QUOTA_FILE`], [`VERSIONS_DIR`] and [`EXPIRY_FILE`]
+pub fn is_internal(name: &OsStr) -> bool {
+    name == TRASH_DIR || name == QUOTA_FILE || name == VERSIONS_DIR || name == EXPIRY_FILE
+}
+
 /// Guarantee that the path is relative and cannot traverse back to parent directories
 /// and optionally prevent traversing hidden directories.
 ///
+/// Paths through miniserve's internal entries are always rejected, see [`is_internal`].
+///
 /// See the unit tests tests::test_sanitize_path* for examples
 pub fn sanitize_path(path: impl AsRef<Path>, traverse_hidden: bool) -> Option<PathBuf> {
     let mut buf = PathBuf::new();
@@ -25,6 +42,7 @@ pub fn sanitize_path(path: impl AsRef<Path>, traverse_hidden: bool) -> Option<Pa
     // Double-check that all components are Normal and check for hidden dirs
     for comp in buf.components() {
         match comp {
+            Component::Normal(name) if is_internal(name) => return None,
             Component::Normal(_) if traverse_hidden => (),
             Component::Normal(name) if !name.to_str()?.starts_with('.') => (),
             _ => return None,
@@ -34,6 +52,47 @@ pub fn sanitize_path(path: impl AsRef<Path>, traverse_hidden: bool) -> Option<Pa
     Some(buf)
 }
 
+/// Path relative to the served path of the file at the URL path `link`, if it may be exposed.
+///
+/// Like the static files, the path can't be hidden, unless `--hidden` is given, or go through a
+/// symlink with `--no-symlinks`.
+pub fn requested_path(conf: &MiniserveConfig, link: &str) -> Option<PathBuf> {
+    let path = link.strip_prefix(&conf.route_prefix)?;
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let path = sanitize_path(&*path, conf.show_hidden)?;
+
+    let mut full_path = conf.path.clone();
+    let traverses_symlink = path.components().any(|component| {
+        full_path.push(component);
+        full_path.is_symlink()
+    });
+    (!(conf.no_symlinks && traverses_symlink)).then_some(path)
+}
+
+/// Path relative to the served path of the file served by `--pretty-urls` at the URL path `link`
+/// when nothing is found there.
+///
+/// The path gets ".html" appended, after removing its trailing slash, e.g. "/about" serves
+/// "about.html".
+pub fn pretty_url_path(link: &str) -> PathBuf {
+    let mut path_base = link[1..].to_string();
+    if path_base.ends_with('/') {
+        path_base.pop();
+    }
+    if !path_base.ends_with("html") {
+        path_base = format!("{path_base}.html");
+    }
+    PathBuf::from(path_base)
+}
+
+/// Path of the local `path` relative to the served directory `root`, resolving the symlinks
+/// leading to its parent directory
+pub fn relative_to_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let parent = path.parent()?.canonicalize().ok()?;
+    Some(parent.strip_prefix(root).ok()?.join(path.file_name()?))
+}
+
 /// Checks if any segment of the path is a symlink.
 ///
 /// This function fails if [`std::fs::symlink_metadata`] fails, which usually
@@ -53,6 +112,76 @@ pub fn contains_symlink(path: impl AsRef<Path>) -> io::Result<bool> {
     Ok(contains_symlink)
 }
 
+/// Rules deciding which files and directories are exposed to clients
+///
+/// The directory listing, archive downloads and WebDAV all go through these rules, so that none
+/// of them exposes an entry that the others hide. Internal entries are never exposed, see
+/// [`is_internal`].
+#[derive(Debug, Clone, Copy)]
+pub struct Visibility {
+    /// Show entries whose name starts with a dot
+    pub show_hidden: bool,
+
+    /// Hide symlinks, and with them everything behind them
+    pub no_symlinks: bool,
+}
+
+impl Visibility {
+    /// true if the entry name starts with a dot
+    pub fn is_hidden(name: &OsStr) -> bool {
+        name.as_encoded_bytes().starts_with(b".")
+    }
+
+    /// true if an entry called `name` may be exposed, `is_symlink` being whether the entry itself
+    /// is a symlink
+    pub fn allows(&self, name: &OsStr, is_symlink: bool) -> bool {
+        let exposed =
+            (self.show_hidden || !Self::is_hidden(name)) && !(self.no_symlinks && is_symlink);
+        exposed && !is_internal(name)
+    }
+}
+
+/// Checks if a file called `file_name` whose contents start with `head` may be uploaded, `accept`
+/// being a comma separated list of file extensions and media types like the HTML `accept`
+/// attribute (e.g. `.pdf,image/*`).
+///
+/// The extension has to be accepted, and the contents have to be of the same kind as the extension
+/// says if their magic bytes are recognized.
+pub fn media_type_allowed(accept: &str, file_name: &str, head: &[u8]) -> bool {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    let guessed = extension
+        .as_deref()
+        .map(|ext| mime_guess::from_ext(ext).iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // Catch e.g. HTML documents disguised as images
+    if let Some(detected) = infer::get(head)
+        && !guessed.is_empty()
+        && !guessed
+            .iter()
+            .any(|mime| detected.mime_type().split('/').next() == Some(mime.type_().as_str()))
+    {
+        return false;
+    }
+
+    accept
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .any(|token| match token.strip_prefix('.') {
+            Some(accepted) => extension
+                .as_deref()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(accepted)),
+            None if token == "*/*" => true,
+            None => guessed.iter().any(|mime| match token.split_once('/') {
+                Some((type_, "*")) => mime.type_().as_str().eq_ignore_ascii_case(type_),
+                _ => mime.essence_str().eq_ignore_ascii_case(token),
+            }),
+        })
+}
+
 /// Get default file creation permissions by umask
 #[cfg(unix)]
 pub fn get_default_filemode() -> u16 {
@@ -85,6 +214,30 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("image/*", "photo.png", b"\x89PNG\r\n\x1a\n", true)]
+    #[case("image/*", "photo.PNG", b"\x89PNG\r\n\x1a\n", true)]
+    #[case("image/*", "photo.jpg", b"\x89PNG\r\n\x1a\n", true)]
+    #[case("image/*", "photo.png", b"<!DOCTYPE html><html></html>", false)]
+    #[case("image/*", "document.pdf", b"%PDF-1.7", false)]
+    #[case("image/*", "photo", b"\x89PNG\r\n\x1a\n", false)]
+    #[case("image/png", "photo.png", b"", true)]
+    #[case("image/jpeg", "photo.png", b"", false)]
+    #[case("audio/*, video/*", "movie.mp4", b"", true)]
+    #[case(".pdf,.txt", "notes.TXT", b"some text", true)]
+    #[case(".pdf,.txt", "document.pdf", b"%PDF-1.7", true)]
+    #[case(".pdf,.txt", "document.pdf", b"\x89PNG\r\n\x1a\n", false)]
+    #[case(".pdf,.txt", "photo.png", b"\x89PNG\r\n\x1a\n", false)]
+    #[case("*/*", "anything", b"", true)]
+    fn test_media_type_allowed(
+        #[case] accept: &str,
+        #[case] file_name: &str,
+        #[case] head: &[u8],
+        #[case] allowed: bool,
+    ) {
+        assert_eq!(media_type_allowed(accept, file_name, head), allowed);
+    }
+
     #[rstest]
     #[case(".foo")]
     #[case("/.foo")]
@@ -92,4 +245,40 @@ mod tests {
     fn test_sanitize_path_no_hidden_files(#[case] input: &str) {
         assert_eq!(sanitize_path(Path::new(input), false), None);
     }
+
+    #[rstest]
+    #[case(".miniserve-trash")]
+    #[case("/.miniserve-trash/foo")]
+    #[case("foo/.miniserve-trash")]
+    #[case(".miniserve-quota.json")]
+    #[case(".miniserve-versions/foo/1")]
+    #[case(".miniserve-expiry.json")]
+    fn test_sanitize_path_no_internal(#[case] input: &str) {
+        assert_eq!(sanitize_path(Path::new(input), true), None);
+    }
+
+    #[rstest]
+    #[case("foo", false, false, false, true)]
+    #[case(".foo", false, false, false, false)]
+    #[case(".foo", false, true, false, true)]
+    #[case("foo", true, false, false, true)]
+    #[case("foo", true, false, true, false)]
+    #[case(".foo", true, true, true, false)]
+    #[case(".miniserve-trash", false, true, false, false)]
+    #[case(".miniserve-quota.json", false, true, false, false)]
+    #[case(".miniserve-versions", false, true, false, false)]
+    #[case(".miniserve-expiry.json", false, true, false, false)]
+    fn test_visibility(
+        #[case] name: &str,
+        #[case] is_symlink: bool,
+        #[case] show_hidden: bool,
+        #[case] no_symlinks: bool,
+        #[case] expected: bool,
+    ) {
+        let visibility = Visibility {
+            show_hidden,
+            no_symlinks,
+        };
+        assert_eq!(visibility.allows(OsStr::new(name), is_symlink), expected);
+    }
 }
@@ -1,24 +1,35 @@
 #![allow(clippy::format_push_string)]
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::ffi::OsStr;
 use std::io;
 use std::path::{Component, Path};
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use actix_web::{
-    HttpMessage, HttpRequest, HttpResponse, dev::ServiceResponse, http::Uri, web, web::Query,
+    HttpMessage, HttpRequest, HttpResponse,
+    dev::ServiceResponse,
+    http::{Uri, header},
+    web,
+    web::Query,
 };
 use bytesize::ByteSize;
 use clap::ValueEnum;
-use comrak::{Options as ComrakOptions, markdown_to_html};
+use futures::{Stream, StreamExt, stream};
 use percent_encoding::{percent_decode_str, utf8_percent_encode};
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
 
 use self::percent_encode_sets::COMPONENT;
 use crate::archive::ArchiveMethod;
 use crate::auth::CurrentUser;
+use crate::config::MiniserveConfig;
 use crate::errors::{self, RuntimeError};
-use crate::renderer;
+use crate::file_utils::Visibility;
+use crate::player::{self, MediaKind};
+use crate::versions::{self, VERSIONS_ROUTE};
+use crate::{drop_box, expiry, markdown, quota, renderer, search, thumbnail, viewer};
 
 /// "percent-encode sets" as defined by WHATWG specs:
 /// https://url.spec.whatwg.org/#percent-encoded-bytes
@@ -47,6 +58,56 @@ pub struct ListingQueryParameters {
     pub order: Option<SortingOrder>,
     pub raw: Option<bool>,
     download: Option<ArchiveMethod>,
+    format: Option<ListingFormat>,
+    search: Option<String>,
+    play: Option<String>,
+}
+
+/// Formats a directory listing can be returned in
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingFormat {
+    /// Regular HTML page
+    Html,
+
+    /// JSON array of entries
+    Json,
+
+    /// One JSON entry per line, for large directories
+    Ndjson,
+
+    /// M3U8 playlist of the audio and video files, for media players
+    M3u8,
+}
+
+impl ListingFormat {
+    /// Format requested by the `format` query parameter, or else by the `Accept` header
+    fn negotiate(req: &HttpRequest, query_format: Option<Self>) -> Self {
+        query_format
+            .or_else(|| {
+                req.get_header::<header::Accept>()?
+                    .ranked()
+                    .into_iter()
+                    .find_map(|mime| match mime.essence_str() {
+                        "text/html" => Some(Self::Html),
+                        "application/json" => Some(Self::Json),
+                        "application/x-ndjson" => Some(Self::Ndjson),
+                        "application/vnd.apple.mpegurl" | "audio/mpegurl" => Some(Self::M3u8),
+                        _ => None,
+                    })
+            })
+            .unwrap_or(Self::Html)
+    }
+
+    /// Content type of a listing in this format
+    fn content_type(self) -> mime::Mime {
+        match self {
+            Self::Html => mime::TEXT_HTML_UTF_8,
+            Self::Json => mime::APPLICATION_JSON,
+            Self::Ndjson => "application/x-ndjson".parse().unwrap(),
+            Self::M3u8 => "application/vnd.apple.mpegurl".parse().unwrap(),
+        }
+    }
 }
 
 /// Available sorting methods
@@ -81,7 +142,8 @@ pub enum SortingOrder {
 }
 
 /// Possible entry types
-#[derive(PartialEq, Clone, Display, Eq)]
+#[derive(PartialEq, Clone, Display, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum EntryType {
     /// Entry is a directory
@@ -110,10 +172,25 @@ pub struct Entry {
 
     /// Path of symlink pointed to
     pub symlink_info: Option<String>,
+
+    /// URL of the history page of the entry, if it has previous versions
+    pub versions_link: Option<String>,
+
+    /// Date after which the entry is deleted, if it's an expiring upload
+    pub expires_at: Option<SystemTime>,
+
+    /// URL of the entry in the viewer, if it's a text file
+    pub view_link: Option<String>,
+
+    /// URL of the thumbnail of the entry, if it's an image
+    pub thumbnail_link: Option<String>,
+
+    /// URL of the entry in the player, if it's an audio or video file
+    pub play_link: Option<String>,
 }
 
 impl Entry {
-    fn new(
+    pub fn new(
         name: String,
         entry_type: EntryType,
         link: String,
@@ -128,6 +205,11 @@ impl Entry {
             size,
             last_modification_date,
             symlink_info,
+            versions_link: None,
+            expires_at: None,
+            view_link: None,
+            thumbnail_link: None,
+            play_link: None,
         }
     }
 
@@ -142,6 +224,131 @@ impl Entry {
     }
 }
 
+/// Machine-readable representation of an [`Entry`], used by the JSON listings
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    name: &'a str,
+
+    #[serde(rename = "type")]
+    entry_type: &'a EntryType,
+
+    /// Size in bytes, `null` for directories
+    size: Option<u64>,
+
+    /// Last modification date in seconds since the Unix epoch
+    mtime: Option<u64>,
+
+    /// Path the entry points to if it's a symlink and `--show-symlink-info` is enabled
+    symlink_target: Option<&'a str>,
+
+    /// Date after which the entry is deleted in seconds since the Unix epoch, `null` unless it's
+    /// an expiring upload
+    expires: Option<u64>,
+
+    link: &'a str,
+}
+
+impl<'a> From<&'a Entry> for JsonEntry<'a> {
+    fn from(entry: &'a Entry) -> Self {
+        Self {
+            name: &entry.name,
+            entry_type: &entry.entry_type,
+            size: entry.size.map(|size| size.as_u64()),
+            mtime: entry
+                .last_modification_date
+                .and_then(|date| date.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+            symlink_target: entry.symlink_info.as_deref(),
+            expires: entry
+                .expires_at
+                .and_then(|date| date.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+            link: &entry.link,
+        }
+    }
+}
+
+/// Render `entries` as JSON, or as NDJSON with one entry per line
+fn json_listing(entries: &[Entry], format: ListingFormat) -> serde_json::Result<HttpResponse> {
+    let entries = entries.iter().map(JsonEntry::from);
+    if format == ListingFormat::Ndjson {
+        let mut body = String::new();
+        for entry in entries {
+            body.push_str(&serde_json::to_string(&entry)?);
+            body.push('\n');
+        }
+        Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header((header::VARY, "Accept"))
+            .body(body))
+    } else {
+        Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header((header::VARY, "Accept"))
+            .body(serde_json::to_string(&entries.collect::<Vec<_>>())?))
+    }
+}
+
+/// Serialize a stream of entries as a JSON array, or as NDJSON with one entry per line
+fn json_stream(
+    entries: impl Stream<Item = Entry> + 'static,
+    format: ListingFormat,
+) -> impl Stream<Item = serde_json::Result<web::Bytes>> {
+    let is_array = format != ListingFormat::Ndjson;
+    let chunk = |bytes: &'static [u8]| stream::iter(is_array.then(|| Ok(web::Bytes::from(bytes))));
+
+    let entries = entries.enumerate().map(move |(i, entry)| {
+        let mut chunk = if is_array && i > 0 {
+            b",".to_vec()
+        } else {
+            vec![]
+        };
+        serde_json::to_writer(&mut chunk, &JsonEntry::from(&entry))?;
+        if !is_array {
+            chunk.push(b'\n');
+        }
+        Ok(web::Bytes::from(chunk))
+    });
+
+    chunk(b"[").chain(entries).chain(chunk(b"]"))
+}
+
+/// Sort `entries` as requested by `query_params`, or else by the configured defaults
+fn sort_entries(
+    entries: &mut [Entry],
+    query_params: &ListingQueryParameters,
+    conf: &MiniserveConfig,
+) {
+    match query_params.sort.unwrap_or(conf.default_sorting_method) {
+        SortingMethod::Name => entries.sort_by(|e1, e2| {
+            alphanumeric_sort::compare_str(e1.name.to_lowercase(), e2.name.to_lowercase())
+        }),
+        SortingMethod::Size => entries.sort_by(|e1, e2| {
+            // If we can't get the size of the entry (directory for instance)
+            // let's consider it's 0b
+            e2.size
+                .unwrap_or_else(|| ByteSize::b(0))
+                .cmp(&e1.size.unwrap_or_else(|| ByteSize::b(0)))
+        }),
+        SortingMethod::Date => entries.sort_by(|e1, e2| {
+            // If, for some reason, we can't get the last modification date of an entry
+            // let's consider it was modified on UNIX_EPOCH (01/01/19270 00:00:00)
+            e2.last_modification_date
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+                .cmp(&e1.last_modification_date.unwrap_or(SystemTime::UNIX_EPOCH))
+        }),
+    };
+
+    if let SortingOrder::Asc = query_params.order.unwrap_or(conf.default_sorting_order) {
+        entries.reverse()
+    }
+
+    // List directories first
+    if conf.dirs_first {
+        entries.sort_by_key(|e| !e.is_dir());
+    }
+}
+
 /// One entry in the path to the listed directory
 pub struct Breadcrumb {
     /// Name of directory
@@ -158,11 +365,11 @@ impl Breadcrumb {
 }
 
 pub async fn file_handler(req: HttpRequest) -> actix_web::Result<actix_files::NamedFile> {
-    let path = &req
-        .app_data::<web::Data<crate::MiniserveConfig>>()
+    let conf = req
+        .app_data::<web::Data<crate::SharedConfig>>()
         .unwrap()
-        .path;
-    actix_files::NamedFile::open(path).map_err(Into::into)
+        .load();
+    actix_files::NamedFile::open(&conf.path).map_err(Into::into)
 }
 
 /// List a directory and renders a HTML file accordingly
@@ -174,7 +381,10 @@ pub fn directory_listing(
     let extensions = req.extensions();
     let current_user: Option<&CurrentUser> = extensions.get::<CurrentUser>();
 
-    let conf = req.app_data::<web::Data<crate::MiniserveConfig>>().unwrap();
+    let conf = req
+        .app_data::<web::Data<crate::SharedConfig>>()
+        .unwrap()
+        .load();
     if conf.disable_indexing {
         return Ok(ServiceResponse::new(
             req.clone(),
@@ -207,6 +417,10 @@ pub fn directory_listing(
     .display()
     .to_string();
 
+    // Only the scripts of the listing page, which carry this nonce, are allowed to run
+    let nonce = nanoid::nanoid!();
+    let content_security_policy = content_security_policy(&nonce);
+
     let breadcrumbs = {
         let title = conf
             .title
@@ -251,18 +465,115 @@ pub fn directory_listing(
     let mut readme: Option<(String, String)> = None;
     let readme_rx: Regex = Regex::new("^readme([.](md|txt))?$").unwrap();
 
+    let visibility = Visibility {
+        show_hidden: conf.show_hidden,
+        no_symlinks: conf.no_symlinks,
+    };
+
+    if let Some(query) = query_params.search.clone() {
+        // Search results would expose the contents of a drop box
+        if !conf.search_enabled || conf.drop_box {
+            return Ok(ServiceResponse::new(
+                req.clone(),
+                HttpResponse::Forbidden()
+                    .content_type(mime::TEXT_PLAIN_UTF_8)
+                    .body("Search is disabled."),
+            ));
+        }
+        let pattern = match search::Pattern::parse(&query) {
+            Ok(pattern) => pattern,
+            Err(err) => return Ok(ServiceResponse::from_err(err, req.clone())),
+        };
+        let limits = search::SearchLimits {
+            max_results: conf.search_max_results,
+            timeout: conf.search_timeout,
+        };
+        let results = search::search(
+            dir.path.clone(),
+            serve_path.to_owned(),
+            pattern,
+            visibility,
+            conf.show_symlink_info,
+            limits,
+        );
+
+        // JSON results are streamed as they are found, in the order they are found in
+        if let format @ (ListingFormat::Json | ListingFormat::Ndjson) =
+            ListingFormat::negotiate(req, query_params.format)
+        {
+            let body = json_stream(results, format);
+            return Ok(ServiceResponse::new(
+                req.clone(),
+                HttpResponse::Ok()
+                    .content_type(format.content_type())
+                    .insert_header((header::VARY, "Accept"))
+                    .body(actix_web::body::BodyStream::new(body)),
+            ));
+        }
+
+        // The HTML page needs all the results to sort them, so it's rendered once the search
+        // is done
+        let started = Instant::now();
+        let current_user = current_user.cloned();
+        let page = async move {
+            let mut entries = results.collect::<Vec<_>>().await;
+            let summary = search::SearchSummary {
+                query,
+                truncated: entries.len() >= limits.max_results
+                    || started.elapsed() >= limits.timeout,
+            };
+            sort_entries(&mut entries, &query_params, &conf);
+            let page = renderer::page(
+                entries,
+                None,
+                &abs_uri,
+                is_root,
+                query_params,
+                &breadcrumbs,
+                &encoded_dir,
+                &conf,
+                current_user.as_ref(),
+                Some(&summary),
+                None,
+                &nonce,
+            );
+            Ok::<_, Infallible>(web::Bytes::from(page.into_string()))
+        };
+        return Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type(mime::TEXT_HTML_UTF_8)
+                .insert_header((header::VARY, "Accept"))
+                .insert_header((header::CONTENT_SECURITY_POLICY, content_security_policy))
+                .body(actix_web::body::BodyStream::new(stream::once(page))),
+        ));
+    }
+
+    let relative_dir = dir.path.strip_prefix(&dir.base).unwrap_or(Path::new(""));
+    // Drop boxes only list what was uploaded during the current session
+    let session = drop_box::session(req);
+    let listed = |name: &OsStr| {
+        !conf.drop_box || drop_box::uploaded_during(session.as_ref(), &relative_dir.join(name))
+    };
+    let versioned = if conf.drop_box {
+        HashSet::new()
+    } else {
+        versions::versioned_names(&conf.path, relative_dir)
+    };
+    let expiry_dates = expiry::expiry_dates(&conf.path, relative_dir);
+
     for entry in dir.path.read_dir()? {
-        if dir.is_visible(&entry) || conf.show_hidden {
-            let entry = entry?;
+        let entry = entry?;
+        let (is_symlink, metadata) = match entry.metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // for symlinks, get the metadata of the original file
+                (true, std::fs::metadata(entry.path()))
+            }
+            res => (false, res),
+        };
+        if visibility.allows(&entry.file_name(), is_symlink) && listed(&entry.file_name()) {
             // show file url as relative to static path
             let file_name = entry.file_name().to_string_lossy().to_string();
-            let (is_symlink, metadata) = match entry.metadata() {
-                Ok(metadata) if metadata.file_type().is_symlink() => {
-                    // for symlinks, get the metadata of the original file
-                    (true, std::fs::metadata(entry.path()))
-                }
-                res => (false, res),
-            };
             let symlink_dest = (is_symlink && conf.show_symlink_info)
                 .then(|| entry.path())
                 .and_then(|path| std::fs::read_link(path).ok())
@@ -274,9 +585,6 @@ pub fn directory_listing(
 
             // if file is a directory, add '/' to the end of the name
             if let Ok(metadata) = metadata {
-                if conf.no_symlinks && is_symlink {
-                    continue;
-                }
                 let last_modification_date = metadata.modified().ok();
 
                 if metadata.is_dir() {
@@ -289,6 +597,21 @@ pub fn directory_listing(
                         symlink_dest,
                     ));
                 } else if metadata.is_file() {
+                    let versions_link = versioned.contains(&entry.file_name()).then(|| {
+                        let path = file_url
+                            .strip_prefix(&conf.route_prefix)
+                            .unwrap_or(&file_url);
+                        format!("{}{VERSIONS_ROUTE}?path={path}", conf.route_prefix)
+                    });
+                    // Drop boxes don't expose their contents in any way
+                    let view_link = (!conf.drop_box
+                        && viewer::viewable(&file_name, metadata.len()))
+                    .then(|| format!("{file_url}?view=1"));
+                    let thumbnail_link = (!conf.drop_box
+                        && thumbnail::has_thumbnail(&file_name, metadata.len()))
+                    .then(|| format!("{file_url}?thumbnail=1"));
+                    let play_link = (!conf.drop_box && MediaKind::of(&file_name).is_some())
+                        .then(|| format!("{file_url}?play=1"));
                     let file_link = match &conf.file_external_url {
                         Some(external_url) => {
                             // Construct the full relative path including subdirectories
@@ -317,30 +640,29 @@ pub fn directory_listing(
                         }
                         None => file_url,
                     };
-                    entries.push(Entry::new(
+                    let mut file_entry = Entry::new(
                         file_name.clone(),
                         EntryType::File,
                         file_link,
                         Some(ByteSize::b(metadata.len())),
                         last_modification_date,
                         symlink_dest,
-                    ));
+                    );
+                    file_entry.versions_link = versions_link;
+                    file_entry.expires_at = expiry_dates.get(&entry.file_name()).copied();
+                    file_entry.view_link = view_link;
+                    file_entry.thumbnail_link = thumbnail_link;
+                    file_entry.play_link = play_link;
+                    entries.push(file_entry);
                     if conf.readme && readme_rx.is_match(&file_name.to_lowercase()) {
                         let ext = file_name.split('.').next_back().unwrap().to_lowercase();
+                        let text = std::fs::read_to_string(entry.path())?;
                         readme = Some((
                             file_name.to_string(),
                             if ext == "md" {
-                                let mut options = ComrakOptions::default();
-
-                                // Enable some GFM extensions
-                                options.extension.strikethrough = true;
-                                options.extension.table = true;
-                                options.extension.autolink = true;
-                                options.extension.tasklist = true;
-
-                                markdown_to_html(&std::fs::read_to_string(entry.path())?, &options)
+                                markdown::render_readme(&text, &conf.route_prefix)
                             } else {
-                                format!("<pre>{}</pre>", &std::fs::read_to_string(entry.path())?)
+                                maud::html! { pre { (text) } }.into_string()
                             },
                         ));
                     }
@@ -351,34 +673,7 @@ pub fn directory_listing(
         }
     }
 
-    match query_params.sort.unwrap_or(conf.default_sorting_method) {
-        SortingMethod::Name => entries.sort_by(|e1, e2| {
-            alphanumeric_sort::compare_str(e1.name.to_lowercase(), e2.name.to_lowercase())
-        }),
-        SortingMethod::Size => entries.sort_by(|e1, e2| {
-            // If we can't get the size of the entry (directory for instance)
-            // let's consider it's 0b
-            e2.size
-                .unwrap_or_else(|| ByteSize::b(0))
-                .cmp(&e1.size.unwrap_or_else(|| ByteSize::b(0)))
-        }),
-        SortingMethod::Date => entries.sort_by(|e1, e2| {
-            // If, for some reason, we can't get the last modification date of an entry
-            // let's consider it was modified on UNIX_EPOCH (01/01/19270 00:00:00)
-            e2.last_modification_date
-                .unwrap_or(SystemTime::UNIX_EPOCH)
-                .cmp(&e1.last_modification_date.unwrap_or(SystemTime::UNIX_EPOCH))
-        }),
-    };
-
-    if let SortingOrder::Asc = query_params.order.unwrap_or(conf.default_sorting_order) {
-        entries.reverse()
-    }
-
-    // List directories first
-    if conf.dirs_first {
-        entries.sort_by_key(|e| !e.is_dir());
-    }
+    sort_entries(&mut entries, &query_params, &conf);
 
     if let Some(archive_method) = query_params.download {
         if !archive_method.is_enabled(conf.tar_enabled, conf.tar_gz_enabled, conf.zip_enabled) {
@@ -409,9 +704,8 @@ pub fn directory_listing(
 
         // Start the actual archive creation in a separate thread.
         let dir = dir.path.to_path_buf();
-        let skip_symlinks = conf.no_symlinks;
         std::thread::spawn(move || {
-            if let Err(err) = archive_method.create_archive(dir, skip_symlinks, pipe) {
+            if let Err(err) = archive_method.create_archive(dir, visibility, pipe) {
                 log::error!("Error during archive creation: {err:?}");
             }
         });
@@ -427,25 +721,127 @@ pub fn directory_listing(
                 ))
                 .body(actix_web::body::BodyStream::new(rx)),
         ))
-    } else {
+    } else if player::play_requested(query_params.play.as_deref()) {
+        let title = percent_decode_str(&encoded_dir).decode_utf8_lossy();
+        let playlist = player::playlist(&entries);
+        let playlist_link = m3u8_link(&query_params);
         Ok(ServiceResponse::new(
             req.clone(),
             HttpResponse::Ok().content_type(mime::TEXT_HTML_UTF_8).body(
-                renderer::page(
-                    entries,
-                    readme,
-                    &abs_uri,
-                    is_root,
-                    query_params,
-                    &breadcrumbs,
-                    &encoded_dir,
-                    conf,
-                    current_user,
-                )
-                .into_string(),
+                renderer::player_page(&title, "./", &playlist, None, Some(&playlist_link), &conf)
+                    .into_string(),
             ),
         ))
+    } else if ListingFormat::negotiate(req, query_params.format) == ListingFormat::M3u8 {
+        let connection_info = req.connection_info();
+        let base_url = format!("{}://{}", connection_info.scheme(), connection_info.host());
+        let file_name = format!(
+            "{}.m3u8",
+            dir.path
+                .file_name()
+                .map_or("playlist".into(), |name| name.to_string_lossy())
+        );
+        Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type(ListingFormat::M3u8.content_type())
+                .insert_header((header::VARY, "Accept"))
+                .append_header((
+                    "Content-Disposition",
+                    format!("attachment; filename={file_name:?}"),
+                ))
+                .body(player::m3u8(&player::playlist(&entries), &base_url)),
+        ))
+    } else if let format @ (ListingFormat::Json | ListingFormat::Ndjson) =
+        ListingFormat::negotiate(req, query_params.format)
+    {
+        let response = json_listing(&entries, format)
+            .map_err(|e| io::Error::other(format!("Failed to serialize the listing: {e}")))?;
+        Ok(ServiceResponse::new(req.clone(), response))
+    } else {
+        if !conf.upload_allowed(relative_dir) {
+            return Ok(ServiceResponse::new(
+                req.clone(),
+                HttpResponse::Ok()
+                    .content_type(mime::TEXT_HTML_UTF_8)
+                    .insert_header((header::VARY, "Accept"))
+                    .insert_header((header::CONTENT_SECURITY_POLICY, content_security_policy))
+                    .body(
+                        renderer::page(
+                            entries,
+                            readme,
+                            &abs_uri,
+                            is_root,
+                            query_params,
+                            &breadcrumbs,
+                            &encoded_dir,
+                            &conf,
+                            current_user,
+                            None,
+                            None,
+                            &nonce,
+                        )
+                        .into_string(),
+                    ),
+            ));
+        }
+
+        // Uploads show the space left for them, which may take a while to compute
+        let relative_dir = relative_dir.to_path_buf();
+        let current_user = current_user.cloned();
+        let page = async move {
+            let user = current_user.as_ref().map(|user| user.name.as_str());
+            let space = quota::space(&conf, &relative_dir, user)
+                .await
+                .map_err(|e| log::warn!("Failed to compute the space left for uploads: {e}"))
+                .ok();
+            let page = renderer::page(
+                entries,
+                readme,
+                &abs_uri,
+                is_root,
+                query_params,
+                &breadcrumbs,
+                &encoded_dir,
+                &conf,
+                current_user.as_ref(),
+                None,
+                space.as_ref(),
+                &nonce,
+            );
+            Ok::<_, Infallible>(web::Bytes::from(page.into_string()))
+        };
+        Ok(ServiceResponse::new(
+            req.clone(),
+            HttpResponse::Ok()
+                .content_type(mime::TEXT_HTML_UTF_8)
+                .insert_header((header::VARY, "Accept"))
+                .insert_header((header::CONTENT_SECURITY_POLICY, content_security_policy))
+                .body(actix_web::body::BodyStream::new(stream::once(page))),
+        ))
+    }
+}
+
+/// Content-Security-Policy of the listing pages, which only run their own scripts carrying `nonce`.
+///
+/// Images may come from anywhere, like the badges of READMEs.
+fn content_security_policy(nonce: &str) -> String {
+    format!(
+        "default-src 'self'; script-src 'nonce-{nonce}'; style-src 'self' 'unsafe-inline'; \
+         img-src * data:; object-src 'none'; base-uri 'none'; form-action 'self'"
+    )
+}
+
+/// Link to the M3U8 playlist of the listed directory, in the order of the listing
+fn m3u8_link(query_params: &ListingQueryParameters) -> String {
+    let mut link = String::from("?format=m3u8");
+    if let Some(sort) = query_params.sort {
+        link.push_str(&format!("&sort={sort}"));
+    }
+    if let Some(order) = query_params.order {
+        link.push_str(&format!("&order={order}"));
     }
+    link
 }
 
 pub fn extract_query_parameters(req: &HttpRequest) -> ListingQueryParameters {
@@ -4,10 +4,11 @@ use std::thread;
 use std::time::Duration;
 
 use actix_files::NamedFile;
-use actix_web::middleware::from_fn;
+use actix_web::middleware::{Next, from_fn};
 use actix_web::{
-    App, HttpRequest, HttpResponse, Responder,
-    dev::{ServiceRequest, ServiceResponse, fn_service},
+    App, Either, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder,
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse, fn_service},
     guard,
     http::{Method, header::ContentType},
     middleware, web,
@@ -15,33 +16,51 @@ use actix_web::{
 use actix_web_httpauth::middleware::HttpAuthentication;
 use anyhow::Result;
 use bytesize::ByteSize;
-use clap::{CommandFactory, Parser, crate_version};
+use clap::{ArgMatches, CommandFactory, crate_version};
 use colored::*;
 use dav_server::{
-    DavHandler, DavMethodSet,
+    DavConfig, DavHandler, DavMethodSet,
     actix::{DavRequest, DavResponse},
+    fakels::FakeLs,
 };
 use fast_qr::QRBuilder;
 use log::{error, info, trace, warn};
 use percent_encoding::percent_decode_str;
 use serde::Deserialize;
 
+mod active_content;
 mod archive;
 mod args;
 mod auth;
 mod config;
+mod config_file;
 mod consts;
+mod drop_box;
 mod errors;
+mod expiry;
+mod extract;
 mod file_op;
 mod file_utils;
 mod listing;
+mod markdown;
+mod paste;
 mod pipe;
+mod player;
+mod quota;
+mod reload;
 mod renderer;
+mod search;
 mod tailscale;
+mod thumbnail;
+mod trash;
+mod tus;
+mod versions;
+mod viewer;
 mod webdav_fs;
 
 use crate::args::LogColor;
-use crate::config::MiniserveConfig;
+use crate::auth::CurrentUser;
+use crate::config::{MiniserveConfig, SharedConfig};
 use crate::errors::{RuntimeError, StartupError};
 use crate::file_op::recursive_dir_size;
 use crate::webdav_fs::RestrictedFs;
@@ -49,7 +68,7 @@ use crate::webdav_fs::RestrictedFs;
 static STYLESHEET: &str = grass::include!("data/style.scss");
 
 fn main() -> Result<()> {
-    let args = args::CliArgs::parse();
+    let (args, matches) = config_file::parse_args()?;
 
     if let Some(shell) = args.print_completions {
         let mut clap_app = args::CliArgs::command();
@@ -65,9 +84,15 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    let print_config = args.print_config;
     let miniserve_config = MiniserveConfig::try_from_args(args)?;
 
-    run(miniserve_config).inspect_err(|e| {
+    if print_config {
+        print!("{}", config_file::render_config(&matches)?);
+        return Ok(());
+    }
+
+    run(miniserve_config, matches).inspect_err(|e| {
         errors::log_error_chain(e.to_string());
     })?;
 
@@ -75,7 +100,10 @@ fn main() -> Result<()> {
 }
 
 #[actix_web::main(miniserve)]
-async fn run(miniserve_config: MiniserveConfig) -> Result<(), StartupError> {
+async fn run(
+    miniserve_config: MiniserveConfig,
+    startup_matches: ArgMatches,
+) -> Result<(), StartupError> {
     let log_level = if miniserve_config.verbose {
         simplelog::LevelFilter::Info
     } else {
@@ -130,6 +158,11 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), StartupError> {
     }
 
     let inside_config = miniserve_config.clone();
+    let shared_config = web::Data::new(SharedConfig::new(miniserve_config.clone()));
+    #[cfg(unix)]
+    let reloadable_config = shared_config.clone().into_inner();
+    let trash_config = shared_config.clone().into_inner();
+    let expiry_config = shared_config.clone().into_inner();
 
     let canon_path = miniserve_config
         .path
@@ -265,8 +298,8 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), StartupError> {
 
     let srv = actix_web::HttpServer::new(move || {
         App::new()
-            .wrap(configure_header(&inside_config.clone()))
-            .app_data(web::Data::new(inside_config.clone()))
+            .wrap(from_fn(add_custom_headers))
+            .app_data(shared_config.clone())
             .app_data(stylesheet.clone())
             .wrap(from_fn(errors::error_page_middleware))
             .wrap(middleware::Logger::default())
@@ -280,6 +313,7 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), StartupError> {
             .route(&inside_config.css_route, web::get().to(css))
             .service(
                 web::scope(&inside_config.route_prefix)
+                    .wrap(from_fn(drop_box::session_middleware))
                     .wrap(middleware::Condition::new(
                         !inside_config.auth.is_empty(),
                         actix_web::middleware::Compat::new(HttpAuthentication::basic(
@@ -309,6 +343,38 @@ async fn run(miniserve_config: MiniserveConfig) -> Result<(), StartupError> {
 
     let srv = srv.shutdown_timeout(0).run();
 
+    #[cfg(unix)]
+    actix_web::rt::spawn(async move {
+        if let Err(e) = reload::reload_on_sighup(reloadable_config, startup_matches).await {
+            error!("Failed to listen for SIGHUP, configuration reloading is disabled: {e}");
+        }
+    });
+    #[cfg(not(unix))]
+    drop(startup_matches);
+
+    if miniserve_config.trash_enabled {
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+                if let Err(e) = trash::purge_expired(&trash_config.load()).await {
+                    error!("Failed to purge the trash: {e}");
+                }
+            }
+        });
+    }
+
+    // Files may be uploaded with an expiry even if `--upload-expiry` isn't given
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(expiry::PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = expiry::purge_expired(&expiry_config.load()).await {
+                error!("Failed to delete the expired uploads: {e}");
+            }
+        }
+    });
+
     if !miniserve_config.quiet {
         println!("Bound to {}", display_sockets.join(", "));
         println!("Serving path {}", path_string.yellow().bold());
@@ -365,11 +431,22 @@ fn create_tcp_listener(addr: SocketAddr) -> io::Result<TcpListener> {
     Ok(TcpListener::from(socket))
 }
 
-fn configure_header(conf: &MiniserveConfig) -> middleware::DefaultHeaders {
-    conf.header.iter().flatten().fold(
-        middleware::DefaultHeaders::new(),
-        |headers, (header_name, header_value)| headers.add((header_name, header_value)),
-    )
+/// Adds the custom headers of the current configuration to responses that don't set them already
+async fn add_custom_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let mut res = next.call(req).await?;
+
+    for (header_name, header_value) in conf.header.iter().flatten() {
+        if !res.headers().contains_key(header_name) {
+            res.headers_mut()
+                .insert(header_name.clone(), header_value.clone());
+        }
+    }
+
+    Ok(res)
 }
 
 /// Configures the Actix application
@@ -405,15 +482,10 @@ fn configure_app(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
             files = files.default_handler(fn_service(|req: ServiceRequest| async {
                 let (req, _) = req.into_parts();
                 let conf = req
-                    .app_data::<web::Data<MiniserveConfig>>()
-                    .expect("Could not get miniserve config");
-                let mut path_base = req.path()[1..].to_string();
-                if path_base.ends_with('/') {
-                    path_base.pop();
-                }
-                if !path_base.ends_with("html") {
-                    path_base = format!("{path_base}.html");
-                }
+                    .app_data::<web::Data<SharedConfig>>()
+                    .expect("Could not get miniserve config")
+                    .load();
+                let path_base = file_utils::pretty_url_path(req.path());
                 let file = NamedFile::open_async(conf.path.join(path_base)).await?;
                 let res = file.into_response(&req);
                 Ok(ServiceResponse::new(req, res))
@@ -426,12 +498,27 @@ fn configure_app(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
 
         let base_path = conf.path.clone();
         let no_symlinks = conf.no_symlinks;
+        let drop_box = conf.drop_box;
         files
             .show_files_listing()
             .files_listing_renderer(listing::directory_listing)
             .prefer_utf8(true)
             .redirect_to_slash_directory()
             .path_filter(move |path, _| {
+                // Internal entries are never served, see `file_utils::is_internal`
+                if path
+                    .components()
+                    .next()
+                    .is_some_and(|root| file_utils::is_internal(root.as_os_str()))
+                {
+                    return false;
+                }
+
+                // Drop boxes only serve listings, see `drop_box`
+                if drop_box && !base_path.join(path).is_dir() {
+                    return false;
+                }
+
                 if !no_symlinks {
                     // no_symlinks not enabled => nothing to filter
                     return true;
@@ -459,41 +546,219 @@ fn configure_app(app: &mut web::ServiceConfig, conf: &MiniserveConfig) {
         if conf.file_upload {
             // Allow file upload
             app.service(web::resource("/upload").route(web::post().to(file_op::upload_file)));
+            // Allow pastes from the command line
+            app.service(
+                web::resource(paste::PASTE_ROUTE).route(web::post().to(paste::create_paste)),
+            );
+            // Allow resumable uploads
+            app.service(
+                web::scope(tus::TUS_ROUTE)
+                    .service(
+                        web::resource("")
+                            .route(web::post().to(tus::create))
+                            .route(web::method(Method::OPTIONS).to(tus::options)),
+                    )
+                    .service(
+                        web::resource("/{id}")
+                            .route(web::head().to(tus::head))
+                            .route(web::patch().to(tus::patch))
+                            .route(web::delete().to(tus::delete))
+                            .route(web::method(Method::OPTIONS).to(tus::options)),
+                    ),
+            );
         }
         if conf.rm_enabled {
             // Allow file and directory deletion
             app.service(web::resource("/rm").route(web::post().to(file_op::rm_file)));
+            if conf.trash_enabled {
+                app.service(
+                    web::scope(trash::TRASH_ROUTE)
+                        .route("", web::get().to(trash::trash_page))
+                        .route("/restore", web::post().to(trash::restore_entry))
+                        .route("/purge", web::post().to(trash::purge_entry)),
+                );
+            }
         }
-        // Handle directories
-        app.service(dir_service());
+        // Render text files with syntax highlighting when asked for
+        app.service(
+            web::resource("/{tail}*")
+                .guard(guard::Get())
+                .guard(guard::fn_guard(viewer::is_view_request))
+                .to(viewer::view_file),
+        );
+        // Render Markdown files as pages, unless their source is asked for
+        app.service(
+            web::resource("/{tail}*")
+                .guard(guard::Get())
+                .guard(guard::fn_guard(markdown::is_markdown_request))
+                .to(markdown::render_file),
+        );
+        // Play audio and video files when asked for
+        app.service(
+            web::resource("/{tail}*")
+                .guard(guard::Get())
+                .guard(guard::fn_guard(player::is_play_request))
+                .to(player::play_file),
+        );
+        // Make thumbnails of images for the gallery view
+        app.service(
+            web::resource("/{tail}*")
+                .guard(guard::Get())
+                .guard(guard::fn_guard(thumbnail::is_thumbnail_request))
+                .to(thumbnail::thumbnail),
+        );
+        // Allow browsing the versions of replaced files, which may be left from earlier runs
+        app.service(
+            web::scope(versions::VERSIONS_ROUTE)
+                .route("", web::get().to(versions::history_page))
+                .route("/download", web::get().to(versions::download_version))
+                .route("/restore", web::post().to(versions::restore_version)),
+        );
+        if conf.file_upload {
+            // Allow copies, and moves if deletion is allowed too. Drop boxes don't expose their
+            // contents in any way.
+            if !conf.drop_box {
+                app.service(web::resource("/cp").route(web::post().to(file_op::cp_file)));
+            }
+            if conf.rm_enabled {
+                app.service(web::resource("/mv").route(web::post().to(file_op::mv_file)));
+            }
+
+            // Allow uploads of raw request bodies to the path of the file. PUT requests are left
+            // to WebDAV when it's enabled.
+            let mut methods = guard::Any(guard::Post());
+            if !conf.webdav_enabled {
+                methods = methods.or(guard::Put());
+            }
+            app.service(
+                web::resource("/{tail}*")
+                    .guard(methods)
+                    .to(file_op::upload_raw),
+            );
+        }
+        // Handle directories, protecting the visitors from the active content of uploads
+        app.service(
+            web::scope("")
+                .guard(guard::Any(guard::Get()).or(guard::Head()))
+                .wrap(from_fn(active_content::protect_uploads))
+                .service(dir_service()),
+        );
     }
 
     if conf.webdav_enabled {
-        let fs = RestrictedFs::new(&conf.path, conf.show_hidden, conf.no_symlinks);
-
-        let dav_server = DavHandler::builder()
-            .filesystem(fs)
-            .methods(DavMethodSet::WEBDAV_RO)
+        // The filesystem is given with each request, see `dav_handler`
+        let mut dav_server = DavHandler::<()>::builder()
             .hide_symlinks(false) // we handle filtering symlinks ourselves in RestrictedFs
-            .strip_prefix(conf.route_prefix.to_owned())
-            .build_handler();
+            .strip_prefix(conf.route_prefix.to_owned());
+        let mut methods = guard::Any(guard::Options())
+            .or(guard::Method(Method::from_bytes(b"PROPFIND").unwrap()));
+        if conf.webdav_write {
+            // Clients like Finder and Windows Explorer refuse to write without locking support
+            dav_server = dav_server
+                .methods(DavMethodSet::WEBDAV_RW)
+                .locksystem(FakeLs::new());
+            for method in [
+                "PUT",
+                "MKCOL",
+                "DELETE",
+                "MOVE",
+                "COPY",
+                "PROPPATCH",
+                "LOCK",
+                "UNLOCK",
+            ] {
+                methods = methods.or(guard::Method(
+                    Method::from_bytes(method.as_bytes()).unwrap(),
+                ));
+            }
+        } else {
+            dav_server = dav_server.methods(DavMethodSet::WEBDAV_RO);
+        }
 
-        app.app_data(web::Data::new(dav_server.clone()));
+        app.app_data(web::Data::new(dav_server.build_handler()));
 
         app.service(
             // actix requires tail segment to be named, even if unused
-            web::resource("/{tail}*")
-                .guard(
-                    guard::Any(guard::Options())
-                        .or(guard::Method(Method::from_bytes(b"PROPFIND").unwrap())),
-                )
-                .to(dav_handler),
+            web::resource("/{tail}*").guard(methods).to(dav_handler),
         );
     }
 }
 
-async fn dav_handler(req: DavRequest, davhandler: web::Data<DavHandler>) -> DavResponse {
-    davhandler.handle(req.request).await.into()
+async fn dav_handler(
+    req: HttpRequest,
+    payload: web::Payload,
+    davhandler: web::Data<DavHandler>,
+) -> Result<Either<DavResponse, HttpResponse>, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    // The body of PUT requests is saved like raw uploads rather than by dav_server
+    let mut payload = payload.into_inner();
+    let mut dav_req = match req.method() {
+        &Method::PUT => DavRequest::from_request(&req, &mut Payload::None).await,
+        _ => DavRequest::from_request(&req, &mut payload).await,
+    }
+    .map_err(|e| RuntimeError::InvalidHttpRequestError(e.to_string()))?;
+    let created = webdav_fs::authorize_write(&req, &mut dav_req, &conf)?;
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    let user_name = current_user.as_ref().map(|user| user.name.clone());
+    let user = user_name.as_deref();
+
+    if let Some(created) = &created
+        && req.method() == Method::PUT
+    {
+        let res = webdav_fs::put_file(&req, &conf, created, user, &mut payload).await?;
+        return Ok(Either::Right(res));
+    }
+
+    // Deleted entries are moved to the trash as a whole, like with the rm form
+    if conf.trash_enabled && req.method() == Method::DELETE {
+        let res = webdav_fs::delete_to_trash(&req, conf.clone(), user_name).await?;
+        quota::forget_usage();
+        return Ok(Either::Right(res));
+    }
+
+    // Copies count against the upload quotas, like other uploads, and so do moves into another
+    // quota, like with the cp and mv forms
+    let reservation = match &created {
+        Some(created) if matches!(req.method().as_str(), "COPY" | "MOVE") => {
+            let source = webdav_fs::relative_path(req.uri().path(), &conf)?;
+            let destination = created.strip_prefix(&conf.path).unwrap_or(created);
+            let copy = req.method().as_str() == "COPY";
+            quota::reserve_transfer(&conf, &source, destination, copy, user).await?
+        }
+        _ => None,
+    };
+
+    // Replaced entries are moved to the trash as a whole too, or kept as versions
+    if let Some(created) = &created {
+        webdav_fs::trash_replaced(&req, &conf, created, user).await?;
+        versions::keep(&conf, created).await?;
+    }
+
+    let fs = DavConfig::new().filesystem(RestrictedFs::new(conf.clone(), user_name.clone()));
+    let res = davhandler.handle_with(fs, dav_req.request).await;
+
+    if let Some(created) = &created
+        && req.method().as_str() == "COPY"
+        && reservation.is_some()
+        && res.status().is_success()
+    {
+        quota::record_copy(&conf, created, user).await;
+    }
+    drop(reservation);
+    if matches!(req.method().as_str(), "DELETE" | "MOVE" | "COPY") && res.status().is_success() {
+        quota::forget_usage();
+    }
+
+    #[cfg(unix)]
+    if let Some(created) = created
+        && res.status().is_success()
+    {
+        file_op::set_created_permissions(&created, conf.upload_chmod).await?;
+    }
+    #[cfg(not(unix))]
+    drop(created);
+
+    Ok(Either::Left(res.into()))
 }
 
 async fn error_404(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
@@ -514,8 +779,9 @@ enum ApiCommand {
 /// least I hope so.
 async fn api(
     command: web::Json<ApiCommand>,
-    config: web::Data<MiniserveConfig>,
+    config: web::Data<SharedConfig>,
 ) -> Result<impl Responder, RuntimeError> {
+    let config = config.load();
     match command.into_inner() {
         ApiCommand::DirSize(path) => {
             if config.directory_size {
@@ -0,0 +1,256 @@
+//! Markdown rendering, for the READMEs of the listing and for `--render-markdown`
+//!
+//! Markdown files are rendered as pages of their own at their plain path, with a table of contents
+//! made of their headings. Their source is still sent as usual with `?raw=true`.
+//!
+//! The rendered HTML is cleaned with [ammonia], which only keeps the tags and attributes of an
+//! allowlist, since the documents may have been uploaded by anyone.
+
+use std::sync::LazyLock;
+
+use actix_web::{HttpRequest, HttpResponse, guard::GuardContext, http::header, web};
+use comrak::nodes::NodeValue;
+use comrak::{Anchorizer, Arena, Options as ComrakOptions, format_html, parse_document};
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::config::SharedConfig;
+use crate::errors::RuntimeError;
+use crate::file_utils::requested_path;
+use crate::renderer;
+use crate::viewer::MAX_VIEW_SIZE;
+
+/// Prefix of the ids of the rendered documents, so that they can't clash with the ones of the page
+const ID_PREFIX: &str = "user-content-";
+
+/// Sanitizer of the rendered HTML
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut sanitizer = ammonia::Builder::default();
+    sanitizer
+        .id_prefix(Some(ID_PREFIX))
+        // Anchors of the headings
+        .add_tag_attributes("a", ["aria-hidden", "id"])
+        .add_allowed_classes("a", ["anchor"])
+        // Checkboxes of the task lists
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["checked", "disabled"])
+        .add_tag_attribute_values("input", "type", ["checkbox"]);
+    sanitizer
+});
+
+/// Query parameters of the Markdown pages
+#[derive(Deserialize, Default)]
+struct MarkdownQueryParameters {
+    raw: Option<String>,
+}
+
+/// A heading of a Markdown document, listed in its table of contents
+pub struct Heading {
+    /// Level of the heading, from 1 to 6
+    pub level: u8,
+
+    /// Text of the heading
+    pub text: String,
+
+    /// Id of the heading in the rendered page
+    pub anchor: String,
+}
+
+/// Options of the Markdown rendering, with some GFM extensions
+fn options() -> ComrakOptions<'static> {
+    let mut options = ComrakOptions::default();
+    options.extension.strikethrough = true;
+    options.extension.table = true;
+    options.extension.autolink = true;
+    options.extension.tasklist = true;
+    options
+}
+
+/// Render the Markdown `text` to sanitized HTML, along with its headings if `with_headings`.
+///
+/// Links and images to absolute paths are made to point below `route_prefix`, like the links of
+/// the listing, while relative ones already resolve against the page.
+fn render(text: &str, route_prefix: &str, with_headings: bool) -> (String, Vec<Heading>) {
+    let mut options = options();
+    if with_headings {
+        options.extension.header_ids = Some(ID_PREFIX.to_string());
+    }
+    let arena = Arena::new();
+    let root = parse_document(&arena, text, &options);
+
+    // Anchors are made in the order of the document, just like comrak does for the ids
+    let mut anchorizer = Anchorizer::new();
+    let mut headings = Vec::new();
+    for node in root.descendants() {
+        let level = match &mut node.data_mut().value {
+            NodeValue::Link(link) | NodeValue::Image(link) => {
+                if link.url.starts_with('/') && !link.url.starts_with("//") {
+                    link.url = format!("{route_prefix}{}", link.url);
+                }
+                continue;
+            }
+            NodeValue::Heading(heading) if with_headings => heading.level,
+            _ => continue,
+        };
+        let text = comrak::html::collect_text(node);
+        headings.push(Heading {
+            level,
+            anchor: format!("{ID_PREFIX}{}", anchorizer.anchorize(&text)),
+            text,
+        });
+    }
+
+    let mut html = String::new();
+    // Writing to a string can't fail
+    let _ = format_html(root, &options, &mut html);
+    (SANITIZER.clean(&html).to_string(), headings)
+}
+
+/// Render the Markdown `text` of a README to HTML
+pub fn render_readme(text: &str, route_prefix: &str) -> String {
+    render(text, route_prefix, false).0
+}
+
+/// true if the request asks for a Markdown file, and Markdown files are rendered as pages
+pub fn is_markdown_request(ctx: &GuardContext) -> bool {
+    let enabled = ctx
+        .app_data::<web::Data<SharedConfig>>()
+        .is_some_and(|conf| conf.load().render_markdown);
+    enabled
+        && ctx.head().uri.path().to_ascii_lowercase().ends_with(".md")
+        && web::Query::<MarkdownQueryParameters>::from_query(
+            ctx.head().uri.query().unwrap_or_default(),
+        )
+        .is_ok_and(|query| !matches!(query.raw.as_deref(), Some("1" | "true")))
+}
+
+/// Handle a request for a Markdown file, rendering it as a page.
+///
+/// Directories, files which are too large and files which aren't text are sent as usual, by
+/// redirecting to their raw URL.
+pub async fn render_file(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let not_found = || RuntimeError::RouteNotFoundError(req.path().to_string());
+
+    // Drop boxes don't expose their contents in any way
+    if conf.drop_box {
+        return Err(not_found());
+    }
+    let link = req.path();
+    let path = requested_path(&conf, link).ok_or_else(not_found)?;
+    let local_path = conf.path.join(&path);
+    let raw = || {
+        HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, format!("{link}?raw=true")))
+            .finish()
+    };
+
+    match fs::metadata(&local_path).await {
+        Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_VIEW_SIZE => {}
+        Ok(_) => return Ok(raw()),
+        Err(_) => return Err(not_found()),
+    }
+    let contents = fs::read(&local_path)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to read {path:?}"), e))?;
+    let Ok(text) = String::from_utf8(contents) else {
+        return Ok(raw());
+    };
+
+    let (html, headings) = render(&text, &conf.route_prefix, true);
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .body(renderer::markdown_page(&path, link, &html, &headings, &conf).into_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("[a](/docs/a.md)", "", r#"href="/docs/a.md""#)]
+    #[case("[a](/docs/a.md)", "/prefix", r#"href="/prefix/docs/a.md""#)]
+    #[case("![a](/img/a.png)", "/prefix", r#"src="/prefix/img/a.png""#)]
+    #[case("[a](docs/a.md)", "/prefix", r#"href="docs/a.md""#)]
+    #[case("[a](../a.md)", "/prefix", r#"href="../a.md""#)]
+    #[case("[a](//example.com/a)", "/prefix", r#"href="//example.com/a""#)]
+    #[case(
+        "[a](https://example.com/a)",
+        "/prefix",
+        r#"href="https://example.com/a""#
+    )]
+    fn links_follow_the_route_prefix(
+        #[case] text: &str,
+        #[case] route_prefix: &str,
+        #[case] expected: &str,
+    ) {
+        let html = render_readme(text, route_prefix);
+        assert!(html.contains(expected), "{html}");
+    }
+
+    #[rstest]
+    #[case("<script>alert(1)</script>", "alert(1)")]
+    #[case("<img src=x onerror=alert(1)>", "onerror")]
+    #[case("[a](javascript:alert(1))", "javascript:")]
+    #[case("<a href=\"#\" onclick=\"alert(1)\">a</a>", "onclick")]
+    #[case("<iframe src=\"https://example.com\"></iframe>", "iframe")]
+    #[case("<p id=\"toc\">a</p>", "id=\"toc\"")]
+    fn html_is_sanitized(#[case] text: &str, #[case] removed: &str) {
+        let html = render_readme(text, "");
+        assert!(!html.contains(removed), "{html}");
+    }
+
+    #[test]
+    fn sanitizing_keeps_the_rendered_markdown() {
+        let (html, _) = render(
+            "# Title\n\n- [x] done\n- [ ] todo\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n\n~~old~~ [link](https://example.com) ![img](a.png)\n",
+            "",
+            true,
+        );
+        for kept in [
+            r#"class="anchor""#,
+            r#"id="user-content-title""#,
+            r#"type="checkbox""#,
+            "checked",
+            r#"align="right""#,
+            "<del>old</del>",
+            r#"href="https://example.com""#,
+            r#"src="a.png""#,
+        ] {
+            assert!(html.contains(kept), "{kept} in {html}");
+        }
+    }
+
+    #[test]
+    fn headings_are_anchored() {
+        let (html, headings) = render(
+            "# Title\n\n## Usage\n\ntext\n\n## Usage\n\n### `code` *here*\n",
+            "",
+            true,
+        );
+        let headings: Vec<_> = headings
+            .iter()
+            .map(|heading| {
+                (
+                    heading.level,
+                    heading.text.as_str(),
+                    heading.anchor.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                (1, "Title", "user-content-title"),
+                (2, "Usage", "user-content-usage"),
+                (2, "Usage", "user-content-usage-1"),
+                (3, "code here", "user-content-code-here"),
+            ]
+        );
+        for (_, _, anchor) in headings {
+            assert!(html.contains(&format!(r#"id="{anchor}""#)), "{html}");
+        }
+    }
+}
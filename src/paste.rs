@@ -0,0 +1,167 @@
+//! Pastes created from the command line, like `curl --data-binary @- http://host/__paste`
+//!
+//! The paste is the request body, or the first field of a multipart form for `curl -F`. It's
+//! saved under a random name like an upload, and the response is its URL in plain text.
+
+use std::path::{Path, PathBuf};
+
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, http::header, web};
+use futures::TryStreamExt;
+use percent_encoding::utf8_percent_encode;
+use serde::Deserialize;
+
+use crate::auth::CurrentUser;
+use crate::config::SharedConfig;
+use crate::errors::RuntimeError;
+use crate::expiry::{self, ExpiryQueryParameters};
+use crate::file_op::{
+    FileHash, UploadLimits, check_content_length, save_file, upload_file_path, upload_target_dir,
+};
+use crate::file_utils::sanitize_path;
+use crate::listing::percent_encode_sets::COMPONENT;
+use crate::{drop_box, quota};
+
+/// Route of the paste endpoint, relative to the route prefix
+pub const PASTE_ROUTE: &str = "/__paste";
+
+/// Characters of the random part of paste names
+const NAME_ALPHABET: [char; 36] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
+    'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+/// Query parameters of the paste endpoint
+#[derive(Deserialize, Default)]
+pub struct PasteQueryParameters {
+    /// Directory to create the paste in, relative to the served path. Defaults to the first
+    /// upload directory.
+    path: Option<PathBuf>,
+}
+
+/// Random name for a paste, keeping the extension of the `filename` it was sent with if any, and
+/// `.txt` otherwise
+fn paste_name(filename: Option<&str>) -> String {
+    let extension = filename
+        .and_then(|filename| Path::new(filename).extension())
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("txt");
+    format!(
+        "paste-{}.{}",
+        nanoid::nanoid!(6, &NAME_ALPHABET),
+        extension.to_ascii_lowercase()
+    )
+}
+
+/// Handle a request to create a paste, in the directory given by the `path` parameter and
+/// expiring after the time given by the `expire` parameter.
+///
+/// The paste goes through the same checks as uploads, and the response is its URL.
+pub async fn create_paste(
+    req: HttpRequest,
+    query: web::Query<PasteQueryParameters>,
+    expire: web::Query<ExpiryQueryParameters>,
+    payload: web::Payload,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    // The route stays registered across configuration reloads which disable pastes
+    if !conf.pastebin_enabled {
+        return Err(RuntimeError::RouteNotFoundError(req.path().to_string()));
+    }
+    let expiry = expire.expiry(&conf)?;
+
+    let dir = match &query.path {
+        Some(path) => path.clone(),
+        None => conf
+            .allowed_upload_dir
+            .first()
+            .map(PathBuf::from)
+            .unwrap_or_default(),
+    };
+    let target_dir = upload_target_dir(&conf, &dir)?;
+    if !target_dir.is_dir() {
+        return Err(RuntimeError::InvalidPathError(format!(
+            "cannot create a paste in {}, since it's not a directory",
+            target_dir.display()
+        )));
+    }
+    let file_hash = FileHash::from_request(&req)?;
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    let user = current_user.as_ref().map(|user| user.name.as_str());
+    let dir = sanitize_path(&dir, conf.show_hidden).unwrap_or_default();
+    let reservation = quota::reserve(&conf, &dir, user);
+
+    // The request holds nothing but the paste
+    let limits = UploadLimits {
+        media_types: conf.uploadable_media_type.as_deref(),
+        max_size: conf
+            .max_upload_file_size
+            .into_iter()
+            .chain(conf.max_upload_request_size)
+            .min(),
+        quota_left: quota::space_left(&conf, &dir, user).await?,
+        reservation: reservation.as_ref(),
+    };
+    check_content_length(&req, limits)?;
+
+    let is_multipart = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .is_some_and(|mime| mime.essence_str() == mime::MULTIPART_FORM_DATA.essence_str());
+    let saved = if is_multipart {
+        let mut form = actix_multipart::Multipart::new(req.headers(), payload);
+        let mut field = form
+            .try_next()
+            .await
+            .map_err(|e| RuntimeError::MultipartError(e.to_string()))?
+            .ok_or_else(|| RuntimeError::MultipartError("The form holds no paste".to_string()))?;
+        let name = paste_name(
+            field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_filename()),
+        );
+        let path = upload_file_path(&target_dir, &name, false, !conf.no_symlinks, false)?;
+        limits.check_media_type(&name, &[])?;
+        save_file(&mut field, path, &conf, limits, file_hash.as_ref()).await?
+    } else {
+        let mut payload = payload;
+        let name = paste_name(None);
+        let path = upload_file_path(&target_dir, &name, false, !conf.no_symlinks, false)?;
+        limits.check_media_type(&name, &[])?;
+        save_file(&mut payload, path, &conf, limits, file_hash.as_ref()).await?
+    };
+    quota::record_upload(&conf, &saved.path, saved.size, user).await;
+    drop(reservation);
+    expiry::record_upload(&conf, &saved.path, expiry).await;
+    drop_box::record_upload(&conf, drop_box::session(&req).as_ref(), &saved.path);
+
+    let app_root_dir = conf.path.canonicalize().map_err(|e| {
+        RuntimeError::IoError("Failed to resolve path served by miniserve".to_string(), e)
+    })?;
+    let relative_path = saved
+        .path
+        .strip_prefix(&app_root_dir)
+        .unwrap_or(&saved.path);
+    let encoded_path: String = relative_path
+        .iter()
+        .map(|component| {
+            format!(
+                "/{}",
+                utf8_percent_encode(&component.to_string_lossy(), COMPONENT)
+            )
+        })
+        .collect();
+    let connection_info = req.connection_info();
+    let url = format!(
+        "{}://{}{}{encoded_path}",
+        connection_info.scheme(),
+        connection_info.host(),
+        conf.route_prefix
+    );
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, url.as_str()))
+        .content_type(mime::TEXT_PLAIN_UTF_8)
+        .body(format!("{url}\n")))
+}
@@ -0,0 +1,212 @@
+//! Player of audio and video files, at the path of a file with `?play=1`
+//!
+//! The media are streamed from their plain URL, whose range requests let the browser seek. The
+//! player of a directory, at its path with `?play=1` too, plays all of its media in the order of
+//! the listing, which can also be downloaded as an M3U8 playlist for media players like VLC and
+//! mpv with `?format=m3u8`.
+
+use actix_web::{HttpRequest, HttpResponse, guard::GuardContext, http::header, web};
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::config::SharedConfig;
+use crate::errors::RuntimeError;
+use crate::file_utils::requested_path;
+use crate::listing::Entry;
+use crate::renderer;
+
+/// Kinds of media the player plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Audio,
+    Video,
+}
+
+impl MediaKind {
+    /// Kind of the media file called `name`, if it's one
+    pub fn of(name: &str) -> Option<Self> {
+        let mime = mime_guess::from_path(name).first()?;
+        // Playlists have media types too, but aren't media themselves
+        if mime.subtype().as_str().contains("mpegurl") || mime.subtype() == "scpls" {
+            return None;
+        }
+        match mime.type_() {
+            mime::AUDIO => Some(Self::Audio),
+            mime::VIDEO => Some(Self::Video),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind, like the HTML element playing it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Audio => "audio",
+            Self::Video => "video",
+        }
+    }
+}
+
+/// A media file of a playlist
+pub struct PlaylistItem {
+    /// Name of the file
+    pub name: String,
+
+    /// URL of the file
+    pub link: String,
+
+    /// Kind of media of the file
+    pub kind: MediaKind,
+}
+
+/// Query parameters of the player
+#[derive(Deserialize, Default)]
+struct PlayQueryParameters {
+    play: Option<String>,
+}
+
+/// true if `play`, the value of the `play` query parameter, asks for the player
+pub fn play_requested(play: Option<&str>) -> bool {
+    matches!(play, Some("1" | "true"))
+}
+
+/// true if the request asks for the player of a file with `?play=1` or `?play=true`.
+///
+/// The player of a directory, whose path ends with a slash, is rendered by the listing.
+pub fn is_play_request(ctx: &GuardContext) -> bool {
+    !ctx.head().uri.path().ends_with('/')
+        && web::Query::<PlayQueryParameters>::from_query(ctx.head().uri.query().unwrap_or_default())
+            .is_ok_and(|query| play_requested(query.play.as_deref()))
+}
+
+/// Media files of the listed `entries`, in the same order
+pub fn playlist(entries: &[Entry]) -> Vec<PlaylistItem> {
+    entries
+        .iter()
+        .filter(|entry| entry.play_link.is_some())
+        .filter_map(|entry| {
+            Some(PlaylistItem {
+                name: entry.name.clone(),
+                link: entry.link.clone(),
+                kind: MediaKind::of(&entry.name)?,
+            })
+        })
+        .collect()
+}
+
+/// M3U8 playlist of the `items`, whose relative links are made absolute with `base_url`
+pub fn m3u8(items: &[PlaylistItem], base_url: &str) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for item in items {
+        // Each entry holds on its own lines
+        let title: String = item
+            .name
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        let url = if item.link.starts_with("http://") || item.link.starts_with("https://") {
+            item.link.clone()
+        } else {
+            format!("{base_url}{}", item.link)
+        };
+        playlist.push_str(&format!("#EXTINF:-1,{title}\n{url}\n"));
+    }
+    playlist
+}
+
+/// Handle a request for the player page of a file.
+///
+/// Directories are redirected to their own player, and files which aren't media are sent as
+/// usual by redirecting to their plain URL.
+pub async fn play_file(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let not_found = || RuntimeError::RouteNotFoundError(req.path().to_string());
+
+    // Drop boxes don't expose their contents in any way
+    if conf.drop_box {
+        return Err(not_found());
+    }
+    let link = req.path();
+    let path = requested_path(&conf, link).ok_or_else(not_found)?;
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let metadata = fs::metadata(conf.path.join(&path))
+        .await
+        .map_err(|_| not_found())?;
+
+    if metadata.is_dir() {
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, format!("{link}/?play=1")))
+            .finish());
+    }
+    let Some(kind) = MediaKind::of(&name) else {
+        return Ok(HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, link))
+            .finish());
+    };
+
+    let dir_link = link.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let title = format!("/{}", path.to_string_lossy());
+    let item = PlaylistItem {
+        name,
+        link: link.to_string(),
+        kind,
+    };
+    let play_all_link = format!("{dir_link}/?play=1");
+    Ok(HttpResponse::Ok().content_type(mime::TEXT_HTML_UTF_8).body(
+        renderer::player_page(
+            &title,
+            &format!("{dir_link}/"),
+            &[item],
+            Some(&play_all_link),
+            None,
+            &conf,
+        )
+        .into_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("song.mp3", Some(MediaKind::Audio))]
+    #[case("track.FLAC", Some(MediaKind::Audio))]
+    #[case("movie.mp4", Some(MediaKind::Video))]
+    #[case("clip.webm", Some(MediaKind::Video))]
+    #[case("playlist.m3u8", None)]
+    #[case("stations.pls", None)]
+    #[case("photo.jpg", None)]
+    fn media_kinds(#[case] name: &str, #[case] expected: Option<MediaKind>) {
+        assert_eq!(MediaKind::of(name), expected);
+    }
+
+    #[test]
+    fn m3u8_playlist() {
+        let items = [
+            PlaylistItem {
+                name: "new\nline.mp3".to_string(),
+                link: "/music/new%0Aline.mp3".to_string(),
+                kind: MediaKind::Audio,
+            },
+            PlaylistItem {
+                name: "movie.mp4".to_string(),
+                link: "https://cdn.example.com/movie.mp4".to_string(),
+                kind: MediaKind::Video,
+            },
+        ];
+        assert_eq!(
+            m3u8(&items, "http://localhost:8080"),
+            "#EXTM3U\n\
+             #EXTINF:-1,new line.mp3\n\
+             http://localhost:8080/music/new%0Aline.mp3\n\
+             #EXTINF:-1,movie.mp4\n\
+             https://cdn.example.com/movie.mp4\n"
+        );
+    }
+}
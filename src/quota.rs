@@ -0,0 +1,419 @@
+//! Upload quotas, see `--upload-quota` and `--user-quota`
+//!
+//! The usage of an upload directory is its size, computed with [`recursive_dir_size`], along with
+//! the size of the versions kept of its files. The usage of a user is the size of the files they
+//! uploaded which are still there, recorded in [`QUOTA_FILE`]. Both are cached for [`CACHE_TTL`]
+//! and updated as uploads complete.
+//!
+//! Uploads in progress hold a [`Reservation`] of the bytes they wrote so far, or of their whole
+//! size when it's known upfront, like for resumable uploads and copies, so that concurrent uploads
+//! can't overrun a quota together.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use async_walkdir::WalkDir;
+use bytesize::ByteSize;
+use futures::StreamExt;
+use log::warn;
+use tokio::fs;
+
+use crate::config::MiniserveConfig;
+use crate::errors::RuntimeError;
+use crate::file_op::recursive_dir_size;
+use crate::file_utils::relative_to_root;
+use crate::versions;
+
+/// Name of the file recording who uploaded which files, at the root of the served path. It's
+/// never listed or served.
+pub const QUOTA_FILE: &str = ".miniserve-quota.json";
+
+/// Time for which computed usages are reused
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// What a quota applies to
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Owner {
+    /// An upload directory of the served directory at the local path, by its relative path
+    Dir(PathBuf, PathBuf),
+
+    /// A user of the served directory at the local path
+    User(PathBuf, String),
+}
+
+/// Cached usages in bytes, along with when they were computed
+static USAGE: LazyLock<Mutex<HashMap<Owner, (u64, Instant)>>> = LazyLock::new(Default::default);
+
+/// Bytes reserved by the uploads in progress
+static RESERVED: LazyLock<Mutex<HashMap<Owner, u64>>> = LazyLock::new(Default::default);
+
+/// Serializes the growth of reservations, so that each one sees those before it
+static RESERVE_LOCK: futures::lock::Mutex<()> = futures::lock::Mutex::new(());
+
+/// Serializes the updates of [`QUOTA_FILE`]
+static LEDGER_LOCK: futures::lock::Mutex<()> = futures::lock::Mutex::new(());
+
+/// Files uploaded by each user, by their path relative to the served path, with their size
+type Ledger = BTreeMap<String, BTreeMap<PathBuf, u64>>;
+
+/// Space available for uploads to a directory
+pub struct Space {
+    /// Bytes left in the quotas uploads count against, if any
+    pub quota_left: Option<u64>,
+
+    /// Bytes available on the filesystem, if known
+    pub disk_free: Option<u64>,
+}
+
+/// Bytes held for an upload in progress in the quotas it counts against, until it's dropped
+pub struct Reservation {
+    /// Owners of the quotas which apply, with their size
+    quotas: Vec<(Owner, u64)>,
+
+    /// Bytes reserved so far
+    size: AtomicU64,
+}
+
+impl Reservation {
+    /// Reserve `size` more bytes for the file called `file_name`, failing if they don't fit in
+    /// one of the quotas along with the usage and the other reservations
+    pub async fn grow(&self, file_name: &str, size: u64) -> Result<(), RuntimeError> {
+        let _lock = RESERVE_LOCK.lock().await;
+        let reserved = self.size.load(Ordering::Relaxed);
+        for (owner, quota) in &self.quotas {
+            let used = cached(owner.clone(), usage(owner)).await?;
+            let others = reserved_by_all(owner).saturating_sub(reserved);
+            check(
+                Some(quota.saturating_sub(used + others)),
+                file_name,
+                reserved + size,
+            )?;
+        }
+
+        let mut all = RESERVED.lock().unwrap();
+        for (owner, _) in &self.quotas {
+            *all.entry(owner.clone()).or_default() += size;
+        }
+        self.size.fetch_add(size, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let size = *self.size.get_mut();
+        let mut all = RESERVED.lock().unwrap();
+        for (owner, _) in &self.quotas {
+            if let Some(reserved) = all.get_mut(owner) {
+                *reserved = reserved.saturating_sub(size);
+                if *reserved == 0 {
+                    all.remove(owner);
+                }
+            }
+        }
+    }
+}
+
+/// Empty reservation for `user` uploading to `dir`, relative to the served path, to be grown as
+/// the upload is written, or `None` if no quota applies. It must be kept until the upload is
+/// recorded with [`record_upload`].
+pub fn reserve(conf: &MiniserveConfig, dir: &Path, user: Option<&str>) -> Option<Reservation> {
+    let quotas = quotas(conf, dir, user);
+    (!quotas.is_empty()).then(|| Reservation {
+        quotas,
+        size: AtomicU64::new(0),
+    })
+}
+
+/// Reservation for `user` copying or moving the entry at `source` to `destination`, both relative
+/// to the served path, holding the size of the whole entry, or `None` if no quota applies.
+///
+/// Copies count against the quotas of the destination. Moves only count against the quota of the
+/// destination's upload directory, when it isn't the one of the source.
+pub async fn reserve_transfer(
+    conf: &MiniserveConfig,
+    source: &Path,
+    destination: &Path,
+    copy: bool,
+    user: Option<&str>,
+) -> Result<Option<Reservation>, RuntimeError> {
+    let dir = destination.parent().unwrap_or(Path::new(""));
+    let source_dir = source.parent().unwrap_or(Path::new(""));
+    let reservation = if copy {
+        reserve(conf, dir, user)
+    } else if upload_dir(conf, dir) != upload_dir(conf, source_dir) {
+        reserve(conf, dir, None)
+    } else {
+        None
+    };
+    let Some(reservation) = reservation else {
+        return Ok(None);
+    };
+
+    let local_path = conf.path.join(source);
+    let size = match fs::metadata(&local_path).await {
+        Ok(metadata) if metadata.is_dir() => recursive_dir_size(&local_path).await?,
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    reservation.grow(&name, size).await?;
+    Ok(Some(reservation))
+}
+
+/// Bytes left for `user` to upload to `dir`, relative to the served path, in the quotas which
+/// apply, or `None` if no quota applies
+pub async fn space_left(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    user: Option<&str>,
+) -> Result<Option<u64>, RuntimeError> {
+    let mut left = None;
+    for (owner, quota) in quotas(conf, dir, user) {
+        let used = cached(owner.clone(), usage(&owner)).await? + reserved_by_all(&owner);
+        left = left.into_iter().chain([quota.saturating_sub(used)]).min();
+    }
+    Ok(left)
+}
+
+/// Space available for `user` to upload to `dir`, relative to the served path
+pub async fn space(
+    conf: &MiniserveConfig,
+    dir: &Path,
+    user: Option<&str>,
+) -> Result<Space, RuntimeError> {
+    Ok(Space {
+        quota_left: space_left(conf, dir, user).await?,
+        disk_free: disk_free(&conf.path.join(dir)),
+    })
+}
+
+/// Fail if a file called `file_name` of `size` bytes doesn't fit in the `quota_left`
+pub fn check(quota_left: Option<u64>, file_name: &str, size: u64) -> Result<(), RuntimeError> {
+    match quota_left {
+        Some(left) if size > left => Err(RuntimeError::QuotaExceededError(format!(
+            "{file_name} exceeds the {} left in the upload quota",
+            ByteSize::b(left)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Count the file of `size` bytes just uploaded to the local `path` against the quotas of its
+/// upload directory and of `user`
+pub async fn record_upload(conf: &MiniserveConfig, path: &Path, size: u64, user: Option<&str>) {
+    if conf.upload_quota.is_none() && conf.user_quota.is_none() {
+        return;
+    }
+    let Some(relative_path) = relative_to_root(&conf.path, path) else {
+        return;
+    };
+
+    if conf.upload_quota.is_some() {
+        let dir = relative_path.parent().unwrap_or(Path::new(""));
+        add_usage(&Owner::Dir(conf.path.clone(), upload_dir(conf, dir)), size);
+    }
+    if let (Some(_), Some(user)) = (conf.user_quota, user) {
+        add_usage(&Owner::User(conf.path.clone(), user.to_owned()), size);
+        if let Err(e) = record_owner(&conf.path, &relative_path, size, user).await {
+            warn!("Failed to record the upload of {relative_path:?} by {user}: {e}");
+        }
+    }
+}
+
+/// Count the files just copied to the local `path`, recursively if it's a directory, against the
+/// quotas of their upload directory and of `user`
+pub async fn record_copy(conf: &MiniserveConfig, path: &Path, user: Option<&str>) {
+    let mut files = vec![];
+    match fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            let mut entries = WalkDir::new(path);
+            while let Some(entry) = entries.next().await {
+                if let Ok(entry) = entry
+                    && let Ok(metadata) = entry.metadata().await
+                    && metadata.is_file()
+                {
+                    files.push((entry.path(), metadata.len()));
+                }
+            }
+        }
+        Ok(metadata) => files.push((path.to_path_buf(), metadata.len())),
+        Err(_) => (),
+    }
+    for (file, size) in files {
+        record_upload(conf, &file, size, user).await;
+    }
+}
+
+/// Forget the cached usages, after entries were removed or moved around
+pub fn forget_usage() {
+    USAGE.lock().unwrap().clear();
+}
+
+/// Owners of the quotas which apply to `user` uploading to `dir`, relative to the served path,
+/// with their size
+fn quotas(conf: &MiniserveConfig, dir: &Path, user: Option<&str>) -> Vec<(Owner, u64)> {
+    let mut quotas = vec![];
+    if let Some(quota) = conf.upload_quota {
+        quotas.push((Owner::Dir(conf.path.clone(), upload_dir(conf, dir)), quota));
+    }
+    if let (Some(quota), Some(user)) = (conf.user_quota, user) {
+        quotas.push((Owner::User(conf.path.clone(), user.to_owned()), quota));
+    }
+    quotas
+}
+
+/// Bytes reserved against the quota of `owner` by all the uploads in progress
+fn reserved_by_all(owner: &Owner) -> u64 {
+    RESERVED
+        .lock()
+        .unwrap()
+        .get(owner)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Compute the usage of `owner`
+async fn usage(owner: &Owner) -> Result<u64, RuntimeError> {
+    match owner {
+        Owner::Dir(root, dir) => dir_usage(root, dir).await,
+        Owner::User(root, user) => user_usage(root, user).await,
+    }
+}
+
+/// Size of the upload directory `dir`, relative to the served directory `root`, with the versions
+/// kept of its files
+async fn dir_usage(root: &Path, dir: &Path) -> Result<u64, RuntimeError> {
+    let mut used = recursive_dir_size(&root.join(dir)).await?;
+
+    // The versions are in the served directory itself otherwise
+    let versions_dir = versions::versions_dir(root, dir);
+    if !dir.as_os_str().is_empty() && versions_dir.is_dir() {
+        used += recursive_dir_size(&versions_dir).await?;
+    }
+    Ok(used)
+}
+
+/// Usage of `owner`, computed with `compute` unless a recent enough value is cached
+async fn cached(
+    owner: Owner,
+    compute: impl Future<Output = Result<u64, RuntimeError>>,
+) -> Result<u64, RuntimeError> {
+    if let Some((used, computed_at)) = USAGE.lock().unwrap().get(&owner)
+        && computed_at.elapsed() < CACHE_TTL
+    {
+        return Ok(*used);
+    }
+
+    let used = compute.await?;
+    USAGE.lock().unwrap().insert(owner, (used, Instant::now()));
+    Ok(used)
+}
+
+/// Add `size` bytes to the cached usage of `owner`, if any
+fn add_usage(owner: &Owner, size: u64) {
+    if let Some((used, _)) = USAGE.lock().unwrap().get_mut(owner) {
+        *used += size;
+    }
+}
+
+/// Allowed upload directory that `dir`, relative to the served path, belongs to. That's the
+/// served path itself if uploads are allowed everywhere.
+fn upload_dir(conf: &MiniserveConfig, dir: &Path) -> PathBuf {
+    conf.allowed_upload_dir
+        .iter()
+        .map(Path::new)
+        .filter(|allowed| dir.starts_with(allowed))
+        .max_by_key(|allowed| allowed.components().count())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// Read the ledger of the served directory `root`
+async fn read_ledger(root: &Path) -> Result<Ledger, RuntimeError> {
+    match fs::read(root.join(QUOTA_FILE)).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Ledger::new()),
+        Err(e) => Err(RuntimeError::IoError(
+            format!("Failed to read {QUOTA_FILE}"),
+            e,
+        )),
+        Ok(ledger) => serde_json::from_slice(&ledger)
+            .map_err(|e| RuntimeError::ParseError(QUOTA_FILE.to_string(), e.to_string())),
+    }
+}
+
+/// Size of the files uploaded by `user` to the served directory `root` which are still there
+async fn user_usage(root: &Path, user: &str) -> Result<u64, RuntimeError> {
+    let ledger = read_ledger(root).await?;
+    let mut used = 0;
+    for path in ledger.get(user).into_iter().flat_map(BTreeMap::keys) {
+        if let Ok(metadata) = fs::symlink_metadata(root.join(path)).await
+            && metadata.is_file()
+        {
+            used += metadata.len();
+        }
+    }
+    Ok(used)
+}
+
+/// Record that `user` uploaded `size` bytes to `path`, relative to the served directory `root`.
+///
+/// Forgets the files of all users which aren't there anymore along the way.
+async fn record_owner(root: &Path, path: &Path, size: u64, user: &str) -> Result<(), RuntimeError> {
+    let _lock = LEDGER_LOCK.lock().await;
+    let mut ledger = read_ledger(root).await?;
+    for files in ledger.values_mut() {
+        files.remove(path);
+        let mut kept = BTreeMap::new();
+        for (path, size) in std::mem::take(files) {
+            if fs::symlink_metadata(root.join(&path)).await.is_ok() {
+                kept.insert(path, size);
+            }
+        }
+        *files = kept;
+    }
+    ledger.retain(|_, files| !files.is_empty());
+    ledger
+        .entry(user.to_owned())
+        .or_default()
+        .insert(path.to_path_buf(), size);
+
+    let ledger = serde_json::to_vec(&ledger)
+        .map_err(|e| RuntimeError::IoError(format!("Failed to write {QUOTA_FILE}"), e.into()))?;
+    fs::write(root.join(QUOTA_FILE), ledger)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to write {QUOTA_FILE}"), e))
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+fn disk_free(path: &Path) -> Option<u64> {
+    let stat = rustix::fs::statvfs(path).ok()?;
+    Some(stat.f_bavail * stat.f_frsize)
+}
+
+#[cfg(not(unix))]
+fn disk_free(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, 100, true)]
+    #[case(Some(100), 100, true)]
+    #[case(Some(100), 101, false)]
+    #[case(Some(0), 0, true)]
+    #[case(Some(0), 1, false)]
+    fn test_check(#[case] quota_left: Option<u64>, #[case] size: u64, #[case] fits: bool) {
+        assert_eq!(check(quota_left, "file", size).is_ok(), fits);
+    }
+}
@@ -0,0 +1,228 @@
+//! Reloading of the configuration while the server is running
+//!
+//! On `SIGHUP`, miniserve parses its command line and config file again, re-reads the auth file
+//! and TLS certificates and swaps in the resulting configuration for all subsequent requests.
+//! Established connections are not interrupted.
+//!
+//! Some options are baked into the running server (e.g. the addresses it is bound to or the routes
+//! it serves) and can't be changed this way. If such an option changes, a warning is logged and
+//! the running value is kept.
+
+use anyhow::Result;
+use clap::ArgMatches;
+use log::warn;
+
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::config_file;
+
+/// Arguments which only take effect on startup
+const STARTUP_ONLY_ARGS: &[&str] = &[
+    "path",
+    "port",
+    "interfaces",
+    "tailscale",
+    "route_prefix",
+    "random_route",
+    "index",
+    "spa",
+    "pretty_urls",
+    "hidden",
+    "no_symlinks",
+    "enable_webdav",
+    "webdav_write",
+    "trash",
+    "drop_box",
+    "compress_response",
+    "color_scheme",
+    "color_scheme_dark",
+    "qrcode",
+    "verbose",
+    "quiet",
+    "log_color",
+];
+
+/// Re-read the configuration and make it the current configuration of `shared`.
+///
+/// `startup_matches` are the arguments the server was started with; changes to options in
+/// [`STARTUP_ONLY_ARGS`] are reported relative to them. Returns the warnings about options that
+/// couldn't be changed.
+pub fn reload(shared: &SharedConfig, startup_matches: &ArgMatches) -> Result<Vec<String>> {
+    let (args, matches) = config_file::try_parse_args()?;
+    let mut new_config = MiniserveConfig::try_from_args(args)?;
+    let current = shared.load();
+
+    let mut warnings = changed_startup_args(startup_matches, &matches)
+        .into_iter()
+        .map(|arg| {
+            format!("The {arg} option can't be changed at runtime, restart miniserve to apply it")
+        })
+        .collect::<Vec<_>>();
+    warnings.extend(keep_startup_options(&mut new_config, &current));
+
+    shared.store(new_config);
+    Ok(warnings)
+}
+
+/// Wait for `SIGHUP` and reload the configuration of `shared` every time it's received
+#[cfg(unix)]
+pub async fn reload_on_sighup(
+    shared: std::sync::Arc<SharedConfig>,
+    startup_matches: ArgMatches,
+) -> std::io::Result<()> {
+    use log::{error, info};
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading configuration");
+        match reload(&shared, &startup_matches) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    warn!("{warning}");
+                }
+                info!("Configuration reloaded");
+            }
+            Err(e) => error!("Failed to reload configuration, keeping the current one: {e:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// Command line names of the startup-only arguments whose values differ between `old` and `new`
+fn changed_startup_args(old: &ArgMatches, new: &ArgMatches) -> Vec<String> {
+    let raw_values = |matches: &ArgMatches, id: &str| {
+        matches
+            .get_raw(id)
+            .map(|values| values.map(ToOwned::to_owned).collect::<Vec<_>>())
+    };
+
+    STARTUP_ONLY_ARGS
+        .iter()
+        .filter(|id| raw_values(old, id) != raw_values(new, id))
+        .map(|id| match *id {
+            "path" => "PATH".to_owned(),
+            id => format!("--{}", id.replace('_', "-")),
+        })
+        .collect()
+}
+
+/// Carry over everything from `current` that the running server depends on into `new`.
+///
+/// Returns warnings for features which were enabled or disabled in `new`, as these need a restart.
+fn keep_startup_options(new: &mut MiniserveConfig, current: &MiniserveConfig) -> Vec<String> {
+    let mut warnings = vec![];
+
+    if new.file_upload != current.file_upload {
+        warnings.push("File uploads can't be enabled or disabled at runtime".to_owned());
+    }
+    if new.rm_enabled != current.rm_enabled {
+        warnings.push("Deletion can't be enabled or disabled at runtime".to_owned());
+    }
+    if new.auth.is_empty() != current.auth.is_empty() {
+        warnings.push("Authentication can't be enabled or disabled at runtime".to_owned());
+        new.auth = current.auth.clone();
+    }
+    if new.tls_rustls_config.is_some() != current.tls_rustls_config.is_some() {
+        warnings.push("TLS can't be enabled or disabled at runtime".to_owned());
+    }
+
+    // The running server keeps using the resolver it was started with, so hand it the new
+    // certificate instead.
+    #[cfg(feature = "tls")]
+    {
+        if let (Some(current_resolver), Some(new_resolver)) =
+            (&current.tls_cert_resolver, &new.tls_cert_resolver)
+        {
+            current_resolver.set(new_resolver.get());
+        }
+        new.tls_rustls_config = current.tls_rustls_config.clone();
+        new.tls_cert_resolver = current.tls_cert_resolver.clone();
+    }
+
+    new.verbose = current.verbose;
+    new.path = current.path.clone();
+    new.port = current.port;
+    new.interfaces = current.interfaces.clone();
+    new.tailscale_dns_name = current.tailscale_dns_name.clone();
+    new.path_explicitly_chosen = current.path_explicitly_chosen;
+    new.no_symlinks = current.no_symlinks;
+    new.show_hidden = current.show_hidden;
+    new.route_prefix = current.route_prefix.clone();
+    new.healthcheck_route = current.healthcheck_route.clone();
+    new.api_route = current.api_route.clone();
+    new.favicon_route = current.favicon_route.clone();
+    new.css_route = current.css_route.clone();
+    new.default_color_scheme = current.default_color_scheme.clone();
+    new.default_color_scheme_dark = current.default_color_scheme_dark.clone();
+    new.index = current.index.clone();
+    new.spa = current.spa;
+    new.quiet = current.quiet;
+    new.pretty_urls = current.pretty_urls;
+    new.show_qrcode = current.show_qrcode;
+    new.file_upload = current.file_upload;
+    new.rm_enabled = current.rm_enabled;
+    new.compress_response = current.compress_response;
+    new.webdav_enabled = current.webdav_enabled;
+    new.webdav_write = current.webdav_write;
+    new.trash_enabled = current.trash_enabled;
+    new.drop_box = current.drop_box;
+    if new.drop_box {
+        new.restrict_to_drop_box();
+    }
+    new.log_color = current.log_color;
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, Parser};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    use crate::args::CliArgs;
+
+    fn matches(args: &[&str]) -> ArgMatches {
+        CliArgs::command()
+            .get_matches_from(std::iter::once("miniserve").chain(args.iter().copied()))
+    }
+
+    #[rstest]
+    #[case(&["/srv"], &["/srv", "--title", "new", "-a", "user:pass"], &[])]
+    #[case(&["/srv"], &["/tmp"], &["PATH"])]
+    #[case(&["-p", "8080"], &["--port", "8081"], &["--port"])]
+    #[case(&["--random-route"], &["--random-route"], &[])]
+    #[case(&["-i", "::1"], &["-i", "::1", "-i", "127.0.0.1", "--hidden"], &["--interfaces", "--hidden"])]
+    fn startup_args_changes_are_detected(
+        #[case] old: &[&str],
+        #[case] new: &[&str],
+        #[case] expected: &[&str],
+    ) {
+        assert_eq!(changed_startup_args(&matches(old), &matches(new)), expected);
+    }
+
+    fn config(args: &[&str]) -> MiniserveConfig {
+        let args = CliArgs::parse_from(std::iter::once("miniserve").chain(args.iter().copied()));
+        MiniserveConfig::try_from_args(args).unwrap()
+    }
+
+    #[test]
+    fn drop_box_stays_restricted() {
+        let current = config(&["-u", "--drop-box"]);
+        let mut new = config(&[
+            "-u",
+            "--enable-search",
+            "--render-markdown",
+            "--readme",
+            "--enable-zip",
+        ]);
+        keep_startup_options(&mut new, &current);
+
+        assert!(new.drop_box);
+        assert!(!new.search_enabled);
+        assert!(!new.render_markdown);
+        assert!(!new.readme);
+        assert!(!new.zip_enabled);
+    }
+}
@@ -1,6 +1,8 @@
-use std::time::SystemTime;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
 
 use actix_web::http::{StatusCode, Uri};
+use bytesize::ByteSize;
 use chrono::{DateTime, Local};
 use chrono_humanize::Humanize;
 use clap::{ValueEnum, crate_name, crate_version};
@@ -10,11 +12,23 @@ use fast_qr::{
     qr::QRCodeError,
 };
 use maud::{DOCTYPE, Markup, PreEscaped, html};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
 use strum::{Display, IntoEnumIterator};
 
 use crate::auth::CurrentUser;
 use crate::consts;
-use crate::listing::{Breadcrumb, Entry, ListingQueryParameters, SortingMethod, SortingOrder};
+use crate::listing::{
+    Breadcrumb, Entry, ListingQueryParameters, SortingMethod, SortingOrder,
+    percent_encode_sets::COMPONENT,
+};
+use crate::markdown::Heading;
+use crate::player::PlaylistItem;
+use crate::quota::Space;
+use crate::search::SearchSummary;
+use crate::trash::{TRASH_ROUTE, TrashEntry};
+use crate::tus::TUS_ROUTE;
+use crate::versions::{VERSIONS_ROUTE, Version};
+use crate::viewer::ViewedFile;
 use crate::{MiniserveConfig, archive::ArchiveMethod};
 
 #[allow(clippy::too_many_arguments)]
@@ -29,6 +43,9 @@ pub fn page(
     encoded_dir: &str,
     conf: &MiniserveConfig,
     current_user: Option<&CurrentUser>,
+    search: Option<&SearchSummary>,
+    space: Option<&Space>,
+    nonce: &str,
 ) -> Markup {
     // If query_params.raw is true, we want render a minimal directory listing
     if query_params.raw.is_some() && query_params.raw.unwrap() {
@@ -37,10 +54,13 @@ pub fn page(
 
     let upload_route = format!("{}/upload", &conf.route_prefix);
     let rm_route = format!("{}/rm", &conf.route_prefix);
+    let mv_route = format!("{}/mv", &conf.route_prefix);
+    let cp_route = format!("{}/cp", &conf.route_prefix);
     let (sort_method, sort_order) = (query_params.sort, query_params.order);
 
     let upload_action = build_upload_action(&upload_route, encoded_dir, sort_method, sort_order);
     let mkdir_action = build_mkdir_action(&upload_route, encoded_dir);
+    let tus_action = format!("{}{TUS_ROUTE}?path={encoded_dir}", conf.route_prefix);
 
     let title_path = breadcrumbs_to_path_string(breadcrumbs);
 
@@ -55,16 +75,27 @@ pub fn page(
             .iter()
             .any(|x| encoded_dir.starts_with(&format!("/{x}")));
 
-    // OR with other conditions in the future if more actions are added
-    let show_actions = conf.rm_enabled && rm_allowed;
-    let actions_conf = show_actions.then(|| ActionsConf {
-        rm_route: &rm_route,
-    });
+    // Moving an entry removes it, so it needs both deletions and uploads
+    let rm_shown = conf.rm_enabled && rm_allowed;
+    let actions = ActionsConf {
+        rm_route: rm_shown.then_some(rm_route.as_str()),
+        mv_route: (rm_shown && conf.file_upload).then_some(mv_route.as_str()),
+        cp_route: (conf.file_upload && !conf.drop_box).then_some(cp_route.as_str()),
+    };
+    let show_actions = actions.rm_route.is_some() || actions.cp_route.is_some();
+    let actions_conf = show_actions.then_some(actions);
+
+    // The gallery view is only offered in directories holding images
+    let gallery_view = entries
+        .iter()
+        .any(|entry| entry.thumbnail_link.is_some())
+        .then(|| gallery(&entries, sort_method, sort_order, nonce));
+    let has_media = entries.iter().any(|entry| entry.play_link.is_some());
 
     html! {
         (DOCTYPE)
         html {
-            (page_header(&title_path, conf.file_upload, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route))
+            (page_header(&title_path, conf.file_upload, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, Some(nonce)))
 
             body #drop-container
             {
@@ -105,6 +136,12 @@ pub fn page(
                         }
                     }
                     div.toolbar {
+                        @if conf.search_enabled {
+                            div.tool_row.search_tools {
+                                (search_form(search, sort_method, sort_order))
+                            }
+                        }
+
                         @if conf.tar_enabled || conf.tar_gz_enabled || conf.zip_enabled {
                             div.tool_row.download_tools {
                                 div.tool data-tool="download" {
@@ -119,14 +156,35 @@ pub fn page(
 
                         div.tool_row.upload_tools {
                             @if conf.file_upload && upload_allowed {
-                                form.tool id="file_submit" data-tool="upload" action=(upload_action) method="POST" enctype="multipart/form-data" {
-                                    p { "Select a file to upload or drag it anywhere into the window" }
+                                form.tool id="file_submit" data-tool="upload" data-tus=(tus_action) action=(upload_action) method="POST" enctype="multipart/form-data" {
+                                    p {
+                                        @if conf.mkdir_enabled {
+                                            "Select files to upload or drag files and folders anywhere into the window"
+                                        } @else {
+                                            "Select a file to upload or drag it anywhere into the window"
+                                        }
+                                    }
                                     div {
                                         @match &conf.uploadable_media_type {
                                             Some(accept) => {input #file-input accept=(accept) type="file" name="file_to_upload" required="" multiple {}},
                                             None => {input #file-input type="file" name="file_to_upload" required="" multiple {}}
                                         }
                                         button type="submit" title="Upload File" { "Upload file" }
+                                        @if conf.mkdir_enabled {
+                                            label.folder_upload title="Upload a folder along with its subfolders" {
+                                                input #folder-input type="file" webkitdirectory="" multiple {}
+                                                "Upload folder"
+                                            }
+                                        }
+                                    }
+                                    @if conf.extract_archives {
+                                        label.extract_archives title="Extract .zip, .tar and .tar.gz files once uploaded, then remove them" {
+                                            input #extract-input type="checkbox" {}
+                                            "Extract archives"
+                                        }
+                                    }
+                                    @if let Some(space) = space {
+                                        (upload_space(space, conf.show_exact_bytes))
                                     }
                                 }
                             }
@@ -147,13 +205,42 @@ pub fn page(
                                     }
                                     div {
                                         input type="text" name="paste_filename" title="Filename" placeholder="Filename (Optional)" autocomplete="off" {}
+                                        select name="paste_expire" title="Expiry" {
+                                            @if let Some(max_expiry) = conf.upload_expiry {
+                                                option value="" selected { "Expires in " (expiry_choice(max_expiry).1) }
+                                            } @else {
+                                                option value="" selected { "Never expires" }
+                                            }
+                                            @for choice in PASTE_EXPIRY_CHOICES {
+                                                @if conf.upload_expiry.is_none_or(|max_expiry| choice < max_expiry) {
+                                                    @let (value, text) = expiry_choice(choice);
+                                                    option value=(value) { "Expires in " (text) }
+                                                }
+                                            }
+                                        }
                                         button type="submit" title="Create file" { "Create file" }
                                     }
                                 }
                             }
                         }
                     }
-                    table {
+                    @if let Some(search) = search {
+                        (search_summary(search, entries.len(), sort_method, sort_order))
+                    }
+                    @if has_media || gallery_view.is_some() {
+                        div.listing_tools {
+                            @if has_media {
+                                (playlist_links(sort_method, sort_order))
+                            }
+                            @if gallery_view.is_some() {
+                                div.layout_toggle {
+                                    button type="button" data-layout="list" aria-pressed="true" { "List" }
+                                    button type="button" data-layout="gallery" aria-pressed="false" { "Gallery" }
+                                }
+                            }
+                        }
+                    }
+                    table #listing {
                         thead {
                             th.name { (sortable_title("name", "Name", sort_method, sort_order)) }
                             th.size { (sortable_title("size", "Size", sort_method, sort_order)) }
@@ -163,7 +250,7 @@ pub fn page(
                             }
                         }
                         tbody {
-                            @if !is_root {
+                            @if !is_root && search.is_none() {
                                 tr {
                                     td colspan=(3 + show_actions as usize) {
                                         p {
@@ -176,10 +263,13 @@ pub fn page(
                                 }
                             }
                             @for entry in entries {
-                                (entry_row(entry, sort_method, sort_order, false, conf.show_exact_bytes, actions_conf, &conf.route_prefix))
+                                (entry_row(entry, sort_method, sort_order, false, conf.show_exact_bytes, actions_conf, &conf.route_prefix, !conf.drop_box))
                             }
                         }
                     }
+                    @if let Some(gallery_view) = gallery_view {
+                        (gallery_view)
+                    }
                     @if let Some(readme) = readme {
                         div id="readme" {
                             h3 id="readme-filename" { (readme.0) }
@@ -192,6 +282,9 @@ pub fn page(
                         (arrow_up())
                     }
                     div.footer {
+                        @if conf.trash_enabled && conf.rm_enabled {
+                            a.trash_link href={ (conf.route_prefix) (TRASH_ROUTE) } { "Trash" }
+                        }
                         @if conf.show_wget_footer {
                             (wget_footer(abs_uri, conf.title.as_deref(), current_user.map(|x| &*x.name),
                                 conf.file_external_url.as_deref()))
@@ -240,6 +333,74 @@ pub fn page(
     }
 }
 
+/// Partial: search box looking for files below the current directory
+fn search_form(
+    search: Option<&SearchSummary>,
+    sort_method: Option<SortingMethod>,
+    sort_order: Option<SortingOrder>,
+) -> Markup {
+    html! {
+        form.tool id="search" data-tool="search" method="GET" {
+            p { "Search files and directories below this one by name or glob pattern (like *.txt)" }
+            div {
+                input type="search" name="search" required="" placeholder="Search"
+                    value=[search.map(|search| &search.query)] {}
+                @if let (Some(method), Some(order)) = (sort_method, sort_order) {
+                    input type="hidden" name="sort" value=(method) {}
+                    input type="hidden" name="order" value=(order) {}
+                }
+                button type="submit" title="Search" { "Search" }
+            }
+        }
+    }
+}
+
+/// Partial: number of search results and a way back to the listing
+fn search_summary(
+    search: &SearchSummary,
+    result_count: usize,
+    sort_method: Option<SortingMethod>,
+    sort_order: Option<SortingOrder>,
+) -> Markup {
+    html! {
+        p.search_summary {
+            (result_count) @if result_count == 1 { " result" } @else { " results" }
+            " for " code { (search.query) }
+            @if search.truncated {
+                " (the search was stopped early, refine it to see more results)"
+            }
+            " – "
+            a href=(parametrized_link(".", sort_method, sort_order, false)) { "Back to the listing" }
+        }
+    }
+}
+
+/// Partial: space left for uploads
+fn upload_space(space: &Space, show_exact_bytes: bool) -> Markup {
+    let format_size = |size: u64| {
+        if show_exact_bytes {
+            format!("{size} B")
+        } else {
+            ByteSize::b(size).to_string()
+        }
+    };
+    html! {
+        @if space.quota_left.is_some() || space.disk_free.is_some() {
+            p.upload_space {
+                @if let Some(quota_left) = space.quota_left {
+                    span.quota_left { (format_size(quota_left)) " left in the upload quota" }
+                }
+                @if space.quota_left.is_some() && space.disk_free.is_some() {
+                    " – "
+                }
+                @if let Some(disk_free) = space.disk_free {
+                    span.disk_free { (format_size(disk_free)) " free on disk" }
+                }
+            }
+        }
+    }
+}
+
 /// Renders the file listing
 pub fn raw(entries: Vec<Entry>, is_root: bool, conf: &MiniserveConfig) -> Markup {
     html! {
@@ -265,7 +426,7 @@ pub fn raw(entries: Vec<Entry>, is_root: bool, conf: &MiniserveConfig) -> Markup
                             }
                         }
                         @for entry in entries {
-                            (entry_row(entry, None, None, true, conf.show_exact_bytes, None, &conf.route_prefix))
+                            (entry_row(entry, None, None, true, conf.show_exact_bytes, None, &conf.route_prefix, !conf.drop_box))
                         }
                     }
                 }
@@ -346,12 +507,11 @@ fn wget_footer(
     let command = format!(
         "wget -rcnp -R 'index.html*'{span_hosts_option}{cut_dirs}{user_params} '{encoded_abs_path}?raw=true'"
     );
-    let click_to_copy = format!("navigator.clipboard.writeText(\"{command}\")");
 
     html! {
         div.downloadDirectory {
             p { "Download folder:" }
-            a.cmd title="Click to copy!" style="cursor: pointer;" onclick=(click_to_copy) { (command) }
+            a.cmd title="Click to copy!" style="cursor: pointer;" data-copy=(command) { (command) }
         }
     }
 }
@@ -462,7 +622,7 @@ fn color_scheme_link(color_scheme: &(&str, &str)) -> Markup {
     let title = format!("Switch to {} theme", color_scheme.0);
 
     html! {
-        a href=(format!("javascript:updateColorScheme(\"{}\")", color_scheme.1)) title=(title) {
+        a href="#" data-color-scheme=(color_scheme.1) title=(title) {
             (color_scheme.0)
         }
     }
@@ -574,13 +734,177 @@ fn rm_form(rm_route: &str, encoded_path: &str, prefix: &str) -> Markup {
     }
 }
 
+/// Partial: move and copy form, prefilled with the current path of the entry
+fn mv_form(
+    mv_route: Option<&str>,
+    cp_route: Option<&str>,
+    encoded_path: &str,
+    prefix: &str,
+) -> Markup {
+    let stripped_path = encoded_path.strip_prefix(prefix).unwrap_or(encoded_path);
+    let query = format!("?path={stripped_path}");
+    let destination = percent_decode_str(stripped_path.trim_end_matches('/')).decode_utf8_lossy();
+
+    html! {
+        details class="mv_form" {
+            summary title="Move or copy" { "⇄" }
+            form method="POST" {
+                input type="text" name="destination" value=(destination) required;
+                @if let Some(mv_route) = mv_route {
+                    button type="submit" formaction={ (mv_route) (query) } { "Move" }
+                }
+                @if let Some(cp_route) = cp_route {
+                    button type="submit" formaction={ (cp_route) (query) } { "Copy" }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct ActionsConf<'a> {
-    /// Route prefix for file removal POST requests.
-    rm_route: &'a str,
+    /// Route prefix for file removal POST requests, if removals are allowed.
+    rm_route: Option<&'a str>,
+
+    /// Route prefix for file move POST requests, if moves are allowed.
+    mv_route: Option<&'a str>,
+
+    /// Route prefix for file copy POST requests, if copies are allowed.
+    cp_route: Option<&'a str>,
+}
+
+/// Partial: links to play the media of the directory and to download their playlist, in the
+/// order of the listing
+fn playlist_links(sort_method: Option<SortingMethod>, sort_order: Option<SortingOrder>) -> Markup {
+    let mut params = String::new();
+    if let Some(sort_method) = sort_method {
+        params.push_str(&format!("&sort={sort_method}"));
+    }
+    if let Some(sort_order) = sort_order {
+        params.push_str(&format!("&order={sort_order}"));
+    }
+
+    html! {
+        div.playlist_tools {
+            a.play_all href={ "?play=1" (params) } { "▶ Play all" }
+            a.m3u8_link href={ "?format=m3u8" (params) } title="Playlist for media players like VLC and mpv" { "Download .m3u8" }
+        }
+    }
+}
+
+/// Partial: gallery view of the `entries`, with the thumbnails of the images and a lightbox to
+/// browse them
+fn gallery(
+    entries: &[Entry],
+    sort_method: Option<SortingMethod>,
+    sort_order: Option<SortingOrder>,
+    nonce: &str,
+) -> Markup {
+    html! {
+        div.gallery #gallery hidden {
+            @for entry in entries {
+                @if let Some(ref thumbnail_link) = entry.thumbnail_link {
+                    a.gallery_item.gallery_image href=(entry.link) title=(entry.name) data-lightbox {
+                        img src=(thumbnail_link) alt=(entry.name) loading="lazy";
+                        span.gallery_name { (entry.name) }
+                    }
+                } @else if entry.is_dir() {
+                    a.gallery_item.directory href=(parametrized_link(&entry.link, sort_method, sort_order, false)) title=(entry.name) {
+                        span.gallery_icon { "📁" }
+                        span.gallery_name { (entry.name) "/" }
+                    }
+                } @else {
+                    a.gallery_item.file href=(entry.link) title=(entry.name) {
+                        span.gallery_icon { "📄" }
+                        span.gallery_name { (entry.name) }
+                    }
+                }
+            }
+        }
+        div.lightbox #lightbox hidden {
+            button.lightbox_close #lightbox_close type="button" title="Close (Esc)" { "✖" }
+            button.lightbox_previous #lightbox_previous type="button" title="Previous image (←)" { (chevron_left()) }
+            figure {
+                img #lightbox_image alt="";
+                figcaption #lightbox_caption { }
+            }
+            button.lightbox_next #lightbox_next type="button" title="Next image (→)" { "▸" }
+        }
+        script nonce=(nonce) {
+            (PreEscaped(r#"
+                const listing = document.getElementById('listing');
+                const gallery = document.getElementById('gallery');
+                const layoutButtons = document.querySelectorAll('.layout_toggle button');
+
+                // Switch between the table and the gallery, and remember the choice
+                function setLayout(layout) {
+                    const isGallery = layout === 'gallery';
+                    listing.hidden = isGallery;
+                    gallery.hidden = !isGallery;
+                    layoutButtons.forEach(button => button.setAttribute('aria-pressed', button.dataset.layout === layout));
+                    localStorage.setItem('layout', isGallery ? 'gallery' : 'list');
+                }
+                layoutButtons.forEach(button => button.addEventListener('click', () => setLayout(button.dataset.layout)));
+                setLayout(localStorage.getItem('layout'));
+
+                const lightbox = document.getElementById('lightbox');
+                const lightboxImage = document.getElementById('lightbox_image');
+                const lightboxCaption = document.getElementById('lightbox_caption');
+                const images = Array.from(gallery.querySelectorAll('a[data-lightbox]'));
+                let current = 0;
+
+                function showImage(index) {
+                    current = (index + images.length) % images.length;
+                    lightboxImage.src = images[current].href;
+                    lightboxImage.alt = images[current].title;
+                    lightboxCaption.textContent = `${images[current].title} (${current + 1}/${images.length})`;
+                    lightbox.hidden = false;
+                }
+
+                function closeLightbox() {
+                    lightbox.hidden = true;
+                    lightboxImage.removeAttribute('src');
+                }
+
+                // Plain clicks open the lightbox, while the others keep opening the image itself
+                images.forEach((link, index) => link.addEventListener('click', event => {
+                    if (event.button !== 0 || event.ctrlKey || event.metaKey || event.shiftKey || event.altKey) {
+                        return;
+                    }
+                    event.preventDefault();
+                    showImage(index);
+                }));
+                document.getElementById('lightbox_close').addEventListener('click', closeLightbox);
+                document.getElementById('lightbox_previous').addEventListener('click', () => showImage(current - 1));
+                document.getElementById('lightbox_next').addEventListener('click', () => showImage(current + 1));
+                lightbox.addEventListener('click', event => {
+                    if (event.target === lightbox) {
+                        closeLightbox();
+                    }
+                });
+                document.addEventListener('keydown', event => {
+                    if (lightbox.hidden) {
+                        return;
+                    }
+                    switch (event.key) {
+                        case 'Escape': closeLightbox(); break;
+                        case 'ArrowLeft': showImage(current - 1); break;
+                        case 'ArrowRight': showImage(current + 1); break;
+                        case 'Home': showImage(0); break;
+                        case 'End': showImage(images.length - 1); break;
+                        default: return;
+                    }
+                    event.preventDefault();
+                });
+            "#))
+        }
+    }
 }
 
 /// Partial: row for an entry
+///
+/// Files are only linked to if `link_files` is true, i.e. if they can be downloaded.
+#[allow(clippy::too_many_arguments)]
 fn entry_row(
     entry: Entry,
     sort_method: Option<SortingMethod>,
@@ -589,6 +913,7 @@ fn entry_row(
     show_exact_bytes: bool,
     actions_conf: Option<ActionsConf>,
     route_prefix: &str,
+    link_files: bool,
 ) -> Markup {
     html! {
         @let entry_type = entry.entry_type.clone();
@@ -608,7 +933,9 @@ fn entry_row(
                             }
                         }
                     } @else if entry.is_file() {
-                        @if let Some(ref symlink_dest) = entry.symlink_info {
+                        @if !link_files {
+                            span.file { (entry.name) }
+                        } @else if let Some(ref symlink_dest) = entry.symlink_info {
                             a.symlink href=(&entry.link) {
                                 (entry.name)
                                 span.symlink-symbol { }
@@ -619,6 +946,21 @@ fn entry_row(
                                 (entry.name)
                             }
                         }
+                        @if let Some(ref view_link) = entry.view_link {
+                            @if !raw {
+                                a.view_link href=(view_link) title="View with syntax highlighting" { "</>" }
+                            }
+                        }
+                        @if let Some(ref play_link) = entry.play_link {
+                            @if !raw {
+                                a.play_link href=(play_link) title="Play" { "▶" }
+                            }
+                        }
+                        @if let Some(ref versions_link) = entry.versions_link {
+                            @if !raw {
+                                a.versions_link href=(versions_link) title="Previous versions" { "⟲" }
+                            }
+                        }
 
                         @if !raw {
                             @if let Some(size) = entry.size {
@@ -637,6 +979,11 @@ fn entry_row(
                                     (sortable_title("date", &modification_timer, sort_method, sort_order))
                                 }
                             }
+                            @if let Some(expiry_timer) = humanize_systemtime(entry.expires_at) {
+                                span.mobile-info.expiry {
+                                    "expires " (expiry_timer)
+                                }
+                            }
                         }
                     }
                 }
@@ -661,10 +1008,20 @@ fn entry_row(
                         (modification_timer)
                     }
                 }
+                @if let Some(expiry_timer) = humanize_systemtime(entry.expires_at) {
+                    span.expiry title=(convert_to_local(entry.expires_at).unwrap_or_default()) {
+                        "expires " (expiry_timer)
+                    }
+                }
             }
             @if let Some(conf) = actions_conf {
                 td.actions-cell {
-                    (rm_form(conf.rm_route, &entry.link, route_prefix))
+                    @if conf.cp_route.is_some() {
+                        (mv_form(conf.mv_route, conf.cp_route, &entry.link, route_prefix))
+                    }
+                    @if let Some(rm_route) = conf.rm_route {
+                        (rm_form(rm_route, &entry.link, route_prefix))
+                    }
                 }
             }
         }
@@ -691,7 +1048,7 @@ fn chevron_down() -> Markup {
     PreEscaped("▾".to_string())
 }
 
-/// Partial: page header
+/// Partial: page header, whose scripts carry the `nonce` of the Content-Security-Policy if any
 fn page_header(
     title: &str,
     file_upload: bool,
@@ -699,6 +1056,7 @@ fn page_header(
     api_route: &str,
     favicon_route: &str,
     css_route: &str,
+    nonce: Option<&str>,
 ) -> Markup {
     html! {
         head {
@@ -712,7 +1070,7 @@ fn page_header(
 
             title { (title) }
 
-            script {
+            script nonce=[nonce] {
                 (PreEscaped(r#"
                     // updates the color scheme by setting the theme data attribute
                     // on body and saving the new theme to local storage
@@ -736,10 +1094,24 @@ fn page_header(
                     addEventListener("load", loadColorScheme);
                     // load saved theme when local storage is changed (synchronize between tabs)
                     addEventListener("storage", loadColorScheme);
+
+                    // handle the links of the theme selector and the commands to copy, which
+                    // can't have inline handlers under the Content-Security-Policy
+                    addEventListener("click", event => {
+                        const themeLink = event.target.closest("a[data-color-scheme]");
+                        if (themeLink) {
+                            event.preventDefault();
+                            updateColorScheme(themeLink.dataset.colorScheme);
+                        }
+                        const command = event.target.closest("[data-copy]");
+                        if (command) {
+                            navigator.clipboard.writeText(command.dataset.copy);
+                        }
+                    });
                 "#))
             }
 
-            script {
+            script nonce=[nonce] {
                 (format!("const API_ROUTE = '{api_route}';"))
                 (PreEscaped(r#"
                     let dirSizeCache = {};
@@ -783,7 +1155,7 @@ fn page_header(
             }
 
             @if file_upload {
-                script {
+                script nonce=[nonce] {
                     (format!("const CONCURRENCY = {web_file_concurrency};"))
                     (PreEscaped(r#"
                     window.onload = function() {
@@ -806,6 +1178,7 @@ fn page_header(
                         const dropContainer = document.querySelector('#drop-container');
                         const dragForm = document.querySelector('.drag-form');
                         const fileInput = document.querySelector('#file-input');
+                        const folderInput = document.querySelector('#folder-input');
                         const collection = [];
 
                         dropContainer.ondragover = function(e) {
@@ -830,11 +1203,44 @@ fn page_header(
 
                         dropContainer.ondrop = function(e) {
                             e.preventDefault();
-                            fileInput.files = e.dataTransfer.files;
-                            form.requestSubmit();
                             dragForm.style.display = 'none';
+                            // Folders can only be uploaded to servers which let directories be created
+                            const entries = Array.from(e.dataTransfer.items || [])
+                                .map(item => item.webkitGetAsEntry && item.webkitGetAsEntry())
+                                .filter(entry => entry);
+                            if (!folderInput || !entries.some(entry => entry.isDirectory)) {
+                                fileInput.files = e.dataTransfer.files;
+                                form.requestSubmit();
+                                return;
+                            }
+                            Promise.all(entries.map(droppedFiles))
+                                .then(items => uploadFiles(items.flat()));
                         };
 
+                        // Files of a dropped entry, along with their path relative to the drop.
+                        // Directories are read in batches, as that's how browsers return them.
+                        async function droppedFiles(entry) {
+                            if (entry.isFile) {
+                                const file = await new Promise((resolve, reject) => entry.file(resolve, reject));
+                                return [{ file, path: entry.fullPath.slice(1) }];
+                            }
+                            const reader = entry.createReader();
+                            const children = [];
+                            let batch;
+                            do {
+                                batch = await new Promise((resolve, reject) => reader.readEntries(resolve, reject));
+                                children.push(...batch);
+                            } while (batch.length > 0);
+                            const files = await Promise.all(children.map(droppedFiles));
+                            return files.flat();
+                        }
+
+                        if (folderInput) {
+                            folderInput.addEventListener('change', function () {
+                                uploadFiles(Array.from(folderInput.files).map(file => ({ file, path: file.webkitRelativePath })));
+                            })
+                        }
+
                         // Event listener for toggling the upload widget display on mobile.
                         uploadWidgetToggle.addEventListener('click', function (e) {
                             e.preventDefault();
@@ -857,7 +1263,7 @@ fn page_header(
 
                         form.addEventListener('submit', function (e) {
                             e.preventDefault()
-                            uploadFiles()
+                            uploadFiles(Array.from(fileInput.files).map(file => ({ file, path: file.name })))
                         })
 
                         // When uploads start, finish or are cancelled, the UI needs to reactively shows those
@@ -894,13 +1300,17 @@ fn page_header(
 
                         // Initiates the file upload process by disabling the ability for more files to be
                         // uploaded and creating async callbacks for each file that needs to be uploaded.
-                        // Given the concurrency set by the server input arguments, it will try to process
-                        // that many uploads at once
-                        function uploadFiles() {
+                        // Each item is a file along with the path to upload it to, relative to the
+                        // current directory. Given the concurrency set by the server input arguments,
+                        // it will try to process that many uploads at once
+                        function uploadFiles(items) {
                             fileInput.disabled = true;
+                            if (folderInput) {
+                                folderInput.disabled = true;
+                            }
 
                             // Map all the files into async callbacks (uploadFile is a function that returns a function)
-                            const callbacks = Array.from(fileInput.files).map(uploadFile);
+                            const callbacks = items.map(uploadFile);
 
                             // Get a list of all the callbacks
                             const concurrency = CONCURRENCY === 0 ? callbacks.length : CONCURRENCY;
@@ -947,6 +1357,144 @@ fn page_header(
                           const file = e.target.files[0];
                         });
 
+                        const TUS_ACTION = form.dataset.tus;
+                        const TUS_CHUNK_SIZE = 8 * 1024 * 1024;
+                        const TUS_MAX_RETRIES = 5;
+
+                        // Send a single request of the tus protocol. The request in flight is kept
+                        // in `upload.xhr` so that it can be aborted.
+                        function tusRequest(upload, method, url, headers, body, onProgress) {
+                            return new Promise((resolve, reject) => {
+                                const xhr = new XMLHttpRequest();
+                                upload.xhr = xhr;
+                                xhr.open(method, url, true);
+                                xhr.setRequestHeader('Tus-Resumable', '1.0.0');
+                                for (const [name, value] of Object.entries(headers)) {
+                                    xhr.setRequestHeader(name, value);
+                                }
+                                if (onProgress) {
+                                    xhr.upload.addEventListener('progress', e => onProgress(e.loaded));
+                                }
+                                xhr.addEventListener('load', () => resolve(xhr));
+                                xhr.addEventListener('error', () => reject(new Error('Network error')));
+                                xhr.addEventListener('abort', () => reject(new Error('Upload aborted')));
+                                xhr.send(body);
+                            })
+                        }
+
+                        // Upload a file in chunks with the tus protocol. The upload URL is remembered
+                        // in the local storage until the upload is complete, so that an interrupted
+                        // upload resumes where it stopped, even after reloading the page. Failed
+                        // chunks are retried with an exponential backoff.
+                        // Resolves with the HTTP status of the upload.
+                        async function tusUpload(file, path, fileHash, upload, onProgress) {
+                            upload.key = `tus:${TUS_ACTION}:${path}:${file.size}:${file.lastModified}`;
+                            upload.location = localStorage.getItem(upload.key);
+                            let offset = null;
+
+                            if (upload.location) {
+                                const resp = await tusRequest(upload, 'HEAD', upload.location, {});
+                                if (resp.status === 200) {
+                                    offset = parseInt(resp.getResponseHeader('Upload-Offset'));
+                                } else {
+                                    localStorage.removeItem(upload.key);
+                                }
+                            }
+
+                            if (offset === null) {
+                                const filename = String.fromCharCode(...new TextEncoder().encode(path));
+                                const headers = {
+                                    'Upload-Length': file.size,
+                                    'Upload-Metadata': `filename ${btoa(filename)}`,
+                                };
+                                if (fileHash) {
+                                    headers['X-File-Hash'] = fileHash;
+                                    headers['X-File-Hash-Function'] = 'SHA256';
+                                }
+                                const extractInput = document.querySelector('#extract-input');
+                                const action = extractInput && extractInput.checked ? `${TUS_ACTION}&extract=true` : TUS_ACTION;
+                                const resp = await tusRequest(upload, 'POST', action, headers);
+                                if (resp.status !== 201) {
+                                    return resp.status;
+                                }
+                                upload.location = resp.getResponseHeader('Location');
+                                offset = 0;
+                                if (file.size > 0) {
+                                    localStorage.setItem(upload.key, upload.location);
+                                }
+                            }
+                            onProgress(offset);
+
+                            let retries = 0;
+                            while (offset < file.size) {
+                                try {
+                                    const chunk = file.slice(offset, offset + TUS_CHUNK_SIZE);
+                                    const headers = {
+                                        'Upload-Offset': offset,
+                                        'Content-Type': 'application/offset+octet-stream',
+                                    };
+                                    const chunkOffset = offset;
+                                    const resp = await tusRequest(upload, 'PATCH', upload.location, headers, chunk,
+                                        loaded => onProgress(chunkOffset + loaded));
+                                    if (resp.status === 204) {
+                                        offset = parseInt(resp.getResponseHeader('Upload-Offset'));
+                                        retries = 0;
+                                        continue;
+                                    }
+                                    // Only server errors and conflicting requests are worth retrying
+                                    if (resp.status < 500 && resp.status !== 409) {
+                                        localStorage.removeItem(upload.key);
+                                        return resp.status;
+                                    }
+                                } catch (e) {
+                                    if (upload.aborted) {
+                                        throw e;
+                                    }
+                                }
+
+                                if (++retries > TUS_MAX_RETRIES) {
+                                    return 0;
+                                }
+                                await new Promise(resolve => setTimeout(resolve, 500 * 2 ** retries));
+                                if (upload.aborted) {
+                                    throw new Error('Upload aborted');
+                                }
+
+                                // Find out how much the server actually received before trying again
+                                try {
+                                    const resp = await tusRequest(upload, 'HEAD', upload.location, {});
+                                    if (resp.status === 200) {
+                                        offset = parseInt(resp.getResponseHeader('Upload-Offset'));
+                                    } else if (resp.status === 404) {
+                                        localStorage.removeItem(upload.key);
+                                        return resp.status;
+                                    }
+                                } catch (e) {
+                                    if (upload.aborted) {
+                                        throw e;
+                                    }
+                                }
+                            }
+
+                            localStorage.removeItem(upload.key);
+                            return 200;
+                        }
+
+                        // Abort a tus upload and delete what was already sent from the server
+                        function abortTusUpload(upload) {
+                            upload.aborted = true;
+                            if (upload.xhr) {
+                                upload.xhr.abort();
+                            }
+                            if (upload.location) {
+                                localStorage.removeItem(upload.key);
+                                fetch(upload.location, {
+                                    method: 'DELETE',
+                                    headers: { 'Tus-Resumable': '1.0.0' },
+                                }).catch(() => {});
+                            }
+                        }
+
                         async function get256FileHash(file) {
                           const arrayBuffer = await file.arrayBuffer();
                           const hashBuffer = await crypto.subtle.digest('SHA-256', arrayBuffer);
@@ -954,11 +1502,11 @@ fn page_header(
                           return hashArray.map(b => b.toString(16).padStart(2, '0')).join('');
                         }
 
-                        // Upload a file. This function will create a upload item in the upload
-                        // widget from an HTML template. It then returns a promise which will
+                        // Upload a file to `path`. This function will create a upload item in the
+                        // upload widget from an HTML template. It then returns a promise which will
                         // be used to upload the file to the server and control the styles and
                         // interactions on the HTML list item.
-                        function uploadFile(file) {
+                        function uploadFile({ file, path }) {
                             const fileUploadItem = fileUploadItemTemplate.content.cloneNode(true)
                             const itemContainer = fileUploadItem.querySelector(".upload_file_item")
                             const itemText = fileUploadItem.querySelector(".upload_file_text")
@@ -970,7 +1518,7 @@ fn page_header(
                             let preCancel = false;
 
                             itemContainer.dataset.state = PENDING
-                            name.textContent = file.name
+                            name.textContent = path
                             size.textContent = formatBytes(file.size)
                             percentText.textContent = "0%"
 
@@ -999,7 +1547,7 @@ fn page_header(
                                     return Promise.resolve()
                                 }
 
-                                // Upload the single file in a multipart request.
+                                // Upload the single file with the tus protocol.
                                 return new Promise(async (resolve, reject) => {
                                     // File hash calculation may fail at times:
                                     //   1. `crypto.subtle` is not available in nonsecure context (e.g. non-HTTPS LAN).
@@ -1007,30 +1555,10 @@ fn page_header(
                                     //   2. For files larger than 2GB, Firefox will refuse to calculate the SHA-256 value,
                                     //      while Chrome will refuse to create a ArrayBuffer (#1541).
                                     const fileHash = await get256FileHash(file).catch(() => "");
-                                    const xhr = new XMLHttpRequest();
-                                    const formData = new FormData();
-                                    formData.append('file', file);
-
-                                    function onReadyStateChange(e) {
-                                        if (e.target.readyState == 4) {
-                                            if (e.target.status == 200) {
-                                                completeSuccess()
-                                            } else {
-                                                failedUpload(e.target.status)
-                                            }
-                                        }
-                                    }
-
-                                    function onError(e) {
-                                        failedUpload()
-                                    }
-
-                                    function onAbort(e) {
-                                        cancelUpload()
-                                    }
+                                    const upload = {};
 
-                                    function onProgress (e) {
-                                        update(Math.round((e.loaded / e.total) * 100));
+                                    function onProgress(loaded) {
+                                        update(file.size ? (loaded / file.size) * 100 : 100);
                                     }
 
                                     function update(uploadPercent) {
@@ -1054,7 +1582,7 @@ fn page_header(
                                     }
 
                                     function cancelUpload() {
-                                        xhr.abort()
+                                        abortTusUpload(upload)
                                         itemText.classList.add(CANCELLED);
                                         bar.classList.add(CANCELLED);
                                         cleanUp(CANCELLED);
@@ -1066,10 +1594,6 @@ fn page_header(
                                         cancel.disabled = true;
                                         cancel.removeEventListener("click", cancelUpload)
                                         uploadCancelButton.removeEventListener("click", cancelUpload)
-                                        xhr.removeEventListener('readystatechange', onReadyStateChange);
-                                        xhr.removeEventListener("error", onError);
-                                        xhr.removeEventListener("abort", onAbort);
-                                        xhr.upload.removeEventListener('progress', onProgress);
                                         resolve()
                                     }
 
@@ -1080,16 +1604,9 @@ fn page_header(
                                         cancelUpload()
                                     } else {
                                         itemContainer.dataset.state = UPLOADING
-                                        xhr.addEventListener('readystatechange', onReadyStateChange);
-                                        xhr.addEventListener("error", onError);
-                                        xhr.addEventListener("abort", onAbort);
-                                        xhr.upload.addEventListener('progress', onProgress);
-                                        xhr.open('post', form.getAttribute("action"), true);
-                                        if (fileHash) {
-                                            xhr.setRequestHeader('X-File-Hash', fileHash);
-                                            xhr.setRequestHeader('X-File-Hash-Function', 'SHA256');
-                                        }
-                                        xhr.send(formData);
+                                        tusUpload(file, path, fileHash, upload, onProgress)
+                                            .then(status => status < 300 ? completeSuccess() : failedUpload(status))
+                                            .catch(() => upload.aborted || failedUpload())
                                     }
                                 })
                             }
@@ -1103,6 +1620,7 @@ fn page_header(
                         const pastebinForm = document.querySelector('form#pastebin');
                         const pastebinFilename = pastebinForm.querySelector('input[name=paste_filename]');
                         const pastebinContent = pastebinForm.querySelector('textarea');
+                        const pastebinExpire = pastebinForm.querySelector('select[name=paste_expire]');
                         pastebinContent.addEventListener('keydown', (event) => {
                             // common convenience of ctrl-enter to submit
                             if (event.key === 'Enter' && (event.ctrlKey || event.metaKey)) {
@@ -1140,6 +1658,9 @@ fn page_header(
                             const container = new DataTransfer();
                             container.items.add(file);
                             fileUploadInput.files = container.files;
+                            if (pastebinExpire.value) {
+                                fileUploadForm.action += `&expire=${encodeURIComponent(pastebinExpire.value)}`;
+                            }
                             fileUploadForm.submit();
                         });
                     }
@@ -1157,13 +1678,527 @@ fn convert_to_local(src_time: Option<SystemTime>) -> Option<String> {
         .map(|date_time| date_time.format("%Y-%m-%d %H:%M:%S %:z").to_string())
 }
 
+/// Expiry choices offered when creating a paste, besides the longest expiry allowed
+const PASTE_EXPIRY_CHOICES: [Duration; 4] = [
+    Duration::from_secs(10 * 60),
+    Duration::from_secs(60 * 60),
+    Duration::from_secs(24 * 60 * 60),
+    Duration::from_secs(7 * 24 * 60 * 60),
+];
+
+/// Value of the `expire` parameter for an upload to expire after `duration`, along with its
+/// description, in the largest unit `duration` is a whole number of
+fn expiry_choice(duration: Duration) -> (String, String) {
+    let secs = duration.as_secs();
+    let units = [
+        (7 * 24 * 60 * 60, "w", "week"),
+        (24 * 60 * 60, "d", "day"),
+        (60 * 60, "h", "hour"),
+        (60, "m", "minute"),
+        (1, "s", "second"),
+    ];
+    let (unit, suffix, name) = units
+        .into_iter()
+        .find(|(unit, _, _)| secs.is_multiple_of(*unit))
+        .unwrap_or(units[4]);
+    let count = secs / unit;
+    let plural = if count == 1 { "" } else { "s" };
+    (
+        format!("{count}{suffix}"),
+        format!("{count} {name}{plural}"),
+    )
+}
+
 /// Converts a SystemTime to a string readable by a human,
 /// and gives a rough approximation of the elapsed time since
 fn humanize_systemtime(time: Option<SystemTime>) -> Option<String> {
     time.map(|time| time.humanize())
 }
 
+/// Renders the history page of the file at `path`, listing its previous `versions` with actions
+/// to download them, and to restore them if `can_restore`
+pub fn versions_page(
+    path: &Path,
+    versions: &[Version],
+    can_restore: bool,
+    conf: &MiniserveConfig,
+) -> Markup {
+    let versions_route = format!("{}{VERSIONS_ROUTE}", conf.route_prefix);
+    let encoded_path = path
+        .iter()
+        .map(|component| utf8_percent_encode(&component.to_string_lossy(), COMPONENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    let dir_link = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            let dir = encoded_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            format!("{}/{dir}/", conf.route_prefix)
+        }
+        _ => format!("{}/", conf.route_prefix),
+    };
+    let title = format!("History of /{}", path.to_string_lossy());
+
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&title, false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
+
+            body {
+                nav {
+                    (color_scheme_selector(conf.hide_theme_selector))
+                }
+                div.container {
+                    span #top { }
+                    h1.title { (title) }
+                    p.versions_summary {
+                        @match conf.max_versions {
+                            Some(max_versions) => {
+                                "The last " (max_versions) " versions of replaced files are kept. "
+                            }
+                            None => "All the versions of replaced files are kept. ",
+                        }
+                        a href=(dir_link) { "Back to the listing" }
+                    }
+                    @if versions.is_empty() {
+                        p.versions_summary { "There are no previous versions of this file." }
+                    } @else {
+                        table {
+                            thead {
+                                th.name { span { "Version" } }
+                                th.size { span { "Size" } }
+                                th.date { span { "Last modification" } }
+                                th.actions { span { "Actions" } }
+                            }
+                            tbody {
+                                @for version in versions {
+                                    @let query = format!("?path={encoded_path}&version={}", version.number);
+                                    tr.entry-type-file {
+                                        td {
+                                            p {
+                                                a.file href={ (versions_route) "/download" (query) } {
+                                                    "Version " (version.number)
+                                                }
+                                            }
+                                        }
+                                        td.size-cell {
+                                            @if conf.show_exact_bytes {
+                                                (maud::display(format!("{} B", version.size)))
+                                            } @else {
+                                                (maud::display(ByteSize::b(version.size)))
+                                            }
+                                        }
+                                        td.date-cell {
+                                            @if let Some(modification_date) = convert_to_local(version.modified) {
+                                                span { (modification_date) " " }
+                                            }
+                                            @if let Some(modification_timer) = humanize_systemtime(version.modified) {
+                                                span.history { (modification_timer) }
+                                            }
+                                        }
+                                        td.actions-cell {
+                                            @if can_restore {
+                                                form.restore_form action={ (versions_route) "/restore" (query) } method="POST" {
+                                                    button type="submit" title="Restore" { "Restore" }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    @if !conf.hide_version_footer {
+                        div.footer {
+                            (version_footer())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the viewer page of the text `file` at `path`, relative to the served path, whose plain
+/// URL is `link`
+pub fn viewer_page(path: &Path, link: &str, file: &ViewedFile, conf: &MiniserveConfig) -> Markup {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir_link = link.rsplit_once('/').map_or("/", |(dir, _)| dir);
+    let title = format!("/{}", path.to_string_lossy());
+
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&title, false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
+
+            body {
+                nav {
+                    (color_scheme_selector(conf.hide_theme_selector))
+                }
+                div.container {
+                    span #top { }
+                    h1.title { (title) }
+                    div.viewer_toolbar {
+                        a href={ (dir_link) "/" } { "Back to the listing" }
+                        span.viewer_info {
+                            (file.lines.len()) " lines, "
+                            @if conf.show_exact_bytes {
+                                (maud::display(format!("{} B", file.size)))
+                            } @else {
+                                (maud::display(ByteSize::b(file.size)))
+                            }
+                            ", " (file.syntax)
+                        }
+                        div.viewer_actions {
+                            button #wrap_toggle type="button" aria-pressed="false" { "Wrap lines" }
+                            a href=(link) { "Raw" }
+                            a href=(link) download=(name) { "Download" }
+                        }
+                    }
+                    table.viewer #viewer {
+                        tbody {
+                            @for (index, line) in file.lines.iter().enumerate() {
+                                @let number = index + 1;
+                                tr id={ "L" (number) } {
+                                    td.line_number {
+                                        a href={ "#L" (number) } data-line=(number) { (number) }
+                                    }
+                                    td.line { (PreEscaped(line)) }
+                                }
+                            }
+                        }
+                    }
+                    @if !conf.hide_version_footer {
+                        div.footer {
+                            (version_footer())
+                        }
+                    }
+                }
+                script {
+                    (PreEscaped(r#"
+                        const viewer = document.getElementById('viewer');
+                        const wrapToggle = document.getElementById('wrap_toggle');
+
+                        // Lines selected by the `#L10` or `#L10-L20` fragment, if any
+                        function selectedLines() {
+                            const match = location.hash.match(/^#L(\d+)(?:-L(\d+))?$/);
+                            if (!match) {
+                                return null;
+                            }
+                            const first = Number(match[1]);
+                            const last = Number(match[2] || match[1]);
+                            return [Math.min(first, last), Math.max(first, last)];
+                        }
+
+                        function highlightSelection(scroll) {
+                            viewer.querySelectorAll('tr.selected').forEach(row => row.classList.remove('selected'));
+                            const lines = selectedLines();
+                            if (!lines) {
+                                return;
+                            }
+                            for (let number = lines[0]; number <= lines[1]; number++) {
+                                document.getElementById(`L${number}`)?.classList.add('selected');
+                            }
+                            if (scroll) {
+                                document.getElementById(`L${lines[0]}`)?.scrollIntoView({ block: 'center' });
+                            }
+                        }
+
+                        // Clicking a line number selects it, and shift-clicking selects a range
+                        let anchorLine = selectedLines()?.[0];
+                        viewer.addEventListener('click', event => {
+                            const link = event.target.closest('a[data-line]');
+                            if (!link) {
+                                return;
+                            }
+                            event.preventDefault();
+                            const line = Number(link.dataset.line);
+                            let hash = `#L${line}`;
+                            if (event.shiftKey && anchorLine !== undefined && anchorLine !== line) {
+                                hash = `#L${Math.min(anchorLine, line)}-L${Math.max(anchorLine, line)}`;
+                            } else {
+                                anchorLine = line;
+                            }
+                            history.replaceState(null, '', hash);
+                            highlightSelection(false);
+                        });
+                        addEventListener('hashchange', () => highlightSelection(true));
+                        highlightSelection(true);
+
+                        function setWrap(wrap) {
+                            viewer.classList.toggle('wrap', wrap);
+                            wrapToggle.setAttribute('aria-pressed', wrap);
+                            localStorage.setItem('viewer_wrap', wrap);
+                        }
+                        wrapToggle.addEventListener('click', () => setWrap(!viewer.classList.contains('wrap')));
+                        setWrap(localStorage.getItem('viewer_wrap') === 'true');
+                    "#))
+                }
+            }
+        }
+    }
+}
+
+/// Renders the page of the Markdown file at `path`, whose rendered HTML is `html`, with a table
+/// of contents of its `headings`
+pub fn markdown_page(
+    path: &Path,
+    link: &str,
+    html: &str,
+    headings: &[Heading],
+    conf: &MiniserveConfig,
+) -> Markup {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir_link = link.rsplit_once('/').map_or("/", |(dir, _)| dir);
+    let title = format!("/{}", path.to_string_lossy());
+
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(&title, false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
+
+            body {
+                nav {
+                    (color_scheme_selector(conf.hide_theme_selector))
+                }
+                div.container {
+                    span #top { }
+                    h1.title { (title) }
+                    div.viewer_toolbar {
+                        a href={ (dir_link) "/" } { "Back to the listing" }
+                        div.viewer_actions {
+                            a.view_source href={ (link) "?view=1" } { "View source" }
+                            a href={ (link) "?raw=true" } { "Raw" }
+                            a href={ (link) "?raw=true" } download=(name) { "Download" }
+                        }
+                    }
+                    @if headings.len() > 1 {
+                        div.toc #toc {
+                            h3 { "Contents" }
+                            ul {
+                                @for heading in headings {
+                                    li class={ "toc_level_" (heading.level) } {
+                                        a href={ "#" (heading.anchor) } { (heading.text) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div.markdown #markdown {
+                        (PreEscaped(html))
+                    }
+                    @if !conf.hide_version_footer {
+                        div.footer {
+                            (version_footer())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the player page of the media `items`, titled `title`, with a link `back_link` to their
+/// directory and optional links to play all the media of the directory and to their playlist
+pub fn player_page(
+    title: &str,
+    back_link: &str,
+    items: &[PlaylistItem],
+    play_all_link: Option<&str>,
+    playlist_link: Option<&str>,
+    conf: &MiniserveConfig,
+) -> Markup {
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header(title, false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
+
+            body {
+                nav {
+                    (color_scheme_selector(conf.hide_theme_selector))
+                }
+                div.container {
+                    span #top { }
+                    h1.title { (title) }
+                    div.viewer_toolbar {
+                        a href=(back_link) { "Back to the listing" }
+                        span.viewer_info #now_playing { }
+                        div.viewer_actions {
+                            @if items.len() > 1 {
+                                button #previous_media type="button" title="Previous" { "⏮" }
+                                button #next_media type="button" title="Next" { "⏭" }
+                            }
+                            @if let Some(play_all_link) = play_all_link {
+                                a href=(play_all_link) { "Play all" }
+                            }
+                            @if let Some(playlist_link) = playlist_link {
+                                a href=(playlist_link) title="Playlist for media players like VLC and mpv" { "Download .m3u8" }
+                            }
+                        }
+                    }
+                    @if items.is_empty() {
+                        p { "There are no audio or video files in this directory." }
+                    } @else {
+                        div.player {
+                            video #video_player controls preload="metadata" playsinline hidden { }
+                            audio #audio_player controls preload="metadata" hidden { }
+                        }
+                        ol.playlist #playlist {
+                            @for item in items {
+                                li {
+                                    a href=(item.link) data-kind=(item.kind.as_str()) { (item.name) }
+                                }
+                            }
+                        }
+                    }
+                    @if !conf.hide_version_footer {
+                        div.footer {
+                            (version_footer())
+                        }
+                    }
+                }
+                @if !items.is_empty() {
+                    script {
+                        (PreEscaped(r#"
+                            const players = {
+                                audio: document.getElementById('audio_player'),
+                                video: document.getElementById('video_player'),
+                            };
+                            const items = Array.from(document.querySelectorAll('#playlist a[data-kind]'));
+                            const nowPlaying = document.getElementById('now_playing');
+                            let current = 0;
+
+                            // Load the media at `index` in the player of its kind, playing it right
+                            // away unless it's the first one loaded with the page
+                            function load(index, autoplay) {
+                                if (index < 0 || index >= items.length) {
+                                    return;
+                                }
+                                current = index;
+                                const item = items[index];
+                                for (const [kind, player] of Object.entries(players)) {
+                                    if (kind === item.dataset.kind) {
+                                        player.hidden = false;
+                                        player.src = item.href;
+                                        if (autoplay) {
+                                            player.play().catch(() => {});
+                                        }
+                                    } else if (!player.hidden) {
+                                        player.pause();
+                                        player.removeAttribute('src');
+                                        player.load();
+                                        player.hidden = true;
+                                    }
+                                }
+                                items.forEach((other, i) => other.parentNode.classList.toggle('playing', i === index));
+                                nowPlaying.textContent = items.length > 1
+                                    ? `${index + 1}/${items.length}: ${item.textContent}`
+                                    : item.textContent;
+                            }
+
+                            // The media are played one after the other
+                            Object.values(players).forEach(player => player.addEventListener('ended', () => load(current + 1, true)));
+                            items.forEach((item, index) => item.addEventListener('click', event => {
+                                if (event.button !== 0 || event.ctrlKey || event.metaKey || event.shiftKey || event.altKey) {
+                                    return;
+                                }
+                                event.preventDefault();
+                                load(index, true);
+                            }));
+                            document.getElementById('previous_media')?.addEventListener('click', () => load(current - 1, true));
+                            document.getElementById('next_media')?.addEventListener('click', () => load(current + 1, true));
+                            load(0, false);
+                        "#))
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Renders an error on the webpage
+/// Renders the trash page, listing `entries` with actions to restore or purge them
+pub fn trash_page(entries: &[TrashEntry], conf: &MiniserveConfig) -> Markup {
+    let trash_route = format!("{}{TRASH_ROUTE}", conf.route_prefix);
+    let purge_route = format!("{trash_route}/purge");
+    let restore_route = format!("{trash_route}/restore");
+
+    html! {
+        (DOCTYPE)
+        html {
+            (page_header("Trash", false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
+
+            body {
+                nav {
+                    (color_scheme_selector(conf.hide_theme_selector))
+                }
+                div.container {
+                    span #top { }
+                    h1.title { "Trash" }
+                    p.trash_summary {
+                        @match conf.trash_retention {
+                            Some(retention) => {
+                                "Deleted files and directories are purged after "
+                                (retention.as_secs() / (24 * 60 * 60)) " days. "
+                            }
+                            None => "Deleted files and directories are kept until they are purged. ",
+                        }
+                        a href={ (conf.route_prefix) "/" } { "Back to the listing" }
+                    }
+                    @if entries.is_empty() {
+                        p.trash_summary { "The trash is empty." }
+                    } @else {
+                        form.trash_purge_all action=(purge_route) method="POST" {
+                            button type="submit" { "Empty the trash" }
+                        }
+                        table {
+                            thead {
+                                th.name { span { "Original path" } }
+                                th.date { span { "Deleted" } }
+                                th.user { span { "Deleted by" } }
+                                th.actions { span { "Actions" } }
+                            }
+                            tbody {
+                                @for entry in entries {
+                                    tr .{ "entry-type-" (if entry.is_dir { "directory" } else { "file" }) } {
+                                        td {
+                                            p {
+                                                "/" (entry.original_path.to_string_lossy())
+                                                @if entry.is_dir { "/" }
+                                            }
+                                        }
+                                        td.date-cell {
+                                            @if let Some(deletion_date) = convert_to_local(Some(entry.deleted_at)) {
+                                                span { (deletion_date) " " }
+                                            }
+                                            @if let Some(deletion_timer) = humanize_systemtime(Some(entry.deleted_at)) {
+                                                span.history { (deletion_timer) }
+                                            }
+                                        }
+                                        td { (entry.deleted_by.as_deref().unwrap_or("")) }
+                                        td.actions-cell {
+                                            form.restore_form action={ (restore_route) "?id=" (entry.id) } method="POST" {
+                                                button type="submit" title="Restore" { "Restore" }
+                                            }
+                                            form.rm_form action={ (purge_route) "?id=" (entry.id) } method="POST" {
+                                                button type="submit" title="Delete permanently" { "✗" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    @if !conf.hide_version_footer {
+                        div.footer {
+                            (version_footer())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub fn render_error(
     error_description: &str,
     error_code: StatusCode,
@@ -1173,7 +2208,7 @@ pub fn render_error(
     html! {
         (DOCTYPE)
         html {
-            (page_header(&error_code.to_string(), false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route))
+            (page_header(&error_code.to_string(), false, conf.web_upload_concurrency, &conf.api_route, &conf.favicon_route, &conf.css_route, None))
 
             body
             {
@@ -1209,7 +2244,7 @@ mod tests {
 
     fn to_html(wget_part: &str) -> String {
         format!(
-            r#"<div class="downloadDirectory"><p>Download folder:</p><a class="cmd" title="Click to copy!" style="cursor: pointer;" onclick="navigator.clipboard.writeText(&quot;wget -rcnp -R 'index.html*' {wget_part}/?raw=true'&quot;)">wget -rcnp -R 'index.html*' {wget_part}/?raw=true'</a></div>"#
+            r#"<div class="downloadDirectory"><p>Download folder:</p><a class="cmd" title="Click to copy!" style="cursor: pointer;" data-copy="wget -rcnp -R 'index.html*' {wget_part}/?raw=true'">wget -rcnp -R 'index.html*' {wget_part}/?raw=true'</a></div>"#
         )
     }
 
@@ -1305,4 +2340,28 @@ mod tests {
             expected_action
         )
     }
+
+    #[test]
+    fn test_mv_form_prefills_decoded_path() {
+        let html = mv_form(
+            Some("/prefix/mv"),
+            Some("/prefix/cp"),
+            "/prefix/some%20dir/",
+            "/prefix",
+        );
+
+        assert!(html.0.contains(r#"value="/some dir""#), "{}", html.0);
+        assert!(
+            html.0
+                .contains(r#"formaction="/prefix/mv?path=/some%20dir/""#),
+            "{}",
+            html.0
+        );
+        assert!(
+            html.0
+                .contains(r#"formaction="/prefix/cp?path=/some%20dir/""#),
+            "{}",
+            html.0
+        );
+    }
 }
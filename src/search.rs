@@ -0,0 +1,250 @@
+//! Recursive filename search from a listed directory
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_walkdir::{Filtering, WalkDir};
+use bytesize::ByteSize;
+use futures::{Stream, StreamExt};
+use percent_encoding::utf8_percent_encode;
+use regex::{Regex, RegexBuilder};
+
+use crate::errors::RuntimeError;
+use crate::file_utils::Visibility;
+use crate::listing::{Entry, EntryType, percent_encode_sets::COMPONENT};
+
+/// Characters that turn a search into a glob pattern
+const GLOB_CHARS: &[char] = &['*', '?', '['];
+
+/// What a search looks for in file names
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Glob pattern (`*`, `?` and `[...]`) matching the whole file name
+    Glob(Regex),
+
+    /// Case-insensitive substring of the file name, stored in lowercase
+    Substring(String),
+}
+
+impl Pattern {
+    /// Parse a search query, which is a glob if it contains any glob characters and a substring
+    /// otherwise. Both are case-insensitive.
+    pub fn parse(query: &str) -> Result<Self, RuntimeError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(RuntimeError::ParseError(
+                "search pattern".to_string(),
+                "the pattern is empty".to_string(),
+            ));
+        }
+
+        if query.contains(GLOB_CHARS) {
+            RegexBuilder::new(&glob_to_regex(query))
+                .case_insensitive(true)
+                .build()
+                .map(Self::Glob)
+                .map_err(|e| RuntimeError::ParseError("search pattern".to_string(), e.to_string()))
+        } else {
+            Ok(Self::Substring(query.to_lowercase()))
+        }
+    }
+
+    /// true if `name` matches the pattern
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Glob(regex) => regex.is_match(name),
+            Self::Substring(substring) => name.to_lowercase().contains(substring),
+        }
+    }
+}
+
+/// Translate a glob pattern into an anchored regex
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    class.push('^');
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' if !class.is_empty() && class != "^" => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' | '[' | ']' | '&' | '~' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        c => class.push(c),
+                    }
+                }
+                if closed {
+                    regex.push('[');
+                    regex.push_str(&class);
+                    regex.push(']');
+                } else {
+                    // Unterminated class, treat everything literally
+                    regex.push_str(&regex::escape(&format!("[{class}")));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Limits applied to every search
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    /// Maximum number of results
+    pub max_results: usize,
+
+    /// Maximum time spent searching
+    pub timeout: Duration,
+}
+
+/// What a finished search is shown with
+pub struct SearchSummary {
+    /// The search query as typed by the user
+    pub query: String,
+
+    /// true if the search stopped early because it hit one of its limits
+    pub truncated: bool,
+}
+
+/// Search `root` recursively for entries whose name matches `pattern`.
+///
+/// Results are streamed as they are found, with their name relative to `root` and their link
+/// relative to `base_link`, the URL path of `root`. Entries that `visibility` hides are skipped
+/// along with their content, and symlinked directories aren't descended into. The stream ends
+/// after `limits.max_results` results or when `limits.timeout` is over.
+pub fn search(
+    root: PathBuf,
+    base_link: String,
+    pattern: Pattern,
+    visibility: Visibility,
+    show_symlink_info: bool,
+    limits: SearchLimits,
+) -> impl Stream<Item = Entry> {
+    let walker = WalkDir::new(&root).filter(move |entry| async move {
+        let is_symlink = entry
+            .file_type()
+            .await
+            .is_ok_and(|file_type| file_type.is_symlink());
+        if visibility.allows(&entry.file_name(), is_symlink) {
+            Filtering::Continue
+        } else {
+            Filtering::IgnoreDir
+        }
+    });
+
+    walker
+        .filter_map(move |entry| {
+            let (root, base_link, pattern) = (root.clone(), base_link.clone(), pattern.clone());
+            async move {
+                let entry = entry.ok()?;
+                if !pattern.matches(&entry.file_name().to_string_lossy()) {
+                    return None;
+                }
+                let is_symlink = entry.file_type().await.ok()?.is_symlink();
+                let relative_path = entry.path().strip_prefix(&root).ok()?.to_path_buf();
+                let show_symlink_info = is_symlink && show_symlink_info;
+                to_entry(&entry.path(), &relative_path, &base_link, show_symlink_info).await
+            }
+        })
+        .take(limits.max_results)
+        .take_until(actix_web::rt::time::sleep(limits.timeout))
+}
+
+/// Build the listing entry of a search result. Like in the listing, entries whose metadata
+/// can't be read (e.g. broken symlinks) are skipped.
+async fn to_entry(
+    path: &Path,
+    relative_path: &Path,
+    base_link: &str,
+    show_symlink_info: bool,
+) -> Option<Entry> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    let entry_type = if metadata.is_dir() {
+        EntryType::Directory
+    } else if metadata.is_file() {
+        EntryType::File
+    } else {
+        return None;
+    };
+
+    let components = relative_path
+        .iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>();
+    let link = format!(
+        "{}/{}",
+        base_link.trim_end_matches('/'),
+        components
+            .iter()
+            .map(|c| utf8_percent_encode(c, COMPONENT).to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+    );
+    let symlink_info = if show_symlink_info {
+        tokio::fs::read_link(path)
+            .await
+            .ok()
+            .map(|target| target.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    Some(Entry::new(
+        components.join("/"),
+        entry_type,
+        link,
+        metadata.is_file().then(|| ByteSize::b(metadata.len())),
+        metadata.modified().ok(),
+        symlink_info,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("*.txt", "^.*\\.txt$")]
+    #[case("file?.rs", "^file.\\.rs$")]
+    #[case("[abc]*", "^[abc].*$")]
+    #[case("[!a-c]*", "^[^a-c].*$")]
+    #[case("[]]", "^[\\]]$")]
+    #[case("[oops", "^\\[oops$")]
+    fn glob_is_translated(#[case] glob: &str, #[case] regex: &str) {
+        assert_eq!(glob_to_regex(glob), regex);
+    }
+
+    #[rstest]
+    #[case("test", "test.txt", true)]
+    #[case("TEST", "my_test.txt", true)]
+    #[case("test", "other.txt", false)]
+    #[case("*.txt", "test.TXT", true)]
+    #[case("*.txt", "test.txt.bak", false)]
+    #[case("t?st.*", "test.rs", true)]
+    #[case("[ab]*", "bravo", true)]
+    #[case("[ab]*", "charlie", false)]
+    fn pattern_matches(#[case] query: &str, #[case] name: &str, #[case] expected: bool) {
+        assert_eq!(Pattern::parse(query).unwrap().matches(name), expected);
+    }
+
+    #[test]
+    fn empty_pattern_is_rejected() {
+        assert!(Pattern::parse("  ").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Thumbnails of images for the gallery view, at the path of the image with `?thumbnail=1`
+//!
+//! Thumbnails are generated on demand and cached in `--thumbnail-cache-dir`, under a hash of the
+//! path, modification time and size of the image so that they're generated again once the image
+//! changes. The cache directory must belong to the user running miniserve, since anyone able to
+//! write to it could replace the thumbnails.
+
+use std::fs::Metadata;
+use std::io::{self, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+use actix_files::NamedFile;
+use actix_web::{HttpRequest, HttpResponse, guard::GuardContext, web};
+use image::{DynamicImage, ImageFormat, ImageReader};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use tokio::fs;
+
+use crate::config::SharedConfig;
+use crate::errors::RuntimeError;
+use crate::file_utils::requested_path;
+
+/// Largest width and height of thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// Size of the largest image thumbnails are made of
+pub const MAX_IMAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Extensions of the images thumbnails are made of
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// Private temporary directory to cache thumbnails in when the user has no cache directory. It's
+/// kept for as long as miniserve runs, across reloads of the configuration.
+static TEMP_CACHE_DIR: LazyLock<io::Result<TempDir>> = LazyLock::new(|| {
+    tempfile::Builder::new()
+        .prefix("miniserve-thumbnails-")
+        .tempdir()
+});
+
+/// Query parameters of the thumbnails
+#[derive(Deserialize, Default)]
+struct ThumbnailQueryParameters {
+    thumbnail: Option<String>,
+}
+
+/// true if the request asks for a thumbnail with `?thumbnail=1` or `?thumbnail=true`
+pub fn is_thumbnail_request(ctx: &GuardContext) -> bool {
+    web::Query::<ThumbnailQueryParameters>::from_query(ctx.head().uri.query().unwrap_or_default())
+        .is_ok_and(|query| matches!(query.thumbnail.as_deref(), Some("1" | "true")))
+}
+
+/// true if a file called `name` of `size` bytes is an image a thumbnail can be made of
+pub fn has_thumbnail(name: &str, size: u64) -> bool {
+    size <= MAX_IMAGE_SIZE
+        && Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            })
+}
+
+/// Directory to cache thumbnails in when `--thumbnail-cache-dir` isn't given: `miniserve/thumbnails`
+/// in the cache directory of the user, or else a private temporary directory
+pub fn default_cache_dir() -> io::Result<PathBuf> {
+    if let Some(dir) = user_cache_dir() {
+        return Ok(dir.join("miniserve").join("thumbnails"));
+    }
+    match &*TEMP_CACHE_DIR {
+        Ok(dir) => Ok(dir.path().to_path_buf()),
+        Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+    }
+}
+
+/// Cache directory of the user, from the environment
+#[cfg(not(windows))]
+fn user_cache_dir() -> Option<PathBuf> {
+    let absolute = |dir: PathBuf| dir.is_absolute().then_some(dir);
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .and_then(absolute)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".cache"))
+                .and_then(absolute)
+        })
+}
+
+#[cfg(windows)]
+fn user_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+/// Fail if the cache directory `dir` exists but doesn't belong to the current user
+#[cfg(unix)]
+pub fn check_cache_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    match std::fs::metadata(dir) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+        Ok(metadata) if metadata.uid() != rustix::process::geteuid().as_raw() => Err(
+            io::Error::new(ErrorKind::PermissionDenied, "not owned by the current user"),
+        ),
+        Ok(_) => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn check_cache_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Name of the thumbnail of the image at the local `path` in the cache, without its extension
+fn cache_key(path: &Path, metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.update(modified.as_nanos().to_le_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(THUMBNAIL_SIZE.to_le_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Cached thumbnail called `key` in `cache_dir`, if any
+fn cached(cache_dir: &Path, key: &str) -> Option<PathBuf> {
+    ["jpg", "png"]
+        .iter()
+        .map(|extension| cache_dir.join(format!("{key}.{extension}")))
+        .find(|path| path.is_file())
+}
+
+/// Make a thumbnail of the image at the local `path`, and save it as `key` in `cache_dir`.
+///
+/// Images with transparency are saved as PNG, and the others as JPEG.
+fn generate(path: &Path, cache_dir: &Path, key: &str) -> Result<PathBuf, String> {
+    let image = ImageReader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())?
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let (image, format, extension) = if image.color().has_alpha() {
+        (image, ImageFormat::Png, "png")
+    } else {
+        let image = DynamicImage::ImageRgb8(image.to_rgb8());
+        (image, ImageFormat::Jpeg, "jpg")
+    };
+
+    // Written next to its final place and moved there, so that it's never served half-written
+    let mut dir_builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dir_builder, 0o700);
+    dir_builder
+        .recursive(true)
+        .create(cache_dir)
+        .map_err(|e| e.to_string())?;
+    let mut file = tempfile::NamedTempFile::new_in(cache_dir).map_err(|e| e.to_string())?;
+    image
+        .write_to(&mut BufWriter::new(file.as_file_mut()), format)
+        .map_err(|e| e.to_string())?;
+    let thumbnail = cache_dir.join(format!("{key}.{extension}"));
+    file.persist(&thumbnail).map_err(|e| e.to_string())?;
+    Ok(thumbnail)
+}
+
+/// Handle a request for the thumbnail of an image, generating it unless it's cached
+pub async fn thumbnail(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let not_found = || RuntimeError::RouteNotFoundError(req.path().to_string());
+
+    // Drop boxes don't expose their contents in any way
+    if conf.drop_box {
+        return Err(not_found());
+    }
+    let path = requested_path(&conf, req.path()).ok_or_else(not_found)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let local_path = conf.path.join(&path);
+    let metadata = match fs::metadata(&local_path).await {
+        Ok(metadata) if metadata.is_file() && has_thumbnail(&name, metadata.len()) => metadata,
+        _ => return Err(not_found()),
+    };
+
+    let key = cache_key(&local_path, &metadata);
+    let thumbnail = match cached(&conf.thumbnail_cache_dir, &key) {
+        Some(thumbnail) => thumbnail,
+        None => {
+            let cache_dir = conf.thumbnail_cache_dir.clone();
+            tokio::task::spawn_blocking(move || generate(&local_path, &cache_dir, &key))
+                .await
+                .map_err(|e| {
+                    RuntimeError::IoError("Failed to make the thumbnail".into(), e.into())
+                })?
+                .map_err(|e| {
+                    RuntimeError::UnsupportedMediaTypeError(format!(
+                        "Failed to make a thumbnail of {path:?}: {e}"
+                    ))
+                })?
+        }
+    };
+
+    let file = NamedFile::open_async(&thumbnail)
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to read the thumbnail".into(), e))?;
+    Ok(file.into_response(&req))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("photo.jpg", 10, true)]
+    #[case("Screenshot.PNG", 10, true)]
+    #[case("animation.gif", 10, true)]
+    #[case("vector.svg", 10, false)]
+    #[case("notes.txt", 10, false)]
+    #[case("photo.jpg", MAX_IMAGE_SIZE + 1, false)]
+    fn images_with_thumbnails(#[case] name: &str, #[case] size: u64, #[case] expected: bool) {
+        assert_eq!(has_thumbnail(name, size), expected);
+    }
+}
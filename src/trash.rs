@@ -0,0 +1,423 @@
+//! Trash for deleted files and directories, see `--trash`
+//!
+//! Every trashed entry gets its own directory below [`TRASH_DIR`], holding the entry itself and
+//! a JSON file describing where it came from.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, http::header, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::args::DuplicateFile;
+use crate::auth::CurrentUser;
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::errors::RuntimeError;
+use crate::file_op::free_file_name;
+use crate::{renderer, versions};
+
+/// Name of the trash directory, at the root of the served path. It's never listed or served,
+/// whether the trash is enabled or not.
+pub const TRASH_DIR: &str = ".miniserve-trash";
+
+/// Route of the trash page, relative to the route prefix
+pub const TRASH_ROUTE: &str = "/__miniserve_internal/trash";
+
+/// Name of the trashed entry inside its trash directory
+const CONTENT: &str = "content";
+
+/// Name of the metadata file inside a trash directory
+const INFO: &str = "info.json";
+
+/// Where a trashed entry came from
+#[derive(Serialize, Deserialize)]
+struct TrashInfo {
+    /// Path of the entry relative to the served path
+    original_path: PathBuf,
+
+    /// Deletion time, in seconds since the Unix epoch
+    deleted_at: u64,
+
+    /// Name of the user who deleted the entry, if authentication is enabled
+    deleted_by: Option<String>,
+}
+
+/// An entry in the trash
+pub struct TrashEntry {
+    /// Identifier of the entry in the trash
+    pub id: String,
+
+    /// Path of the entry relative to the served path
+    pub original_path: PathBuf,
+
+    /// Deletion time
+    pub deleted_at: SystemTime,
+
+    /// Name of the user who deleted the entry, if authentication is enabled
+    pub deleted_by: Option<String>,
+
+    /// true if the entry is a directory
+    pub is_dir: bool,
+}
+
+/// Query parameters used by the trash restore and purge APIs
+#[derive(Deserialize)]
+pub struct TrashQueryParameters {
+    /// Entry to restore or purge, all of them if purging without one
+    id: Option<String>,
+}
+
+/// Move `path` into the trash of the served directory `root`.
+///
+/// `original_path` is the path of the entry relative to `root`, which it's restored to.
+pub async fn move_to_trash(
+    root: &Path,
+    path: &Path,
+    original_path: &Path,
+    deleted_by: Option<&str>,
+) -> Result<(), RuntimeError> {
+    let trash_dir = root.join(TRASH_DIR);
+    fs::create_dir_all(&trash_dir)
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to create the trash directory".into(), e))?;
+
+    let id = nanoid::nanoid!(12);
+    let entry_dir = trash_dir.join(&id);
+    fs::create_dir(&entry_dir)
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to create a trash entry".into(), e))?;
+
+    let info = TrashInfo {
+        original_path: original_path.to_path_buf(),
+        deleted_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        deleted_by: deleted_by.map(str::to_owned),
+    };
+    let moved = async {
+        let info = serde_json::to_vec(&info).map_err(std::io::Error::other)?;
+        fs::write(entry_dir.join(INFO), info).await?;
+        move_entry(path, &entry_dir.join(CONTENT)).await
+    }
+    .await;
+    if let Err(e) = moved {
+        let _ = fs::remove_dir_all(&entry_dir).await;
+        return Err(RuntimeError::IoError(
+            format!("Failed to move {original_path:?} to the trash"),
+            e,
+        ));
+    }
+
+    info!("Moved {original_path:?} to the trash as {id}");
+    Ok(())
+}
+
+/// Move the entry at `from` to `to`, copying it over and removing it when they're on different
+/// filesystems, like when a directory of the served path is a mount point
+async fn move_entry(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to).await {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            warn!("{from:?} must be copied to {to:?} because it's on a different filesystem");
+            let (from, to) = (from.to_path_buf(), to.to_path_buf());
+            tokio::task::spawn_blocking(move || copy_and_remove(&from, &to))
+                .await
+                .map_err(std::io::Error::other)?
+        }
+        res => res,
+    }
+}
+
+/// Copy the entry at `from` to `to` as it is, recursively if it's a directory, and remove it once
+/// it's completely copied.
+///
+/// Symlinks are copied as symlinks. A partial copy is removed if the copy fails.
+fn copy_and_remove(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Err(e) = copy_entry(from, to) {
+        let _ = match std::fs::symlink_metadata(to) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(to),
+            _ => std::fs::remove_file(to),
+        };
+        return Err(e);
+    }
+
+    // The copy is complete, so the entry is trashed even if some of it can't be removed
+    let removed = match std::fs::symlink_metadata(from)? {
+        metadata if metadata.is_dir() => std::fs::remove_dir_all(from),
+        _ => std::fs::remove_file(from),
+    };
+    if let Err(e) = removed {
+        warn!("Failed to remove {from:?} after copying it to {to:?}: {e}");
+    }
+    Ok(())
+}
+
+/// Copy the entry at `from` to `to` as it is, recursively if it's a directory
+fn copy_entry(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())
+    } else if metadata.is_symlink() {
+        let target = std::fs::read_link(from)?;
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, to);
+        #[cfg(windows)]
+        return if from.is_dir() {
+            std::os::windows::fs::symlink_dir(target, to)
+        } else {
+            std::os::windows::fs::symlink_file(target, to)
+        };
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Entries in the trash of the served directory `root`, most recently deleted first.
+///
+/// Trash entries which can't be read are skipped.
+pub async fn entries(root: &Path) -> Result<Vec<TrashEntry>, RuntimeError> {
+    let mut read_dir = match fs::read_dir(root.join(TRASH_DIR)).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        res => res.map_err(|e| RuntimeError::IoError("Failed to read the trash".into(), e))?,
+    };
+
+    let mut entries = vec![];
+    while let Some(dir_entry) = read_dir
+        .next_entry()
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to read the trash".into(), e))?
+    {
+        let id = dir_entry.file_name().to_string_lossy().into_owned();
+        match entry(root, &id).await {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping unreadable trash entry {id}: {e}"),
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+
+    Ok(entries)
+}
+
+/// Read the trash entry `id` of the served directory `root`
+async fn entry(root: &Path, id: &str) -> Result<TrashEntry, RuntimeError> {
+    let not_found = || RuntimeError::RouteNotFoundError(format!("Trash entry {id}"));
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(not_found());
+    }
+
+    let entry_dir = root.join(TRASH_DIR).join(id);
+    let info = fs::read(entry_dir.join(INFO))
+        .await
+        .map_err(|_| not_found())?;
+    let info: TrashInfo = serde_json::from_slice(&info)
+        .map_err(|e| RuntimeError::ParseError(format!("Trash entry {id}"), e.to_string()))?;
+    let metadata = fs::symlink_metadata(entry_dir.join(CONTENT))
+        .await
+        .map_err(|_| not_found())?;
+
+    Ok(TrashEntry {
+        id: id.to_owned(),
+        original_path: info.original_path,
+        deleted_at: UNIX_EPOCH + Duration::from_secs(info.deleted_at),
+        deleted_by: info.deleted_by,
+        is_dir: metadata.is_dir(),
+    })
+}
+
+/// Move the trash entry `id` of the served directory of `conf` back to its original path.
+///
+/// Recreates the missing parent directories. If the original path is taken again,
+/// `on_duplicate_files` decides what happens: overwritten entries go to the trash in turn, and
+/// versioned files are kept as versions.
+async fn restore(
+    conf: &MiniserveConfig,
+    entry: &TrashEntry,
+    restored_by: Option<&str>,
+) -> Result<(), RuntimeError> {
+    let root = &conf.path;
+    let mut target = root.join(&entry.original_path);
+    if let Ok(metadata) = fs::symlink_metadata(&target).await {
+        match conf.on_duplicate_files {
+            DuplicateFile::Error => return Err(RuntimeError::DuplicateFileError),
+            DuplicateFile::Overwrite => {
+                move_to_trash(root, &target, &entry.original_path, restored_by).await?
+            }
+            DuplicateFile::Version if metadata.is_dir() || entry.is_dir => {
+                return Err(RuntimeError::DuplicateFileError);
+            }
+            DuplicateFile::Version => versions::keep(conf, &target).await?,
+            DuplicateFile::Rename => target = free_file_name(&target),
+        }
+    }
+
+    let entry_dir = root.join(TRASH_DIR).join(&entry.id);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).await.map_err(|e| {
+            RuntimeError::IoError(format!("Failed to create {}", parent.display()), e)
+        })?;
+    }
+    move_entry(&entry_dir.join(CONTENT), &target)
+        .await
+        .map_err(|e| {
+            RuntimeError::IoError(format!("Failed to restore {:?}", entry.original_path), e)
+        })?;
+    purge(root, &entry.id).await
+}
+
+/// Permanently delete the trash entry `id` of the served directory `root`
+async fn purge(root: &Path, id: &str) -> Result<(), RuntimeError> {
+    fs::remove_dir_all(root.join(TRASH_DIR).join(id))
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to purge trash entry {id}"), e))
+}
+
+/// Permanently delete the entries of the trash of `conf` older than its retention period
+pub async fn purge_expired(conf: &MiniserveConfig) -> Result<(), RuntimeError> {
+    let Some(retention) = conf.trash_retention else {
+        return Ok(());
+    };
+
+    for entry in entries(&conf.path).await? {
+        if entry.deleted_at.elapsed().is_ok_and(|age| age > retention) {
+            info!(
+                "Purging {:?} from the trash after the retention period",
+                entry.original_path
+            );
+            purge(&conf.path, &entry.id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Trash entries that the configuration allows the current user to see, i.e. entries which
+/// could have been deleted with the current deletion rules
+async fn allowed_entries(conf: &MiniserveConfig) -> Result<Vec<TrashEntry>, RuntimeError> {
+    let mut entries = entries(&conf.path).await?;
+    entries.retain(|entry| conf.rm_allowed(&entry.original_path));
+    Ok(entries)
+}
+
+/// Handle a request for the trash page
+pub async fn trash_page(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    purge_expired(&conf).await?;
+    let entries = allowed_entries(&conf).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .body(renderer::trash_page(&entries, &conf).into_string()))
+}
+
+/// Handle a request to restore a trash entry
+pub async fn restore_entry(
+    req: HttpRequest,
+    query: web::Query<TrashQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let id = query.id.as_deref().ok_or_else(|| {
+        RuntimeError::InvalidHttpRequestError("Missing value for 'id' parameter".to_string())
+    })?;
+    let entry = entry(&conf.path, id).await?;
+    if !conf.rm_allowed(&entry.original_path) {
+        return Err(RuntimeError::RmForbiddenError);
+    }
+
+    let current_user = req.extensions().get::<CurrentUser>().cloned();
+    restore(
+        &conf,
+        &entry,
+        current_user.as_ref().map(|user| user.name.as_str()),
+    )
+    .await?;
+
+    Ok(back_to_trash(&req, &conf))
+}
+
+/// Handle a request to permanently delete a trash entry, or all of them
+pub async fn purge_entry(
+    req: HttpRequest,
+    query: web::Query<TrashQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let entries = match query.id.as_deref() {
+        Some(id) => vec![entry(&conf.path, id).await?],
+        None => allowed_entries(&conf).await?,
+    };
+
+    for entry in entries {
+        if !conf.rm_allowed(&entry.original_path) {
+            return Err(RuntimeError::RmForbiddenError);
+        }
+        purge(&conf.path, &entry.id).await?;
+    }
+
+    Ok(back_to_trash(&req, &conf))
+}
+
+/// Redirect to the referer, or to the trash page
+fn back_to_trash(req: &HttpRequest, conf: &MiniserveConfig) -> HttpResponse {
+    let trash_route = format!("{}{TRASH_ROUTE}", conf.route_prefix);
+    let return_path = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(&trash_route);
+
+    HttpResponse::SeeOther()
+        .append_header((header::LOCATION, return_path))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_and_remove_moves_whole_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let from = tmp.path().join("from");
+        std::fs::create_dir_all(from.join("sub")).unwrap();
+        std::fs::write(from.join("sub/.hidden"), "hidden").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("sub/.hidden", from.join("link")).unwrap();
+
+        let to = tmp.path().join("to");
+        copy_and_remove(&from, &to).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(
+            std::fs::read_to_string(to.join("sub/.hidden")).unwrap(),
+            "hidden"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            std::fs::read_link(to.join("link")).unwrap(),
+            Path::new("sub/.hidden")
+        );
+    }
+
+    #[test]
+    fn failed_copies_keep_the_entry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let from = tmp.path().join("from");
+        std::fs::write(&from, "content").unwrap();
+
+        // The destination's parent doesn't exist
+        let to = tmp.path().join("missing/to");
+        assert!(copy_and_remove(&from, &to).is_err());
+
+        assert_eq!(std::fs::read_to_string(&from).unwrap(), "content");
+    }
+}
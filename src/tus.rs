@@ -0,0 +1,608 @@
+//! Resumable uploads with the [tus 1.0](https://tus.io/protocols/resumable-upload) protocol
+//!
+//! Supports the core protocol along with the creation, expiration and termination extensions.
+//! Uploads in progress are kept in a `miniserve-tus` directory inside the temporary upload
+//! directory, as a `{id}.part` file with the data received so far and a `{id}.json` file
+//! describing where it goes. Once complete, uploads are finalized just like multipart uploads.
+
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+    http::{StatusCode, header},
+    web,
+};
+use base64::Engine;
+use futures::StreamExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::auth::CurrentUser;
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::errors::RuntimeError;
+use crate::expiry::{self, ExpiryQueryParameters};
+use crate::extract::{ExtractQueryParameters, extract_upload};
+use crate::file_op::{
+    FileHash, FileOpQueryParameters, MEDIA_TYPE_DETECTION_LEN, UploadLimits, move_upload,
+    resolve_duplicate, upload_file_path, upload_target_dir,
+};
+use crate::file_utils::sanitize_path;
+use crate::{drop_box, quota};
+
+/// Route of the tus upload creation endpoint, relative to the route prefix. Uploads are at
+/// `{TUS_ROUTE}/{id}`.
+pub const TUS_ROUTE: &str = "/upload/tus";
+
+/// Version of the protocol, which all requests must be made with
+const TUS_VERSION: &str = "1.0.0";
+
+/// Supported protocol extensions
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+
+/// Content type of the PATCH requests
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Time after which untouched uploads are discarded
+const EXPIRATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Uploads which are currently being written to, to reject concurrent PATCH requests
+static IN_PROGRESS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Reservations of the uploads in the upload quotas, by id, made for their whole length when
+/// they're created
+static RESERVATIONS: LazyLock<Mutex<HashMap<String, quota::Reservation>>> =
+    LazyLock::new(Default::default);
+
+/// Where an upload goes, saved next to its data
+#[derive(Serialize, Deserialize)]
+struct TusUpload {
+    /// Directory to upload to, relative to the served path
+    dir: PathBuf,
+
+    /// Name of the uploaded file
+    filename: String,
+
+    /// Total size of the upload
+    length: u64,
+
+    /// Name of the hash function and expected hash of the file, from `X-File-Hash-Function` and
+    /// `X-File-Hash`
+    hash: Option<(String, String)>,
+
+    /// Name of the user who created the upload, if authentication is enabled
+    user: Option<String>,
+
+    /// Drop box session the upload was created in, if the drop box is enabled
+    #[serde(default)]
+    session: Option<String>,
+
+    /// Extract the file once complete if it's an archive
+    #[serde(default)]
+    extract: bool,
+
+    /// Time after which the file expires once complete, if it does
+    #[serde(default)]
+    expiry: Option<Duration>,
+}
+
+/// Marks an upload as being written to until dropped
+struct InProgress(String);
+
+impl InProgress {
+    fn acquire(id: &str) -> Option<Self> {
+        let mut in_progress = IN_PROGRESS.lock().unwrap();
+        in_progress
+            .insert(id.to_owned())
+            .then(|| Self(id.to_owned()))
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        IN_PROGRESS.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Directory holding the uploads in progress
+fn state_dir(conf: &MiniserveConfig) -> PathBuf {
+    conf.temp_upload_directory
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("miniserve-tus")
+}
+
+/// Paths of the data and the description of the upload `id`, if it's a valid upload id
+fn state_paths(conf: &MiniserveConfig, id: &str) -> Option<(PathBuf, PathBuf)> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    let dir = state_dir(conf);
+    Some((
+        dir.join(format!("{id}.part")),
+        dir.join(format!("{id}.json")),
+    ))
+}
+
+/// Response builder with the headers common to all tus responses
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut resp = HttpResponse::build(status);
+    resp.insert_header(("Tus-Resumable", TUS_VERSION));
+    resp
+}
+
+/// Response to requests made with an unsupported protocol version, if this one is
+fn check_version(req: &HttpRequest) -> Option<HttpResponse> {
+    let version = req.headers().get("Tus-Resumable");
+    (version.and_then(|v| v.to_str().ok()) != Some(TUS_VERSION)).then(|| {
+        tus_response(StatusCode::PRECONDITION_FAILED)
+            .insert_header(("Tus-Version", TUS_VERSION))
+            .finish()
+    })
+}
+
+/// Parse an integer header of a tus request
+fn u64_header(req: &HttpRequest, name: &str) -> Result<u64, RuntimeError> {
+    let value = req
+        .headers()
+        .get(name)
+        .ok_or_else(|| RuntimeError::InvalidHttpRequestError(format!("Missing '{name}' header")))?;
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| RuntimeError::ParseError(format!("'{name}' header"), "not a number".into()))
+}
+
+/// File name given in the `Upload-Metadata` header of a creation request
+fn metadata_filename(req: &HttpRequest) -> Result<String, RuntimeError> {
+    let metadata = req
+        .headers()
+        .get("Upload-Metadata")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let encoded = metadata
+        .split(',')
+        .filter_map(|pair| pair.trim().split_once(' '))
+        .find_map(|(key, value)| (key == "filename").then_some(value))
+        .ok_or_else(|| {
+            RuntimeError::InvalidHttpRequestError(
+                "Missing 'filename' in 'Upload-Metadata' header".to_string(),
+            )
+        })?;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|filename| String::from_utf8(filename).ok())
+        .ok_or_else(|| RuntimeError::ParseError("upload file name".into(), "invalid base64".into()))
+}
+
+/// Restrictions on `upload`
+async fn limits<'a>(
+    conf: &'a MiniserveConfig,
+    upload: &TusUpload,
+) -> Result<UploadLimits<'a>, RuntimeError> {
+    let dir = sanitize_path(&upload.dir, conf.show_hidden).unwrap_or_default();
+    Ok(UploadLimits {
+        media_types: conf.uploadable_media_type.as_deref(),
+        max_size: conf.max_upload_file_size,
+        quota_left: quota::space_left(conf, &dir, upload.user.as_deref()).await?,
+        reservation: None,
+    })
+}
+
+/// Reservation of the whole length of `upload` in the upload quotas, if any applies
+async fn reserve(
+    conf: &MiniserveConfig,
+    upload: &TusUpload,
+) -> Result<Option<quota::Reservation>, RuntimeError> {
+    let dir = sanitize_path(&upload.dir, conf.show_hidden).unwrap_or_default();
+    let reservation = quota::reserve(conf, &dir, upload.user.as_deref());
+    if let Some(reservation) = &reservation {
+        reservation.grow(&upload.filename, upload.length).await?;
+    }
+    Ok(reservation)
+}
+
+/// Path to save `upload` at once it's complete, checking that the configuration still allows it
+fn target_path(conf: &MiniserveConfig, upload: &TusUpload) -> Result<PathBuf, RuntimeError> {
+    let dir = upload_target_dir(conf, &upload.dir)?;
+    if !dir.is_dir() {
+        return Err(RuntimeError::InvalidPathError(format!(
+            "cannot upload file to {}, since it's not a directory",
+            dir.display()
+        )));
+    }
+    upload_file_path(
+        &dir,
+        &upload.filename,
+        conf.show_hidden,
+        !conf.no_symlinks,
+        conf.mkdir_enabled,
+    )
+}
+
+/// Time at which the upload with the data at `part` expires
+async fn expires_at(part: &Path) -> Option<SystemTime> {
+    let modified = fs::metadata(part).await.ok()?.modified().ok()?;
+    Some(modified + EXPIRATION)
+}
+
+/// Remove the data and the description of the upload `id`, releasing its reservation
+async fn discard(id: &str, part: &Path, info: &Path) {
+    RESERVATIONS.lock().unwrap().remove(id);
+    for path in [part, info] {
+        if let Err(e) = fs::remove_file(path).await
+            && e.kind() != ErrorKind::NotFound
+        {
+            warn!("Failed to remove {path:?}: {e}");
+        }
+    }
+}
+
+/// Remove the uploads which haven't been written to for longer than [`EXPIRATION`]
+async fn discard_expired(conf: &MiniserveConfig) {
+    let Ok(mut read_dir) = fs::read_dir(state_dir(conf)).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name.strip_suffix(".json") else {
+            continue;
+        };
+        let Some((part, info)) = state_paths(conf, id) else {
+            continue;
+        };
+        let Some(_guard) = InProgress::acquire(id) else {
+            continue;
+        };
+        if expires_at(&part)
+            .await
+            .is_none_or(|expires| expires < SystemTime::now())
+        {
+            info!("Discarding expired upload {id}");
+            discard(id, &part, &info).await;
+        }
+    }
+}
+
+/// Read the upload `id` made by the current user
+async fn load(
+    req: &HttpRequest,
+    conf: &MiniserveConfig,
+    id: &str,
+) -> Result<(TusUpload, PathBuf, PathBuf), RuntimeError> {
+    let not_found = || RuntimeError::RouteNotFoundError(format!("Upload {id}"));
+    let (part, info) = state_paths(conf, id).ok_or_else(not_found)?;
+    let upload = fs::read(&info).await.map_err(|_| not_found())?;
+    let upload: TusUpload = serde_json::from_slice(&upload)
+        .map_err(|e| RuntimeError::ParseError(format!("Upload {id}"), e.to_string()))?;
+
+    // Uploads of other users are never revealed
+    let current_user = req
+        .extensions()
+        .get::<CurrentUser>()
+        .map(|u| u.name.clone());
+    if upload.user != current_user
+        || expires_at(&part)
+            .await
+            .is_none_or(|expires| expires < SystemTime::now())
+    {
+        return Err(not_found());
+    }
+
+    Ok((upload, part, info))
+}
+
+/// Size of the data received so far for the upload with the data at `part`
+async fn offset(part: &Path) -> Result<u64, RuntimeError> {
+    fs::metadata(part)
+        .await
+        .map(|metadata| metadata.len())
+        .map_err(|e| RuntimeError::IoError(format!("Failed to read {part:?}"), e))
+}
+
+/// Move the complete upload `id` to its destination, following the same rules as multipart uploads
+async fn finish(
+    conf: &MiniserveConfig,
+    id: &str,
+    upload: &TusUpload,
+    part: &Path,
+    info: &Path,
+) -> Result<(), RuntimeError> {
+    // The reservation made on creation is gone if miniserve was restarted since
+    let reserved = RESERVATIONS.lock().unwrap().remove(id);
+    let finished = async {
+        let file_path = target_path(conf, upload)?;
+
+        let mut head = Vec::new();
+        fs::File::open(part)
+            .await
+            .map_err(|e| RuntimeError::IoError(format!("Failed to read {part:?}"), e))?
+            .take(MEDIA_TYPE_DETECTION_LEN as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(|e| RuntimeError::IoError(format!("Failed to read {part:?}"), e))?;
+        // Other uploads may have used up the quotas in the meantime, unless the file holds its
+        // place in them
+        let mut limits = limits(conf, upload).await?;
+        if reserved.is_some() {
+            limits.quota_left = None;
+        }
+        limits.check_size(&upload.filename, upload.length)?;
+        limits.check_media_type(&upload.filename, &head)?;
+
+        if let Some((function, expected_hash)) = &upload.hash {
+            let file_hash = FileHash::new(function, expected_hash.clone())?;
+            let mut hasher = file_hash.get_hasher();
+            let mut file = fs::File::open(part)
+                .await
+                .map_err(|e| RuntimeError::IoError(format!("Failed to read {part:?}"), e))?;
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let read = file
+                    .read(&mut buf)
+                    .await
+                    .map_err(|e| RuntimeError::IoError(format!("Failed to read {part:?}"), e))?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..read]);
+            }
+
+            let actual_hash = hex::encode(hasher.finalize());
+            if &actual_hash != expected_hash {
+                warn!(
+                    "The expected file hash {expected_hash} did not match the calculated hash of {actual_hash}."
+                );
+                return Err(RuntimeError::UploadHashMismatchError);
+            }
+        }
+
+        // The file is reserved before being moved, in case other uploads complete meanwhile
+        let reservation = match reserved {
+            Some(reservation) => Some(reservation),
+            None => reserve(conf, upload).await?,
+        };
+
+        let file_path = resolve_duplicate(file_path, conf.on_duplicate_files)?;
+        info!("File upload successful to {part:?}. Moving to {file_path:?}");
+        move_upload(conf, part, &file_path).await?;
+        quota::record_upload(conf, &file_path, upload.length, upload.user.as_deref()).await;
+        drop(reservation);
+        let session = upload.session.clone().map(drop_box::Session);
+        let extracted = upload.extract
+            && conf.extract_archives
+            && extract_upload(
+                conf,
+                &file_path,
+                upload.user.as_deref(),
+                session.as_ref(),
+                upload.expiry,
+            )
+            .await?;
+        if !extracted {
+            expiry::record_upload(conf, &file_path, upload.expiry).await;
+            drop_box::record_upload(conf, session.as_ref(), &file_path);
+        }
+        Ok(())
+    }
+    .await;
+
+    // A failed upload can't be completed anymore
+    discard(id, part, info).await;
+    finished
+}
+
+/// Handle a request for the capabilities of the server
+pub async fn options(req: HttpRequest) -> HttpResponse {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let mut resp = tus_response(StatusCode::NO_CONTENT);
+    resp.insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS));
+    if let Some(max_size) = conf.max_upload_file_size {
+        resp.insert_header(("Tus-Max-Size", max_size));
+    }
+    resp.finish()
+}
+
+/// Handle a request to create an upload in the directory given by the `path` parameter, to be
+/// extracted once complete if the `extract` parameter says so, and to expire after the time given
+/// by the `expire` parameter.
+///
+/// The file name is given by the `filename` key of the `Upload-Metadata` header and the expected
+/// hash by the same headers as multipart uploads.
+pub async fn create(
+    req: HttpRequest,
+    query: web::Query<FileOpQueryParameters>,
+    extract: web::Query<ExtractQueryParameters>,
+    expire: web::Query<ExpiryQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    if let Some(resp) = check_version(&req) {
+        return Ok(resp);
+    }
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+
+    let upload = TusUpload {
+        dir: query.path.clone(),
+        filename: metadata_filename(&req)?,
+        length: u64_header(&req, "Upload-Length")?,
+        hash: FileHash::from_request(&req)?
+            .map(|hash| (hash.function().to_owned(), hash.get_hash().to_owned())),
+        user: req
+            .extensions()
+            .get::<CurrentUser>()
+            .map(|u| u.name.clone()),
+        session: drop_box::session(&req).map(|session| session.0),
+        extract: extract.extract,
+        expiry: expire.expiry(&conf)?,
+    };
+
+    // Expired uploads release their reservations first
+    discard_expired(&conf).await;
+
+    // Fail early rather than after the whole file has been sent
+    let limits = limits(&conf, &upload).await?;
+    limits.check_size(&upload.filename, upload.length)?;
+    limits.check_media_type(&upload.filename, &[])?;
+    resolve_duplicate(target_path(&conf, &upload)?, conf.on_duplicate_files)?;
+
+    // The whole file is reserved, so that the data sent later always fits in the quotas
+    let reservation = reserve(&conf, &upload).await?;
+
+    let id = nanoid::nanoid!();
+    let (part, info) = state_paths(&conf, &id).unwrap();
+    let created = async {
+        fs::create_dir_all(state_dir(&conf)).await?;
+        fs::write(
+            &info,
+            serde_json::to_vec(&upload).map_err(std::io::Error::other)?,
+        )
+        .await?;
+        fs::write(&part, b"").await
+    }
+    .await;
+    if let Err(e) = created {
+        discard(&id, &part, &info).await;
+        return Err(match e.kind() {
+            ErrorKind::PermissionDenied => {
+                RuntimeError::InsufficientPermissionsError(part.display().to_string())
+            }
+            _ => RuntimeError::IoError("Failed to create upload".to_string(), e),
+        });
+    }
+
+    if let Some(reservation) = reservation {
+        RESERVATIONS.lock().unwrap().insert(id.clone(), reservation);
+    }
+    if upload.length == 0 {
+        finish(&conf, &id, &upload, &part, &info).await?;
+    }
+
+    let location = format!("{}{TUS_ROUTE}/{id}", conf.route_prefix);
+    let mut resp = tus_response(StatusCode::CREATED);
+    resp.insert_header((header::LOCATION, location));
+    if upload.length > 0
+        && let Some(expires) = expires_at(&part).await
+    {
+        resp.insert_header((
+            "Upload-Expires",
+            header::HttpDate::from(expires).to_string(),
+        ));
+    }
+    Ok(resp.finish())
+}
+
+/// Handle a request for the offset of an upload
+pub async fn head(req: HttpRequest, id: web::Path<String>) -> Result<HttpResponse, RuntimeError> {
+    if let Some(resp) = check_version(&req) {
+        return Ok(resp);
+    }
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let (upload, part, _) = load(&req, &conf, &id).await?;
+
+    Ok(tus_response(StatusCode::OK)
+        .insert_header(("Upload-Offset", offset(&part).await?))
+        .insert_header(("Upload-Length", upload.length))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+        .finish())
+}
+
+/// Handle a request to append data to an upload, finalizing it once complete
+pub async fn patch(
+    req: HttpRequest,
+    id: web::Path<String>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, RuntimeError> {
+    if let Some(resp) = check_version(&req) {
+        return Ok(resp);
+    }
+    if req.content_type() != OFFSET_OCTET_STREAM {
+        return Ok(tus_response(StatusCode::UNSUPPORTED_MEDIA_TYPE).finish());
+    }
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let (upload, part, info) = load(&req, &conf, &id).await?;
+    let Some(_guard) = InProgress::acquire(&id) else {
+        return Ok(tus_response(StatusCode::CONFLICT).body("Upload is already in progress"));
+    };
+
+    // The configuration may have changed since the upload was created
+    target_path(&conf, &upload)?;
+
+    let offset = offset(&part).await?;
+    if u64_header(&req, "Upload-Offset")? != offset {
+        return Ok(tus_response(StatusCode::CONFLICT)
+            .insert_header(("Upload-Offset", offset))
+            .body("Upload-Offset doesn't match the current offset"));
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(&part)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to open {part:?}"), e))?;
+    let mut new_offset = offset;
+    let mut result = Ok(());
+
+    // Whatever was received is kept if the client goes away, so that it can resume from there
+    while let Some(Ok(bytes)) = payload.next().await {
+        let remaining = upload.length - new_offset;
+        let len = (bytes.len() as u64).min(remaining);
+        if let Err(e) = file.write_all(&bytes[..len as usize]).await {
+            result = Err(RuntimeError::IoError("Failed to write to file".into(), e));
+            break;
+        }
+        new_offset += len;
+        if len < bytes.len() as u64 {
+            result = Err(RuntimeError::InvalidHttpRequestError(
+                "Received more data than the 'Upload-Length' of the upload".to_string(),
+            ));
+            break;
+        }
+    }
+    if let Err(e) = file.flush().await {
+        result = result.and(Err(RuntimeError::IoError(
+            "Failed to flush all the file writes to disk".into(),
+            e,
+        )));
+    }
+    drop(file);
+    result?;
+
+    let mut resp = tus_response(StatusCode::NO_CONTENT);
+    resp.insert_header(("Upload-Offset", new_offset));
+    if new_offset == upload.length {
+        finish(&conf, &id, &upload, &part, &info).await?;
+    } else if let Some(expires) = expires_at(&part).await {
+        resp.insert_header((
+            "Upload-Expires",
+            header::HttpDate::from(expires).to_string(),
+        ));
+    }
+    Ok(resp.finish())
+}
+
+/// Handle a request to cancel an upload
+pub async fn delete(req: HttpRequest, id: web::Path<String>) -> Result<HttpResponse, RuntimeError> {
+    if let Some(resp) = check_version(&req) {
+        return Ok(resp);
+    }
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let (_, part, info) = load(&req, &conf, &id).await?;
+    let Some(_guard) = InProgress::acquire(&id) else {
+        return Ok(tus_response(StatusCode::CONFLICT).body("Upload is already in progress"));
+    };
+
+    discard(&id, &part, &info).await;
+    Ok(tus_response(StatusCode::NO_CONTENT).finish())
+}
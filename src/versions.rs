@@ -0,0 +1,276 @@
+//! Previous versions of replaced files, see `--on-duplicate-files version`
+//!
+//! The versions of a file are kept below [`VERSIONS_DIR`], in a directory at the same relative
+//! path as the file itself. They are numbered from 1 in the order they were replaced, and keep
+//! the modification time of the file they were.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use actix_files::NamedFile;
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    web,
+};
+use log::info;
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::args::DuplicateFile;
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::errors::RuntimeError;
+use crate::file_utils::{relative_to_root, sanitize_path};
+use crate::{quota, renderer};
+
+/// Name of the versions directory, at the root of the served path. It's never listed or served.
+pub const VERSIONS_DIR: &str = ".miniserve-versions";
+
+/// Route of the history page of a file, relative to the route prefix
+pub const VERSIONS_ROUTE: &str = "/__miniserve_internal/versions";
+
+/// A previous version of a file
+pub struct Version {
+    /// Number of the version, the latest one having the highest
+    pub number: u64,
+
+    /// Size in bytes
+    pub size: u64,
+
+    /// Last modification date of the file when it was replaced
+    pub modified: Option<SystemTime>,
+}
+
+/// Query parameters used by the history page and the version download and restore APIs
+#[derive(Deserialize)]
+pub struct VersionQueryParameters {
+    /// Path of the file relative to the served path
+    path: PathBuf,
+
+    /// Number of the version to download or restore
+    version: Option<u64>,
+}
+
+/// Keep the file at the local `path` as its latest version before it's replaced, if `conf`
+/// versions replaced files.
+///
+/// Anything but regular files is left alone, to be replaced as usual.
+pub async fn keep(conf: &MiniserveConfig, path: &Path) -> Result<(), RuntimeError> {
+    if !matches!(conf.on_duplicate_files, DuplicateFile::Version)
+        || !fs::symlink_metadata(path).await.is_ok_and(|m| m.is_file())
+    {
+        return Ok(());
+    }
+    let Some(relative_path) = relative_to_root(&conf.path, path) else {
+        return Ok(());
+    };
+
+    let dir = versions_dir(&conf.path, &relative_path);
+    let number = stash(&dir, path).await?;
+    info!("Kept the replaced {relative_path:?} as version {number}");
+    prune(&dir, conf.max_versions).await
+}
+
+/// Previous versions of the file at `path`, relative to the served directory `root`, latest
+/// first
+pub async fn versions(root: &Path, path: &Path) -> Result<Vec<Version>, RuntimeError> {
+    let dir = versions_dir(root, path);
+    let mut versions = vec![];
+    for number in numbers(&dir).await?.into_iter().rev() {
+        if let Ok(metadata) = fs::symlink_metadata(dir.join(number.to_string())).await {
+            versions.push(Version {
+                number,
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+    }
+    Ok(versions)
+}
+
+/// Names of the entries of `dir`, relative to the served directory `root`, which have previous
+/// versions
+pub fn versioned_names(root: &Path, dir: &Path) -> HashSet<OsString> {
+    std::fs::read_dir(versions_dir(root, dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.file_name())
+        .collect()
+}
+
+/// Directory holding the versions of `path`, relative to the served directory `root`
+pub fn versions_dir(root: &Path, path: &Path) -> PathBuf {
+    root.join(VERSIONS_DIR).join(path)
+}
+
+/// Numbers of the versions in the versions directory `dir`, in ascending order
+async fn numbers(dir: &Path) -> Result<Vec<u64>, RuntimeError> {
+    let mut read_dir = match fs::read_dir(dir).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        res => res.map_err(|e| RuntimeError::IoError("Failed to read the versions".into(), e))?,
+    };
+
+    let mut numbers = vec![];
+    while let Some(entry) = read_dir
+        .next_entry()
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to read the versions".into(), e))?
+    {
+        if let Some(number) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+            numbers.push(number);
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Move the local `path` into the versions directory `dir` as the latest version, returning its
+/// number
+async fn stash(dir: &Path, path: &Path) -> Result<u64, RuntimeError> {
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to create the versions directory".into(), e))?;
+    let number = numbers(dir).await?.last().map_or(1, |latest| latest + 1);
+    fs::rename(path, dir.join(number.to_string()))
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to keep a version of {path:?}"), e))?;
+    Ok(number)
+}
+
+/// Delete the oldest versions in the versions directory `dir` beyond `max_versions`
+async fn prune(dir: &Path, max_versions: Option<usize>) -> Result<(), RuntimeError> {
+    let Some(max_versions) = max_versions else {
+        return Ok(());
+    };
+
+    let numbers = numbers(dir).await?;
+    let excess = numbers.len().saturating_sub(max_versions);
+    for number in &numbers[..excess] {
+        fs::remove_file(dir.join(number.to_string()))
+            .await
+            .map_err(|e| RuntimeError::IoError(format!("Failed to delete version {number}"), e))?;
+    }
+    Ok(())
+}
+
+/// Path of the file of `query` relative to the served path, if its history may be accessed
+fn file_path(
+    conf: &MiniserveConfig,
+    query: &VersionQueryParameters,
+) -> Result<PathBuf, RuntimeError> {
+    // Drop boxes don't expose their contents in any way
+    if conf.drop_box {
+        return Err(RuntimeError::RouteNotFoundError(VERSIONS_ROUTE.to_string()));
+    }
+    sanitize_path(&query.path, conf.show_hidden)
+        .filter(|path| path.file_name().is_some())
+        .ok_or_else(|| {
+            RuntimeError::InvalidPathError("Invalid value for 'path' parameter".to_string())
+        })
+}
+
+/// Local path of the version of `query` of the file at `path`, relative to the served path
+async fn version_path(
+    conf: &MiniserveConfig,
+    path: &Path,
+    query: &VersionQueryParameters,
+) -> Result<PathBuf, RuntimeError> {
+    let number = query.version.ok_or_else(|| {
+        RuntimeError::InvalidHttpRequestError("Missing value for 'version' parameter".to_string())
+    })?;
+    let version = versions_dir(&conf.path, path).join(number.to_string());
+    match fs::symlink_metadata(&version).await {
+        Ok(metadata) if metadata.is_file() => Ok(version),
+        _ => Err(RuntimeError::RouteNotFoundError(format!(
+            "Version {number} of {path:?}"
+        ))),
+    }
+}
+
+/// Handle a request for the history page of a file
+pub async fn history_page(
+    req: HttpRequest,
+    query: web::Query<VersionQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let path = file_path(&conf, &query)?;
+    let versions = versions(&conf.path, &path).await?;
+    let can_restore = conf.upload_allowed(path.parent().unwrap_or(Path::new("")));
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .body(renderer::versions_page(&path, &versions, can_restore, &conf).into_string()))
+}
+
+/// Handle a request to download a previous version of a file
+pub async fn download_version(
+    req: HttpRequest,
+    query: web::Query<VersionQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let path = file_path(&conf, &query)?;
+    let version = version_path(&conf, &path, &query).await?;
+
+    let file = NamedFile::open_async(&version)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to open {version:?}"), e))?;
+    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+    Ok(file
+        .set_content_type(mime_guess::from_path(&path).first_or_octet_stream())
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        })
+        .into_response(&req))
+}
+
+/// Handle a request to restore a previous version of a file.
+///
+/// The current file becomes the latest version in turn, so that restoring can be undone.
+pub async fn restore_version(
+    req: HttpRequest,
+    query: web::Query<VersionQueryParameters>,
+) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let path = file_path(&conf, &query)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    if !conf.upload_allowed(dir) {
+        return Err(RuntimeError::UploadForbiddenError);
+    }
+    let version = version_path(&conf, &path, &query).await?;
+
+    let versions_dir = versions_dir(&conf.path, &path);
+    let target = conf.path.join(&path);
+    match fs::symlink_metadata(&target).await {
+        Ok(metadata) if metadata.is_dir() => return Err(RuntimeError::DuplicateFileError),
+        Ok(_) => {
+            stash(&versions_dir, &target).await?;
+        }
+        Err(_) => {
+            let parent = conf.path.join(dir);
+            fs::create_dir_all(&parent).await.map_err(|e| {
+                RuntimeError::IoError(format!("Failed to create {}", parent.display()), e)
+            })?;
+        }
+    }
+    fs::rename(&version, &target)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to restore {path:?}"), e))?;
+    prune(&versions_dir, conf.max_versions).await?;
+    quota::forget_usage();
+
+    let listing = format!("{}/", conf.route_prefix);
+    let return_path = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(&listing);
+    Ok(HttpResponse::SeeOther()
+        .append_header((header::LOCATION, return_path))
+        .finish())
+}
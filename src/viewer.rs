@@ -0,0 +1,226 @@
+//! Viewer of text files with syntax highlighting, at the path of the file with `?view=1`
+//!
+//! Files are highlighted with [syntect], which marks tokens with CSS classes prefixed by
+//! [`CLASS_PREFIX`] so that the themes can color them. Files which are too large to be viewed
+//! comfortably, or aren't text, are downloaded as usual instead.
+
+use std::path::Path;
+use std::sync::LazyLock;
+
+use actix_web::{HttpRequest, HttpResponse, guard::GuardContext, http::header, web};
+use serde::Deserialize;
+use syntect::html::{ClassStyle, line_tokens_to_classed_spans};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use tokio::fs;
+
+use crate::config::{MiniserveConfig, SharedConfig};
+use crate::errors::RuntimeError;
+use crate::file_utils::requested_path;
+use crate::renderer;
+
+/// Size of the largest file which is rendered by the viewer
+pub const MAX_VIEW_SIZE: u64 = 1024 * 1024;
+
+/// Prefix of the CSS classes of highlighted tokens
+const CLASS_PREFIX: &str = "hl-";
+
+/// Syntaxes of the languages known to the viewer
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Query parameters of the viewer
+#[derive(Deserialize, Default)]
+struct ViewQueryParameters {
+    view: Option<String>,
+}
+
+/// A text file ready to be rendered
+pub struct ViewedFile {
+    /// Name of the language the file is highlighted as
+    pub syntax: String,
+
+    /// Highlighted HTML of each line, with its tokens wrapped in classed spans
+    pub lines: Vec<String>,
+
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+/// true if the request asks for the viewer with `?view=1` or `?view=true`
+pub fn is_view_request(ctx: &GuardContext) -> bool {
+    web::Query::<ViewQueryParameters>::from_query(ctx.head().uri.query().unwrap_or_default())
+        .is_ok_and(|query| matches!(query.view.as_deref(), Some("1" | "true")))
+}
+
+/// true if a file called `name` of `size` bytes is likely to be shown by the viewer
+pub fn viewable(name: &str, size: u64) -> bool {
+    size <= MAX_VIEW_SIZE
+        && (mime_guess::from_path(name)
+            .first()
+            .is_some_and(|mime| mime.type_() == mime::TEXT)
+            || find_syntax(name, "").is_some())
+}
+
+/// Syntax of the file called `name`, starting with `first_line`, if it's known
+fn find_syntax(name: &str, first_line: &str) -> Option<&'static SyntaxReference> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str());
+    extension
+        .and_then(|extension| SYNTAXES.find_syntax_by_extension(extension))
+        // Some syntaxes match whole file names, like Makefile
+        .or_else(|| SYNTAXES.find_syntax_by_extension(name))
+        .or_else(|| SYNTAXES.find_syntax_by_first_line(first_line))
+}
+
+/// Highlight the `text` of the file called `name`, line by line.
+///
+/// Each line is self-contained: the spans of the tokens spanning several lines are closed at the
+/// end of each line and reopened at the start of the next one.
+fn highlight(name: &str, text: &str) -> (String, Vec<String>) {
+    let first_line = text.lines().next().unwrap_or_default();
+    let syntax = find_syntax(name, first_line).unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let style = ClassStyle::SpacedPrefixed {
+        prefix: CLASS_PREFIX,
+    };
+
+    let mut parse_state = ParseState::new(syntax);
+    let mut scope_stack = ScopeStack::new();
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(text) {
+        let mut html = String::new();
+        for scope in scope_stack.as_slice() {
+            let classes = scope
+                .build_string()
+                .split('.')
+                .map(|atom| format!("{CLASS_PREFIX}{atom}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            html.push_str(&format!("<span class=\"{classes}\">"));
+        }
+        let highlighted = parse_state
+            .parse_line(line, &SYNTAXES)
+            .ok()
+            .and_then(|ops| line_tokens_to_classed_spans(line, &ops, style, &mut scope_stack).ok());
+        match highlighted {
+            Some((line_html, _)) => html.push_str(&line_html),
+            // Lines which can't be parsed are shown as they are
+            None => {
+                html = maud::html! { (line) }.into_string();
+                scope_stack = ScopeStack::new();
+            }
+        }
+        html.push_str(&"</span>".repeat(scope_stack.len()));
+        html.retain(|c| c != '\n' && c != '\r');
+        lines.push(html);
+    }
+    (syntax.name.clone(), lines)
+}
+
+/// Read and highlight the file at `path`, relative to the served path, unless it's too large or
+/// isn't text
+async fn view(conf: &MiniserveConfig, path: &Path) -> Result<Option<ViewedFile>, RuntimeError> {
+    let local_path = conf.path.join(path);
+    let metadata = match fs::metadata(&local_path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Ok(None),
+    };
+    if metadata.len() > MAX_VIEW_SIZE {
+        return Ok(None);
+    }
+    let contents = fs::read(&local_path)
+        .await
+        .map_err(|e| RuntimeError::IoError(format!("Failed to read {path:?}"), e))?;
+    let text = match String::from_utf8(contents) {
+        Ok(text) if !text.contains('\0') => text,
+        _ => return Ok(None),
+    };
+
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let (syntax, lines) = tokio::task::spawn_blocking(move || highlight(&name, &text))
+        .await
+        .map_err(|e| RuntimeError::IoError("Failed to highlight the file".into(), e.into()))?;
+    Ok(Some(ViewedFile {
+        syntax,
+        lines,
+        size: metadata.len(),
+    }))
+}
+
+/// Handle a request for the viewer page of a file.
+///
+/// Directories, files which are too large and files which aren't text are sent as usual, by
+/// redirecting to their plain URL.
+pub async fn view_file(req: HttpRequest) -> Result<HttpResponse, RuntimeError> {
+    let conf = req.app_data::<web::Data<SharedConfig>>().unwrap().load();
+    let not_found = || RuntimeError::RouteNotFoundError(req.path().to_string());
+
+    // Drop boxes don't expose their contents in any way
+    if conf.drop_box {
+        return Err(not_found());
+    }
+    let link = req.path();
+    let path = requested_path(&conf, link).ok_or_else(not_found)?;
+
+    match view(&conf, &path).await? {
+        Some(file) => Ok(HttpResponse::Ok()
+            .content_type(mime::TEXT_HTML_UTF_8)
+            .body(renderer::viewer_page(&path, link, &file, &conf).into_string())),
+        None => Ok(HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, link))
+            .finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("main.rs", "echo hi\n", "Rust")]
+    #[case("Makefile", "echo hi\n", "Makefile")]
+    #[case("script", "#!/bin/bash\necho hi\n", "Bourne Again Shell (bash)")]
+    #[case("server.log", "echo hi\n", "Plain Text")]
+    fn highlight_detects_syntax(#[case] name: &str, #[case] text: &str, #[case] syntax: &str) {
+        let (detected, _) = highlight(name, text);
+        assert_eq!(detected, syntax);
+    }
+
+    #[test]
+    fn highlighted_lines_are_self_contained() {
+        let (_, lines) = highlight("main.rs", "/* a\nmultiline\ncomment */\nfn main() {}\n");
+        assert_eq!(lines.len(), 4);
+        for line in &lines {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count()
+            );
+            assert!(!line.contains('\n'));
+        }
+        assert!(lines[1].contains("hl-comment"));
+        assert!(lines[3].contains("hl-keyword") || lines[3].contains("hl-storage"));
+    }
+
+    #[test]
+    fn highlight_escapes_html() {
+        let (_, lines) = highlight("notes.txt", "<script>alert(1)</script>\n");
+        assert!(lines[0].contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!lines[0].contains("<script>"));
+    }
+
+    #[rstest]
+    #[case("main.rs", 10, true)]
+    #[case("server.log", 10, true)]
+    #[case("Makefile", 10, true)]
+    #[case("photo.jpg", 10, false)]
+    #[case("main.rs", MAX_VIEW_SIZE + 1, false)]
+    fn viewable_files(#[case] name: &str, #[case] size: u64, #[case] expected: bool) {
+        assert_eq!(viewable(name, size), expected);
+    }
+}
@@ -1,6 +1,8 @@
 //! Helper types and functions to allow configuring hidden files visibility
-//! for WebDAV handlers
+//! for WebDAV handlers, and to apply the upload and removal rules to WebDAV writes
 
+use actix_web::{HttpRequest, HttpResponse, dev::Payload, http::Uri};
+use dav_server::actix::DavRequest;
 use dav_server::{
     davpath::DavPath,
     fs::{
@@ -11,53 +13,213 @@ use dav_server::{
     localfs::LocalFs,
 };
 use futures::StreamExt;
+use log::error;
+use percent_encoding::utf8_percent_encode;
 use std::ffi::OsStr;
 #[cfg(target_family = "unix")]
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
 
+use crate::args::DuplicateFile;
+use crate::config::MiniserveConfig;
+use crate::errors::RuntimeError;
+use crate::file_op::{
+    UploadLimits, check_content_length, free_file_name, save_file, upload_file_path,
+};
+use crate::file_utils::{Visibility, is_internal, sanitize_path};
+use crate::listing::percent_encode_sets::COMPONENT;
+use crate::{expiry, quota, trash};
+
 /// A dav_server local filesystem backend that can be configured to deny access
 /// to files and directories with names starting with a dot.
+///
+/// It's made for each request from the configuration at that time, so that writes follow the
+/// current upload rules.
 #[derive(Clone)]
 pub struct RestrictedFs {
     local: Box<LocalFs>,
     base_path: PathBuf,
-    show_hidden: bool,
-    no_symlinks: bool,
+    visibility: Visibility,
+    conf: Arc<MiniserveConfig>,
+    /// Name of the user making the request, recorded with the entries it moves to the trash
+    user: Option<String>,
 }
 
 impl RestrictedFs {
-    /// Creates a new RestrictedFs serving the local path at "base".
-    /// If "show_hidden" is false, access to hidden files is prevented.
-    /// If "no_symlinks" is true, access to symlinks is prevented.
-    pub fn new<P: AsRef<Path>>(base: P, show_hidden: bool, no_symlinks: bool) -> Box<RestrictedFs> {
-        let base_path = base.as_ref().to_path_buf();
-        let local = LocalFs::new(base, false, false, false);
+    /// Creates a new RestrictedFs serving the path of `conf`.
+    /// Unless hidden files are shown, access to them is prevented.
+    /// With `--no-symlinks`, access to symlinks is prevented.
+    pub fn new(conf: Arc<MiniserveConfig>, user: Option<String>) -> Box<RestrictedFs> {
+        let base_path = conf.path.clone();
+        let local = LocalFs::new(&base_path, false, false, false);
         Box::new(RestrictedFs {
             local,
             base_path,
-            show_hidden,
-            no_symlinks,
+            visibility: Visibility {
+                show_hidden: conf.show_hidden,
+                no_symlinks: conf.no_symlinks,
+            },
+            conf,
+            user,
         })
     }
 
     /// true if the path is allowed to appear in responses (not hidden and/or not a symlink, depending on flags)
+    ///
+    /// Paths through miniserve's internal entries are never allowed.
     async fn is_path_allowed(&self, path: &DavPath) -> bool {
-        if self.no_symlinks && path_has_symlink_components(path, &self.base_path).await {
+        if path_has_internal_components(path) {
+            return false;
+        }
+        if self.visibility.no_symlinks && path_has_symlink_components(path, &self.base_path).await {
             return false;
         }
-        if !self.show_hidden && path_has_hidden_components(path) {
+        if !self.visibility.show_hidden && path_has_hidden_components(path) {
             return false;
         }
         true
     }
+
+    /// Local path of `path` if it's allowed to be accessed
+    async fn allowed_local_path(&self, path: &DavPath) -> Result<PathBuf, DavFsError> {
+        if self.is_path_allowed(path).await {
+            Ok(self.base_path.join(path.as_rel_ospath()))
+        } else {
+            Err(DavFsError::NotFound)
+        }
+    }
+
+    /// Move `path`, found at `local_path`, to the trash
+    async fn move_to_trash(&self, path: &DavPath, local_path: &Path) -> Result<(), RuntimeError> {
+        trash::move_to_trash(
+            &self.base_path,
+            local_path,
+            path.as_rel_ospath(),
+            self.user.as_deref(),
+        )
+        .await
+    }
+}
+
+/// Report a failure to move an entry to the trash, which WebDAV can't tell the cause of
+fn trash_failure(err: RuntimeError) -> DavFsError {
+    error!("{err}");
+    DavFsError::GeneralFailure
+}
+
+/// Move the target of a WebDAV DELETE request to the trash, see `--trash`.
+///
+/// Directories are moved as a whole, where dav_server would remove their entries one by one.
+pub async fn delete_to_trash(
+    req: &HttpRequest,
+    conf: Arc<MiniserveConfig>,
+    user: Option<String>,
+) -> Result<HttpResponse, RuntimeError> {
+    let path = dav_path(req.uri().path(), &conf)?;
+    let fs = RestrictedFs::new(conf, user);
+    let not_found = || RuntimeError::RouteNotFoundError(req.path().to_owned());
+    let local_path = fs
+        .allowed_local_path(&path)
+        .await
+        .map_err(|_| not_found())?;
+    if local_path.symlink_metadata().is_err() {
+        return Err(not_found());
+    }
+    fs.move_to_trash(&path, &local_path).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Move the entry at the local `path`, which a COPY or MOVE request is about to replace, to the
+/// trash if it's enabled, see `--trash`.
+///
+/// Directories are moved as a whole, where dav_server would remove their entries one by one.
+pub async fn trash_replaced(
+    req: &HttpRequest,
+    conf: &MiniserveConfig,
+    path: &Path,
+    user: Option<&str>,
+) -> Result<(), RuntimeError> {
+    let replaces = matches!(req.method().as_str(), "COPY" | "MOVE")
+        && matches!(conf.on_duplicate_files, DuplicateFile::Overwrite)
+        && req.headers().get("Overwrite").is_none_or(|h| h != "F")
+        && path.symlink_metadata().is_ok();
+    if !conf.trash_enabled || !replaces {
+        return Ok(());
+    }
+    let relative_path = path.strip_prefix(&conf.path).unwrap_or(path);
+    trash::move_to_trash(&conf.path, path, relative_path, user).await
+}
+
+/// Save the body of a WebDAV PUT request to the local `path`, like a raw upload.
+///
+/// The file is written to a temporary file within the upload limits and quotas, and only replaces
+/// the current one, which is kept as a version if `on_duplicate_files` says so, once complete.
+pub async fn put_file(
+    req: &HttpRequest,
+    conf: &MiniserveConfig,
+    path: &Path,
+    user: Option<&str>,
+    payload: &mut Payload,
+) -> Result<HttpResponse, RuntimeError> {
+    let parent = path.parent().unwrap_or(path);
+    let dir = parent.strip_prefix(&conf.path).unwrap_or(Path::new(""));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let path = upload_file_path(
+        parent,
+        &file_name,
+        conf.show_hidden,
+        !conf.no_symlinks,
+        false,
+    )?;
+    if path.is_dir() {
+        return Err(RuntimeError::InvalidPathError(format!(
+            "cannot replace the directory {} with a file",
+            dir.join(&*file_name).display()
+        )));
+    }
+    let replaced = path.symlink_metadata().is_ok();
+    let reservation = quota::reserve(conf, dir, user);
+
+    // The request holds nothing but the file
+    let limits = UploadLimits {
+        media_types: conf.uploadable_media_type.as_deref(),
+        max_size: conf
+            .max_upload_file_size
+            .into_iter()
+            .chain(conf.max_upload_request_size)
+            .min(),
+        quota_left: quota::space_left(conf, dir, user).await?,
+        reservation: reservation.as_ref(),
+    };
+    check_content_length(req, limits)?;
+    limits.check_media_type(&file_name, &[])?;
+
+    let saved = save_file(payload, path, conf, limits, None).await?;
+    quota::record_upload(conf, &saved.path, saved.size, user).await;
+    drop(reservation);
+    expiry::record_upload(conf, &saved.path, conf.upload_expiry).await;
+
+    Ok(if replaced {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::Created().finish()
+    })
 }
 
 /// true if any normal component of path either starts with dot or can't be turned into a str
 fn path_has_hidden_components(path: &DavPath) -> bool {
     path.as_rel_ospath().components().any(|c| match c {
-        Component::Normal(name) => name.to_str().is_none_or(|s| s.starts_with('.')),
+        Component::Normal(name) => name.to_str().is_none() || Visibility::is_hidden(name),
+        _ => panic!("dav path should not contain any non-normal components"),
+    })
+}
+
+/// true if any component of path is an internal entry of miniserve, see [`is_internal`]
+fn path_has_internal_components(path: &DavPath) -> bool {
+    path.as_rel_ospath().components().any(|c| match c {
+        Component::Normal(name) => is_internal(name),
         _ => panic!("dav path should not contain any non-normal components"),
     })
 }
@@ -91,10 +253,16 @@ impl DavFileSystem for RestrictedFs {
     ) -> DavFsFuture<'a, Box<dyn DavFile>> {
         Box::pin(async move {
             if !self.is_path_allowed(path).await {
-                Err(DavFsError::NotFound)
-            } else {
-                self.local.open(path, options).await
+                return Err(DavFsError::NotFound);
+            }
+            // Files may only be written to where they may be uploaded
+            let writes = options.write || options.append || options.truncate;
+            let parent = path.as_rel_ospath().parent().unwrap_or(Path::new(""));
+            if (writes || options.create || options.create_new) && !self.conf.upload_allowed(parent)
+            {
+                return Err(DavFsError::Forbidden);
             }
+            self.local.open(path, options).await
         })
     }
 
@@ -108,14 +276,10 @@ impl DavFileSystem for RestrictedFs {
                 return Err(DavFsError::NotFound);
             }
 
-            if self.show_hidden && !self.no_symlinks {
-                return self.local.read_dir(path, meta).await;
-            }
-
+            // Every entry is filtered, since miniserve's internal entries are never exposed
+            let visibility = self.visibility;
             let dav_path = path.as_rel_ospath();
             let base_path = self.base_path.join(dav_path);
-            let show_hidden = self.show_hidden;
-            let no_symlinks = self.no_symlinks;
 
             let stream = self.local.read_dir(path, meta).await?;
 
@@ -124,24 +288,17 @@ impl DavFileSystem for RestrictedFs {
                 async move {
                     match entry_res {
                         Ok(e) => {
-                            if !show_hidden && e.name().starts_with(b".") {
-                                return None;
-                            }
-                            if no_symlinks {
-                                let name = e.name();
-                                #[cfg(not(target_os = "windows"))]
-                                let os_string = OsStr::from_bytes(&name);
-                                #[cfg(target_os = "windows")]
-                                let os_string: &OsStr =
-                                    std::str::from_utf8(&name).unwrap().as_ref();
-                                let entry_path = base_path.join(os_string);
-                                if let Ok(md) = fs::symlink_metadata(&entry_path).await
-                                    && md.file_type().is_symlink()
-                                {
-                                    return None;
-                                }
-                            }
-                            Some(Ok(e))
+                            let name = e.name();
+                            #[cfg(not(target_os = "windows"))]
+                            let os_string = OsStr::from_bytes(&name);
+                            #[cfg(target_os = "windows")]
+                            let os_string: &OsStr = std::str::from_utf8(&name).unwrap().as_ref();
+
+                            let is_symlink = visibility.no_symlinks
+                                && fs::symlink_metadata(base_path.join(os_string))
+                                    .await
+                                    .is_ok_and(|md| md.file_type().is_symlink());
+                            visibility.allows(os_string, is_symlink).then_some(Ok(e))
                         }
                         Err(e) => Some(Err(e)),
                     }
@@ -171,4 +328,204 @@ impl DavFileSystem for RestrictedFs {
             }
         })
     }
+
+    // Writes are done directly rather than through `LocalFs` so that new directories get the same
+    // default permissions as with the mkdir form, and so that removing a directory also removes
+    // its hidden content, like the rm form does.
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath) -> DavFsFuture<'a, ()> {
+        Box::pin(async move {
+            let path = self.allowed_local_path(path).await?;
+            Ok(fs::create_dir(path).await?)
+        })
+    }
+
+    // With `--trash`, removed entries are moved to the trash instead. The visible entries of
+    // directories are removed before them, so only the ones which still have hidden content are.
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath) -> DavFsFuture<'a, ()> {
+        Box::pin(async move {
+            let local_path = self.allowed_local_path(path).await?;
+            if !self.conf.trash_enabled {
+                return Ok(fs::remove_dir_all(local_path).await?);
+            }
+            if fs::remove_dir(&local_path).await.is_err() {
+                self.move_to_trash(path, &local_path)
+                    .await
+                    .map_err(trash_failure)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath) -> DavFsFuture<'a, ()> {
+        Box::pin(async move {
+            let local_path = self.allowed_local_path(path).await?;
+            if self.conf.trash_enabled {
+                return self
+                    .move_to_trash(path, &local_path)
+                    .await
+                    .map_err(trash_failure);
+            }
+            Ok(fs::remove_file(local_path).await?)
+        })
+    }
+
+    fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> DavFsFuture<'a, ()> {
+        Box::pin(async move {
+            let from = self.allowed_local_path(from).await?;
+            let to = self.allowed_local_path(to).await?;
+            Ok(fs::rename(from, to).await?)
+        })
+    }
+
+    fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> DavFsFuture<'a, ()> {
+        Box::pin(async move {
+            let from = self.allowed_local_path(from).await?;
+            let to = self.allowed_local_path(to).await?;
+            fs::copy(from, to).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Apply the rules of the upload and rm forms to a WebDAV request modifying files.
+///
+/// Creating an entry needs uploads to be allowed in its parent directory and removing it needs
+/// removal to be allowed for the entry itself. MOVE does both. Locking an entry, which may create
+/// it, and changing its properties need uploads to be allowed too. If the target of a PUT, COPY or
+/// MOVE already exists, `on_duplicate_files` decides whether the request fails, overwrites it,
+/// keeps it as a version or is redirected to a free name.
+///
+/// Returns the local path of the entry created by the request, if any.
+pub fn authorize_write(
+    req: &HttpRequest,
+    dav_req: &mut DavRequest,
+    conf: &MiniserveConfig,
+) -> Result<Option<PathBuf>, RuntimeError> {
+    let method = req.method().as_str();
+    if ![
+        "PUT",
+        "MKCOL",
+        "DELETE",
+        "MOVE",
+        "COPY",
+        "LOCK",
+        "PROPPATCH",
+    ]
+    .contains(&method)
+    {
+        return Ok(None);
+    }
+
+    let target = relative_path(req.uri().path(), conf)?;
+    if matches!(method, "LOCK" | "PROPPATCH") {
+        return if conf.upload_allowed(target.parent().unwrap_or(Path::new(""))) {
+            Ok(None)
+        } else {
+            Err(RuntimeError::UploadForbiddenError)
+        };
+    }
+    if matches!(method, "DELETE" | "MOVE") && !conf.rm_allowed(&target) {
+        return Err(RuntimeError::RmForbiddenError);
+    }
+
+    let created = match method {
+        "DELETE" => return Ok(None),
+        "MOVE" | "COPY" => {
+            let destination = req
+                .headers()
+                .get("Destination")
+                .and_then(|h| h.to_str().ok())
+                .ok_or_else(|| {
+                    RuntimeError::InvalidHttpRequestError(
+                        "Missing or invalid Destination header".to_string(),
+                    )
+                })?;
+            let destination = match destination.parse::<Uri>() {
+                Ok(uri) => uri.path().to_owned(),
+                Err(_) => destination.to_owned(),
+            };
+            relative_path(&destination, conf)?
+        }
+        _ => target,
+    };
+
+    let parent = created.parent().unwrap_or(Path::new(""));
+    if !conf.upload_allowed(parent) {
+        return Err(RuntimeError::UploadForbiddenError);
+    }
+    if method == "MKCOL" {
+        if !conf.mkdir_enabled {
+            return Err(RuntimeError::InsufficientPermissionsError(
+                created.display().to_string(),
+            ));
+        }
+        return Ok(None);
+    }
+
+    // Broken symlinks count as existing too, as they would be overwritten
+    let local_path = conf.path.join(&created);
+    if let Ok(metadata) = local_path.symlink_metadata() {
+        match conf.on_duplicate_files {
+            DuplicateFile::Error => return Err(RuntimeError::DuplicateFileError),
+            // Replacing a whole directory is a removal, which is only allowed where removals are
+            DuplicateFile::Overwrite if metadata.is_dir() && !conf.rm_allowed(&created) => {
+                return Err(RuntimeError::RmForbiddenError);
+            }
+            DuplicateFile::Overwrite => (),
+            // Only files have versions, kept once a PUT completes or before COPY and MOVE
+            // requests are handled
+            DuplicateFile::Version if local_path.is_dir() => {
+                return Err(RuntimeError::DuplicateFileError);
+            }
+            DuplicateFile::Version => (),
+            DuplicateFile::Rename => {
+                let renamed = free_file_name(&local_path);
+                let url = url_path(renamed.strip_prefix(&conf.path).unwrap(), conf);
+                let invalid = || RuntimeError::InvalidPathError(url.clone());
+                if method == "PUT" {
+                    *dav_req.request.uri_mut() = url.parse().map_err(|_| invalid())?;
+                } else {
+                    let destination = url.parse().map_err(|_| invalid())?;
+                    dav_req
+                        .request
+                        .headers_mut()
+                        .insert("Destination", destination);
+                }
+                return Ok(Some(renamed));
+            }
+        }
+    }
+
+    Ok(Some(local_path))
+}
+
+/// Path relative to the served directory of the URL path `url_path`.
+///
+/// Hidden paths are treated as not found unless they are shown.
+pub fn relative_path(url_path: &str, conf: &MiniserveConfig) -> Result<PathBuf, RuntimeError> {
+    let dav_path = dav_path(url_path, conf)?;
+    sanitize_path(dav_path.as_rel_ospath(), conf.show_hidden)
+        .ok_or_else(|| RuntimeError::RouteNotFoundError(url_path.to_owned()))
+}
+
+/// WebDAV path of the URL path `url_path`, below the route prefix
+fn dav_path(url_path: &str, conf: &MiniserveConfig) -> Result<DavPath, RuntimeError> {
+    let not_found = || RuntimeError::RouteNotFoundError(url_path.to_owned());
+    let mut dav_path = DavPath::new(url_path).map_err(|_| not_found())?;
+    dav_path
+        .set_prefix(&conf.route_prefix)
+        .map_err(|_| not_found())?;
+    Ok(dav_path)
+}
+
+/// URL path of `path`, relative to the served directory
+fn url_path(path: &Path, conf: &MiniserveConfig) -> String {
+    let mut url = conf.route_prefix.clone();
+    for component in path.iter() {
+        url.push('/');
+        url.extend(utf8_percent_encode(&component.to_string_lossy(), COMPONENT));
+    }
+    url
 }
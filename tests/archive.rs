@@ -1,4 +1,4 @@
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 
 use reqwest::{StatusCode, blocking::Client};
 use rstest::rstest;
@@ -7,7 +7,10 @@ use zip::ZipArchive;
 
 mod fixtures;
 
-use crate::fixtures::{Error, TestServer, reqwest_client, server};
+use crate::fixtures::{
+    BROKEN_SYMLINK, DIRECTORY_SYMLINK, Error, FILE_IN_DIR_BEHIND_SYMLINKED_DIR, FILE_SYMLINK,
+    FILES, HIDDEN_DIRECTORIES, HIDDEN_FILES, TestServer, reqwest_client, server,
+};
 
 enum ArchiveKind {
     TarGz,
@@ -204,34 +207,101 @@ fn archives_links_and_downloads(
     Ok(())
 }
 
-enum ExpectedLen {
-    /// Exact byte length expected.
-    Exact(usize),
-    /// Minimum byte length expected.
-    Min(usize),
+/// Names of the entries in the archive of the served directory, relative to its top-level folder.
+fn archive_entry_names(
+    reqwest_client: &Client,
+    server: &TestServer,
+    kind: ArchiveKind,
+) -> Result<Vec<String>, Error> {
+    let bytes = reqwest_client
+        .get(server.url().join(kind.download_param())?)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    let tar_entry_names = |reader: &mut dyn Read| -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        for entry in tar::Archive::new(reader).entries()? {
+            names.push(entry?.path()?.to_string_lossy().into_owned());
+        }
+        Ok(names)
+    };
+    let names = match kind {
+        ArchiveKind::TarGz => tar_entry_names(&mut libflate::gzip::Decoder::new(bytes.as_ref())?)?,
+        ArchiveKind::Tar => tar_entry_names(&mut bytes.as_ref())?,
+        ArchiveKind::Zip => ZipArchive::new(Cursor::new(bytes))?
+            .file_names()
+            .map(ToOwned::to_owned)
+            .collect(),
+    };
+
+    Ok(names
+        .iter()
+        .filter_map(|name| name.split_once('/'))
+        .map(|(_, name)| name.trim_end_matches('/').to_owned())
+        .filter(|name| !name.is_empty())
+        .collect())
 }
 
-/// Broken symlinks (from [`fixtures::BROKEN_SYMLINK`]) yield different archive behaviors:
-/// - tar_gz: a file with only partial header fields. See "rfc1952 § 2.3.1. Member header and trailer".
-/// - tar: a tarball containing a subset of files.
-/// - zip: an empty file.
+/// Archives only contain hidden files if `--hidden` is given, like the listing. Broken symlinks
+/// (from [`fixtures::BROKEN_SYMLINK`]) are skipped instead of breaking the archive.
 #[rstest]
-#[case::tar_gz(ArchiveKind::TarGz, ExpectedLen::Exact(10))]
-#[case::tar(ArchiveKind::Tar, ExpectedLen::Min(512 + 512 + 2 * 512))]
-#[case::zip(ArchiveKind::Zip, ExpectedLen::Exact(0))]
-fn archive_behave_differently_with_broken_symlinks(
-    #[case] kind: ArchiveKind,
-    #[case] expected: ExpectedLen,
-    #[with(&[ArchiveKind::TarGz.server_option(), ArchiveKind::Tar.server_option(), ArchiveKind::Zip.server_option()])]
-    server: TestServer,
+fn archives_honor_hidden_files_rules(
+    #[values(ArchiveKind::TarGz, ArchiveKind::Tar, ArchiveKind::Zip)] kind: ArchiveKind,
+    #[values(false, true)] show_hidden: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let mut args = vec![kind.server_option()];
+    if show_hidden {
+        args.push("--hidden");
+    }
+    let server = server(&args);
+
+    let names = archive_entry_names(&reqwest_client, &server, kind)?;
+    for &file in FILES {
+        assert!(names.iter().any(|name| name == file), "{file} is missing");
+    }
+    let hidden_entries = HIDDEN_FILES
+        .iter()
+        .map(|file| file.to_string())
+        .chain(HIDDEN_FILES.iter().map(|file| format!("dira/{file}")))
+        .chain(
+            HIDDEN_DIRECTORIES
+                .iter()
+                .map(|dir| format!("{dir}test.txt")),
+        );
+    for entry in hidden_entries {
+        assert_eq!(names.contains(&entry), show_hidden, "{entry}");
+    }
+    assert!(!names.iter().any(|name| name == BROKEN_SYMLINK));
+
+    Ok(())
+}
+
+/// Archives don't contain symlinks nor what's behind them with `--no-symlinks`.
+#[rstest]
+fn archives_honor_no_symlinks(
+    #[values(ArchiveKind::TarGz, ArchiveKind::Tar, ArchiveKind::Zip)] kind: ArchiveKind,
+    #[values(false, true)] no_symlinks: bool,
     reqwest_client: Client,
 ) -> Result<(), Error> {
-    let (status_code, byte_len) = download_archive_bytes(&reqwest_client, &server, kind)?;
-    assert_eq!(status_code, StatusCode::OK);
+    let mut args = vec![kind.server_option()];
+    if no_symlinks {
+        args.push("--no-symlinks");
+    }
+    let server = server(&args);
 
-    match expected {
-        ExpectedLen::Exact(len) => assert_eq!(byte_len, len),
-        ExpectedLen::Min(len) => assert!(byte_len >= len),
+    let names = archive_entry_names(&reqwest_client, &server, kind)?;
+    for entry in [
+        DIRECTORY_SYMLINK.trim_end_matches('/'),
+        FILE_SYMLINK,
+        FILE_IN_DIR_BEHIND_SYMLINKED_DIR,
+    ] {
+        assert_eq!(
+            names.iter().any(|name| name == entry),
+            !no_symlinks,
+            "{entry}"
+        );
     }
 
     Ok(())
@@ -265,3 +335,31 @@ fn zip_archives_store_entry_name_in_unix_style(
 
     Ok(())
 }
+
+/// ZIP archives are streamed with data descriptors and contain the full content of every file.
+#[rstest]
+fn zip_archives_are_streamed_with_file_contents(
+    #[with(&["--enable-zip"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url().join("someDir/?download=zip")?)
+        .send()?
+        .error_for_status()?;
+
+    // Streamed responses don't know their length upfront
+    assert!(resp.content_length().is_none());
+
+    let mut archive = ZipArchive::new(Cursor::new(resp.bytes()?))?;
+    for (name, content) in [
+        ("someDir/alpha", "alpha file content"),
+        ("someDir/some_sub_dir/bravo", "bravo file content"),
+    ] {
+        let mut entry = archive.by_name(name)?;
+        let mut actual = String::new();
+        entry.read_to_string(&mut actual)?;
+        assert_eq!(actual, content);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::process::Command;
+
+use assert_cmd::{cargo, prelude::*};
+use assert_fs::{TempDir, prelude::*};
+use predicates::str::contains;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use select::{document::Document, predicate::Name};
+
+mod fixtures;
+
+use crate::fixtures::{Error, reqwest_client, server};
+
+/// Write `content` into a config file inside a fresh temporary directory.
+fn config_file(content: &str) -> Result<TempDir, Error> {
+    let dir = TempDir::new()?;
+    dir.child("miniserve.toml").write_str(content)?;
+    Ok(dir)
+}
+
+fn page_title(reqwest_client: &Client, url: reqwest::Url) -> Result<String, Error> {
+    let body = reqwest_client.get(url).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    Ok(parsed.find(Name("title")).next().ok_or("No title")?.text())
+}
+
+#[rstest]
+fn config_file_sets_options(reqwest_client: Client) -> Result<(), Error> {
+    let config = config_file("title = \"from config\"\nheader = [\"x-config: yes\"]")?;
+    let config_path = config.child("miniserve.toml");
+    let server = server(&["--config", config_path.to_str().unwrap()]);
+
+    let resp = reqwest_client.get(server.url()).send()?;
+    assert_eq!(resp.headers().get("x-config").unwrap(), "yes");
+    assert!(page_title(&reqwest_client, server.url())?.starts_with("from config"));
+
+    Ok(())
+}
+
+#[rstest]
+fn command_line_overrides_config_file(reqwest_client: Client) -> Result<(), Error> {
+    let config = config_file("title = \"from config\"")?;
+    let config_path = config.child("miniserve.toml");
+    let server = server(&[
+        "--config",
+        config_path.to_str().unwrap(),
+        "--title",
+        "from cli",
+    ]);
+
+    assert!(page_title(&reqwest_client, server.url())?.starts_with("from cli"));
+
+    Ok(())
+}
+
+#[test]
+/// Print the merged configuration and exit.
+fn print_config_shows_merged_config() -> Result<(), Error> {
+    let config = config_file("port = 1234\ntitle = \"from config\"\nhidden = true")?;
+
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .arg("--config")
+        .arg(config.child("miniserve.toml").path())
+        .arg("--print-config")
+        .arg("--title")
+        .arg("from cli")
+        .arg(config.path())
+        .assert()
+        .success()
+        .stdout(contains("port = 1234"))
+        .stdout(contains("title = \"from cli\""))
+        .stdout(contains("hidden = true"))
+        .stdout(contains("verbose = false"));
+
+    Ok(())
+}
+
+#[rstest]
+#[case("not_an_option = true", "Unknown option 'not_an_option'")]
+#[case("port = \"not a port\"", "invalid value")]
+#[case("spa = true", "--index <INDEX>")]
+/// Config file values go through the same validation as command line arguments.
+fn invalid_config_file_is_rejected(
+    #[case] content: &str,
+    #[case] message: &str,
+) -> Result<(), Error> {
+    let config = config_file(content)?;
+
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .arg("--config")
+        .arg(config.child("miniserve.toml").path())
+        .arg("--print-config")
+        .arg(config.path())
+        .assert()
+        .failure()
+        .stderr(contains(message));
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+mod fixtures;
+
+use assert_cmd::{Command, cargo};
+use fixtures::{DIRECTORIES, Error, FILES, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, multipart};
+use rstest::rstest;
+use select::{document::Document, predicate::Attr};
+
+use crate::fixtures::reqwest_client;
+
+/// Start a drop box session, returning its cookie
+fn start_session(client: &Client, server: &TestServer) -> Result<String, Error> {
+    let resp = client.get(server.url()).send()?.error_for_status()?;
+    let cookie = resp.headers()["Set-Cookie"].to_str()?;
+    assert!(cookie.contains("HttpOnly"));
+    Ok(cookie.split(';').next().unwrap().to_owned())
+}
+
+/// Listing of `path` as seen from the session with `cookie`
+fn listing(
+    client: &Client,
+    server: &TestServer,
+    path: &str,
+    cookie: &str,
+) -> Result<String, Error> {
+    Ok(client
+        .get(server.url().join(path)?)
+        .header("Cookie", cookie)
+        .send()?
+        .error_for_status()?
+        .text()?)
+}
+
+#[rstest]
+fn drop_box_hides_existing_entries(
+    #[with(&["-u", "--drop-box"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let cookie = start_session(&reqwest_client, &server)?;
+    let page = listing(&reqwest_client, &server, "", &cookie)?;
+
+    let parsed = Document::from(page.as_str());
+    assert!(parsed.find(Attr("id", "file_submit")).next().is_some());
+    for entry in FILES.iter().chain(DIRECTORIES) {
+        assert!(
+            parsed
+                .find(|x: &select::node::Node| x.text() == *entry)
+                .next()
+                .is_none()
+        );
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn drop_box_lists_own_uploads(
+    #[with(&["-u", "--drop-box", "--mkdir"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let cookie = start_session(&reqwest_client, &server)?;
+    reqwest_client
+        .put(server.url().join("dira/mine.txt")?)
+        .header("Cookie", &cookie)
+        .body("mine")
+        .send()?
+        .error_for_status()?;
+    let form = multipart::Form::new().part(
+        "file_to_upload",
+        multipart::Part::text("also mine").file_name("form.txt"),
+    );
+    reqwest_client
+        .post(server.url().join("upload?path=/")?)
+        .header("Cookie", &cookie)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+
+    let page = listing(&reqwest_client, &server, "", &cookie)?;
+    assert!(page.contains("form.txt"));
+    assert!(page.contains("dira/"));
+    assert!(!page.contains("test.txt"));
+    // Uploads aren't linked to, as they can't be downloaded
+    assert!(!page.contains("href=\"/form.txt\""));
+    let page = listing(&reqwest_client, &server, "dira/", &cookie)?;
+    assert!(page.contains("mine.txt"));
+
+    // Other sessions don't see them
+    let other = start_session(&reqwest_client, &server)?;
+    assert!(other != cookie);
+    let page = listing(&reqwest_client, &server, "", &other)?;
+    assert!(!page.contains("form.txt"));
+    assert!(!page.contains("dira/"));
+
+    Ok(())
+}
+
+#[rstest]
+#[case("test.txt")]
+#[case("dira/uploaded.txt")]
+#[case("dira/uploaded.txt?download=tar_gz")]
+fn drop_box_never_serves_files(
+    #[case] path: &str,
+    #[with(&["-u", "--drop-box", "-z"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let cookie = start_session(&reqwest_client, &server)?;
+    reqwest_client
+        .put(server.url().join("dira/uploaded.txt")?)
+        .header("Cookie", &cookie)
+        .body("uploaded")
+        .send()?
+        .error_for_status()?;
+
+    let resp = reqwest_client
+        .get(server.url().join(path)?)
+        .header("Cookie", &cookie)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn drop_box_disables_archives_and_copies(
+    #[with(&["-u", "--drop-box", "-z"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url().join("?download=tar_gz")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let mut url = server.url().join("cp")?;
+    url.query_pairs_mut()
+        .append_pair("path", "test.txt")
+        .append_pair("destination", "copy.txt");
+    let resp = reqwest_client.post(url).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(!server.path().join("copy.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(&["--drop-box"])]
+#[case(&["-u", "--drop-box", "-R"])]
+#[case(&["-u", "--drop-box", "--enable-search"])]
+#[case(&["-u", "--drop-box", "--enable-webdav"])]
+#[case(&["-u", "--drop-box", "--disable-indexing"])]
+fn drop_box_rejects_exposing_options(#[case] args: &[&str]) -> Result<(), Error> {
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .args(args)
+        .arg(".")
+        .assert()
+        .failure();
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+mod fixtures;
+
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+use fixtures::{Error, TestServer, server};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, multipart};
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Class, Name, Text};
+use serde_json::Value;
+
+use crate::fixtures::reqwest_client;
+
+/// Upload `name` to the root of `server` from the raw request body, with the given query
+fn upload(client: &Client, server: &TestServer, name: &str, query: &str) -> Result<(), Error> {
+    client
+        .put(server.url().join(&format!("{name}{query}"))?)
+        .body("contents")
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Expiry date of `name` in the JSON listing of the root of `server`, in seconds since the Unix
+/// epoch
+fn listed_expiry(client: &Client, server: &TestServer, name: &str) -> Result<Value, Error> {
+    let entries: Vec<Value> = client
+        .get(server.url().join("?format=json")?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let entry = entries.iter().find(|entry| entry["name"] == name).unwrap();
+    Ok(entry["expires"].clone())
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[rstest]
+fn uploads_expire_after_the_requested_time(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    upload(&reqwest_client, &server, "expiring.txt", "?expire=1s")?;
+    upload(&reqwest_client, &server, "kept.txt", "")?;
+    assert!(server.path().join("expiring.txt").exists());
+
+    // Uploads delete what expired without waiting for the background task
+    sleep(Duration::from_millis(1500));
+    upload(&reqwest_client, &server, "trigger.txt", "")?;
+    assert!(!server.path().join("expiring.txt").exists());
+    assert!(server.path().join("kept.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_expire_after_the_configured_time(
+    #[with(&["-u", "--upload-expiry", "1s"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let part = multipart::Part::text("contents").file_name("expiring.txt");
+    let form = multipart::Form::new().part("file_to_upload", part);
+    reqwest_client
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+    assert!(server.path().join("expiring.txt").exists());
+
+    sleep(Duration::from_millis(1500));
+    upload(&reqwest_client, &server, "trigger.txt", "")?;
+    assert!(!server.path().join("expiring.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("", 24 * 60 * 60)]
+#[case("?expire=1h", 60 * 60)]
+#[case("?expire=2w", 24 * 60 * 60)]
+fn requested_expiry_cant_exceed_the_configured_one(
+    #[case] query: &str,
+    #[case] expected_secs: u64,
+    #[with(&["-u", "--upload-expiry", "1d"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let before = now();
+    upload(&reqwest_client, &server, "expiring.txt", query)?;
+    let expires = listed_expiry(&reqwest_client, &server, "expiring.txt")?
+        .as_u64()
+        .unwrap();
+    assert!((before + expected_secs..=now() + expected_secs).contains(&expires));
+
+    Ok(())
+}
+
+#[rstest]
+fn uploads_dont_expire_by_default(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    upload(&reqwest_client, &server, "kept.txt", "")?;
+    assert_eq!(
+        listed_expiry(&reqwest_client, &server, "kept.txt")?,
+        Value::Null
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case("?expire=soon")]
+#[case("?expire=0s")]
+fn invalid_expiry_is_rejected(
+    #[case] query: &str,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .put(server.url().join(&format!("rejected.txt{query}"))?)
+        .body("contents")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(!server.path().join("rejected.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn files_replaced_without_expiry_are_kept(
+    #[with(&["-u", "-o", "overwrite"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    upload(&reqwest_client, &server, "replaced.txt", "?expire=1s")?;
+    upload(&reqwest_client, &server, "replaced.txt", "")?;
+
+    sleep(Duration::from_millis(1500));
+    upload(&reqwest_client, &server, "trigger.txt", "")?;
+    assert!(server.path().join("replaced.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn listing_shows_when_uploads_expire(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    upload(&reqwest_client, &server, "expiring.txt", "?expire=3d")?;
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    let parsed = Document::from(body.as_str());
+    let row = parsed
+        .find(Name("tr"))
+        .find(|row| row.find(Text).any(|text| text.text() == "expiring.txt"))
+        .unwrap();
+    let expiry = row.find(Class("expiry")).next().unwrap().text();
+    assert!(expiry.starts_with("expires in"), "{expiry}");
+    assert_eq!(parsed.find(Class("expiry")).count(), 2);
+
+    Ok(())
+}
+
+#[rstest]
+fn expiry_file_is_never_served(
+    #[with(&["-u", "-H"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    upload(&reqwest_client, &server, "expiring.txt", "?expire=1h")?;
+    assert!(server.path().join(".miniserve-expiry.json").exists());
+
+    let resp = reqwest_client
+        .get(server.url().join(".miniserve-expiry.json")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    assert!(!body.contains(".miniserve-expiry.json"));
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--pastebin"]), &["Never expires", "Expires in 10 minutes", "Expires in 1 week"], &[])]
+#[case(server(&["-u", "--pastebin", "--upload-expiry", "1d"]), &["Expires in 1 day", "Expires in 1 hour"], &["Never expires", "Expires in 1 week"])]
+fn pastebin_offers_expiry_choices(
+    #[case] server: TestServer,
+    #[case] offered: &[&str],
+    #[case] not_offered: &[&str],
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    for choice in offered {
+        assert!(body.contains(choice), "{choice} isn't offered");
+    }
+    for choice in not_offered {
+        assert!(!body.contains(choice), "{choice} is offered");
+    }
+
+    Ok(())
+}
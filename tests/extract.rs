@@ -0,0 +1,301 @@
+mod fixtures;
+
+use std::io::{Cursor, Write};
+
+use fixtures::{Error, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response, multipart};
+use rstest::rstest;
+use serde_json::Value;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::fixtures::{reqwest_client, reqwest_client_no_redirect};
+
+/// A zip archive holding the `files`, given by name and contents, compressed with `method`
+fn zip_archive(files: &[(&str, &[u8])], method: CompressionMethod) -> Result<Vec<u8>, Error> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(method);
+    for (name, contents) in files {
+        zip.start_file(*name, options)?;
+        zip.write_all(contents)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// A tar archive holding the `files`, given by name and contents
+fn tar_archive(files: &[(&str, &[u8])]) -> Result<Vec<u8>, Error> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, *contents)?;
+    }
+    Ok(tar.into_inner()?)
+}
+
+/// Upload `archive` called `name` to the root of `server` with a multipart form
+fn upload(
+    client: &Client,
+    server: &TestServer,
+    name: &str,
+    archive: Vec<u8>,
+    extract: bool,
+) -> Result<Response, Error> {
+    let part = multipart::Part::bytes(archive).file_name(name.to_string());
+    let form = multipart::Form::new().part("file_to_upload", part);
+    let query = if extract { "&extract=true" } else { "" };
+    Ok(client
+        .post(server.url().join(&format!("/upload?path=/{query}"))?)
+        .multipart(form)
+        .send()?)
+}
+
+const FILES: &[(&str, &[u8])] = &[
+    ("extracted.txt", b"top level"),
+    ("nested/dir/deep.txt", b"deeply nested"),
+];
+
+#[rstest]
+#[case(CompressionMethod::Stored)]
+#[case(CompressionMethod::Deflated)]
+fn extracting_zip_works(
+    #[case] method: CompressionMethod,
+    #[with(&["-u", "-U", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let archive = zip_archive(FILES, method)?;
+    let resp = upload(&reqwest_client, &server, "archive.zip", archive, true)?;
+    assert!(resp.status().is_success());
+
+    for (name, contents) in FILES {
+        assert_eq!(std::fs::read(server.path().join(name))?, *contents);
+    }
+    assert!(!server.path().join("archive.zip").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-U", "--extract-archives"]), false)]
+#[case(server(&["-u", "-U"]), true)]
+fn archives_are_kept_unless_extraction_is_enabled_and_asked_for(
+    #[case] server: TestServer,
+    #[case] extract: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let archive = zip_archive(FILES, CompressionMethod::Stored)?;
+    let resp = upload(&reqwest_client, &server, "archive.zip", archive, extract)?;
+    assert!(resp.status().is_success());
+
+    assert!(server.path().join("archive.zip").exists());
+    assert!(!server.path().join("extracted.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("archive.tar", false)]
+#[case("archive.tar.gz", true)]
+#[case("archive.tgz", true)]
+fn extracting_raw_tar_upload_works(
+    #[case] name: &str,
+    #[case] gzip: bool,
+    #[with(&["-u", "-U", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let mut archive = tar_archive(FILES)?;
+    if gzip {
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+        encoder.write_all(&archive)?;
+        archive = encoder.finish().into_result()?;
+    }
+
+    let resp = reqwest_client
+        .put(server.url().join(&format!("{name}?extract=true"))?)
+        .body(archive)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.json::<Value>()?["extracted"], true);
+
+    for (name, contents) in FILES {
+        assert_eq!(std::fs::read(server.path().join(name))?, *contents);
+    }
+    assert!(!server.path().join(name).exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("../escaped.txt")]
+#[case("nested/../../escaped.txt")]
+#[case("/escaped.txt")]
+#[case(".miniserve-trash/escaped.txt")]
+fn extraction_rejects_paths_leaving_the_directory(
+    #[case] name: &str,
+    #[with(&["-u", "-U", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let archive = zip_archive(
+        &[("harmless.txt", b"harmless"), (name, b"escaped")],
+        CompressionMethod::Stored,
+    )?;
+    let resp = upload(&reqwest_client, &server, "archive.zip", archive, true)?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Nothing is extracted from an archive with an invalid entry
+    assert!(!server.path().join("harmless.txt").exists());
+    assert!(!server.path().join("archive.zip").exists());
+    assert!(!server.path().parent().unwrap().join("escaped.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("link", "../outside", false)]
+#[case("link", "/etc", false)]
+#[case("nested/link", "../extracted.txt", true)]
+fn extraction_rejects_symlinks_leaving_the_directory(
+    #[case] link: &str,
+    #[case] target: &str,
+    #[case] allowed: bool,
+    #[with(&["-u", "-U", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    tar.append_link(&mut header, link, target)?;
+    let archive = tar.into_inner()?;
+
+    let resp = upload(&reqwest_client, &server, "archive.tar", archive, true)?;
+    assert_eq!(resp.status().is_success(), allowed);
+    assert_eq!(server.path().join(link).symlink_metadata().is_ok(), allowed);
+
+    Ok(())
+}
+
+#[rstest]
+fn extraction_rejects_symlinks_to_hidden_entries(
+    #[with(&["-u", "-R", "--trash", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join(".env"), "SECRET=1")?;
+    let mut tar = tar::Builder::new(Vec::new());
+    for (link, target) in [("e", ".env"), ("t", ".miniserve-trash")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        tar.append_link(&mut header, link, target)?;
+    }
+    let archive = tar.into_inner()?;
+
+    let resp = reqwest_client
+        .put(server.url().join("archive.tar?extract=true")?)
+        .body(archive)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    for link in ["e", "t"] {
+        assert!(server.path().join(link).symlink_metadata().is_err());
+        let resp = reqwest_client.get(server.url().join(link)?).send()?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn extraction_rejects_zip_bombs(
+    #[with(&["-u", "-U", "--extract-archives", "--max-extract-size", "1MB"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let zeros = vec![0; 2_000_000];
+    let archive = zip_archive(&[("zeros", &zeros)], CompressionMethod::Deflated)?;
+    assert!(archive.len() < 100_000);
+
+    let resp = upload(&reqwest_client, &server, "bomb.zip", archive, true)?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(!server.path().join("zeros").exists());
+    assert!(!server.path().join("bomb.zip").exists());
+
+    Ok(())
+}
+
+/// Extracted files must be accepted as uploads, one by one
+#[rstest]
+#[case(server(&["-u", "-M", ".zip,.png", "--extract-archives"]), "evil.html", b"<script>alert(1)</script>", StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&["-u", "-M", ".zip,.png", "--extract-archives"]), "evil.png", b"<html><script>alert(1)</script>", StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&["-u", "--max-file-size", "1KB", "--extract-archives"]), "large.txt", &[b'a'; 2000], StatusCode::PAYLOAD_TOO_LARGE)]
+fn extracted_files_follow_the_upload_limits(
+    #[case] server: TestServer,
+    #[case] name: &str,
+    #[case] contents: &[u8],
+    #[case] status: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let files = [
+        ("accepted.png", b"\x89PNG\r\n\x1a\n".as_slice()),
+        (name, contents),
+    ];
+    let archive = zip_archive(&files, CompressionMethod::Deflated)?;
+    let resp = upload(&reqwest_client, &server, "archive.zip", archive, true)?;
+    assert_eq!(resp.status(), status);
+    assert!(!server.path().join(name).exists());
+    assert!(!server.path().join("accepted.png").exists());
+    assert!(!server.path().join("archive.zip").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--extract-archives"]), StatusCode::CONFLICT, "existing.txt", b"existing")]
+#[case(server(&["-u", "--extract-archives", "-o", "overwrite"]), StatusCode::SEE_OTHER, "existing.txt", b"extracted")]
+#[case(server(&["-u", "--extract-archives", "-o", "rename"]), StatusCode::SEE_OTHER, "existing-1.txt", b"extracted")]
+fn extraction_follows_duplicate_policy(
+    #[case] server: TestServer,
+    #[case] status: StatusCode,
+    #[case] checked_file: &str,
+    #[case] contents: &[u8],
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("existing.txt"), "existing")?;
+
+    let archive = zip_archive(&[("existing.txt", b"extracted")], CompressionMethod::Stored)?;
+    let resp = upload(
+        &reqwest_client_no_redirect,
+        &server,
+        "archive.zip",
+        archive,
+        true,
+    )?;
+    assert_eq!(resp.status(), status);
+    assert_eq!(std::fs::read(server.path().join(checked_file))?, contents);
+
+    Ok(())
+}
+
+#[rstest]
+fn extraction_needs_mkdir_for_new_directories(
+    #[with(&["-u", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let archive = zip_archive(FILES, CompressionMethod::Stored)?;
+    let resp = upload(&reqwest_client, &server, "archive.zip", archive, true)?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert!(!server.path().join("extracted.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn upload_form_offers_extraction(
+    #[with(&["-u", "--extract-archives"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    assert!(body.contains(r#"id="extract-input""#));
+
+    Ok(())
+}
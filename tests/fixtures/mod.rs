@@ -71,6 +71,18 @@ pub fn reqwest_client() -> Client {
     reqwest_client.build().unwrap()
 }
 
+/// Default reqwest client which doesn't follow redirections, to see the responses themselves.
+#[fixture]
+pub fn reqwest_client_no_redirect() -> Client {
+    if rustls::crypto::CryptoProvider::get_default().is_none() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    }
+    let reqwest_client = ClientBuilder::new()
+        .tls_danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::none());
+    reqwest_client.build().unwrap()
+}
+
 /// Test fixture which creates a temporary directory with a few files and directories inside.
 /// The directories also contain files.
 #[fixture]
@@ -232,6 +244,10 @@ impl TestServer {
     pub fn port(&self) -> u16 {
         self.port
     }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
 }
 
 impl Drop for TestServer {
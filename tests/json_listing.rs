@@ -0,0 +1,180 @@
+use pretty_assertions::assert_eq;
+use reqwest::{blocking::Client, header};
+use rstest::rstest;
+use serde_json::Value;
+
+mod fixtures;
+
+use crate::fixtures::{
+    DIRECTORIES, DIRECTORY_SYMLINK, Error, FILE_SYMLINK, FILES, HIDDEN_FILES, TestServer,
+    reqwest_client, server,
+};
+
+/// Fetch the JSON listing of `path` using `?format=json`.
+fn json_listing(client: &Client, server: &TestServer, path: &str) -> Result<Vec<Value>, Error> {
+    let resp = client
+        .get(server.url().join(path)?)
+        .send()?
+        .error_for_status()?;
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    Ok(resp.json()?)
+}
+
+fn names(entries: &[Value]) -> Vec<&str> {
+    entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect()
+}
+
+#[rstest]
+fn json_listing_contains_entries(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    let entries = json_listing(&reqwest_client, &server, "?format=json")?;
+    let names = names(&entries);
+
+    for &file in FILES {
+        let entry = &entries[names.iter().position(|&n| n == file).unwrap()];
+        assert_eq!(entry["type"], "file");
+        assert!(entry["size"].as_u64().is_some());
+        assert!(entry["mtime"].as_u64().is_some());
+        assert_eq!(entry["symlink_target"], Value::Null);
+    }
+    for &dir in DIRECTORIES {
+        let name = dir.strip_suffix('/').unwrap();
+        let entry = &entries[names.iter().position(|&n| n == name).unwrap()];
+        assert_eq!(entry["type"], "directory");
+        assert_eq!(entry["size"], Value::Null);
+    }
+    for &hidden in HIDDEN_FILES {
+        assert!(!names.contains(&hidden));
+    }
+
+    let entry = &entries[names.iter().position(|&n| n == "test.txt").unwrap()];
+    assert_eq!(entry["link"], "/test.txt");
+
+    Ok(())
+}
+
+#[rstest]
+#[case(&["--hidden"], true, true)]
+#[case(&["--no-symlinks"], false, false)]
+fn json_listing_honors_hidden_and_symlink_rules(
+    #[case] args: &[&str],
+    #[case] shows_hidden: bool,
+    #[case] shows_symlinks: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let server = server(args);
+    let entries = json_listing(&reqwest_client, &server, "?format=json")?;
+    let names = names(&entries);
+
+    for &hidden in HIDDEN_FILES {
+        assert_eq!(names.contains(&hidden), shows_hidden);
+    }
+    for symlink in [DIRECTORY_SYMLINK.strip_suffix('/').unwrap(), FILE_SYMLINK] {
+        assert_eq!(names.contains(&symlink), shows_symlinks);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn json_listing_shows_symlink_target(
+    #[with(&["--show-symlink-info"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let entries = json_listing(&reqwest_client, &server, "?format=json")?;
+    let entry = entries.iter().find(|e| e["name"] == FILE_SYMLINK).unwrap();
+
+    assert!(entry["symlink_target"].as_str().is_some());
+
+    Ok(())
+}
+
+#[rstest]
+fn json_listing_is_sorted(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    let by_name_asc = json_listing(&reqwest_client, &server, "?format=json&sort=name&order=asc")?;
+    let mut by_name_desc = json_listing(
+        &reqwest_client,
+        &server,
+        "?format=json&sort=name&order=desc",
+    )?;
+    by_name_desc.reverse();
+    assert_eq!(names(&by_name_asc), names(&by_name_desc));
+
+    for (order, larger_first) in [("asc", false), ("desc", true)] {
+        let sizes = json_listing(
+            &reqwest_client,
+            &server,
+            &format!("?format=json&sort=size&order={order}"),
+        )?
+        .iter()
+        .map(|entry| entry["size"].as_u64().unwrap_or(0))
+        .collect::<Vec<_>>();
+        assert!(
+            sizes.windows(2).all(|w| if larger_first {
+                w[0] >= w[1]
+            } else {
+                w[0] <= w[1]
+            }),
+            "{sizes:?} isn't sorted {order}"
+        );
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case("application/json", "application/json")]
+#[case("application/x-ndjson", "application/x-ndjson")]
+#[case(
+    "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    "text/html; charset=utf-8"
+)]
+#[case("*/*", "text/html; charset=utf-8")]
+fn listing_format_is_negotiated(
+    #[case] accept: &str,
+    #[case] content_type: &str,
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url())
+        .header(header::ACCEPT, accept)
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        content_type
+    );
+    assert_eq!(resp.headers().get(header::VARY).unwrap(), "Accept");
+
+    Ok(())
+}
+
+#[rstest]
+fn ndjson_listing_has_one_entry_per_line(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client
+        .get(server.url().join("?format=ndjson")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let entries = body
+        .lines()
+        .map(serde_json::from_str::<Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(
+        names(&entries),
+        names(&json_listing(&reqwest_client, &server, "?format=json")?)
+    );
+
+    Ok(())
+}
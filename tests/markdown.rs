@@ -0,0 +1,231 @@
+mod fixtures;
+
+use std::fs;
+
+use fixtures::{Error, TestServer, server};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Predicate};
+
+use crate::fixtures::{reqwest_client, reqwest_client_no_redirect};
+
+const DOCUMENT: &str = "# Guide\n\n\
+    Read [the intro](intro.md) and [the notes](/someDir/notes.md).\n\n\
+    ![logo](/images/logo.png)\n\n\
+    ## Install\n\n\
+    ### From source\n";
+
+#[rstest]
+fn markdown_files_are_rendered(
+    #[with(&["--render-markdown"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("someDir/guide.md"), DOCUMENT)?;
+    let resp = reqwest_client
+        .get(server.url().join("someDir/guide.md")?)
+        .send()?
+        .error_for_status()?;
+    assert!(
+        resp.headers()[header::CONTENT_TYPE]
+            .to_str()?
+            .starts_with("text/html")
+    );
+    let parsed = Document::from_read(resp)?;
+    let markdown = parsed.find(Attr("id", "markdown")).next().unwrap();
+
+    assert_eq!(markdown.find(Name("h1")).next().unwrap().text(), "Guide");
+    let links: Vec<_> = markdown
+        .find(Name("p").descendant(Name("a")))
+        .filter_map(|link| link.attr("href"))
+        .collect();
+    assert_eq!(links, ["intro.md", "/someDir/notes.md"]);
+    assert_eq!(
+        markdown.find(Name("img")).next().unwrap().attr("src"),
+        Some("/images/logo.png")
+    );
+    assert!(
+        parsed
+            .find(Name("a").and(Attr("href", "/someDir/")))
+            .next()
+            .is_some()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn markdown_pages_have_a_table_of_contents(
+    #[with(&["--render-markdown"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("guide.md"), DOCUMENT)?;
+    let body = reqwest_client
+        .get(server.url().join("guide.md")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+
+    let toc: Vec<_> = parsed
+        .find(Attr("id", "toc").descendant(Name("li")))
+        .map(|item| {
+            let link = item.find(Name("a")).next().unwrap();
+            (
+                item.attr("class").unwrap_or_default().to_owned(),
+                link.attr("href").unwrap_or_default().to_owned(),
+                link.text(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        toc,
+        [
+            ("toc_level_1", "#user-content-guide", "Guide"),
+            ("toc_level_2", "#user-content-install", "Install"),
+            ("toc_level_3", "#user-content-from-source", "From source"),
+        ]
+        .map(|(class, href, text)| (class.to_owned(), href.to_owned(), text.to_owned()))
+    );
+    for anchor in [
+        "user-content-guide",
+        "user-content-install",
+        "user-content-from-source",
+    ] {
+        assert!(parsed.find(Attr("id", anchor)).next().is_some());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn markdown_pages_link_to_their_source(
+    #[with(&["--render-markdown"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("guide.md"), DOCUMENT)?;
+    let body = reqwest_client
+        .get(server.url().join("guide.md")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let source = parsed
+        .find(Class("view_source"))
+        .next()
+        .unwrap()
+        .attr("href")
+        .unwrap()
+        .to_owned();
+    assert_eq!(source, "/guide.md?view=1");
+
+    // The source is shown by the viewer, and the raw file is sent as usual
+    let body = reqwest_client
+        .get(server.url().join(&source)?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert_eq!(parsed.find(Class("line")).next().unwrap().text(), "# Guide");
+
+    let raw = reqwest_client
+        .get(server.url().join("guide.md?raw=true")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(raw, DOCUMENT);
+
+    Ok(())
+}
+
+#[rstest]
+fn markdown_pages_work_with_route_prefix(
+    #[with(&["--render-markdown", "--route-prefix", "prefix"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("someDir/guide.md"), DOCUMENT)?;
+    let body = reqwest_client
+        .get(server.url().join("prefix/someDir/guide.md")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let markdown = parsed.find(Attr("id", "markdown")).next().unwrap();
+
+    let links: Vec<_> = markdown
+        .find(Name("p").descendant(Name("a")))
+        .filter_map(|link| link.attr("href"))
+        .collect();
+    assert_eq!(links, ["intro.md", "/prefix/someDir/notes.md"]);
+    assert_eq!(
+        markdown.find(Name("img")).next().unwrap().attr("src"),
+        Some("/prefix/images/logo.png")
+    );
+    assert_eq!(
+        parsed
+            .find(Class("view_source"))
+            .next()
+            .unwrap()
+            .attr("href"),
+        Some("/prefix/someDir/guide.md?view=1")
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&[] as &[&str]))]
+#[case(server(&["--render-markdown", "-u", "--drop-box"]))]
+fn markdown_files_are_only_rendered_when_enabled(
+    #[case] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("guide.md"), DOCUMENT)?;
+    let body = reqwest_client_no_redirect
+        .get(server.url().join("guide.md")?)
+        .send()?;
+    let parsed = Document::from_read(body)?;
+
+    assert!(parsed.find(Attr("id", "markdown")).next().is_none());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["--render-markdown"]), ".hidden.md")]
+#[case(server(&["--render-markdown"]), "missing.md")]
+#[case(server(&["--render-markdown", "--no-symlinks"]), "linked.md")]
+fn markdown_pages_follow_the_rules_of_files(
+    #[case] server: TestServer,
+    #[case] name: &str,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("guide.md"), DOCUMENT)?;
+    fs::write(server.path().join(".hidden.md"), DOCUMENT)?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        server.path().join("guide.md"),
+        server.path().join("linked.md"),
+    )?;
+
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join(name)?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn binary_markdown_files_are_sent_raw(
+    #[with(&["--render-markdown"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("binary.md"), [0, 159, 146, 150])?;
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join("binary.md")?)
+        .send()?;
+
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()[header::LOCATION], "/binary.md?raw=true");
+
+    Ok(())
+}
@@ -0,0 +1,305 @@
+mod fixtures;
+
+use fixtures::{Error, TestServer, server};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use rstest::rstest;
+
+use crate::fixtures::{DIRECTORY_SYMLINK, FILES, HIDDEN_FILES, reqwest_client};
+
+/// Send a move or copy request for `path` to `destination`, both unencoded.
+fn transfer(
+    reqwest_client: &Client,
+    server: &TestServer,
+    route: &str,
+    path: &str,
+    destination: &str,
+) -> Result<Response, Error> {
+    let mut url = server.url().join(route)?;
+    url.query_pairs_mut().append_pair("path", path);
+    Ok(reqwest_client
+        .post(url)
+        .form(&[("destination", destination)])
+        .send()?)
+}
+
+#[rstest]
+#[case(server(&[] as &[&str]), "cp")]
+#[case(server(&[] as &[&str]), "mv")]
+#[case(server(&["-R"]), "cp")]
+#[case(server(&["-R"]), "mv")]
+#[case(server(&["-u"]), "mv")]
+fn mv_and_cp_are_disabled_by_default(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = transfer(&reqwest_client, &server, route, FILES[0], "moved.txt")?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(server.path().join(FILES[0]).exists());
+    assert!(!server.path().join("moved.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(FILES[0], "renamed.txt")]
+#[case(FILES[1], "dirb/moved.html")]
+#[case(FILES[3], "dir space/moved")]
+#[case("dira", "dirb/dira")]
+#[case("someDir/some_sub_dir", "renamed_dir")]
+fn mv_works(
+    #[case] path: &str,
+    #[case] destination: &str,
+    #[with(&["-u", "-R"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let is_dir = server.path().join(path).is_dir();
+
+    transfer(&reqwest_client, &server, "mv", path, destination)?.error_for_status()?;
+
+    assert!(!server.path().join(path).exists());
+    assert_eq!(server.path().join(destination).is_dir(), is_dir);
+    assert!(server.path().join(destination).exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(FILES[0], "copied.txt")]
+#[case(FILES[1], "dirb/copied.html")]
+#[case("dira", "dirb/dira")]
+#[case("dir space", "copied dir")]
+fn cp_works(
+    #[case] path: &str,
+    #[case] destination: &str,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    transfer(&reqwest_client, &server, "cp", path, destination)?.error_for_status()?;
+
+    let (source, copy) = (server.path().join(path), server.path().join(destination));
+    assert!(source.exists());
+    if source.is_dir() {
+        for file in FILES {
+            assert!(copy.join(file).exists());
+        }
+    } else {
+        assert_eq!(std::fs::read(source)?, std::fs::read(copy)?);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn cp_skips_hidden_entries(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    transfer(&reqwest_client, &server, "cp", "dira", "copy")?.error_for_status()?;
+
+    assert!(server.path().join("copy").join(FILES[0]).exists());
+    for file in HIDDEN_FILES {
+        assert!(server.path().join("dira").join(file).exists());
+        assert!(!server.path().join("copy").join(file).exists());
+    }
+
+    Ok(())
+}
+
+/// Moves need the source to be removable and the destination to be writable, copies only the
+/// latter.
+#[rstest]
+#[case(server(&["-u", "someDir", "-R"]), "mv", "someDir/moved", StatusCode::OK)]
+#[case(server(&["-u", "someDir", "-R"]), "mv", "dirb/moved", StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "-R", "someDir"]), "mv", "someDir/moved", StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "someDir", "-R", "dira"]), "mv", "someDir/moved", StatusCode::OK)]
+#[case(server(&["-u", "someDir"]), "cp", "someDir/copied", StatusCode::OK)]
+#[case(server(&["-u", "someDir"]), "cp", "dirb/copied", StatusCode::FORBIDDEN)]
+fn mv_and_cp_are_restricted(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    #[case] destination: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let path = format!("dira/{}", FILES[0]);
+    let resp = transfer(&reqwest_client, &server, route, &path, destination)?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(
+        server.path().join(destination).exists(),
+        expected.is_success()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-R"]), "mv", StatusCode::CONFLICT, None)]
+#[case(server(&["-u", "-R", "-o", "error"]), "cp", StatusCode::CONFLICT, None)]
+#[case(server(&["-u", "-R", "-o", "overwrite"]), "mv", StatusCode::OK, Some("test.txt"))]
+#[case(server(&["-u", "-o", "overwrite"]), "cp", StatusCode::OK, Some("test.txt"))]
+#[case(server(&["-u", "-R", "-o", "rename"]), "mv", StatusCode::OK, Some("test-1.txt"))]
+#[case(server(&["-u", "-o", "rename"]), "cp", StatusCode::OK, Some("test-1.txt"))]
+fn mv_and_cp_respect_on_duplicate_files(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    #[case] expected: StatusCode,
+    #[case] written: Option<&str>,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let contents = std::fs::read(server.path().join("test.txt"))?;
+    std::fs::write(server.path().join("test.html"), "source")?;
+
+    let resp = transfer(&reqwest_client, &server, route, "test.html", "test.txt")?;
+    assert_eq!(resp.status(), expected);
+
+    match written {
+        Some(written) => assert_eq!(std::fs::read(server.path().join(written))?, b"source"),
+        None => assert_eq!(std::fs::read(server.path().join("test.txt"))?, contents),
+    }
+    if written == Some("test-1.txt") {
+        assert_eq!(std::fs::read(server.path().join("test.txt"))?, contents);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-o", "overwrite"]), "cp", StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "-R", "someDir", "-o", "overwrite"]), "mv", StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "-R", "-o", "overwrite"]), "cp", StatusCode::OK)]
+#[case(server(&["-u", "-R", "-o", "overwrite"]), "mv", StatusCode::OK)]
+fn replacing_directories_requires_rm(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("someDir/replacing"), "source")?;
+
+    let resp = transfer(&reqwest_client, &server, route, "someDir/replacing", "dira")?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(server.path().join("dira").is_dir(), !expected.is_success());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("dira", "dira/inside")]
+#[case("someDir", "someDir/some_sub_dir/inside")]
+#[case("dira/test.txt", "dira")]
+#[case("dira", "")]
+#[case("", "dirb/root")]
+fn mv_rejects_invalid_destinations(
+    #[case] path: &str,
+    #[case] destination: &str,
+    #[with(&["-u", "-R", "-o", "overwrite"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = transfer(&reqwest_client, &server, "mv", path, destination)?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(server.path().join("dira/test.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-R", "-o", "rename"]), "cp", StatusCode::OK)]
+#[case(server(&["-u", "-R", "-o", "rename"]), "mv", StatusCode::OK)]
+#[case(server(&["-u", "-R", "-o", "overwrite"]), "cp", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u", "-R", "-o", "version"]), "mv", StatusCode::BAD_REQUEST)]
+fn transfers_onto_themselves_follow_on_duplicate_files(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let contents = std::fs::read(server.path().join("test.txt"))?;
+
+    let resp = transfer(&reqwest_client, &server, route, "test.txt", "test.txt")?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(
+        server.path().join("test-1.txt").exists(),
+        expected.is_success()
+    );
+    let kept = if route == "mv" && expected.is_success() {
+        "test-1.txt"
+    } else {
+        "test.txt"
+    };
+    assert_eq!(std::fs::read(server.path().join(kept))?, contents);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-R"]), HIDDEN_FILES[0], "visible", false)]
+#[case(server(&["-u", "-R"]), FILES[0], ".hidden", false)]
+#[case(server(&["-u", "-R", "-H"]), HIDDEN_FILES[0], "visible", true)]
+#[case(server(&["-u", "-R", "-H"]), FILES[0], ".hidden", true)]
+fn mv_honors_hidden_files(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] destination: &str,
+    #[case] should_succeed: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = transfer(&reqwest_client, &server, "mv", path, destination)?;
+    assert_eq!(resp.status().is_success(), should_succeed);
+    assert_eq!(server.path().join(destination).exists(), should_succeed);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-R"]), true)]
+#[case(server(&["-u", "-R", "--no-symlinks"]), false)]
+fn mv_through_symlinks(
+    #[case] server: TestServer,
+    #[case] should_succeed: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let through_link = format!("{DIRECTORY_SYMLINK}{}", FILES[0]);
+
+    let resp = transfer(&reqwest_client, &server, "mv", &through_link, "moved")?;
+    assert_eq!(resp.status().is_success(), should_succeed);
+    let resp = transfer(&reqwest_client, &server, "cp", FILES[1], &through_link)?;
+    assert_eq!(resp.status().is_success(), should_succeed);
+
+    Ok(())
+}
+
+#[rstest]
+fn mv_works_with_route_prefix(
+    #[with(&["-u", "-R", "--route-prefix", "foo"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // The redirection to the listing goes to "/" without a referer, so only the move is checked
+    transfer(&reqwest_client, &server, "foo/mv", FILES[0], "dira/moved")?;
+    assert!(!server.path().join(FILES[0]).exists());
+    assert!(server.path().join("dira/moved").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "-R"]), &["Move", "Copy"])]
+#[case(server(&["-u"]), &["Copy"])]
+#[case(server(&["-R"]), &[])]
+fn listing_shows_mv_and_cp_actions(
+    #[case] server: TestServer,
+    #[case] buttons: &[&str],
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    assert_eq!(body.contains("mv_form"), !buttons.is_empty());
+    for button in ["Move", "Copy"] {
+        assert_eq!(
+            body.contains(&format!(">{button}</button>")),
+            buttons.contains(&button)
+        );
+    }
+
+    Ok(())
+}
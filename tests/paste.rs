@@ -1,4 +1,5 @@
-use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, multipart};
 use rstest::rstest;
 use select::{document::Document, predicate::Attr};
 
@@ -9,7 +10,7 @@ use crate::fixtures::{Error, TestServer, reqwest_client, server};
 // There are few tests here because the pastebin is implemented by converting a textareas content
 // into an in-memory blob/file, and adding that file to the existing file upload form. We can't
 // test the JS here, and any testing the actual "upload" would just be retesting the existing
-// uploader.
+// uploader. Pastes created from the command line through `/__paste` are tested below.
 
 #[rstest]
 #[case::without_flag(&["--upload-files"], false)]
@@ -35,3 +36,146 @@ fn paste_entry_only_appears_with_flag(
 
     Ok(())
 }
+
+/// Path of the paste at `url`, relative to the root of `server`
+fn paste_path(server: &TestServer, url: &str) -> String {
+    url.trim_end()
+        .strip_prefix(server.url().as_str())
+        .unwrap()
+        .to_string()
+}
+
+#[rstest]
+fn pasting_raw_body_returns_its_url(
+    #[with(&["-u", "--pastebin"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .post(server.url().join("/__paste")?)
+        .body("pasted text")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let url = resp.text()?;
+    assert!(url.ends_with(".txt\n"), "{url}");
+
+    let path = paste_path(&server, &url);
+    assert!(path.starts_with("paste-"), "{path}");
+    assert_eq!(
+        std::fs::read_to_string(server.path().join(&path))?,
+        "pasted text"
+    );
+    assert_eq!(
+        reqwest_client.get(url.trim_end()).send()?.text()?,
+        "pasted text"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(multipart::Part::text("pasted text"), ".txt")]
+#[case(multipart::Part::text("pasted text").file_name("script.SH"), ".sh")]
+#[case(multipart::Part::text("pasted text").file_name("../../escape"), ".txt")]
+fn pasting_form_returns_its_url(
+    #[case] part: multipart::Part,
+    #[case] extension: &str,
+    #[with(&["-u", "--pastebin"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let form = multipart::Form::new().part("f", part);
+    let resp = reqwest_client
+        .post(server.url().join("/__paste")?)
+        .multipart(form)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let path = paste_path(&server, &resp.text()?);
+    assert!(path.ends_with(extension), "{path}");
+    assert_eq!(
+        std::fs::read_to_string(server.path().join(&path))?,
+        "pasted text"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "dira", "--pastebin"]), "/__paste", "dira/")]
+#[case(server(&["-u", "dira", "-u", "someDir", "--pastebin"]), "/__paste?path=someDir", "someDir/")]
+#[case(server(&["-u", "--pastebin", "--route-prefix", "prefix"]), "/prefix/__paste", "prefix/")]
+fn pastes_go_to_the_upload_directory(
+    #[case] server: TestServer,
+    #[case] endpoint: &str,
+    #[case] expected_prefix: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .post(server.url().join(endpoint)?)
+        .body("pasted text")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let url = resp.text()?;
+    let path = paste_path(&server, &url);
+    assert!(path.starts_with(expected_prefix), "{path}");
+    let local_path = path.strip_prefix("prefix/").unwrap_or(&path);
+    assert!(server.path().join(local_path).is_file());
+    assert_eq!(
+        reqwest_client.get(url.trim_end()).send()?.text()?,
+        "pasted text"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u"]), StatusCode::NOT_FOUND)]
+#[case(server(&["-u", "someDir", "--pastebin"]), StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "--pastebin", "-a", "user:pass"]), StatusCode::UNAUTHORIZED)]
+#[case(server(&["-u", "--pastebin", "--max-file-size", "4B"]), StatusCode::PAYLOAD_TOO_LARGE)]
+fn pastes_are_refused_unless_allowed(
+    #[case] server: TestServer,
+    #[case] status: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let endpoint = if status == StatusCode::FORBIDDEN {
+        "/__paste?path=elsewhere"
+    } else {
+        "/__paste"
+    };
+    let resp = reqwest_client
+        .post(server.url().join(endpoint)?)
+        .body("pasted text")
+        .send()?;
+    assert_eq!(resp.status(), status);
+    assert!(!server.path().join("__paste").exists());
+    let pastes = std::fs::read_dir(server.path())?
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .is_ok_and(|entry| entry.file_name().to_string_lossy().starts_with("paste-"))
+        })
+        .count();
+    assert_eq!(pastes, 0);
+
+    Ok(())
+}
+
+/// Errors are sent to the command line as they are, rather than as web pages
+#[rstest]
+#[case(server(&["-u", "--pastebin", "--max-file-size", "4B"]), "__paste")]
+#[case(server(&["-u", "--pastebin", "--max-file-size", "4B", "--route-prefix", "foo"]), "foo/__paste")]
+fn paste_errors_are_plain_text(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .post(server.url().join(route)?)
+        .body("pasted text")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resp.headers()["Content-Type"], "text/plain; charset=utf-8");
+
+    Ok(())
+}
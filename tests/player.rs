@@ -0,0 +1,263 @@
+mod fixtures;
+
+use std::fs;
+
+use fixtures::{Error, TestServer, server};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Predicate};
+use serde_json::Value;
+
+use crate::fixtures::{reqwest_client, reqwest_client_no_redirect};
+
+/// Write a few media files of different sizes to `dir` in `server`, next to `test.mkv` and
+/// `⎙.mp4` which are there already
+fn write_media(server: &TestServer, dir: &str) -> Result<(), Error> {
+    fs::write(server.path().join(dir).join("b.mp3"), [0; 30])?;
+    fs::write(server.path().join(dir).join("a.mp4"), [0; 10])?;
+    fs::write(server.path().join(dir).join("c.ogg"), [0; 20])?;
+    Ok(())
+}
+
+/// Names and kinds of the media of the playlist of a player page
+fn playlist(body: &str) -> Vec<(String, String)> {
+    Document::from(body)
+        .find(Attr("id", "playlist").descendant(Name("a")))
+        .map(|link| {
+            (
+                link.text(),
+                link.attr("data-kind").unwrap_or_default().to_owned(),
+            )
+        })
+        .collect()
+}
+
+#[rstest]
+#[case("b.mp3", "audio")]
+#[case("a.mp4", "video")]
+fn media_files_have_a_player(
+    #[case] name: &str,
+    #[case] kind: &str,
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    write_media(&server, "")?;
+    let body = reqwest_client
+        .get(server.url().join(&format!("{name}?play=1"))?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    assert_eq!(playlist(&body), [(name.to_owned(), kind.to_owned())]);
+    let parsed = Document::from(body.as_str());
+    assert!(
+        parsed
+            .find(Attr("id", format!("{kind}_player").as_str()))
+            .next()
+            .is_some()
+    );
+    assert!(
+        parsed
+            .find(Name("a").and(Attr("href", "/?play=1")))
+            .next()
+            .is_some()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn media_are_streamed_with_range_requests(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("song.mp3"), b"0123456789")?;
+    let resp = reqwest_client
+        .get(server.url().join("song.mp3")?)
+        .header(header::RANGE, "bytes=2-5")
+        .send()?;
+
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resp.text()?, "2345");
+
+    Ok(())
+}
+
+#[rstest]
+#[case("test.txt", "/test.txt")]
+#[case("dira?play=1", "/dira/?play=1")]
+fn other_entries_are_redirected(
+    #[case] path: &str,
+    #[case] location: &str,
+    server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    let url = if path.contains('?') {
+        server.url().join(path)?
+    } else {
+        server.url().join(&format!("{path}?play=1"))?
+    };
+    let resp = reqwest_client_no_redirect.get(url).send()?;
+
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()[header::LOCATION], location);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&[] as &[&str]), ".hidden.mp3")]
+#[case(server(&["--no-symlinks"]), "linked.mp3")]
+#[case(server(&["-u", "--drop-box"]), "b.mp3")]
+fn player_follows_the_rules_of_files(
+    #[case] server: TestServer,
+    #[case] name: &str,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    write_media(&server, "")?;
+    fs::write(server.path().join(".hidden.mp3"), [0; 10])?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        server.path().join("b.mp3"),
+        server.path().join("linked.mp3"),
+    )?;
+
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join(&format!("{name}?play=1"))?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+#[case("sort=name&order=asc")]
+#[case("sort=size&order=desc")]
+#[case("sort=size&order=asc")]
+fn directory_player_follows_the_listing_order(
+    #[case] sorting: &str,
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    write_media(&server, "")?;
+    let listing: Vec<Value> = reqwest_client
+        .get(server.url().join(&format!("?format=json&{sorting}"))?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let expected: Vec<_> = listing
+        .iter()
+        .filter_map(|entry| entry["name"].as_str())
+        .filter(|name| {
+            [".mp3", ".mp4", ".ogg", ".mkv"]
+                .iter()
+                .any(|ext| name.ends_with(ext))
+        })
+        .map(str::to_owned)
+        .collect();
+    assert_eq!(expected.len(), 5);
+
+    let body = reqwest_client
+        .get(server.url().join(&format!("?play=1&{sorting}"))?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let names: Vec<_> = playlist(&body).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, expected);
+    assert!(body.contains(&format!(
+        "?format=m3u8&amp;{}",
+        sorting.replace('&', "&amp;")
+    )));
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&[] as &[&str]), "", "")]
+#[case(server(&["--route-prefix", "prefix"]), "prefix/", "/prefix")]
+fn directories_have_m3u8_playlists(
+    #[case] server: TestServer,
+    #[case] dir: &str,
+    #[case] prefix: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    write_media(&server, "")?;
+    let resp = reqwest_client
+        .get(
+            server
+                .url()
+                .join(&format!("{dir}?format=m3u8&sort=name&order=desc"))?,
+        )
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        resp.headers()[header::CONTENT_TYPE],
+        "application/vnd.apple.mpegurl"
+    );
+    assert!(
+        resp.headers()[header::CONTENT_DISPOSITION]
+            .to_str()?
+            .starts_with("attachment; filename=")
+    );
+    let host = format!("localhost:{}", server.port());
+    let body = resp.text()?;
+    assert!(body.starts_with("#EXTM3U\n"));
+    assert!(body.contains(&format!(
+        "#EXTINF:-1,a.mp4\nhttp://{host}{prefix}/a.mp4\n\
+         #EXTINF:-1,b.mp3\nhttp://{host}{prefix}/b.mp3\n\
+         #EXTINF:-1,c.ogg\nhttp://{host}{prefix}/c.ogg\n"
+    )));
+    assert!(body.contains(&format!("http://{host}{prefix}/%E2%8E%99.mp4\n")));
+    assert!(!body.contains("test.txt"));
+
+    Ok(())
+}
+
+#[rstest]
+fn m3u8_playlists_can_be_negotiated(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    write_media(&server, "")?;
+    let resp = reqwest_client
+        .get(server.url())
+        .header(header::ACCEPT, "application/vnd.apple.mpegurl")
+        .send()?
+        .error_for_status()?;
+
+    assert!(resp.text()?.starts_with("#EXTM3U\n"));
+
+    Ok(())
+}
+
+#[rstest]
+fn listing_links_media_to_the_player(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let url = server.url().join("someDir/")?;
+    let body = reqwest_client.get(url.clone()).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Class("play_all")).next().is_none());
+
+    write_media(&server, "someDir")?;
+    let body = reqwest_client.get(url).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let play_links: Vec<_> = parsed
+        .find(Name("a").and(Class("play_link")))
+        .filter_map(|link| link.attr("href"))
+        .collect();
+    assert!(play_links.contains(&"/someDir/b.mp3?play=1"));
+    assert!(play_links.contains(&"/someDir/a.mp4?play=1"));
+    assert!(!play_links.iter().any(|link| link.contains("alpha")));
+    assert_eq!(
+        parsed.find(Class("play_all")).next().unwrap().attr("href"),
+        Some("?play=1")
+    );
+
+    Ok(())
+}
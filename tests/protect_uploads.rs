@@ -0,0 +1,216 @@
+mod fixtures;
+
+use std::fs;
+
+use fixtures::{Error, TestServer, server};
+use reqwest::blocking::{Client, Response};
+use reqwest::header;
+use rstest::rstest;
+
+use crate::fixtures::reqwest_client;
+
+const SCRIPT: &str = "<script>alert(document.cookie)</script>";
+
+/// Fetch `path` from `server`, making sure it's found
+fn get(client: &Client, server: &TestServer, path: &str) -> Result<Response, Error> {
+    Ok(client
+        .get(server.url().join(path)?)
+        .send()?
+        .error_for_status()?)
+}
+
+/// Value of the `name` header of `resp`, if any
+fn header_value(resp: &Response, name: header::HeaderName) -> Option<&str> {
+    resp.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn assert_protected(resp: &Response) {
+    assert!(
+        header_value(resp, header::CONTENT_DISPOSITION)
+            .is_some_and(|disposition| disposition.starts_with("attachment"))
+    );
+    assert_eq!(
+        header_value(resp, header::CONTENT_SECURITY_POLICY),
+        Some("sandbox")
+    );
+    assert_eq!(
+        header_value(resp, header::X_CONTENT_TYPE_OPTIONS),
+        Some("nosniff")
+    );
+}
+
+fn assert_not_protected(resp: &Response) {
+    assert!(
+        !header_value(resp, header::CONTENT_DISPOSITION)
+            .is_some_and(|disposition| disposition.starts_with("attachment"))
+    );
+    assert_ne!(
+        header_value(resp, header::CONTENT_SECURITY_POLICY),
+        Some("sandbox")
+    );
+}
+
+#[rstest]
+#[case("evil.html")]
+#[case("evil.svg")]
+#[case("evil.xhtml")]
+#[case("evil.xml")]
+fn active_uploads_are_attachments(
+    #[case] name: &str,
+    #[with(&["-u", "--protect-uploads"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join(name), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, name)?;
+
+    assert_protected(&resp);
+    // The file itself is unchanged
+    assert_eq!(resp.text()?, SCRIPT);
+
+    Ok(())
+}
+
+#[rstest]
+fn file_names_are_kept(
+    #[with(&["-u", "--protect-uploads"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("evil.html"), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, "evil.html")?;
+
+    assert_eq!(
+        header_value(&resp, header::CONTENT_DISPOSITION),
+        Some("attachment; filename=\"evil.html\"")
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn other_uploads_are_not_sniffed(
+    #[with(&["-u", "--protect-uploads"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("notes.txt"), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, "notes.txt")?;
+
+    assert_not_protected(&resp);
+    assert_eq!(
+        header_value(&resp, header::X_CONTENT_TYPE_OPTIONS),
+        Some("nosniff")
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u"]), "evil.html")]
+#[case(server(&["-u", "someDir", "--protect-uploads"]), "evil.html")]
+#[case(server(&["-u", "--protect-uploads", "--trusted-path", "dira"]), "dira/evil.html")]
+#[case(server(&["-u", "--protect-uploads", "--trusted-path", "someDir,dira"]), "dira/evil.html")]
+fn other_files_are_served_as_usual(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join(path), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, path)?;
+
+    assert_not_protected(&resp);
+    assert_eq!(resp.text()?, SCRIPT);
+
+    Ok(())
+}
+
+#[rstest]
+fn only_upload_directories_are_protected(
+    #[with(&["-u", "someDir", "--protect-uploads"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("someDir/evil.html"), SCRIPT)?;
+    fs::write(server.path().join("someDir/some_sub_dir/evil.svg"), SCRIPT)?;
+
+    assert_protected(&get(&reqwest_client, &server, "someDir/evil.html")?);
+    assert_protected(&get(
+        &reqwest_client,
+        &server,
+        "someDir/some_sub_dir/evil.svg",
+    )?);
+    assert_not_protected(&get(&reqwest_client, &server, "test.html")?);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--protect-uploads"]), "")]
+#[case(server(&["-u", "--protect-uploads", "--route-prefix", "prefix"]), "prefix/")]
+fn pages_of_miniserve_are_not_protected(
+    #[case] server: TestServer,
+    #[case] prefix: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("evil.html"), SCRIPT)?;
+
+    assert_not_protected(&get(&reqwest_client, &server, prefix)?);
+    assert_not_protected(&get(
+        &reqwest_client,
+        &server,
+        &format!("{prefix}evil.html?view=1"),
+    )?);
+    assert_protected(&get(
+        &reqwest_client,
+        &server,
+        &format!("{prefix}evil.html"),
+    )?);
+
+    Ok(())
+}
+
+#[rstest]
+fn index_files_are_protected(
+    #[with(&["-u", "--protect-uploads", "--index", "index.html"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("index.html"), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, "")?;
+
+    assert_protected(&resp);
+    assert_eq!(resp.text()?, SCRIPT);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--protect-uploads", "--pretty-urls"]), "evil", "evil.html")]
+#[case(server(&["-u", "someDir", "--protect-uploads", "--pretty-urls"]), "someDir/evil", "someDir/evil.html")]
+#[case(server(&["-u", "--protect-uploads", "--spa", "--index", "test.html"]), "spa-route", "test.html")]
+fn fallback_files_are_protected(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] served: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join(served), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, path)?;
+
+    assert_protected(&resp);
+    assert_eq!(resp.text()?, SCRIPT);
+
+    Ok(())
+}
+
+#[rstest]
+fn fallback_files_outside_uploads_are_not_protected(
+    #[with(&["-u", "someDir", "--protect-uploads", "--pretty-urls"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("evil.html"), SCRIPT)?;
+    let resp = get(&reqwest_client, &server, "evil")?;
+
+    assert_not_protected(&resp);
+    assert_eq!(resp.text()?, SCRIPT);
+
+    Ok(())
+}
@@ -0,0 +1,461 @@
+mod fixtures;
+
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use assert_cmd::{Command, cargo};
+use fixtures::{Error, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::{Body, Client, multipart};
+use rstest::rstest;
+
+use crate::fixtures::{reqwest_client, reqwest_client_no_redirect};
+
+/// Upload `size` bytes to `path` with a raw PUT request, as `user` if given
+fn put(
+    client: &Client,
+    server: &TestServer,
+    path: &str,
+    size: usize,
+    user: Option<&str>,
+) -> Result<StatusCode, Error> {
+    let mut request = client.put(server.url().join(path)?).body(vec![b'a'; size]);
+    if let Some(user) = user {
+        request = request.basic_auth(user, Some("secret"));
+    }
+    Ok(request.send()?.status())
+}
+
+/// Check that a large upload sent with [`put`] was rejected for exceeding the quota.
+///
+/// The server may reject it before reading all of its body and close the connection, which the
+/// client sees as a failure to send the request.
+fn assert_over_quota(result: Result<StatusCode, Error>) {
+    match result {
+        Ok(status) => assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE),
+        Err(err) => assert!(
+            err.downcast_ref::<reqwest::Error>()
+                .is_some_and(reqwest::Error::is_request),
+            "{err}"
+        ),
+    }
+}
+
+#[rstest]
+#[case(900, StatusCode::CREATED)]
+#[case(1000, StatusCode::INSUFFICIENT_STORAGE)]
+fn upload_dir_quota_is_enforced(
+    #[case] size: usize,
+    #[case] expected: StatusCode,
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // someDir already holds 36 bytes
+    let status = put(&reqwest_client, &server, "someDir/new.bin", size, None)?;
+    assert_eq!(status, expected);
+    assert_eq!(
+        server.path().join("someDir/new.bin").exists(),
+        expected.is_success()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn upload_dir_quota_counts_previous_uploads(
+    #[with(&["-u", "--upload-quota", "1MiB"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let status = put(&reqwest_client, &server, "first.bin", 600 * 1024, None)?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_over_quota(put(
+        &reqwest_client,
+        &server,
+        "second.bin",
+        600 * 1024,
+        None,
+    ));
+    assert!(!server.path().join("second.bin").exists());
+
+    Ok(())
+}
+
+/// Body of `size` bytes whose end is held back until something is sent on the channel
+struct HeldBody {
+    size: usize,
+    release: Receiver<()>,
+}
+
+impl Read for HeldBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.size == 0 {
+            let _ = self.release.recv();
+            return Ok(0);
+        }
+        let read = self.size.min(buf.len());
+        buf[..read].fill(b'a');
+        self.size -= read;
+        Ok(read)
+    }
+}
+
+#[rstest]
+fn concurrent_uploads_respect_quota(
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB", "--size-display", "exact"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let (release, held) = mpsc::channel();
+    let url = server.url().join("someDir/first.bin")?;
+    let client = reqwest_client.clone();
+    let first = thread::spawn(move || {
+        let body = HeldBody {
+            size: 600,
+            release: held,
+        };
+        client
+            .put(url)
+            .body(Body::new(body))
+            .send()
+            .map(|r| r.status())
+    });
+
+    // Wait for the bytes of the first upload to be reserved, someDir already holding 36 bytes
+    let listing = server.url().join("someDir/")?;
+    let reserved = (0..100).any(|_| {
+        thread::sleep(Duration::from_millis(50));
+        reqwest_client
+            .get(listing.clone())
+            .send()
+            .and_then(|r| r.text())
+            .is_ok_and(|body| body.contains("388 B left in the upload quota"))
+    });
+    assert!(reserved);
+
+    assert_over_quota(put(
+        &reqwest_client,
+        &server,
+        "someDir/second.bin",
+        600,
+        None,
+    ));
+    assert!(!server.path().join("someDir/second.bin").exists());
+
+    release.send(())?;
+    assert_eq!(first.join().unwrap()?, StatusCode::CREATED);
+    assert!(server.path().join("someDir/first.bin").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn upload_dir_quota_counts_versions(
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB", "-o", "version"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // someDir already holds 36 bytes, and keeps the replaced files as versions
+    for _ in 0..2 {
+        let status = put(&reqwest_client, &server, "someDir/file.bin", 400, None)?;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    assert_over_quota(put(&reqwest_client, &server, "someDir/file.bin", 400, None));
+
+    Ok(())
+}
+
+#[rstest]
+fn multipart_upload_respects_quota(
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    let upload = |name: &str, size: usize| -> Result<StatusCode, Error> {
+        let part = multipart::Part::bytes(vec![b'a'; size]).file_name(name.to_owned());
+        let form = multipart::Form::new().part("file_to_upload", part);
+        Ok(reqwest_client_no_redirect
+            .post(server.url().join("/upload?path=/someDir")?)
+            .multipart(form)
+            .send()?
+            .status())
+    };
+
+    assert_eq!(upload("big.bin", 2000)?, StatusCode::INSUFFICIENT_STORAGE);
+    assert!(!server.path().join("someDir/big.bin").exists());
+    assert_eq!(upload("small.bin", 500)?, StatusCode::SEE_OTHER);
+    assert!(server.path().join("someDir/small.bin").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn user_quota_is_enforced(
+    #[with(&["-u", "--user-quota", "1KiB", "-a", "alice:secret", "-a", "bob:secret", "-H"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let status = put(&reqwest_client, &server, "alice-1.bin", 600, Some("alice"))?;
+    assert_eq!(status, StatusCode::CREATED);
+    let status = put(&reqwest_client, &server, "alice-2.bin", 600, Some("alice"))?;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    let status = put(&reqwest_client, &server, "bob-1.bin", 600, Some("bob"))?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The record of who uploaded what is never exposed
+    let resp = reqwest_client
+        .get(server.url().join(".miniserve-quota.json")?)
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let listing = reqwest_client
+        .get(server.url())
+        .basic_auth("alice", Some("secret"))
+        .send()?
+        .text()?;
+    assert!(!listing.contains(".miniserve-quota.json"));
+
+    Ok(())
+}
+
+#[rstest]
+fn user_quota_is_freed_by_deletions(
+    #[with(&["-u", "-R", "--user-quota", "1KiB", "-a", "alice:secret"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let status = put(&reqwest_client, &server, "alice-1.bin", 600, Some("alice"))?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let mut url = server.url().join("rm")?;
+    url.query_pairs_mut().append_pair("path", "alice-1.bin");
+    reqwest_client
+        .post(url)
+        .basic_auth("alice", Some("secret"))
+        .send()?
+        .error_for_status()?;
+
+    let status = put(&reqwest_client, &server, "alice-2.bin", 600, Some("alice"))?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_upload_respects_quota(
+    #[with(&["-u", "--upload-quota", "1MiB"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .post(server.url().join("upload/tus?path=/")?)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", 2 * 1024 * 1024)
+        .header("Upload-Metadata", "filename YmlnLmJpbg==")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+    Ok(())
+}
+
+#[rstest]
+fn webdav_put_respects_quota(
+    #[with(&["-u", "--enable-webdav", "--webdav-write", "--upload-quota", "1MiB"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // The rejected upload is sent last, as the server may close its connection
+    let status = put(&reqwest_client, &server, "small.bin", 1024, None)?;
+    assert!(status.is_success());
+
+    assert_over_quota(put(
+        &reqwest_client,
+        &server,
+        "big.bin",
+        2 * 1024 * 1024,
+        None,
+    ));
+    assert!(!server.path().join("big.bin").exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_uploads_are_reserved_on_creation(
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let create = |filename: &str| -> Result<StatusCode, Error> {
+        Ok(reqwest_client
+            .post(server.url().join("upload/tus?path=/someDir")?)
+            .header("Tus-Resumable", "1.0.0")
+            .header("Upload-Length", 600)
+            .header("Upload-Metadata", format!("filename {filename}"))
+            .send()?
+            .status())
+    };
+
+    // Neither upload has sent any data yet
+    assert_eq!(create("Zmlyc3QuYmlu")?, StatusCode::CREATED);
+    assert_eq!(
+        create("c2Vjb25kLmJpbg==")?,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+
+    Ok(())
+}
+
+/// Send a move or copy request for `path` to `destination` through the `route` form
+fn transfer(
+    client: &Client,
+    server: &TestServer,
+    route: &str,
+    path: &str,
+    destination: &str,
+    user: Option<&str>,
+) -> Result<StatusCode, Error> {
+    let mut url = server.url().join(route)?;
+    url.query_pairs_mut().append_pair("path", path);
+    let mut request = client.post(url).form(&[("destination", destination)]);
+    if let Some(user) = user {
+        request = request.basic_auth(user, Some("secret"));
+    }
+    Ok(request.send()?.status())
+}
+
+#[rstest]
+fn cp_respects_quota(
+    #[with(&["-u", "someDir", "--upload-quota", "1KiB"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("someDir/big.bin"), vec![b'a'; 600])?;
+    let status = transfer(
+        &reqwest_client_no_redirect,
+        &server,
+        "cp",
+        "someDir/big.bin",
+        "someDir/copy.bin",
+        None,
+    )?;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert!(!server.path().join("someDir/copy.bin").exists());
+
+    let status = transfer(
+        &reqwest_client_no_redirect,
+        &server,
+        "cp",
+        "someDir/alpha",
+        "someDir/copy",
+        None,
+    )?;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+
+    Ok(())
+}
+
+#[rstest]
+fn mv_respects_quota_of_other_upload_dirs(
+    #[with(&["-u", "someDir", "-u", "dira", "-R", "--upload-quota", "1KiB"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("dira/big.bin"), vec![b'a'; 1000])?;
+    let status = transfer(
+        &reqwest_client_no_redirect,
+        &server,
+        "mv",
+        "dira/big.bin",
+        "someDir/big.bin",
+        None,
+    )?;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert!(server.path().join("dira/big.bin").exists());
+
+    // Moves inside an upload directory don't change its usage
+    std::fs::write(server.path().join("someDir/big.bin"), vec![b'a'; 1000])?;
+    let status = transfer(
+        &reqwest_client_no_redirect,
+        &server,
+        "mv",
+        "someDir/big.bin",
+        "someDir/some_sub_dir/big.bin",
+        None,
+    )?;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+
+    Ok(())
+}
+
+#[rstest]
+fn copies_count_against_user_quota(
+    #[with(&["-u", "--user-quota", "1KiB", "-a", "alice:secret"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    let client = &reqwest_client_no_redirect;
+    let status = put(client, &server, "alice-1.bin", 400, Some("alice"))?;
+    assert_eq!(status, StatusCode::CREATED);
+    let status = transfer(
+        client,
+        &server,
+        "cp",
+        "alice-1.bin",
+        "alice-2.bin",
+        Some("alice"),
+    )?;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+
+    let status = put(client, &server, "alice-3.bin", 400, Some("alice"))?;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+
+    Ok(())
+}
+
+#[rstest]
+fn webdav_copy_respects_quota(
+    #[with(&["-u", "someDir", "--enable-webdav", "--webdav-write", "--upload-quota", "1KiB"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join("someDir/big.bin"), vec![b'a'; 600])?;
+    let resp = reqwest_client
+        .request(
+            reqwest::Method::from_bytes(b"COPY")?,
+            server.url().join("someDir/big.bin")?,
+        )
+        .header(
+            "Destination",
+            server.url().join("someDir/copy.bin")?.as_str(),
+        )
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::INSUFFICIENT_STORAGE);
+    assert!(!server.path().join("someDir/copy.bin").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--upload-quota", "1GiB"]), true)]
+#[case(server(&["-u"]), false)]
+fn listing_shows_space_left(
+    #[case] server: TestServer,
+    #[case] quota: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let listing = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert_eq!(listing.contains("left in the upload quota"), quota);
+    #[cfg(unix)]
+    assert!(listing.contains("free on disk"));
+
+    Ok(())
+}
+
+#[test]
+fn user_quota_requires_auth() -> Result<(), Error> {
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .args(["-u", "--user-quota", "1GiB", "."])
+        .assert()
+        .failure();
+
+    Ok(())
+}
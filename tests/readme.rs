@@ -3,8 +3,9 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use reqwest::blocking::Client;
+use reqwest::header;
 use rstest::rstest;
-use select::predicate::Attr;
+use select::predicate::{Attr, Name};
 use select::{document::Document, node::Node};
 
 mod fixtures;
@@ -143,3 +144,78 @@ fn show_nested_readme_contents(
     }
     Ok(())
 }
+
+/// Escape text readmes, and sanitize the HTML of Markdown ones
+#[rstest]
+#[case("README.txt", "<script>alert(1)</script>", "<script>alert(1)</script>")]
+#[case(
+    "README.md",
+    "<script>alert(1)</script>\n\n[click](javascript:alert(1))",
+    "click"
+)]
+fn readme_contents_are_sanitized(
+    #[with(&["--readme"])] server: TestServer,
+    reqwest_client: Client,
+    #[case] readme_name: &str,
+    #[case] contents: &str,
+    #[case] text: &str,
+) -> Result<(), Error> {
+    std::fs::write(server.path().join(readme_name), contents)?;
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let readme = parsed.find(Attr("id", "readme-contents")).next().unwrap();
+
+    assert!(readme.text().contains(text));
+    assert!(readme.find(Name("script")).next().is_none());
+    assert!(!readme.find(Name("a")).any(|link| {
+        link.attr("href")
+            .is_some_and(|href| href.contains("javascript"))
+    }));
+
+    Ok(())
+}
+
+/// Only let the scripts of the listing run, through a nonce in its Content-Security-Policy
+#[rstest]
+#[case(server(&["--readme"]))]
+#[case(server(&["--readme", "-u"]))]
+fn listing_has_a_content_security_policy(
+    #[case] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let policy = resp.headers()[header::CONTENT_SECURITY_POLICY]
+        .to_str()?
+        .to_owned();
+    let parsed = Document::from_read(resp)?;
+
+    assert!(policy.contains("object-src 'none'"));
+    let nonce = policy
+        .split(';')
+        .find_map(|directive| directive.trim().strip_prefix("script-src 'nonce-"))
+        .and_then(|nonce| nonce.strip_suffix('\''))
+        .unwrap();
+    let scripts: Vec<_> = parsed.find(Name("script")).collect();
+    assert!(!scripts.is_empty());
+    assert!(
+        scripts
+            .iter()
+            .all(|script| script.attr("nonce") == Some(nonce))
+    );
+    // Event handlers can't run under the policy
+    assert!(
+        !parsed
+            .find(Name("a"))
+            .filter_map(|link| link.attr("href"))
+            .any(|href| href.starts_with("javascript:"))
+    );
+    assert!(parsed.find(Attr("onclick", ())).next().is_none());
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+#![cfg(unix)]
+
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+use assert_fs::{TempDir, prelude::*};
+use reqwest::{StatusCode, blocking::Client};
+use rstest::rstest;
+use select::{document::Document, predicate::Name};
+
+mod fixtures;
+
+use crate::fixtures::{Error, TestServer, reqwest_client, server};
+
+/// Send SIGHUP to the server and wait until `reloaded` returns true.
+fn reload(server: &TestServer, reloaded: impl Fn() -> Result<bool, Error>) -> Result<(), Error> {
+    let status = Command::new("kill")
+        .args(["-HUP", &server.pid().to_string()])
+        .status()?;
+    assert!(status.success());
+
+    for _ in 0..50 {
+        if reloaded()? {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(100));
+    }
+    Err("Configuration wasn't reloaded".into())
+}
+
+fn status_with_auth(
+    client: &Client,
+    server: &TestServer,
+    username: &str,
+    password: &str,
+) -> Result<StatusCode, Error> {
+    Ok(client
+        .get(server.url())
+        .basic_auth(username, Some(password))
+        .send()?
+        .status())
+}
+
+#[rstest]
+fn auth_file_is_reloaded_on_sighup(reqwest_client: Client) -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let auth_file = dir.child("auth.txt");
+    auth_file.write_str("joe:123\n")?;
+    let server = server(&["--auth-file", auth_file.to_str().unwrap()]);
+
+    assert_eq!(
+        status_with_auth(&reqwest_client, &server, "joe", "123")?,
+        StatusCode::OK
+    );
+
+    auth_file.write_str("bob:456\n")?;
+    reload(&server, || {
+        Ok(status_with_auth(&reqwest_client, &server, "bob", "456")? == StatusCode::OK)
+    })?;
+
+    assert_eq!(
+        status_with_auth(&reqwest_client, &server, "joe", "123")?,
+        StatusCode::UNAUTHORIZED
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn config_file_is_reloaded_on_sighup(reqwest_client: Client) -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let config = dir.child("miniserve.toml");
+    config.write_str("title = \"before\"\nheader = [\"x-reload: before\"]")?;
+    let server = server(&["--config", config.to_str().unwrap()]);
+
+    let title = || -> Result<String, Error> {
+        let body = reqwest_client
+            .get(server.url())
+            .send()?
+            .error_for_status()?;
+        let parsed = Document::from_read(body)?;
+        Ok(parsed.find(Name("title")).next().ok_or("No title")?.text())
+    };
+    assert!(title()?.starts_with("before"));
+
+    config.write_str("title = \"after\"\nheader = [\"x-reload: after\"]")?;
+    reload(&server, || Ok(title()?.starts_with("after")))?;
+
+    let resp = reqwest_client.get(server.url()).send()?;
+    assert_eq!(resp.headers().get("x-reload").unwrap(), "after");
+
+    Ok(())
+}
+
+#[rstest]
+/// Options that can't be applied at runtime keep their old value, and an invalid config file
+/// doesn't take down the running server.
+fn startup_options_are_kept_on_sighup(reqwest_client: Client) -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let config = dir.child("miniserve.toml");
+    config.write_str("title = \"before\"")?;
+    let server = server(&["--config", config.to_str().unwrap()]);
+
+    config.write_str("title = \"after\"\nroute_prefix = \"prefix\"")?;
+    reload(&server, || {
+        let body = reqwest_client.get(server.url()).send()?.text()?;
+        Ok(body.contains("<title>after"))
+    })?;
+    assert_eq!(
+        reqwest_client.get(server.url()).send()?.status(),
+        StatusCode::OK
+    );
+
+    config.write_str("not_an_option = true")?;
+    let status = Command::new("kill")
+        .args(["-HUP", &server.pid().to_string()])
+        .status()?;
+    assert!(status.success());
+    sleep(Duration::from_millis(500));
+
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(body.contains("<title>after"));
+
+    Ok(())
+}
+
+#[test]
+/// New connections get the new certificate after a reload.
+fn tls_certificate_is_reloaded_on_sighup() -> Result<(), Error> {
+    let dir = TempDir::new()?;
+    let cert = dir.child("cert.pem");
+    let key = dir.child("key.pem");
+    cert.write_file("tests/data/cert_rsa.pem".as_ref())?;
+    key.write_file("tests/data/key_pkcs8.pem".as_ref())?;
+    let server = server(&[
+        "--tls-cert",
+        cert.to_str().unwrap(),
+        "--tls-key",
+        key.to_str().unwrap(),
+    ]);
+
+    if rustls::crypto::CryptoProvider::get_default().is_none() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    }
+    // Use a fresh client every time so that the TLS session isn't resumed
+    let peer_certificate = || -> Result<Vec<u8>, Error> {
+        let client = reqwest::blocking::ClientBuilder::new()
+            .tls_danger_accept_invalid_certs(true)
+            .tls_info(true)
+            .build()?;
+        let resp = client.get(server.url()).send()?.error_for_status()?;
+        let tls_info = resp
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .ok_or("No TLS info")?;
+        Ok(tls_info
+            .peer_certificate()
+            .ok_or("No certificate")?
+            .to_vec())
+    };
+    let old_certificate = peer_certificate()?;
+
+    cert.write_file("tests/data/cert_ec.pem".as_ref())?;
+    key.write_file("tests/data/key_ec.pem".as_ref())?;
+    reload(&server, || Ok(peer_certificate()? != old_certificate))?;
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use pretty_assertions::assert_eq;
+use reqwest::{StatusCode, blocking::Client, header};
+use rstest::rstest;
+use select::{document::Document, predicate::Attr};
+use serde_json::Value;
+
+mod fixtures;
+
+use crate::fixtures::{
+    DEEPLY_NESTED_FILE, Error, FILES, HIDDEN_FILES, TestServer, reqwest_client, server,
+};
+
+/// Search below `path` and return the sorted names of the results.
+fn search(client: &Client, server: &TestServer, path: &str) -> Result<Vec<String>, Error> {
+    let entries: Vec<Value> = client
+        .get(server.url().join(path)?)
+        .header(header::ACCEPT, "application/json")
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let mut names = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+#[rstest]
+fn search_is_disabled_by_default(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url().join("?search=test")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let body = Document::from_read(reqwest_client.get(server.url()).send()?)?;
+    assert!(body.find(Attr("data-tool", "search")).next().is_none());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("?search=bravo", &["someDir/some_sub_dir/bravo"])]
+#[case("?search=SOME_SUB", &["someDir/some_sub_dir"])]
+#[case("?search=*.rs", &[DEEPLY_NESTED_FILE])]
+#[case("?search=[ab]????", &["someDir/alpha", "someDir/some_sub_dir/bravo"])]
+#[case("someDir/?search=a", &["alpha", "some_sub_dir/bravo"])]
+#[case("?search=nothing_matches", &[])]
+fn search_finds_nested_entries(
+    #[case] path: &str,
+    #[case] expected: &[&str],
+    #[with(&["--enable-search"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    assert_eq!(search(&reqwest_client, &server, path)?, expected);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["--enable-search"]), "")]
+#[case(server(&["--enable-search", "--route-prefix", "foo"]), "/foo")]
+fn search_results_link_to_entries(
+    #[case] server: TestServer,
+    #[case] prefix: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let dir = server.url().join(&format!("{prefix}/someDir/"))?;
+    let expected_link = format!("{prefix}/someDir/some_sub_dir/bravo");
+
+    let entries: Vec<Value> = reqwest_client
+        .get(dir.join("?search=bravo&format=json")?)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["link"], *expected_link);
+
+    let body = reqwest_client
+        .get(dir.join("?search=bravo")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(
+        parsed
+            .find(Attr("href", expected_link.as_str()))
+            .next()
+            .is_some()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn search_honors_hidden_files(
+    #[values(false, true)] show_hidden: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let server = if show_hidden {
+        server(&["--enable-search", "--hidden"])
+    } else {
+        server(&["--enable-search"])
+    };
+
+    let names = search(&reqwest_client, &server, "?search=hidden_file1")?;
+    assert_eq!(names.contains(&HIDDEN_FILES[0].to_owned()), show_hidden);
+    assert_eq!(
+        names.contains(&format!(".hidden_dir1/{}", HIDDEN_FILES[0])),
+        show_hidden
+    );
+
+    // Content of hidden directories isn't searched either
+    let names = search(&reqwest_client, &server, "?search=test.txt")?;
+    assert!(names.contains(&"dira/test.txt".to_owned()));
+    assert_eq!(
+        names.contains(&".hidden_dir1/test.txt".to_owned()),
+        show_hidden
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn search_honors_no_symlinks(
+    #[values(false, true)] no_symlinks: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let server = if no_symlinks {
+        server(&["--enable-search", "--no-symlinks"])
+    } else {
+        server(&["--enable-search"])
+    };
+
+    let names = search(&reqwest_client, &server, "?search=symlink")?;
+    let expected: &[&str] = if no_symlinks {
+        &[]
+    } else {
+        // Broken symlinks are skipped, just like in the listing
+        &["dir_symlink", "file_symlink"]
+    };
+    assert_eq!(names, expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn search_results_are_limited(
+    #[with(&["--enable-search", "--search-max-results", "2"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    assert_eq!(search(&reqwest_client, &server, "?search=test")?.len(), 2);
+
+    let body = reqwest_client
+        .get(server.url().join("?search=test")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(body.contains("2 results for"));
+    assert!(body.contains("the search was stopped early"));
+
+    Ok(())
+}
+
+#[rstest]
+fn search_results_can_be_streamed_as_ndjson(
+    #[with(&["--enable-search"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url().join("dira/?search=test")?)
+        .header(header::ACCEPT, "application/x-ndjson")
+        .send()?
+        .error_for_status()?;
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/x-ndjson"
+    );
+
+    let body = resp.text()?;
+    let mut names = body
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).map(|entry| entry["name"].clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    names.sort_by_key(|name| name.to_string());
+    let mut expected = FILES
+        .iter()
+        .filter(|file| file.contains("test"))
+        .map(|file| Value::from(*file))
+        .collect::<Vec<_>>();
+    expected.sort_by_key(|name| name.to_string());
+    assert_eq!(names, expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn search_box_keeps_the_query(
+    #[with(&["--enable-search"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client
+        .get(server.url().join("?search=bravo")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let input = parsed
+        .find(Attr("name", "search"))
+        .next()
+        .ok_or("No search box")?;
+    assert_eq!(input.attr("value"), Some("bravo"));
+
+    Ok(())
+}
+
+#[rstest]
+fn empty_search_is_rejected(
+    #[with(&["--enable-search"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .get(server.url().join("?search=%20")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
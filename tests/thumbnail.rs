@@ -0,0 +1,201 @@
+mod fixtures;
+
+use std::path::Path;
+
+use assert_cmd::{Command, cargo};
+use assert_fs::TempDir;
+use fixtures::{Error, TestServer, server};
+use image::{GenericImageView, RgbImage, RgbaImage};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Predicate};
+
+use crate::fixtures::reqwest_client;
+
+/// Fetch the thumbnail of `name` from `server`, returning its content type and dimensions
+fn thumbnail(
+    client: &Client,
+    server: &TestServer,
+    name: &str,
+) -> Result<(String, (u32, u32)), Error> {
+    let resp = client
+        .get(server.url().join(&format!("{name}?thumbnail=1"))?)
+        .send()?
+        .error_for_status()?;
+    let content_type = resp.headers()["content-type"].to_str()?.to_owned();
+    let image = image::load_from_memory(&resp.bytes()?)?;
+    Ok((content_type, image.dimensions()))
+}
+
+/// Start a server caching its thumbnails in `cache`
+fn server_with_cache(cache: &Path, args: &[&str]) -> TestServer {
+    let mut args = args.to_vec();
+    args.extend(["--thumbnail-cache-dir", cache.to_str().unwrap()]);
+    server(args)
+}
+
+#[rstest]
+fn thumbnails_are_scaled_down_images(reqwest_client: Client) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), &[]);
+    RgbImage::new(1000, 500).save(server.path().join("photo.png"))?;
+    RgbaImage::new(300, 600).save(server.path().join("icon.png"))?;
+
+    let (content_type, dimensions) = thumbnail(&reqwest_client, &server, "photo.png")?;
+    assert_eq!(content_type, "image/jpeg");
+    assert_eq!(dimensions, (256, 128));
+
+    // Transparency is kept
+    let (content_type, dimensions) = thumbnail(&reqwest_client, &server, "icon.png")?;
+    assert_eq!(content_type, "image/png");
+    assert_eq!(dimensions, (128, 256));
+
+    Ok(())
+}
+
+#[rstest]
+fn thumbnails_are_cached_until_the_image_changes(reqwest_client: Client) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), &[]);
+    RgbImage::new(1000, 500).save(server.path().join("photo.jpg"))?;
+
+    thumbnail(&reqwest_client, &server, "photo.jpg")?;
+    thumbnail(&reqwest_client, &server, "photo.jpg")?;
+    assert_eq!(cache.path().read_dir()?.count(), 1);
+
+    RgbImage::new(500, 1000).save(server.path().join("photo.jpg"))?;
+    let (_, dimensions) = thumbnail(&reqwest_client, &server, "photo.jpg")?;
+    assert_eq!(dimensions, (128, 256));
+    assert_eq!(cache.path().read_dir()?.count(), 2);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(&[], ".hidden.png")]
+#[case(&["--no-symlinks"], "linked.png")]
+#[case(&["--no-symlinks"], "dir_symlink/photo.png")]
+#[case(&["-u", "--drop-box"], "photo.png")]
+#[case(&[], "missing.png")]
+#[case(&[], "test.txt")]
+fn thumbnails_follow_the_rules_of_files(
+    #[case] args: &[&str],
+    #[case] name: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), args);
+    for image in [".hidden.png", "photo.png", "dira/photo.png"] {
+        RgbImage::new(10, 10).save(server.path().join(image))?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        server.path().join("photo.png"),
+        server.path().join("linked.png"),
+    )?;
+
+    let resp = reqwest_client
+        .get(server.url().join(&format!("{name}?thumbnail=1"))?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn broken_images_have_no_thumbnail(reqwest_client: Client) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), &[]);
+    std::fs::write(server.path().join("broken.png"), "not an image")?;
+
+    let resp = reqwest_client
+        .get(server.url().join("broken.png?thumbnail=1")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    Ok(())
+}
+
+#[rstest]
+fn gallery_is_offered_in_directories_with_images(reqwest_client: Client) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), &[]);
+
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    assert!(parsed.find(Attr("id", "gallery")).next().is_none());
+    assert!(parsed.find(Class("layout_toggle")).next().is_none());
+
+    RgbImage::new(10, 10).save(server.path().join("photo.png"))?;
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let gallery = parsed.find(Attr("id", "gallery")).next().unwrap();
+    let image = gallery
+        .find(Name("a").and(Class("gallery_image")))
+        .next()
+        .unwrap();
+    assert_eq!(image.attr("href"), Some("/photo.png"));
+    assert_eq!(
+        image.find(Name("img")).next().unwrap().attr("src"),
+        Some("/photo.png?thumbnail=1")
+    );
+    // Other entries are shown too, without thumbnails
+    assert!(gallery.find(Class("directory")).next().is_some());
+    assert!(parsed.find(Attr("id", "lightbox")).next().is_some());
+    assert!(parsed.find(Class("layout_toggle")).next().is_some());
+
+    Ok(())
+}
+
+#[rstest]
+fn thumbnails_work_with_route_prefix(reqwest_client: Client) -> Result<(), Error> {
+    let cache = TempDir::new()?;
+    let server = server_with_cache(cache.path(), &["--route-prefix", "prefix"]);
+    RgbImage::new(1000, 500).save(server.path().join("photo.png"))?;
+
+    let body = reqwest_client
+        .get(server.url().join("prefix/")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let src = parsed
+        .find(Attr("id", "gallery").descendant(Name("img")))
+        .next()
+        .unwrap()
+        .attr("src")
+        .map(str::to_owned);
+    assert_eq!(src.as_deref(), Some("/prefix/photo.png?thumbnail=1"));
+
+    let (_, dimensions) = thumbnail(&reqwest_client, &server, "prefix/photo.png")?;
+    assert_eq!(dimensions, (256, 128));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn cache_dir_must_belong_to_the_current_user() -> Result<(), Error> {
+    // Only root may give a directory away, and the root directory belongs to root otherwise
+    let cache = TempDir::new()?;
+    let cache = match std::os::unix::fs::chown(cache.path(), Some(65534), None) {
+        Ok(()) => cache.path().to_path_buf(),
+        Err(_) => "/".into(),
+    };
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .arg("--thumbnail-cache-dir")
+        .arg(&cache)
+        .arg(".")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("not owned by the current user"));
+
+    Ok(())
+}
@@ -0,0 +1,418 @@
+mod fixtures;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use assert_cmd::{Command, cargo};
+use fixtures::{DIRECTORIES, Error, FILES, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use select::{document::Document, predicate::Class};
+use serde_json::Value;
+
+use crate::fixtures::reqwest_client;
+
+const TRASH_DIR: &str = ".miniserve-trash";
+const TRASH_ROUTE: &str = "__miniserve_internal/trash";
+
+/// Delete `path` through the rm API
+fn rm(client: &Client, server: &TestServer, path: &str) -> Result<(), Error> {
+    let mut url = server.url().join("rm")?;
+    url.query_pairs_mut().append_pair("path", path);
+    client.post(url).send()?.error_for_status()?;
+    Ok(())
+}
+
+/// Directories of the entries in the trash
+fn trash_entries(server: &TestServer) -> Result<Vec<PathBuf>, Error> {
+    let trash = server.path().join(TRASH_DIR);
+    if !trash.exists() {
+        return Ok(vec![]);
+    }
+    Ok(fs::read_dir(trash)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Metadata of a trash entry
+fn trash_info(entry: &Path) -> Result<Value, Error> {
+    Ok(serde_json::from_slice(&fs::read(entry.join("info.json"))?)?)
+}
+
+/// Submit the form with class `class` of the trash page
+fn submit_trash_form(client: &Client, server: &TestServer, class: &str) -> Result<(), Error> {
+    let page = client
+        .get(server.url().join(TRASH_ROUTE)?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(page)?;
+    let action = parsed
+        .find(Class(class))
+        .next()
+        .and_then(|form| form.attr("action"))
+        .ok_or("No form found")?;
+    client
+        .post(server.url().join(action)?)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[rstest]
+#[case(FILES[0])]
+#[case(DIRECTORIES[2])]
+#[case("someDir/some_sub_dir")]
+fn rm_moves_to_trash(
+    #[case] path: &str,
+    #[with(&["-R", "--trash"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let is_dir = server.path().join(path).is_dir();
+    rm(&reqwest_client, &server, path)?;
+
+    assert!(!server.path().join(path).exists());
+    let entries = trash_entries(&server)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].join("content").is_dir(), is_dir);
+    assert_eq!(
+        trash_info(&entries[0])?["original_path"],
+        path.trim_end_matches('/')
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case("cp")]
+#[case("mv")]
+fn replaced_entries_are_moved_to_trash(
+    #[case] route: &str,
+    #[with(&["-u", "-R", "--trash", "-o", "overwrite"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let contents = fs::read(server.path().join(FILES[0]))?;
+    fs::write(server.path().join("replacing"), "source")?;
+    let mut url = server.url().join(route)?;
+    url.query_pairs_mut().append_pair("path", "replacing");
+    reqwest_client
+        .post(url)
+        .form(&[("destination", FILES[0])])
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(fs::read(server.path().join(FILES[0]))?, b"source");
+    let entries = trash_entries(&server)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(fs::read(entries[0].join("content"))?, contents);
+    assert_eq!(trash_info(&entries[0])?["original_path"], FILES[0]);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(FILES[0])]
+#[case("someDir")]
+fn webdav_delete_moves_to_trash(
+    #[case] path: &str,
+    #[with(&["-R", "--trash", "--enable-webdav", "--webdav-write", "-u", "--route-prefix", "prefix"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let is_dir = server.path().join(path).is_dir();
+    let url = server.url().join(&format!("prefix/{path}"))?;
+    let resp = reqwest_client.delete(url).send()?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    assert!(!server.path().join(path).exists());
+    let entries = trash_entries(&server)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].join("content").is_dir(), is_dir);
+    assert_eq!(trash_info(&entries[0])?["original_path"], path);
+
+    Ok(())
+}
+
+#[rstest]
+#[case("COPY")]
+#[case("MOVE")]
+fn webdav_replaced_directories_move_to_trash(
+    #[case] method: &str,
+    #[with(&["-R", "--trash", "--enable-webdav", "--webdav-write", "-u", "-o", "overwrite"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .request(
+            reqwest::Method::from_bytes(method.as_bytes())?,
+            server.url().join(DIRECTORIES[0])?,
+        )
+        .header("Destination", server.url().join("someDir/")?.as_str())
+        .send()?;
+    assert!(resp.status().is_success());
+
+    assert!(!server.path().join("someDir/alpha").exists());
+    let entries = trash_entries(&server)?;
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].join("content/some_sub_dir/bravo").is_file());
+    assert_eq!(trash_info(&entries[0])?["original_path"], "someDir");
+
+    Ok(())
+}
+
+#[rstest]
+fn rm_deletes_without_trash(
+    #[with(&["-R"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    rm(&reqwest_client, &server, FILES[0])?;
+
+    assert!(!server.path().join(FILES[0]).exists());
+    assert!(trash_entries(&server)?.is_empty());
+    let resp = reqwest_client.get(server.url().join(TRASH_ROUTE)?).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_is_never_exposed(
+    #[with(&["-R", "--trash", "--hidden"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    rm(&reqwest_client, &server, FILES[0])?;
+    let entry = &trash_entries(&server)?[0];
+    let entry_name = entry.file_name().unwrap().to_str().unwrap();
+
+    let listing = reqwest_client.get(server.url()).send()?.text()?;
+    assert!(!listing.contains(TRASH_DIR));
+
+    for path in [
+        format!("{TRASH_DIR}/"),
+        format!("{TRASH_DIR}/{entry_name}/info.json"),
+    ] {
+        let resp = reqwest_client.get(server.url().join(&path)?).send()?;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    // Neither can it be deleted
+    let mut url = server.url().join("rm")?;
+    url.query_pairs_mut().append_pair("path", TRASH_DIR);
+    let resp = reqwest_client.post(url).send()?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_page_lists_deleted_entries(
+    #[with(&["-R", "--trash"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let page = reqwest_client
+        .get(server.url().join(TRASH_ROUTE)?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(page.contains("The trash is empty."));
+
+    rm(&reqwest_client, &server, "someDir/alpha")?;
+    let page = reqwest_client
+        .get(server.url().join(TRASH_ROUTE)?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(page.contains("/someDir/alpha"));
+    assert!(page.contains("purged after 30 days"));
+
+    // The listing links to it
+    let listing = reqwest_client.get(server.url()).send()?.text()?;
+    assert!(listing.contains(&format!("href=\"/{TRASH_ROUTE}\"")));
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_entries_can_be_restored(
+    #[with(&["-R", "--trash"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let contents = fs::read(server.path().join("someDir/some_sub_dir/bravo"))?;
+    rm(&reqwest_client, &server, "someDir")?;
+    assert!(!server.path().join("someDir").exists());
+
+    submit_trash_form(&reqwest_client, &server, "restore_form")?;
+
+    assert_eq!(
+        fs::read(server.path().join("someDir/some_sub_dir/bravo"))?,
+        contents
+    );
+    assert!(trash_entries(&server)?.is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-R", "--trash"]), StatusCode::CONFLICT, "test.txt")]
+#[case(server(&["-R", "--trash", "-o", "rename"]), StatusCode::OK, "test-1.txt")]
+#[case(server(&["-R", "--trash", "-o", "overwrite"]), StatusCode::OK, "test.txt")]
+fn restoring_respects_on_duplicate_files(
+    #[case] server: TestServer,
+    #[case] expected: StatusCode,
+    #[case] restored: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("test.txt"), "deleted")?;
+    rm(&reqwest_client, &server, "test.txt")?;
+    fs::write(server.path().join("test.txt"), "new")?;
+
+    let id = trash_entries(&server)?[0]
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    let resp = reqwest_client
+        .post(
+            server
+                .url()
+                .join(&format!("{TRASH_ROUTE}/restore?id={id}"))?,
+        )
+        .send()?;
+    assert_eq!(resp.status(), expected);
+
+    if expected.is_success() {
+        assert_eq!(fs::read(server.path().join(restored))?, b"deleted");
+    } else {
+        assert_eq!(fs::read(server.path().join("test.txt"))?, b"new");
+    }
+    if restored == "test-1.txt" {
+        assert_eq!(fs::read(server.path().join("test.txt"))?, b"new");
+    }
+    // The overwritten file is trashed in turn
+    let left_in_trash = if expected.is_success() && restored == "test.txt" {
+        1
+    } else {
+        usize::from(!expected.is_success())
+    };
+    assert_eq!(trash_entries(&server)?.len(), left_in_trash);
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_entries_can_be_purged(
+    #[with(&["-R", "--trash"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    for file in &FILES[..3] {
+        rm(&reqwest_client, &server, file)?;
+    }
+    assert_eq!(trash_entries(&server)?.len(), 3);
+
+    submit_trash_form(&reqwest_client, &server, "rm_form")?;
+    assert_eq!(trash_entries(&server)?.len(), 2);
+
+    submit_trash_form(&reqwest_client, &server, "trash_purge_all")?;
+    assert!(trash_entries(&server)?.is_empty());
+    for file in &FILES[..3] {
+        assert!(!server.path().join(file).exists());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_only_shows_entries_from_allowed_dirs(
+    #[with(&["-R", "someDir", "--trash"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    rm(&reqwest_client, &server, "someDir/alpha")?;
+    let trash = server.path().join(TRASH_DIR);
+    fs::create_dir_all(trash.join("other/content"))?;
+    let info = serde_json::json!({
+        "original_path": "dira",
+        "deleted_at": SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        "deleted_by": null,
+    });
+    fs::write(trash.join("other/info.json"), info.to_string())?;
+
+    let page = reqwest_client
+        .get(server.url().join(TRASH_ROUTE)?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    assert!(page.contains("/someDir/alpha"));
+    assert!(!page.contains("/dira"));
+
+    for action in ["restore", "purge"] {
+        let resp = reqwest_client
+            .post(
+                server
+                    .url()
+                    .join(&format!("{TRASH_ROUTE}/{action}?id=other"))?,
+            )
+            .send()?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn trash_records_the_deleting_user(
+    #[with(&["-R", "--trash", "-a", "alice:secret"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let mut url = server.url().join("rm")?;
+    url.query_pairs_mut().append_pair("path", FILES[0]);
+    reqwest_client
+        .post(url)
+        .basic_auth("alice", Some("secret"))
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        trash_info(&trash_entries(&server)?[0])?["deleted_by"],
+        "alice"
+    );
+
+    let resp = reqwest_client.get(server.url().join(TRASH_ROUTE)?).send()?;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-R", "--trash"]), false)]
+#[case(server(&["-R", "--trash", "--trash-retention", "0"]), true)]
+fn expired_entries_are_purged(
+    #[case] server: TestServer,
+    #[case] kept: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    rm(&reqwest_client, &server, FILES[0])?;
+    let entry = &trash_entries(&server)?[0];
+    let mut info = trash_info(entry)?;
+    info["deleted_at"] = Value::from(0);
+    fs::write(entry.join("info.json"), info.to_string())?;
+
+    reqwest_client
+        .get(server.url().join(TRASH_ROUTE)?)
+        .send()?
+        .error_for_status()?;
+    assert_eq!(entry.exists(), kept);
+
+    Ok(())
+}
+
+#[test]
+fn trash_requires_rm() -> Result<(), Error> {
+    Command::new(cargo::cargo_bin!("miniserve"))
+        .args(["--trash", "."])
+        .assert()
+        .failure();
+
+    Ok(())
+}
@@ -0,0 +1,412 @@
+mod fixtures;
+
+use base64::Engine;
+use fixtures::{Error, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::Url;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use rstest::rstest;
+use select::{document::Document, predicate::Attr};
+use sha2::{Digest, Sha256};
+
+use crate::fixtures::reqwest_client;
+
+const CONTENTS: &[u8] = b"Some contents uploaded in several parts";
+
+/// Add the protocol version header to a tus request
+fn tus(request: RequestBuilder) -> RequestBuilder {
+    request.header("Tus-Resumable", "1.0.0")
+}
+
+/// Create an upload of `length` bytes called `filename` in `dir`
+fn create(
+    client: &Client,
+    server: &TestServer,
+    dir: &str,
+    filename: &str,
+    length: usize,
+) -> Result<Response, Error> {
+    let mut url = server.url().join("upload/tus")?;
+    url.query_pairs_mut().append_pair("path", dir);
+    let filename = base64::engine::general_purpose::STANDARD.encode(filename);
+    Ok(tus(client.post(url))
+        .header("Upload-Length", length)
+        .header(
+            "Upload-Metadata",
+            format!("filename {filename},filetype dGV4dA=="),
+        )
+        .send()?)
+}
+
+/// URL of an upload created with [`create`]
+fn location(server: &TestServer, resp: &Response) -> Result<Url, Error> {
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let location = resp.headers()["Location"].to_str()?;
+    Ok(server.url().join(location)?)
+}
+
+/// Append `data` to the upload at `url` at `offset`
+fn patch(client: &Client, url: &Url, offset: usize, data: &[u8]) -> Result<Response, Error> {
+    Ok(tus(client.patch(url.clone()))
+        .header("Upload-Offset", offset)
+        .header("Content-Type", "application/offset+octet-stream")
+        .body(data.to_vec())
+        .send()?)
+}
+
+/// Offset of the upload at `url`
+fn offset(client: &Client, url: &Url) -> Result<u64, Error> {
+    let resp = tus(client.head(url.clone())).send()?.error_for_status()?;
+    Ok(resp.headers()["Upload-Offset"].to_str()?.parse()?)
+}
+
+#[rstest]
+fn tus_is_disabled_by_default(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    let resp = create(&reqwest_client, &server, "", "file.txt", CONTENTS.len())?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_advertises_capabilities(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .request(reqwest::Method::OPTIONS, server.url().join("upload/tus")?)
+        .send()?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["Tus-Version"], "1.0.0");
+    assert_eq!(
+        resp.headers()["Tus-Extension"],
+        "creation,expiration,termination"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case("", "resumed.txt")]
+#[case("dira", "resumed.txt")]
+#[case("", "😀 résumé.txt")]
+fn tus_upload_resumes(
+    #[case] dir: &str,
+    #[case] filename: &str,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(&reqwest_client, &server, dir, filename, CONTENTS.len())?;
+    assert!(resp.headers().contains_key("Upload-Expires"));
+    let url = location(&server, &resp)?;
+    assert_eq!(offset(&reqwest_client, &url)?, 0);
+
+    let resp = patch(&reqwest_client, &url, 0, &CONTENTS[..10])?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(resp.headers()["Upload-Offset"], "10");
+    assert!(!server.path().join(dir).join(filename).exists());
+
+    // Resume from wherever the server says the upload stopped
+    let resumed = offset(&reqwest_client, &url)? as usize;
+    assert_eq!(resumed, 10);
+    let resp = patch(&reqwest_client, &url, resumed, &CONTENTS[resumed..])?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        std::fs::read(server.path().join(dir).join(filename))?,
+        CONTENTS
+    );
+    // The upload is gone once it's complete
+    let resp = tus(reqwest_client.head(url)).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_empty_upload_is_complete_on_creation(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(&reqwest_client, &server, "", "empty.txt", 0)?;
+    location(&server, &resp)?;
+    assert_eq!(std::fs::read(server.path().join("empty.txt"))?, b"");
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_upload_creates_folders(
+    #[with(&["-u", "--mkdir"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(
+        &reqwest_client,
+        &server,
+        "",
+        "folder/sub/file.txt",
+        CONTENTS.len(),
+    )?;
+    let url = location(&server, &resp)?;
+    assert!(!server.path().join("folder").exists());
+    patch(&reqwest_client, &url, 0, CONTENTS)?.error_for_status()?;
+    assert_eq!(
+        std::fs::read(server.path().join("folder/sub/file.txt"))?,
+        CONTENTS
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_rejects_invalid_requests(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .post(server.url().join("upload/tus?path=")?)
+        .header("Upload-Length", 1)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(resp.headers()["Tus-Version"], "1.0.0");
+
+    let resp = create(&reqwest_client, &server, "", "file.txt", CONTENTS.len())?;
+    let url = location(&server, &resp)?;
+
+    let resp = patch(&reqwest_client, &url, 5, CONTENTS)?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = tus(reqwest_client.patch(url.clone()))
+        .header("Upload-Offset", 0)
+        .body(CONTENTS)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let resp = patch(&reqwest_client, &url, 0, &[CONTENTS, b"too much"].concat())?;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(offset(&reqwest_client, &url)?, CONTENTS.len() as u64);
+    assert!(!server.path().join("file.txt").exists());
+
+    let resp = tus(reqwest_client.head(server.url().join("upload/tus/..%2F..%2Fetc")?)).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_upload_can_be_terminated(
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(
+        &reqwest_client,
+        &server,
+        "",
+        "cancelled.txt",
+        CONTENTS.len(),
+    )?;
+    let url = location(&server, &resp)?;
+    patch(&reqwest_client, &url, 0, &CONTENTS[..10])?.error_for_status()?;
+
+    let resp = tus(reqwest_client.delete(url.clone())).send()?;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let resp = tus(reqwest_client.head(url.clone())).send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = patch(&reqwest_client, &url, 10, &CONTENTS[10..])?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(!server.path().join("cancelled.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u"]), StatusCode::CONFLICT, None)]
+#[case(server(&["-u", "-o", "overwrite"]), StatusCode::CREATED, Some("test.txt"))]
+#[case(server(&["-u", "-o", "rename"]), StatusCode::CREATED, Some("test-1.txt"))]
+fn tus_respects_on_duplicate_files(
+    #[case] server: TestServer,
+    #[case] expected: StatusCode,
+    #[case] written: Option<&str>,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(&reqwest_client, &server, "", "test.txt", CONTENTS.len())?;
+    assert_eq!(resp.status(), expected);
+
+    if let Some(written) = written {
+        let url = location(&server, &resp)?;
+        patch(&reqwest_client, &url, 0, CONTENTS)?.error_for_status()?;
+        assert_eq!(std::fs::read(server.path().join(written))?, CONTENTS);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(&format!("{:x}", Sha256::digest(CONTENTS)), true)]
+#[case(&format!("{:x}", Sha256::digest(b"other contents")), false)]
+fn tus_verifies_file_hash(
+    #[case] hash: &str,
+    #[case] should_succeed: bool,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let filename = base64::engine::general_purpose::STANDARD.encode("hashed.txt");
+    let resp = tus(reqwest_client.post(server.url().join("upload/tus?path=")?))
+        .header("Upload-Length", CONTENTS.len())
+        .header("Upload-Metadata", format!("filename {filename}"))
+        .header("X-File-Hash", hash)
+        .header("X-File-Hash-Function", "SHA256")
+        .send()?;
+    let url = location(&server, &resp)?;
+
+    let resp = patch(&reqwest_client, &url, 0, CONTENTS)?;
+    assert_eq!(resp.status().is_success(), should_succeed);
+    assert_eq!(server.path().join("hashed.txt").exists(), should_succeed);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "someDir"]), "dira", "file.txt", StatusCode::FORBIDDEN)]
+#[case(server(&["-u"]), "test.txt", "file.txt", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u"]), "", ".hidden", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u", "--no-symlinks"]), "dir_symlink", "file.txt", StatusCode::FORBIDDEN)]
+fn tus_upload_is_restricted(
+    #[case] server: TestServer,
+    #[case] dir: &str,
+    #[case] filename: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = create(&reqwest_client, &server, dir, filename, CONTENTS.len())?;
+    assert_eq!(resp.status(), expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_upload_belongs_to_its_creator(
+    #[with(&["-u", "-a", "alice:secret", "-a", "bob:secret"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let mut url = server.url().join("upload/tus")?;
+    url.query_pairs_mut().append_pair("path", "");
+    let filename = base64::engine::general_purpose::STANDARD.encode("alice.txt");
+    let resp = tus(reqwest_client.post(url))
+        .basic_auth("alice", Some("secret"))
+        .header("Upload-Length", CONTENTS.len())
+        .header("Upload-Metadata", format!("filename {filename}"))
+        .send()?;
+    let url = location(&server, &resp)?;
+
+    let resp = tus(reqwest_client.head(url.clone()))
+        .basic_auth("bob", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = tus(reqwest_client.head(url))
+        .basic_auth("alice", Some("secret"))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[rstest]
+fn tus_upload_is_chmoded(
+    #[with(&["-u", "--chmod", "640"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let resp = create(&reqwest_client, &server, "", "chmod.txt", CONTENTS.len())?;
+    let url = location(&server, &resp)?;
+    patch(&reqwest_client, &url, 0, CONTENTS)?.error_for_status()?;
+
+    let mode = std::fs::metadata(server.path().join("chmod.txt"))?.mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u"]), "/upload/tus?path=/")]
+#[case(server(&["-u", "--route-prefix", "foo"]), "/foo/upload/tus?path=/")]
+fn tus_is_used_by_the_web_uploader(
+    #[case] server: TestServer,
+    #[case] expected: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let url = match expected.starts_with("/foo") {
+        true => server.url().join("foo/")?,
+        false => server.url(),
+    };
+    let body = reqwest_client.get(url).send()?.error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let form = parsed
+        .find(Attr("id", "file_submit"))
+        .next()
+        .expect("Couldn't find element with id=file_submit");
+    assert_eq!(form.attr("data-tus"), Some(expected));
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_respects_the_max_file_size(
+    #[with(&["-u", "--max-file-size", "16B"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .request(reqwest::Method::OPTIONS, server.url().join("upload/tus")?)
+        .send()?
+        .error_for_status()?;
+    assert_eq!(resp.headers()["Tus-Max-Size"], "16");
+
+    let resp = create(&reqwest_client, &server, "", "big.txt", CONTENTS.len())?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let resp = create(&reqwest_client, &server, "", "small.txt", 16)?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[rstest]
+fn tus_respects_media_types(
+    #[with(&["-u", "-m", "image"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // The extension is checked on creation
+    let resp = create(&reqwest_client, &server, "", "notes.txt", CONTENTS.len())?;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // The contents once they're all there
+    let html = b"<!DOCTYPE html><html><script>alert(1)</script></html>";
+    let resp = create(&reqwest_client, &server, "", "photo.png", html.len())?;
+    let url = location(&server, &resp)?;
+    let resp = patch(&reqwest_client, &url, 0, html)?;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(!server.path().join("photo.png").exists());
+
+    Ok(())
+}
+
+/// Errors are sent to tus clients as they are, rather than as web pages
+#[rstest]
+#[case(server(&["-u", "--max-file-size", "16B"]), "upload/tus?path=/")]
+#[case(server(&["-u", "--max-file-size", "16B", "--route-prefix", "foo"]), "foo/upload/tus?path=/")]
+fn tus_errors_are_plain_text(
+    #[case] server: TestServer,
+    #[case] route: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let filename = base64::engine::general_purpose::STANDARD.encode("big.txt");
+    let resp = tus(reqwest_client.post(server.url().join(route)?))
+        .header("Upload-Length", CONTENTS.len())
+        .header("Upload-Metadata", format!("filename {filename}"))
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resp.headers()["Content-Type"], "text/plain; charset=utf-8");
+
+    Ok(())
+}
@@ -2,6 +2,7 @@ use std::fs::create_dir_all;
 use std::path::Path;
 
 use assert_fs::fixture::TempDir;
+use reqwest::StatusCode;
 use reqwest::blocking::{Client, multipart};
 use reqwest::header::HeaderMap;
 use rstest::rstest;
@@ -10,7 +11,9 @@ use select::predicate::{Attr, Text};
 
 mod fixtures;
 
-use crate::fixtures::{Error, TestServer, reqwest_client, server, tmpdir};
+use crate::fixtures::{
+    Error, TestServer, reqwest_client, reqwest_client_no_redirect, server, tmpdir,
+};
 
 // Generate the hashes using the following
 // ```bash
@@ -616,3 +619,141 @@ fn chmod(
 
     Ok(())
 }
+
+/// Upload `files`, given as name and contents, in a single multipart request
+fn upload_multipart(
+    reqwest_client: &Client,
+    server: &TestServer,
+    files: &[(&str, &[u8])],
+) -> Result<reqwest::blocking::Response, Error> {
+    let form = files
+        .iter()
+        .fold(multipart::Form::new(), |form, (name, contents)| {
+            let part = multipart::Part::bytes(contents.to_vec()).file_name(name.to_string());
+            form.part("file_to_upload", part)
+        });
+    Ok(reqwest_client
+        .post(server.url().join("/upload?path=/")?)
+        .multipart(form)
+        .send()?)
+}
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+const HTML: &[u8] = b"<!DOCTYPE html><html><script>alert(1)</script></html>";
+
+/// The media types given with `-m` or `-M` are enforced by the server, not just suggested to the
+/// browser
+#[rstest]
+#[case(server(&["-u", "-m", "image"]), "photo.png", PNG, StatusCode::SEE_OTHER)]
+#[case(server(&["-u", "-m", "image"]), "page.png", HTML, StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&["-u", "-m", "image"]), "page.html", HTML, StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&["-u", "-m", "audio", "-m", "video"]), "photo.png", PNG, StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&["-u", "-M", ".txt,.png"]), "notes.txt", b"notes", StatusCode::SEE_OTHER)]
+#[case(server(&["-u", "-M", ".txt,.png"]), "photo.png", PNG, StatusCode::SEE_OTHER)]
+#[case(server(&["-u", "-M", ".txt"]), "photo.png", PNG, StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+fn uploaded_media_types_are_enforced(
+    #[case] server: TestServer,
+    #[case] file_name: &str,
+    #[case] contents: &[u8],
+    #[case] expected: StatusCode,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    let resp = upload_multipart(
+        &reqwest_client_no_redirect,
+        &server,
+        &[(file_name, contents)],
+    )?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(
+        server.path().join(file_name).exists(),
+        expected == StatusCode::SEE_OTHER
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--max-file-size", "16B"]), &[16], &[true])]
+#[case(server(&["-u", "--max-file-size", "16B"]), &[17], &[false])]
+#[case(server(&["-u", "--max-file-size", "16B"]), &[8, 1000], &[true, false])]
+#[case(server(&["-u", "--max-request-size", "1KiB"]), &[300, 300], &[true, true])]
+#[case(server(&["-u", "--max-request-size", "1KiB"]), &[600, 600], &[false, false])]
+#[case(server(&["-u", "--max-file-size", "1KiB", "--max-request-size", "10KiB"]), &[2000], &[false])]
+fn upload_size_limits_are_enforced(
+    #[case] server: TestServer,
+    #[case] sizes: &[usize],
+    #[case] uploaded: &[bool],
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let names = (0..sizes.len())
+        .map(|i| format!("file-{i}.bin"))
+        .collect::<Vec<_>>();
+    let contents = sizes
+        .iter()
+        .map(|&size| vec![b'a'; size])
+        .collect::<Vec<_>>();
+    let files = names
+        .iter()
+        .zip(&contents)
+        .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+        .collect::<Vec<_>>();
+
+    let resp = upload_multipart(&reqwest_client, &server, &files)?;
+    let all_uploaded = uploaded.iter().all(|&u| u);
+    assert_eq!(resp.status().is_success(), all_uploaded);
+    if !all_uploaded {
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    for (name, &uploaded) in names.iter().zip(uploaded) {
+        assert_eq!(server.path().join(name).exists(), uploaded);
+    }
+
+    Ok(())
+}
+
+/// Files of uploaded folders are named by their relative path, and the missing directories are
+/// created if creating directories is allowed
+#[rstest]
+#[case(server(&["-u", "--mkdir"]), "folder/sub/file.txt", true)]
+#[case(server(&["-u", "--mkdir"]), "someDir/file.txt", true)]
+#[case(server(&["-u"]), "folder/sub/file.txt", false)]
+#[case(server(&["-u"]), "someDir/file.txt", true)]
+#[case(server(&["-u", "--mkdir"]), "folder/.hidden/file.txt", false)]
+fn uploading_folders_creates_directories(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] ok: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload_multipart(&reqwest_client, &server, &[(path, b"in a folder")])?;
+    assert_eq!(resp.status().is_success(), ok);
+    assert_eq!(server.path().join(path).is_file(), ok);
+    if !ok {
+        assert!(!server.path().join("folder").exists());
+    }
+
+    Ok(())
+}
+
+/// Folders can only be selected for upload if creating directories is allowed
+#[rstest]
+#[case(server(&["-u", "--mkdir"]), true)]
+#[case(server(&["-u"]), false)]
+fn folder_upload_requires_mkdir(
+    #[case] server: TestServer,
+    #[case] shown: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let input = parsed.find(Attr("id", "folder-input")).next();
+    assert_eq!(input.is_some(), shown);
+    if let Some(input) = input {
+        assert!(input.attr("webkitdirectory").is_some());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+mod fixtures;
+
+use fixtures::{Error, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use rstest::rstest;
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::fixtures::reqwest_client;
+
+const CONTENTS: &[u8] = b"Uploaded without a multipart form";
+
+/// Upload `CONTENTS` to `path` with a `method` request
+fn upload(
+    client: &Client,
+    server: &TestServer,
+    method: reqwest::Method,
+    path: &str,
+) -> Result<Response, Error> {
+    Ok(client
+        .request(method, server.url().join(path)?)
+        .body(CONTENTS)
+        .send()?)
+}
+
+#[rstest]
+#[case(reqwest::Method::PUT)]
+#[case(reqwest::Method::POST)]
+fn raw_upload_is_disabled_by_default(
+    #[case] method: reqwest::Method,
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, method, "uploaded.txt")?;
+    assert!(resp.status().is_client_error());
+    assert!(!server.path().join("uploaded.txt").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(reqwest::Method::PUT, "uploaded.txt", "/uploaded.txt")]
+#[case(reqwest::Method::POST, "uploaded.txt", "/uploaded.txt")]
+#[case(reqwest::Method::PUT, "dira/uploaded.txt", "/dira/uploaded.txt")]
+#[case(
+    reqwest::Method::PUT,
+    "dir%20space/%F0%9F%98%80.txt",
+    "/dir space/😀.txt"
+)]
+fn raw_upload_works(
+    #[case] method: reqwest::Method,
+    #[case] path: &str,
+    #[case] expected_path: &str,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, method, path)?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let body: Value = resp.json()?;
+    assert_eq!(body["path"], expected_path);
+    assert_eq!(body["size"], CONTENTS.len());
+    assert_eq!(body["hash"], format!("{:x}", Sha256::digest(CONTENTS)));
+    assert_eq!(body["hash_function"], "SHA256");
+    assert_eq!(
+        std::fs::read(server.path().join(&expected_path[1..]))?,
+        CONTENTS
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn raw_upload_works_with_route_prefix(
+    #[with(&["-u", "--route-prefix", "foo"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(
+        &reqwest_client,
+        &server,
+        reqwest::Method::PUT,
+        "foo/dira/up.txt",
+    )?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.json::<Value>()?["path"], "/dira/up.txt");
+    assert_eq!(std::fs::read(server.path().join("dira/up.txt"))?, CONTENTS);
+
+    Ok(())
+}
+
+#[rstest]
+#[case("SHA256", format!("{:x}", Sha256::digest(CONTENTS)), StatusCode::CREATED)]
+#[case("sha512", format!("{:x}", Sha512::digest(CONTENTS)), StatusCode::CREATED)]
+#[case("SHA256", format!("{:x}", Sha256::digest(b"other")), StatusCode::BAD_REQUEST)]
+#[case("SHA128", String::from("abc"), StatusCode::BAD_REQUEST)]
+fn raw_upload_verifies_file_hash(
+    #[case] function: &str,
+    #[case] hash: String,
+    #[case] expected: StatusCode,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .put(server.url().join("hashed.txt")?)
+        .header("X-File-Hash-Function", function)
+        .header("X-File-Hash", &hash)
+        .body(CONTENTS)
+        .send()?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(
+        server.path().join("hashed.txt").exists(),
+        expected.is_success()
+    );
+    if expected.is_success() {
+        let body: Value = resp.json()?;
+        assert_eq!(body["hash"], hash);
+        assert_eq!(body["hash_function"], function.to_ascii_uppercase());
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u"]), StatusCode::CONFLICT, None)]
+#[case(server(&["-u", "-o", "overwrite"]), StatusCode::CREATED, Some("test.txt"))]
+#[case(server(&["-u", "-o", "rename"]), StatusCode::CREATED, Some("test-1.txt"))]
+fn raw_upload_respects_on_duplicate_files(
+    #[case] server: TestServer,
+    #[case] expected: StatusCode,
+    #[case] written: Option<&str>,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let contents = std::fs::read(server.path().join("test.txt"))?;
+    let resp = upload(&reqwest_client, &server, reqwest::Method::PUT, "test.txt")?;
+    assert_eq!(resp.status(), expected);
+
+    match written {
+        Some(written) => {
+            assert_eq!(resp.json::<Value>()?["path"], format!("/{written}"));
+            assert_eq!(std::fs::read(server.path().join(written))?, CONTENTS);
+        }
+        None => assert_eq!(std::fs::read(server.path().join("test.txt"))?, contents),
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "someDir"]), "dira/file.txt", StatusCode::FORBIDDEN)]
+#[case(server(&["-u", "someDir"]), "someDir/file.txt", StatusCode::CREATED)]
+#[case(server(&["-u"]), "dira/", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u"]), "missing/file.txt", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u"]), "test.txt/file.txt", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u"]), ".hidden.txt", StatusCode::BAD_REQUEST)]
+#[case(server(&["-u", "-H"]), ".hidden.txt", StatusCode::CREATED)]
+#[case(server(&["-u", "--no-symlinks"]), "dir_symlink/file.txt", StatusCode::FORBIDDEN)]
+fn raw_upload_is_restricted(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, reqwest::Method::PUT, path)?;
+    assert_eq!(resp.status(), expected);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[rstest]
+fn raw_upload_is_chmoded(
+    #[with(&["-u", "--chmod", "640"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    upload(&reqwest_client, &server, reqwest::Method::PUT, "chmod.txt")?.error_for_status()?;
+
+    let mode = std::fs::metadata(server.path().join("chmod.txt"))?.mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--enable-webdav"]), StatusCode::NOT_FOUND)]
+#[case(server(&["-u", "--enable-webdav", "--webdav-write"]), StatusCode::CREATED)]
+fn raw_put_is_left_to_webdav(
+    #[case] server: TestServer,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // WebDAV answers PUT requests without a JSON body
+    let resp = upload(&reqwest_client, &server, reqwest::Method::PUT, "put.txt")?;
+    assert_eq!(resp.status(), expected);
+    assert!(!resp.text()?.contains("hash"));
+    assert_eq!(
+        server.path().join("put.txt").exists(),
+        expected.is_success()
+    );
+
+    let resp = upload(
+        &reqwest_client,
+        &server,
+        reqwest::Method::POST,
+        "posted.txt",
+    )?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[rstest]
+#[case("rm")]
+#[case("mv")]
+fn raw_upload_does_not_shadow_disabled_actions(
+    #[case] route: &str,
+    #[with(&["-u"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, reqwest::Method::POST, route)?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(!server.path().join(route).exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["-u", "--max-file-size", "1KiB"]), "small.txt", StatusCode::CREATED)]
+#[case(server(&["-u", "--max-file-size", "8B"]), "big.txt", StatusCode::PAYLOAD_TOO_LARGE)]
+#[case(server(&["-u", "--max-request-size", "8B"]), "big.txt", StatusCode::PAYLOAD_TOO_LARGE)]
+#[case(server(&["-u", "-M", ".txt"]), "notes.txt", StatusCode::CREATED)]
+#[case(server(&["-u", "-m", "image"]), "notes.txt", StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+fn raw_upload_respects_limits(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, reqwest::Method::PUT, path)?;
+    assert_eq!(resp.status(), expected);
+    assert_eq!(server.path().join(path).exists(), expected.is_success());
+
+    Ok(())
+}
+
+/// Errors are sent to upload clients as they are, rather than as web pages
+#[rstest]
+#[case(server(&["-u", "--max-file-size", "8B"]), "big.txt", reqwest::Method::PUT)]
+#[case(server(&["-u", "--max-file-size", "8B"]), "big.txt", reqwest::Method::POST)]
+#[case(server(&["-u", "--max-file-size", "8B", "--route-prefix", "foo"]), "foo/big.txt", reqwest::Method::PUT)]
+fn raw_upload_errors_are_plain_text(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] method: reqwest::Method,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = upload(&reqwest_client, &server, method, path)?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resp.headers()["Content-Type"], "text/plain; charset=utf-8");
+
+    Ok(())
+}
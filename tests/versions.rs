@@ -0,0 +1,248 @@
+mod fixtures;
+
+use std::fs;
+
+use fixtures::{Error, TestServer, server};
+use pretty_assertions::assert_eq;
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use select::{document::Document, predicate::Class};
+
+use crate::fixtures::reqwest_client;
+
+/// Replace the contents of `path` with a raw upload
+fn put(client: &Client, server: &TestServer, path: &str, body: &str) -> Result<(), Error> {
+    client
+        .put(server.url().join(path)?)
+        .body(body.to_owned())
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Version numbers listed on the history page of `path`
+fn listed_versions(client: &Client, server: &TestServer, path: &str) -> Result<Vec<String>, Error> {
+    let mut url = server.url().join("__miniserve_internal/versions")?;
+    url.query_pairs_mut().append_pair("path", path);
+    let body = client.get(url).send()?.error_for_status()?.text()?;
+    let parsed = Document::from(body.as_str());
+    Ok(parsed.find(Class("file")).map(|node| node.text()).collect())
+}
+
+#[rstest]
+fn replaced_files_are_versioned(
+    #[with(&["-u", "-o", "version"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let original = fs::read_to_string(server.path().join("test.txt"))?;
+    put(&reqwest_client, &server, "test.txt", "second")?;
+    put(&reqwest_client, &server, "test.txt", "third")?;
+
+    assert_eq!(fs::read_to_string(server.path().join("test.txt"))?, "third");
+    assert_eq!(
+        listed_versions(&reqwest_client, &server, "test.txt")?,
+        ["Version 2", "Version 1"]
+    );
+
+    // The listing links to the history of versioned files only
+    let body = reqwest_client.get(server.url()).send()?.text()?;
+    let parsed = Document::from(body.as_str());
+    let links = parsed
+        .find(Class("versions_link"))
+        .filter_map(|node| node.attr("href"))
+        .collect::<Vec<_>>();
+    assert_eq!(links, ["/__miniserve_internal/versions?path=/test.txt"]);
+    assert!(!body.contains(".miniserve-versions"));
+
+    let resp = reqwest_client
+        .get(
+            server
+                .url()
+                .join("__miniserve_internal/versions/download?path=test.txt&version=1")?,
+        )
+        .send()?
+        .error_for_status()?;
+    assert_eq!(
+        resp.headers()["Content-Disposition"],
+        "attachment; filename=\"test.txt\""
+    );
+    assert_eq!(resp.text()?, original);
+
+    Ok(())
+}
+
+#[rstest]
+fn versions_are_never_served_directly(
+    #[with(&["-u", "-o", "version", "--hidden"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    put(&reqwest_client, &server, "test.txt", "second")?;
+    assert!(
+        server
+            .path()
+            .join(".miniserve-versions/test.txt/1")
+            .is_file()
+    );
+
+    let resp = reqwest_client
+        .get(server.url().join(".miniserve-versions/test.txt/1")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn oldest_versions_are_pruned(
+    #[with(&["-u", "-o", "version", "--max-versions", "2"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    for body in ["second", "third", "fourth", "fifth"] {
+        put(&reqwest_client, &server, "test.txt", body)?;
+    }
+
+    assert_eq!(
+        listed_versions(&reqwest_client, &server, "test.txt")?,
+        ["Version 4", "Version 3"]
+    );
+    assert_eq!(
+        fs::read_to_string(server.path().join(".miniserve-versions/test.txt/4"))?,
+        "fourth"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn versions_can_be_restored(
+    #[with(&["-u", "-o", "version", "--max-versions", "2"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let original = fs::read_to_string(server.path().join("test.txt"))?;
+    put(&reqwest_client, &server, "test.txt", "second")?;
+    put(&reqwest_client, &server, "test.txt", "third")?;
+
+    reqwest_client
+        .post(
+            server
+                .url()
+                .join("__miniserve_internal/versions/restore?path=test.txt&version=1")?,
+        )
+        .send()?
+        .error_for_status()?;
+
+    // The replaced file is kept in turn, and the restored version isn't pruned to make room
+    assert_eq!(
+        fs::read_to_string(server.path().join("test.txt"))?,
+        original
+    );
+    assert_eq!(
+        listed_versions(&reqwest_client, &server, "test.txt")?,
+        ["Version 3", "Version 2"]
+    );
+    assert_eq!(
+        fs::read_to_string(server.path().join(".miniserve-versions/test.txt/3"))?,
+        "third"
+    );
+
+    Ok(())
+}
+
+#[rstest]
+#[case(
+    "__miniserve_internal/versions/restore?path=someDir/test.txt&version=9",
+    StatusCode::NOT_FOUND
+)]
+#[case(
+    "__miniserve_internal/versions/restore?path=someDir/test.txt",
+    StatusCode::BAD_REQUEST
+)]
+#[case(
+    "__miniserve_internal/versions/restore?path=../test.txt&version=1",
+    StatusCode::FORBIDDEN
+)]
+fn invalid_restores_are_rejected(
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+    #[with(&["-u", "someDir", "-o", "version"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client.post(server.url().join(path)?).send()?;
+    assert_eq!(resp.status(), expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn moves_keep_replaced_files_as_versions(
+    #[with(&["-u", "-R", "-o", "version"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let replaced = fs::read_to_string(server.path().join("test.txt"))?;
+    let mut url = server.url().join("mv")?;
+    url.query_pairs_mut().append_pair("path", "test.html");
+    reqwest_client
+        .post(url)
+        .form(&[("destination", "test.txt")])
+        .send()?
+        .error_for_status()?;
+
+    assert_eq!(
+        fs::read_to_string(server.path().join(".miniserve-versions/test.txt/1"))?,
+        replaced
+    );
+
+    // Directories can't be versioned
+    let mut url = server.url().join("mv")?;
+    url.query_pairs_mut().append_pair("path", "dira");
+    let resp = reqwest_client
+        .post(url)
+        .form(&[("destination", "dirb")])
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    Ok(())
+}
+
+#[rstest]
+fn webdav_put_keeps_replaced_files_as_versions(
+    #[with(&["-u", "--enable-webdav", "--webdav-write", "-o", "version"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let replaced = fs::read_to_string(server.path().join("test.txt"))?;
+    put(&reqwest_client, &server, "test.txt", "second")?;
+
+    assert_eq!(
+        fs::read_to_string(server.path().join("test.txt"))?,
+        "second"
+    );
+    assert_eq!(
+        fs::read_to_string(server.path().join(".miniserve-versions/test.txt/1"))?,
+        replaced
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn failed_webdav_puts_keep_no_versions(
+    #[with(&["-u", "--enable-webdav", "--webdav-write", "-o", "version", "--max-file-size", "8B"])]
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let original = fs::read_to_string(server.path().join("test.txt"))?;
+    let resp = reqwest_client
+        .put(server.url().join("test.txt")?)
+        .body("more than eight bytes")
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    assert_eq!(
+        fs::read_to_string(server.path().join("test.txt"))?,
+        original
+    );
+    assert!(!server.path().join(".miniserve-versions/test.txt").exists());
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+mod fixtures;
+
+use std::fs;
+
+use fixtures::{Error, FILE_SYMLINK, TestServer, server};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use rstest::rstest;
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Predicate};
+
+use crate::fixtures::{reqwest_client, reqwest_client_no_redirect};
+
+#[rstest]
+fn viewer_shows_numbered_lines(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    fs::write(server.path().join("notes.txt"), "first\nsecond\nthird\n")?;
+    let body = reqwest_client
+        .get(server.url().join("notes.txt?view=1")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+
+    for (n, text) in ["first", "second", "third"].iter().enumerate() {
+        let row = parsed
+            .find(Name("tr").and(Attr("id", format!("L{}", n + 1).as_str())))
+            .next()
+            .unwrap();
+        assert_eq!(row.find(Class("line")).next().unwrap().text(), *text);
+    }
+    assert!(
+        parsed
+            .find(Name("a").and(Attr("href", "/notes.txt")))
+            .next()
+            .is_some()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn viewer_highlights_known_languages(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("main.rs"), "fn main() {}\n")?;
+    let body = reqwest_client
+        .get(server.url().join("main.rs?view=true")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    assert!(body.contains("Rust"));
+    assert!(body.contains("hl-keyword") || body.contains("hl-storage"));
+
+    Ok(())
+}
+
+#[rstest]
+fn viewer_escapes_html(server: TestServer, reqwest_client: Client) -> Result<(), Error> {
+    fs::write(
+        server.path().join("page.html"),
+        "<script>alert(1)</script>\n",
+    )?;
+    let body = reqwest_client
+        .get(server.url().join("page.html?view=1")?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    assert!(!body.contains("<script>alert(1)</script>"));
+    let parsed = Document::from(body.as_str());
+    let line = parsed.find(Class("line")).next().unwrap().text();
+    assert_eq!(line, "<script>alert(1)</script>");
+
+    Ok(())
+}
+
+#[rstest]
+#[case("large.txt", vec![b'a'; 2 * 1024 * 1024])]
+#[case("binary.txt", vec![0, 159, 146, 150])]
+fn files_which_cant_be_viewed_are_downloaded(
+    #[case] name: &str,
+    #[case] contents: Vec<u8>,
+    server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join(name), contents)?;
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join(&format!("{name}?view=1"))?)
+        .send()?;
+
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()["location"], format!("/{name}").as_str());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&[] as &[&str]), ".hidden_file1")]
+#[case(server(&["-H", "-u"]), ".miniserve-expiry.json")]
+#[case(server(&["--no-symlinks"]), FILE_SYMLINK)]
+#[case(server(&["--no-symlinks"]), "dir_symlink/test.txt")]
+fn viewer_respects_hidden_and_symlink_rules(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join(".miniserve-expiry.json"), "{}")?;
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join(&format!("{path}?view=1"))?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn viewer_is_disabled_in_drop_boxes(
+    #[with(&["-u", "--drop-box"])] server: TestServer,
+    reqwest_client_no_redirect: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client_no_redirect
+        .get(server.url().join("test.txt?view=1")?)
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[rstest]
+fn listing_links_text_files_to_the_viewer(
+    server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    fs::write(server.path().join("photo.png"), [137, 80, 78, 71])?;
+    let body = reqwest_client
+        .get(server.url())
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+    let view_links: Vec<_> = parsed
+        .find(Name("a").and(Class("view_link")))
+        .filter_map(|link| link.attr("href").map(str::to_owned))
+        .collect();
+
+    assert!(view_links.contains(&"/test.txt?view=1".to_string()));
+    assert!(!view_links.iter().any(|link| link.contains("photo.png")));
+    assert!(!view_links.iter().any(|link| link.contains("test.mkv")));
+
+    Ok(())
+}
+
+#[rstest]
+fn viewer_works_with_route_prefix(
+    #[with(&["--route-prefix", "prefix"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let body = reqwest_client
+        .get(server.url().join("prefix/test.txt?view=1")?)
+        .send()?
+        .error_for_status()?;
+    let parsed = Document::from_read(body)?;
+
+    assert_eq!(
+        parsed.find(Class("line")).next().unwrap().text(),
+        "Test Hello Yes"
+    );
+    assert!(
+        parsed
+            .find(Name("a").and(Attr("href", "/prefix/test.txt")))
+            .next()
+            .is_some()
+    );
+
+    Ok(())
+}
@@ -1,9 +1,13 @@
+use std::io::Cursor;
 use std::process::Command;
 
 use assert_cmd::{cargo, prelude::*};
 use assert_fs::TempDir;
 use predicates::str::contains;
-use reqwest::{Method, blocking::Client};
+use reqwest::{
+    Method, StatusCode,
+    blocking::{Body, Client},
+};
 use reqwest_dav::{
     ClientBuilder as DavClientBuilder,
     list_cmd::{ListEntity, ListFile, ListFolder},
@@ -128,6 +132,38 @@ fn webdav_respects_no_symlink_flag(#[case] server: TestServer, #[case] symlinks_
     assert_eq!(symlinks_should_show, list_nested_file.is_ok());
 }
 
+#[rstest]
+#[case(server(&["--enable-webdav"]))]
+#[case(server(&["--enable-webdav", "--hidden"]))]
+fn webdav_never_exposes_internal_entries(#[case] server: TestServer) -> Result<(), Error> {
+    let internal_dirs = [".miniserve-trash", ".miniserve-versions"];
+    let internal_files = [".miniserve-quota.json", ".miniserve-expiry.json"];
+    for dir in internal_dirs {
+        std::fs::create_dir(server.path().join(dir))?;
+        std::fs::write(server.path().join(dir).join("entry"), "internal")?;
+    }
+    for file in internal_files {
+        std::fs::write(server.path().join(file), "{}")?;
+    }
+
+    let list = list_webdav(server.url(), "/")?;
+    for name in internal_dirs.iter().chain(&internal_files) {
+        assert!(!list.iter().any(|el| match el {
+            ListEntity::File(ListFile { href, .. }) => href.contains(name),
+            ListEntity::Folder(ListFolder { href, .. }) => href.contains(name),
+        }));
+    }
+    for path in [
+        ".miniserve-trash/",
+        ".miniserve-versions/entry",
+        ".miniserve-quota.json",
+    ] {
+        assert!(list_webdav(server.url(), &format!("/{path}")).is_err());
+    }
+
+    Ok(())
+}
+
 #[rstest]
 fn webdav_works_with_route_prefix(
     #[with(&["--enable-webdav", "--route-prefix", "test-prefix"])] server: TestServer,
@@ -162,3 +198,288 @@ fn webdav_single_file_refuses_starting(tmpdir: TempDir) {
             FILES[0]
         )));
 }
+
+/// Arguments enabling all WebDAV writes
+const WRITE_ARGS: &[&str] = &["--enable-webdav", "--webdav-write", "-u", "-U", "-R"];
+
+fn dav_method(name: &str) -> Method {
+    Method::from_bytes(name.as_bytes()).unwrap()
+}
+
+#[rstest]
+#[case(server(&["--enable-webdav", "-u", "-U", "-R"]))]
+#[case(server(&["--webdav-write", "--enable-webdav"]))]
+fn webdav_writes_need_permissions(
+    #[case] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let put = reqwest_client
+        .put(server.url().join("new.txt")?)
+        .body("new")
+        .send()?;
+    assert!(!put.status().is_success());
+    assert!(!server.path().join("new.txt").exists());
+
+    let mkcol = reqwest_client
+        .request(dav_method("MKCOL"), server.url().join("newdir")?)
+        .send()?;
+    assert!(!mkcol.status().is_success());
+    assert!(!server.path().join("newdir").exists());
+
+    let delete = reqwest_client.delete(server.url().join(FILES[0])?).send()?;
+    assert!(!delete.status().is_success());
+    assert!(server.path().join(FILES[0]).exists());
+
+    Ok(())
+}
+
+#[rstest]
+fn webdav_write_creates_and_removes_entries(
+    #[with(WRITE_ARGS)] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    reqwest_client
+        .request(dav_method("MKCOL"), server.url().join("newdir")?)
+        .send()?
+        .error_for_status()?;
+    assert!(server.path().join("newdir").is_dir());
+
+    reqwest_client
+        .put(server.url().join("newdir/new%20file.txt")?)
+        .body("new content")
+        .send()?
+        .error_for_status()?;
+    let new_file = server.path().join("newdir").join("new file.txt");
+    assert_eq!(std::fs::read_to_string(&new_file)?, "new content");
+
+    reqwest_client
+        .request(
+            dav_method("COPY"),
+            server.url().join("newdir/new%20file.txt")?,
+        )
+        .header("Destination", server.url().join("copy.txt")?.as_str())
+        .send()?
+        .error_for_status()?;
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("copy.txt"))?,
+        "new content"
+    );
+
+    reqwest_client
+        .request(dav_method("MOVE"), server.url().join("newdir/")?)
+        .header("Destination", "/moved")
+        .send()?
+        .error_for_status()?;
+    assert!(!server.path().join("newdir").exists());
+    assert!(server.path().join("moved").join("new file.txt").is_file());
+
+    reqwest_client
+        .delete(server.url().join("moved/")?)
+        .send()?
+        .error_for_status()?;
+    assert!(!server.path().join("moved").exists());
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&["--enable-webdav", "--webdav-write", "-u", "someDir", "-R", "someDir"]), "")]
+#[case(server(&["--enable-webdav", "--webdav-write", "-u", "someDir", "-R", "someDir", "--route-prefix", "foo"]), "foo/")]
+fn webdav_write_respects_allowed_dirs(
+    #[case] server: TestServer,
+    #[case] prefix: &str,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let url = |path: &str| server.url().join(&format!("{prefix}{path}"));
+
+    let resp = reqwest_client.put(url("new.txt")?).body("new").send()?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = reqwest_client.delete(url(FILES[0])?).send()?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Moving out of an allowed directory needs uploads to be allowed at the destination
+    let resp = reqwest_client
+        .request(dav_method("MOVE"), url("someDir/alpha")?)
+        .header("Destination", url("alpha")?.as_str())
+        .send()?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert!(server.path().join("someDir/alpha").exists());
+
+    reqwest_client
+        .put(url("someDir/new.txt")?)
+        .body("new")
+        .send()?
+        .error_for_status()?;
+    assert!(server.path().join("someDir/new.txt").exists());
+    reqwest_client
+        .delete(url("someDir/alpha")?)
+        .send()?
+        .error_for_status()?;
+    assert!(!server.path().join("someDir/alpha").exists());
+
+    Ok(())
+}
+
+const LOCK_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:lockinfo xmlns:D="DAV:">
+  <D:lockscope><D:exclusive/></D:lockscope>
+  <D:locktype><D:write/></D:locktype>
+</D:lockinfo>"#;
+
+const PROPPATCH_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:example">
+  <D:set><D:prop><Z:note>changed</Z:note></D:prop></D:set>
+</D:propertyupdate>"#;
+
+#[rstest]
+#[case("LOCK", LOCK_BODY)]
+#[case("PROPPATCH", PROPPATCH_BODY)]
+fn webdav_lock_and_proppatch_respect_allowed_dirs(
+    #[case] method: &str,
+    #[case] body: &'static str,
+    #[with(&["--enable-webdav", "--webdav-write", "-u", "someDir"])] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    for path in [FILES[0], "locked.txt"] {
+        let resp = reqwest_client
+            .request(dav_method(method), server.url().join(path)?)
+            .body(body)
+            .send()?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+    assert!(!server.path().join("locked.txt").exists());
+
+    let resp = reqwest_client
+        .request(dav_method(method), server.url().join("someDir/alpha")?)
+        .body(body)
+        .send()?;
+    // Properties can't be stored, but the request gets through
+    assert_ne!(resp.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[rstest]
+#[case("error", StatusCode::CONFLICT, "Test Hello Yes", None)]
+#[case("overwrite", StatusCode::NO_CONTENT, "new", None)]
+#[case("rename", StatusCode::CREATED, "Test Hello Yes", Some("test-1.txt"))]
+fn webdav_write_respects_on_duplicate_files(
+    #[case] policy: &str,
+    #[case] status: StatusCode,
+    #[case] original_content: &str,
+    #[case] renamed: Option<&str>,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let server = server(&[WRITE_ARGS, &["--on-duplicate-files", policy]].concat());
+
+    let resp = reqwest_client
+        .put(server.url().join("test.txt")?)
+        .body("new")
+        .send()?;
+    assert_eq!(resp.status(), status);
+    assert_eq!(
+        std::fs::read_to_string(server.path().join("test.txt"))?,
+        original_content
+    );
+    if let Some(renamed) = renamed {
+        assert_eq!(std::fs::read_to_string(server.path().join(renamed))?, "new");
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(&[], StatusCode::FORBIDDEN)]
+#[case(&["-R"], StatusCode::NO_CONTENT)]
+fn webdav_replacing_directories_needs_rm_permission(
+    #[case] args: &[&str],
+    #[case] status: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let base = ["--enable-webdav", "--webdav-write", "-u", "-o", "overwrite"];
+    let server = server(&[&base[..], args].concat());
+
+    for method in ["COPY", "MOVE"] {
+        std::fs::create_dir_all(server.path().join("dira"))?;
+        std::fs::write(server.path().join("someDir/alpha"), "alpha")?;
+        let resp = reqwest_client
+            .request(dav_method(method), server.url().join("dira/")?)
+            .header("Destination", server.url().join("someDir/")?.as_str())
+            .send()?;
+        assert_eq!(resp.status(), status);
+        let replaced = status.is_success();
+        assert_eq!(!server.path().join("someDir/alpha").exists(), replaced);
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(&[WRITE_ARGS, &["--max-file-size", "8B"]].concat()), "big.txt", StatusCode::PAYLOAD_TOO_LARGE)]
+#[case(server(&[WRITE_ARGS, &["--max-request-size", "8B"]].concat()), "big.txt", StatusCode::PAYLOAD_TOO_LARGE)]
+#[case(server(&[WRITE_ARGS, &["-m", "image"]].concat()), "notes.txt", StatusCode::UNSUPPORTED_MEDIA_TYPE)]
+#[case(server(&[WRITE_ARGS, &["-M", ".txt"]].concat()), "notes.txt", StatusCode::CREATED)]
+fn webdav_put_respects_limits(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] expected: StatusCode,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    // Without a Content-Length, the limits apply to what's actually sent
+    for body in [
+        Body::from("more than eight bytes"),
+        Body::new(Cursor::new("more than eight bytes")),
+    ] {
+        let resp = reqwest_client
+            .put(server.url().join(path)?)
+            .body(body)
+            .send()?;
+        assert_eq!(resp.status(), expected);
+        assert_eq!(server.path().join(path).exists(), expected.is_success());
+        if expected.is_success() {
+            std::fs::remove_file(server.path().join(path))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[rstest]
+#[case(server(WRITE_ARGS), "dir_symlink/new.txt", true)]
+#[case(server(&[WRITE_ARGS, &["--no-symlinks"]].concat()), "dir_symlink/new.txt", false)]
+#[case(server(WRITE_ARGS), ".hidden_dir1/new.txt", false)]
+#[case(server(&[WRITE_ARGS, &["--hidden"]].concat()), ".hidden_dir1/new.txt", true)]
+fn webdav_write_respects_visibility(
+    #[case] server: TestServer,
+    #[case] path: &str,
+    #[case] should_write: bool,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    let resp = reqwest_client
+        .put(server.url().join(path)?)
+        .body("new")
+        .send()?;
+    assert_eq!(resp.status().is_success(), should_write);
+    assert_eq!(server.path().join(path).exists(), should_write);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[rstest]
+fn webdav_write_applies_chmod(
+    #[with(&[WRITE_ARGS, &["--chmod", "0640"]].concat())] server: TestServer,
+    reqwest_client: Client,
+) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    reqwest_client
+        .put(server.url().join("new.txt")?)
+        .body("new")
+        .send()?
+        .error_for_status()?;
+    let metadata = std::fs::metadata(server.path().join("new.txt"))?;
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+
+    Ok(())
+}